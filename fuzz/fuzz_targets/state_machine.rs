@@ -0,0 +1,9 @@
+//! Feeds random event sequences through the door state machine.
+//!
+//! Run with `cargo +nightly fuzz run state_machine` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| garaged::machine::fuzz(data));
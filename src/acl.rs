@@ -0,0 +1,86 @@
+//! Broker ACL self-test.
+//!
+//! After connecting, a unique probe payload is published to an `acl_probe`
+//! sibling of every configured topic while subscribed to it. Probes that
+//! don't come back within the timeout point at a publish or subscribe ACL
+//! gap. Siblings are used so probes never clobber retained state or confuse
+//! Home Assistant; brokers with exact-topic ACLs will need the probe topics
+//! granted too.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use tokio::time::Instant;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const PROBE_SEGMENT: &str = "acl_probe";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AclReport {
+    pub problem: bool,
+    /// Probe topics that never made the round trip.
+    pub gaps: Vec<String>,
+    pub subscribe_refused: usize,
+}
+
+#[derive(Debug)]
+pub struct AclProbe {
+    payload: String,
+    pending: BTreeSet<String>,
+    subscribe_refused: usize,
+    deadline: Instant,
+}
+
+impl AclProbe {
+    pub fn start<'a, I: IntoIterator<Item = &'a str>>(topics: I) -> AclProbe {
+        let pending = topics.into_iter().map(probe_topic).collect();
+        AclProbe {
+            payload: format!("garaged-{}-{}", std::process::id(), Utc::now().timestamp_micros()),
+            pending,
+            subscribe_refused: 0,
+            deadline: Instant::now() + PROBE_TIMEOUT,
+        }
+    }
+
+    pub fn probe_topics(&self) -> impl Iterator<Item = &str> {
+        self.pending.iter().map(String::as_str)
+    }
+
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// Checks an incoming publish, returning true if it was one of our probes.
+    pub fn received(&mut self, topic: &str, payload: &[u8]) -> bool {
+        payload == self.payload.as_bytes() && self.pending.remove(topic)
+    }
+
+    pub fn subscribe_refused(&mut self) {
+        self.subscribe_refused += 1;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn report(self) -> AclReport {
+        AclReport {
+            problem: !self.pending.is_empty() || self.subscribe_refused > 0,
+            gaps: self.pending.into_iter().collect(),
+            subscribe_refused: self.subscribe_refused,
+        }
+    }
+}
+
+fn probe_topic(topic: &str) -> String {
+    match topic.rsplit_once('/') {
+        Some((parent, _)) => format!("{}/{}", parent, PROBE_SEGMENT),
+        None => PROBE_SEGMENT.to_owned(),
+    }
+}
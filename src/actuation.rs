@@ -0,0 +1,176 @@
+//! Relay presses as a chain of stages, run in the order `[actuation]`
+//! lists them. Every press goes through the chain, whether a command, the
+//! wall button or an automated close asked for it, so a site-specific
+//! safety step is another stage here and an arm in the daemon's
+//! `run_stage`, with nothing that presses the relay needing to change.
+//!
+//! Stages ahead of `pulse` may refuse the press; once the relay has been
+//! pressed there is nothing left to refuse. A stage may also hold the press
+//! for a while, as the warning does while it sounds; the loop carries on
+//! meanwhile and runs the rest of the stages once the hold is over.
+//!
+//! Presses wait their turn in a [`PressQueue`], run one at a time by the
+//! daemon's loop at least `actuation.spacing_ms` apart, since an opener can
+//! miss a second pulse that follows the first too closely. A command sent
+//! twice before its press has run, say from a double tap in Home Assistant,
+//! only presses once.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tokio::time::Instant;
+
+use crate::door::{Command, Trigger};
+
+/// Presses held at most, beyond which more are refused.
+pub const MAX_QUEUED: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Stage {
+    /// Refuses a press within `rate_limit.relay_cooldown_secs` of the last,
+    /// unless it stops the door.
+    RateLimit,
+    /// Refuses presses in maintenance mode, and remote or automated presses
+    /// that would close the door into an obstructed doorway.
+    Interlock,
+    /// Sounds the warning output before a press that closes the door,
+    /// unless a close countdown already did.
+    Warning,
+    /// Presses the relay.
+    Pulse,
+    /// Follows the press to see the door respond, reporting one that never
+    /// moved it.
+    Verify,
+}
+
+impl Stage {
+    /// Whether the stage only makes sense before the relay is pressed.
+    pub fn before_pulse(self) -> bool {
+        matches!(self, Stage::RateLimit | Stage::Interlock | Stage::Warning)
+    }
+}
+
+/// Checks that `stages` press the relay exactly once, with no stage twice
+/// and everything that must come first ahead of the pulse.
+pub fn check(stages: &[Stage]) -> Result<(), String> {
+    let mut seen = BTreeSet::new();
+    if let Some(stage) = stages.iter().find(|s| !seen.insert(**s)) {
+        return Err(format!("actuation stage {} is listed twice", stage));
+    }
+    let pulse = stages.iter().position(|s| *s == Stage::Pulse)
+        .ok_or_else(|| "actuation stages must include pulse".to_owned())?;
+    if let Some(stage) = stages[pulse..].iter().find(|s| s.before_pulse()) {
+        return Err(format!("actuation stage {} must come before pulse", stage));
+    }
+    Ok(())
+}
+
+/// A press on its way through the stages, with what was known about the
+/// door before any of them ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Press {
+    pub cause: Trigger,
+    /// Whether it stops a moving door rather than starting a run.
+    pub stops: bool,
+    /// Whether the sensors will see the run start.
+    pub visible: bool,
+    /// Whether it sets the door closing.
+    pub closes: bool,
+}
+
+/// What a stage made of a press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Proceed,
+    /// Refused, with the reason as a short code.
+    Refuse(&'static str),
+    /// Carry on with the next stage after this long.
+    Hold(Duration),
+}
+
+/// A press paused part way through the stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Held {
+    pub press: Press,
+    /// Index of the stage to carry on from.
+    pub next: usize,
+    pub until: Instant,
+}
+
+/// A press waiting its turn, with the command that asked for it if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Queued {
+    pub cause: Trigger,
+    pub command: Option<Command>,
+}
+
+/// What became of a press handed to [`PressQueue::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enqueued {
+    Added,
+    /// The same command was already waiting, so this one was dropped.
+    Coalesced,
+    /// [`MAX_QUEUED`] presses were already waiting.
+    Full,
+}
+
+#[derive(Debug, Default)]
+pub struct PressQueue {
+    pending: VecDeque<Queued>,
+    /// When the relay was last pressed.
+    last: Option<Instant>,
+}
+
+impl PressQueue {
+    /// Queues a press. Presses without a command, like the wall button's,
+    /// are never coalesced: pressing twice means something there.
+    pub fn push(&mut self, press: Queued) -> Enqueued {
+        if press.command.is_some() && self.pending.contains(&press) {
+            Enqueued::Coalesced
+        } else if self.pending.len() >= MAX_QUEUED {
+            Enqueued::Full
+        } else {
+            self.pending.push_back(press);
+            Enqueued::Added
+        }
+    }
+
+    /// When the next press may run, `spacing` after the last, or `None`
+    /// with nothing waiting.
+    pub fn deadline(&self, spacing: Duration) -> Option<Instant> {
+        self.pending.front()?;
+        Some(self.last.map_or_else(Instant::now, |last| last + spacing))
+    }
+
+    /// Takes the next press if its turn has come.
+    pub fn pop(&mut self, spacing: Duration) -> Option<Queued> {
+        if self.deadline(spacing)? > Instant::now() {
+            return None;
+        }
+        self.pending.pop_front()
+    }
+
+    /// Notes the relay being pressed, which the spacing is counted from.
+    pub fn pressed(&mut self) {
+        self.last = Some(Instant::now());
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Drops everything waiting, returning how many there were.
+    pub fn clear(&mut self) -> usize {
+        let dropped = self.pending.len();
+        self.pending.clear();
+        dropped
+    }
+}
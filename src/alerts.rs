@@ -0,0 +1,43 @@
+//! "Door left open" alerts with repeat and backoff.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::LeftOpenAlertConfig;
+
+/// Schedules alerts while the door stays open: the first after the
+/// configured threshold, then repeats with a growing interval.
+#[derive(Debug, Default)]
+pub struct LeftOpenAlerts {
+    next: Option<Instant>,
+    interval: Duration,
+    sent: u32,
+}
+
+impl LeftOpenAlerts {
+    pub fn door_opened(&mut self, since: Instant, config: &LeftOpenAlertConfig) {
+        self.next = Some(since + config.after());
+        self.interval = config.repeat();
+        self.sent = 0;
+    }
+
+    /// Clears the schedule, returning how many alerts went out while open.
+    pub fn door_closed(&mut self) -> u32 {
+        self.next = None;
+        std::mem::take(&mut self.sent)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /// Records an alert going out and schedules the next one. Returns the
+    /// sequence number of this alert, starting from 1.
+    pub fn fire(&mut self, config: &LeftOpenAlertConfig) -> u32 {
+        self.sent += 1;
+        self.next = Some(Instant::now() + self.interval);
+        self.interval = self.interval.mul_f64(config.backoff).min(config.max_repeat());
+        self.sent
+    }
+}
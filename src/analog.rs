@@ -0,0 +1,84 @@
+//! Analog inputs sampled periodically, such as the Iono Pi's AV1-AV4
+//! voltage and AI1-AI4 current inputs, for a backup battery or a current
+//! clamp on the opener motor.
+//!
+//! Each input is a sysfs file holding a raw reading, scaled and offset into
+//! the configured unit. The Iono Pi kernel module reports millivolts and
+//! microamps.
+
+use std::io;
+use std::path::Path;
+
+use tokio::sync::mpsc;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, warn};
+
+use crate::availability::SensorFailure;
+use crate::config::{AnalogConfig, AnalogInputConfig};
+
+#[derive(Debug, Clone)]
+pub struct Reading {
+    pub id: String,
+    /// Already scaled into the input's unit.
+    pub value: f64,
+}
+
+/// Starts sampling the configured inputs. Without any, the channel closes
+/// straight away.
+pub fn spawn(config: Option<AnalogConfig>) -> mpsc::Receiver<Result<Reading, SensorFailure>> {
+    let (tx, rx) = mpsc::channel(16);
+    let config = match config {
+        Some(c) if !c.inputs.is_empty() => c,
+        _ => return rx,
+    };
+    tokio::spawn(async move {
+        let mut timer = interval(config.poll());
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            timer.tick().await;
+            let poll = config.clone();
+            let readings = match tokio::task::spawn_blocking(move || read_all(&poll)).await {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!(error = %e, "analog poll failed");
+                    continue;
+                }
+            };
+            for reading in readings {
+                if tx.send(reading).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Reads every input, logging the ones that fail.
+fn read_all(config: &AnalogConfig) -> Vec<Result<Reading, SensorFailure>> {
+    config.inputs.iter()
+        .map(|input| match read(input) {
+            Ok(value) => {
+                debug!(input = %input.id, value, "read analog input");
+                Ok(Reading { id: input.id.clone(), value })
+            }
+            Err(e) => {
+                warn!(input = %input.id, path = %input.path().display(), error = %e, "failed to read analog input");
+                Err(SensorFailure { id: input.id.clone(), error: e.to_string() })
+            }
+        })
+        .collect()
+}
+
+/// Reads `input` and scales the value.
+pub fn read(input: &AnalogInputConfig) -> io::Result<f64> {
+    let raw = read_raw(&input.path())?;
+    Ok(raw * input.scale + input.offset)
+}
+
+fn read_raw(path: &Path) -> io::Result<f64> {
+    std::fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("not a number: {}", e)))
+}
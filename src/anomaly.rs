@@ -0,0 +1,182 @@
+//! Spotting a door that runs differently than it used to, as a failing
+//! spring, a binding track or a slipping belt first shows up.
+//!
+//! Full runs between the end sensors are timed and compared with a baseline
+//! learned for each direction from the first `motor.anomaly_learn_runs`
+//! runs, and kept up to date by normal runs after that. A run much slower
+//! or faster than the baseline is an anomaly, as is a closing door heading
+//! back up without a press, the way an opener reverses off an obstacle.
+//!
+//! Runs can only be timed with the open sensor fitted. The baselines are
+//! relearned after a restart.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use strum::Display;
+use tokio::time::Instant;
+
+use crate::door::{Position, Status};
+
+/// Weight of each normal run in a learned baseline.
+const BASELINE_WEIGHT: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AnomalyKind {
+    /// The run took longer than the baseline allows.
+    Slow,
+    /// The run was over sooner than the baseline allows.
+    Fast,
+    /// The door went back up partway through closing.
+    Reversed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    /// The end the door was heading for.
+    pub direction: Status,
+    /// How long the run took, if it finished.
+    pub secs: Option<f64>,
+    pub baseline_secs: Option<f64>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Baseline {
+    mean: Option<Duration>,
+    runs: u32,
+}
+
+impl Baseline {
+    /// Folds in a run, as a plain average while learning.
+    fn add(&mut self, run: Duration, learn_runs: u32) {
+        self.runs = self.runs.saturating_add(1);
+        let weight = if self.runs <= learn_runs { 1.0 / f64::from(self.runs) } else { BASELINE_WEIGHT };
+        self.mean = Some(match self.mean {
+            Some(mean) => mean.mul_f64(1.0 - weight) + run.mul_f64(weight),
+            None => run,
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct AnomalyDetector {
+    tolerance: f64,
+    learn_runs: u32,
+    /// The run being timed, by the end it is heading for.
+    run: Option<(Status, Instant)>,
+    opening: Baseline,
+    closing: Baseline,
+    /// The last anomaly, until a normal run clears it.
+    problem: Option<Anomaly>,
+}
+
+impl AnomalyDetector {
+    /// Runs more than `tolerance` of the baseline off it are anomalies,
+    /// once `learn_runs` runs have been timed in that direction.
+    pub fn new(tolerance: f64, learn_runs: u32) -> AnomalyDetector {
+        AnomalyDetector {
+            tolerance,
+            learn_runs,
+            run: None,
+            opening: Baseline::default(),
+            closing: Baseline::default(),
+            problem: None,
+        }
+    }
+
+    pub fn set_thresholds(&mut self, tolerance: f64, learn_runs: u32) {
+        self.tolerance = tolerance;
+        self.learn_runs = learn_runs;
+    }
+
+    /// Follows the tracker from `from` to `to`, returning an anomaly when a
+    /// run ends in one. Anything that isn't a run from end to end, like a
+    /// press stopping the door, stops the timing.
+    pub fn position_changed(&mut self, from: Position, to: Position) -> Option<Anomaly> {
+        let run = self.run.take();
+        let (direction, started) = match (from, to) {
+            (Position::Closed, Position::Opening) => {
+                self.run = Some((Status::Open, Instant::now()));
+                return None;
+            }
+            (Position::Open, Position::Closing) => {
+                self.run = Some((Status::Closed, Instant::now()));
+                return None;
+            }
+            (Position::Closing, Position::Opening | Position::Open) => {
+                let anomaly = Anomaly { kind: AnomalyKind::Reversed, direction: Status::Closed, secs: None, baseline_secs: None };
+                self.problem = Some(anomaly);
+                return Some(anomaly);
+            }
+            (Position::Opening, Position::Open) | (Position::Closing, Position::Closed) => run?,
+            _ => return None,
+        };
+        if Some(direction) != run_end(to) {
+            return None;
+        }
+        let elapsed = started.elapsed();
+        let learn_runs = self.learn_runs;
+        let baseline = match direction {
+            Status::Open => &mut self.opening,
+            Status::Closed => &mut self.closing,
+        };
+        let kind = match baseline.mean {
+            Some(mean) if baseline.runs >= learn_runs => {
+                let ratio = elapsed.as_secs_f64() / mean.as_secs_f64();
+                if ratio > 1.0 + self.tolerance {
+                    Some(AnomalyKind::Slow)
+                } else if ratio < 1.0 - self.tolerance {
+                    Some(AnomalyKind::Fast)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let kind = match kind {
+            Some(kind) => kind,
+            None => {
+                baseline.add(elapsed, learn_runs);
+                self.problem = None;
+                return None;
+            }
+        };
+        let anomaly = Anomaly {
+            kind,
+            direction,
+            secs: Some(round(elapsed)),
+            baseline_secs: baseline.mean.map(round),
+        };
+        self.problem = Some(anomaly);
+        Some(anomaly)
+    }
+
+    /// The last anomaly, unless a normal run has followed it.
+    pub fn problem(&self) -> Option<&Anomaly> {
+        self.problem.as_ref()
+    }
+
+    /// The learned time for a full run towards `direction`, once learned.
+    pub fn baseline(&self, direction: Status) -> Option<Duration> {
+        let baseline = match direction {
+            Status::Open => &self.opening,
+            Status::Closed => &self.closing,
+        };
+        baseline.mean.filter(|_| baseline.runs >= self.learn_runs)
+    }
+}
+
+fn run_end(position: Position) -> Option<Status> {
+    match position {
+        Position::Open => Some(Status::Open),
+        Position::Closed => Some(Status::Closed),
+        _ => None,
+    }
+}
+
+fn round(d: Duration) -> f64 {
+    (d.as_secs_f64() * 10.0).round() / 10.0
+}
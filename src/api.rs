@@ -0,0 +1,243 @@
+//! In-process control API shared by the local front ends (HTTP and the
+//! control socket).
+//!
+//! Front ends hold an [`ApiHandle`]; the daemon loop owns the matching
+//! [`ApiServer`], answers command requests and keeps the snapshot current.
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+use crate::auth::Identity;
+use crate::countdown::CloseReason;
+use crate::door::{Command, Position, Source};
+use crate::error::{Error, HistoryError};
+use crate::history::{History, HistoryEntry, HistoryQuery};
+use crate::lockout::ActiveLockout;
+use crate::maintenance::MaintenanceState;
+use crate::metrics::Metrics;
+use crate::quarantine::{self, QuarantinedSource};
+use crate::stats::Heatmap;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Snapshot {
+    /// `None` until the status sensor has been read for the first time.
+    pub state: Option<Position>,
+    /// Estimated percent open.
+    pub position: Option<u8>,
+    pub close_countdown: Option<u64>,
+    pub close_reason: Option<CloseReason>,
+    pub lockout: Option<ActiveLockout>,
+    pub vacation_lock: bool,
+    pub maintenance: bool,
+    /// Served on its own endpoint rather than with the status.
+    #[serde(skip)]
+    pub heatmap: Heatmap,
+    /// Served on its own endpoint rather than with the status.
+    #[serde(skip)]
+    pub quarantine: Vec<QuarantinedSource>,
+    /// Served on its own endpoint rather than with the status.
+    #[serde(skip)]
+    pub metrics: Metrics,
+}
+
+/// Serializable summary of a failed request, keyed by stable error codes.
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn unavailable() -> Failure {
+        Failure {
+            error: "unavailable",
+            reason: None,
+            message: "daemon is not accepting commands".to_owned(),
+        }
+    }
+}
+
+impl From<&Error> for Failure {
+    fn from(e: &Error) -> Failure {
+        let reason = match e {
+            Error::CommandRejected { reason } => Some(reason.code()),
+            _ => None,
+        };
+        Failure { error: e.code(), reason, message: e.to_string() }
+    }
+}
+
+/// Whether a command would be accepted right now, and if not which rule
+/// blocks it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub command: String,
+    pub allowed: bool,
+    /// Rejection code of the blocking rule, e.g. `lockout` or `already_open`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked_by: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// An admin identity is overriding an active lockout window.
+    pub lockout_override: bool,
+}
+
+pub struct CommandRequest {
+    pub command: Command,
+    /// Which front end sent it.
+    pub source: Source,
+    /// Who sent the command, if the front end authenticated them.
+    pub identity: Option<Identity>,
+    pub reply: oneshot::Sender<Result<(), Failure>>,
+}
+
+/// Asks for the [`Decision`] on a command without carrying it out.
+pub struct QueryRequest {
+    pub command: Command,
+    pub identity: Option<Identity>,
+    pub reply: oneshot::Sender<Result<Decision, Failure>>,
+}
+
+pub enum QuarantineRequest {
+    /// A front end was shown a token nobody recognises, by its source.
+    FailedAuth(String),
+    /// Lifts the quarantine on a source, or on all of them with `all`,
+    /// answering with the sources released.
+    Release {
+        source: String,
+        by: String,
+        reply: oneshot::Sender<Vec<String>>,
+    },
+}
+
+/// Switches the vacation lock, answering with whether it is now on.
+pub struct VacationLockRequest {
+    pub locked: bool,
+    /// Who switched it, for the events topic.
+    pub by: String,
+    pub reply: oneshot::Sender<bool>,
+}
+
+/// Turns maintenance mode on or off, answering with the resulting state.
+pub struct MaintenanceRequest {
+    pub active: bool,
+    pub reason: Option<String>,
+    /// Needs to be an admin to turn maintenance mode off.
+    pub identity: Option<Identity>,
+    pub reply: oneshot::Sender<Result<MaintenanceState, Failure>>,
+}
+
+#[derive(Clone)]
+pub struct ApiHandle {
+    snapshot: watch::Receiver<Snapshot>,
+    commands: mpsc::Sender<CommandRequest>,
+    queries: mpsc::Sender<QueryRequest>,
+    quarantine: mpsc::Sender<QuarantineRequest>,
+    maintenance: mpsc::Sender<MaintenanceRequest>,
+    vacation_lock: mpsc::Sender<VacationLockRequest>,
+    /// Everything published on the events topic, as it is published.
+    events: broadcast::Sender<Value>,
+    /// Queried directly, without going through the daemon loop.
+    history: Option<History>,
+}
+
+impl ApiHandle {
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.borrow().clone()
+    }
+
+    pub async fn command(&self, command: Command, source: Source, identity: Option<Identity>) -> Result<(), Failure> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(CommandRequest { command, source, identity, reply }).await
+            .map_err(|_| Failure::unavailable())?;
+        response.await.map_err(|_| Failure::unavailable())?
+    }
+
+    pub async fn query(&self, command: Command, identity: Option<Identity>) -> Result<Decision, Failure> {
+        let (reply, response) = oneshot::channel();
+        self.queries.send(QueryRequest { command, identity, reply }).await
+            .map_err(|_| Failure::unavailable())?;
+        response.await.map_err(|_| Failure::unavailable())?
+    }
+
+    /// Counts an unknown token towards quarantining it, without waiting.
+    pub fn failed_auth(&self, token: &str) {
+        let _ = self.quarantine.try_send(QuarantineRequest::FailedAuth(quarantine::token_key(token)));
+    }
+
+    pub async fn release(&self, source: String, by: String) -> Result<Vec<String>, Failure> {
+        let (reply, response) = oneshot::channel();
+        self.quarantine.send(QuarantineRequest::Release { source, by, reply }).await
+            .map_err(|_| Failure::unavailable())?;
+        response.await.map_err(|_| Failure::unavailable())
+    }
+
+    pub async fn maintenance(&self, active: bool, reason: Option<String>, identity: Option<Identity>) -> Result<MaintenanceState, Failure> {
+        let (reply, response) = oneshot::channel();
+        self.maintenance.send(MaintenanceRequest { active, reason, identity, reply }).await
+            .map_err(|_| Failure::unavailable())?;
+        response.await.map_err(|_| Failure::unavailable())?
+    }
+
+    pub async fn vacation_lock(&self, locked: bool, by: String) -> Result<bool, Failure> {
+        let (reply, response) = oneshot::channel();
+        self.vacation_lock.send(VacationLockRequest { locked, by, reply }).await
+            .map_err(|_| Failure::unavailable())?;
+        response.await.map_err(|_| Failure::unavailable())
+    }
+
+    /// Events from now on, as published on the events topic. A receiver
+    /// that falls too far behind misses some.
+    pub fn events(&self) -> broadcast::Receiver<Value> {
+        self.events.subscribe()
+    }
+
+    pub async fn history(&self, query: HistoryQuery) -> Result<Vec<HistoryEntry>, Failure> {
+        let history = self.history.as_ref()
+            .ok_or_else(|| Failure::from(&Error::from(HistoryError::Unavailable)))?;
+        history.query(query).await.map_err(|e| Failure::from(&Error::from(e)))
+    }
+}
+
+pub struct ApiServer {
+    pub snapshot: watch::Sender<Snapshot>,
+    pub commands: mpsc::Receiver<CommandRequest>,
+    pub queries: mpsc::Receiver<QueryRequest>,
+    pub quarantine: mpsc::Receiver<QuarantineRequest>,
+    pub maintenance: mpsc::Receiver<MaintenanceRequest>,
+    pub vacation_lock: mpsc::Receiver<VacationLockRequest>,
+    pub events: broadcast::Sender<Value>,
+}
+
+pub fn channel(history: Option<History>) -> (ApiHandle, ApiServer) {
+    let (snapshot_tx, snapshot_rx) = watch::channel(Snapshot::default());
+    let (commands_tx, commands_rx) = mpsc::channel(8);
+    let (queries_tx, queries_rx) = mpsc::channel(8);
+    let (quarantine_tx, quarantine_rx) = mpsc::channel(8);
+    let (maintenance_tx, maintenance_rx) = mpsc::channel(8);
+    let (vacation_lock_tx, vacation_lock_rx) = mpsc::channel(8);
+    let (events, _) = broadcast::channel(64);
+    let handle = ApiHandle {
+        snapshot: snapshot_rx,
+        commands: commands_tx,
+        queries: queries_tx,
+        quarantine: quarantine_tx,
+        maintenance: maintenance_tx,
+        vacation_lock: vacation_lock_tx,
+        events: events.clone(),
+        history,
+    };
+    let server = ApiServer {
+        snapshot: snapshot_tx,
+        commands: commands_rx,
+        queries: queries_rx,
+        quarantine: quarantine_rx,
+        maintenance: maintenance_rx,
+        vacation_lock: vacation_lock_rx,
+        events,
+    };
+    (handle, server)
+}
@@ -0,0 +1,114 @@
+//! Delivery of access events to an external audit system.
+//!
+//! Every event published on the events topic (keypad access, commands
+//! refused by the vacation lock, the lock being switched) is also POSTed to
+//! a webhook, signed with an HMAC-SHA256 of the body in the `X-Signature`
+//! header as `sha256=<hex>`. Failed deliveries are retried with exponential
+//! backoff; events that still can't be delivered are appended to a
+//! dead-letter file, one JSON object per line, for manual replay.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::auth::hex;
+use crate::config::AuditConfig;
+use crate::delivery::{self, Target};
+use crate::http_client::{self, HttpsClient};
+use crate::subsystems::StatusReporter;
+
+/// Events waiting for delivery before new ones go straight to the
+/// dead-letter file.
+const QUEUE: usize = 256;
+
+/// Handle for queueing events to the webhook.
+pub struct Audit {
+    tx: mpsc::Sender<Value>,
+    dead_letter: PathBuf,
+}
+
+impl Audit {
+    /// Starts the delivery task, which reports how deliveries go as the
+    /// `notifications` subsystem. `storage_dir` holds the dead-letter file
+    /// unless the config names one.
+    pub fn spawn(config: AuditConfig, storage_dir: &Path, status: StatusReporter) -> Audit {
+        let dead_letter = config.dead_letter.clone()
+            .unwrap_or_else(|| storage_dir.join("audit_dead_letter.jsonl"));
+        let (tx, rx) = mpsc::channel(QUEUE);
+        let delivery = Delivery { config, client: http_client::build(), dead_letter: dead_letter.clone(), status };
+        tokio::spawn(delivery.run(rx));
+        Audit { tx, dead_letter }
+    }
+
+    /// Queues `event` without waiting.
+    pub fn send(&self, event: Value) {
+        if let Err(e) = self.tx.try_send(event) {
+            let event = match e {
+                mpsc::error::TrySendError::Full(event) | mpsc::error::TrySendError::Closed(event) => event,
+            };
+            warn!("audit delivery backed up, writing event to the dead-letter file");
+            dead_letter(&self.dead_letter, &event, "delivery queue full", 0);
+        }
+    }
+}
+
+struct Delivery {
+    config: AuditConfig,
+    client: HttpsClient,
+    dead_letter: PathBuf,
+    status: StatusReporter,
+}
+
+impl Delivery {
+    async fn run(self, mut rx: mpsc::Receiver<Value>) {
+        while let Some(event) = rx.recv().await {
+            self.deliver(event).await;
+        }
+    }
+
+    async fn deliver(&self, event: Value) {
+        let body = event.to_string();
+        let signature = sign(self.config.secret.expose(), body.as_bytes());
+        let target = Target {
+            name: "audit webhook",
+            url: &self.config.url,
+            max_attempts: self.config.max_attempts,
+            backoff: self.config.backoff(),
+            max_backoff: self.config.max_backoff(),
+            timeout: self.config.timeout(),
+        };
+        let headers = [("X-Signature", signature.as_str())];
+        if let Err(failure) = delivery::post(&self.client, &target, &headers, &body, &self.status).await {
+            error!(attempts = failure.attempts, error = %failure.message, "giving up on audit event, writing it to the dead-letter file");
+            dead_letter(&self.dead_letter, &event, &failure.message, failure.attempts);
+        }
+    }
+}
+
+/// `sha256=<hex HMAC-SHA256 of body>`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex(&mac.finalize().into_bytes()))
+}
+
+fn dead_letter(path: &Path, event: &Value, error: &str, attempts: u32) {
+    let record = json!({ "event": event, "error": error, "attempts": attempts, "failed_at": Utc::now() });
+    if let Err(e) = append(path, &record) {
+        error!(path = %path.display(), error = %e, event = %event, "failed to write audit dead-letter file, event lost");
+    }
+}
+
+fn append(path: &Path, record: &Value) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", record)
+}
@@ -0,0 +1,299 @@
+//! Credential validation behind pluggable identity providers.
+//!
+//! Keypad codes and API tokens are checked against each configured provider
+//! in turn; the first one that recognizes the credential decides who it
+//! belongs to.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use futures::future::BoxFuture;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request, StatusCode};
+use ldap3::{LdapConnAsync, LdapConnSettings, LdapError, Scope, SearchEntry};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use strum::Display;
+use tracing::{debug, warn};
+
+use crate::config::{AuthConfig, ProviderConfig};
+use crate::error::AuthError;
+use crate::http_client::{self, HttpsClient};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialKind {
+    #[strum(serialize = "keypad")]
+    Keypad,
+    #[strum(serialize = "token")]
+    Token,
+}
+
+#[derive(Clone)]
+pub struct Credential {
+    pub kind: CredentialKind,
+    pub secret: String,
+}
+
+impl Credential {
+    pub fn new(kind: CredentialKind, secret: impl Into<String>) -> Credential {
+        Credential { kind, secret: secret.into() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub id: String,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+pub trait IdentityProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns the identity owning `credential`, or `None` if this provider
+    /// doesn't recognize it.
+    fn validate<'a>(&'a self, credential: &'a Credential) -> BoxFuture<'a, Result<Option<Identity>, AuthError>>;
+}
+
+/// Tries each configured provider in order.
+#[derive(Default)]
+pub struct Authenticator {
+    providers: Vec<Box<dyn IdentityProvider>>,
+}
+
+impl Authenticator {
+    pub fn from_config(config: &AuthConfig) -> Authenticator {
+        let providers = config.providers.iter()
+            .map(|p| -> Box<dyn IdentityProvider> {
+                match p {
+                    ProviderConfig::File { path } => Box::new(LocalFileProvider::new(path.clone())),
+                    ProviderConfig::Http { url, timeout_secs } => {
+                        Box::new(HttpProvider::new(url.clone(), Duration::from_secs(*timeout_secs)))
+                    }
+                    ProviderConfig::Ldap { .. } => Box::new(LdapProvider::new(p.clone())),
+                }
+            })
+            .collect();
+        Authenticator { providers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+
+    pub async fn validate(&self, credential: &Credential) -> Result<Option<Identity>, AuthError> {
+        for provider in &self.providers {
+            if let Some(identity) = provider.validate(credential).await? {
+                debug!(provider = provider.name(), id = %identity.id, kind = %credential.kind, "credential accepted");
+                return Ok(Some(identity));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CredentialFile {
+    #[serde(default)]
+    credential: Vec<StoredCredential>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StoredCredential {
+    id: String,
+    kind: CredentialKind,
+    /// An argon2id PHC string from `garaged --hash-secret`.
+    hash: String,
+    #[serde(default)]
+    admin: bool,
+}
+
+/// Credentials stored as salted hashes in a TOML file, re-read on every
+/// check so edits apply without a restart.
+pub struct LocalFileProvider {
+    path: PathBuf,
+}
+
+impl LocalFileProvider {
+    pub fn new(path: PathBuf) -> LocalFileProvider {
+        LocalFileProvider { path }
+    }
+
+    async fn lookup(&self, credential: &Credential) -> Result<Option<Identity>, AuthError> {
+        let text = tokio::fs::read_to_string(&self.path).await
+            .map_err(|e| AuthError::Provider(format!("failed to read {}: {}", self.path.display(), e)))?;
+        let file: CredentialFile = toml::from_str(&text)
+            .map_err(|e| AuthError::Provider(format!("failed to parse {}: {}", self.path.display(), e)))?;
+        let (kind, secret) = (credential.kind, credential.secret.clone());
+        // Each argon2 check takes a good fraction of a second on a Pi.
+        let found = tokio::task::spawn_blocking(move || {
+            file.credential.into_iter()
+                .filter(|c| c.kind == kind)
+                .find(|c| verify_hash(&c.hash, &secret))
+        }).await.map_err(|e| AuthError::Provider(format!("credential check failed: {}", e)))?;
+        Ok(found.map(|c| Identity { id: c.id, admin: c.admin }))
+    }
+}
+
+impl IdentityProvider for LocalFileProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn validate<'a>(&'a self, credential: &'a Credential) -> BoxFuture<'a, Result<Option<Identity>, AuthError>> {
+        Box::pin(self.lookup(credential))
+    }
+}
+
+/// Delegates validation to an external directory service. The credential is
+/// POSTed as JSON; a 200 response carries the identity, 401/403/404 mean
+/// the credential is unknown.
+pub struct HttpProvider {
+    url: String,
+    timeout: Duration,
+    client: HttpsClient,
+}
+
+impl HttpProvider {
+    pub fn new(url: String, timeout: Duration) -> HttpProvider {
+        HttpProvider { url, timeout, client: http_client::build() }
+    }
+
+    async fn lookup(&self, credential: &Credential) -> Result<Option<Identity>, AuthError> {
+        let body = json!({ "kind": credential.kind, "secret": credential.secret });
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .map_err(|e| AuthError::Provider(format!("invalid request: {}", e)))?;
+        let response = tokio::time::timeout(self.timeout, self.client.request(request)).await
+            .map_err(|_| AuthError::Provider(format!("{} timed out", self.url)))?
+            .map_err(|e| AuthError::Provider(format!("{} failed: {}", self.url, e)))?;
+        match response.status() {
+            StatusCode::OK => {
+                let body = hyper::body::to_bytes(response.into_body()).await
+                    .map_err(|e| AuthError::Provider(format!("{} failed: {}", self.url, e)))?;
+                let identity = serde_json::from_slice(&body)
+                    .map_err(|e| AuthError::Provider(format!("invalid identity from {}: {}", self.url, e)))?;
+                Ok(Some(identity))
+            }
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Ok(None),
+            status => Err(AuthError::Provider(format!("{} returned {}", self.url, status))),
+        }
+    }
+}
+
+impl IdentityProvider for HttpProvider {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn validate<'a>(&'a self, credential: &'a Credential) -> BoxFuture<'a, Result<Option<Identity>, AuthError>> {
+        Box::pin(self.lookup(credential))
+    }
+}
+
+/// Looks credentials up in an LDAP directory: binds as the service account,
+/// then searches for the entry the kind's filter matches. Exactly one entry
+/// must match; its id attribute names the person, and membership of the
+/// admin group makes them an admin.
+pub struct LdapProvider {
+    config: ProviderConfig,
+}
+
+impl LdapProvider {
+    /// `config` must be a [`ProviderConfig::Ldap`].
+    pub fn new(config: ProviderConfig) -> LdapProvider {
+        LdapProvider { config }
+    }
+
+    async fn lookup(&self, credential: &Credential) -> Result<Option<Identity>, AuthError> {
+        let ProviderConfig::Ldap {
+            url, bind_dn, bind_password, base_dn, keypad_filter, token_filter, id_attribute, admin_group, timeout_secs,
+        } = &self.config else {
+            return Ok(None);
+        };
+        let template = match credential.kind {
+            CredentialKind::Keypad => keypad_filter,
+            CredentialKind::Token => token_filter,
+        };
+        let filter = match template {
+            Some(t) => t.replace("{secret}", &ldap3::ldap_escape(&credential.secret)),
+            None => return Ok(None),
+        };
+        let timeout = Duration::from_secs(*timeout_secs);
+        let failed = |e: LdapError| AuthError::Provider(format!("{} failed: {}", url, e));
+        let settings = LdapConnSettings::new().set_conn_timeout(timeout);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, url).await.map_err(failed)?;
+        ldap3::drive!(conn);
+        ldap.with_timeout(timeout).simple_bind(bind_dn, bind_password.expose()).await
+            .and_then(|r| r.success())
+            .map_err(failed)?;
+        let attributes = vec![id_attribute.as_str(), "memberOf"];
+        let (entries, _) = ldap.with_timeout(timeout).search(base_dn, Scope::Subtree, &filter, attributes).await
+            .and_then(|r| r.success())
+            .map_err(failed)?;
+        let _ = ldap.unbind().await;
+        let mut entries = entries.into_iter().map(SearchEntry::construct);
+        let entry = match (entries.next(), entries.next()) {
+            (Some(entry), None) => entry,
+            (None, _) => return Ok(None),
+            (Some(_), Some(_)) => {
+                warn!(url = %url, kind = %credential.kind, "credential matches more than one ldap entry, refusing it");
+                return Ok(None);
+            }
+        };
+        let id = entry.attrs.get(id_attribute).and_then(|v| v.first()).cloned()
+            .ok_or_else(|| AuthError::Provider(format!("{} has no {} attribute", entry.dn, id_attribute)))?;
+        let admin = admin_group.as_ref().is_some_and(|group| {
+            entry.attrs.get("memberOf").is_some_and(|groups| groups.iter().any(|g| g.eq_ignore_ascii_case(group)))
+        });
+        Ok(Some(Identity { id, admin }))
+    }
+}
+
+impl IdentityProvider for LdapProvider {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    fn validate<'a>(&'a self, credential: &'a Credential) -> BoxFuture<'a, Result<Option<Identity>, AuthError>> {
+        Box::pin(self.lookup(credential))
+    }
+}
+
+/// Hashes a secret for the credentials file with argon2id and a random
+/// salt. A keypad code has few enough digits that a fast hash of it could
+/// be brute forced from a copy of the file.
+pub fn hash_secret(secret: &str) -> String {
+    let salt = SaltString::encode_b64(&rand_salt()).expect("16 bytes is a valid salt length");
+    Argon2::default().hash_password(secret.as_bytes(), &salt)
+        .expect("default argon2 parameters are valid")
+        .to_string()
+}
+
+/// Checks `secret` against an argon2 PHC string. Anything else matches
+/// nothing.
+fn verify_hash(stored: &str, secret: &str) -> bool {
+    match PasswordHash::new(stored) {
+        Ok(hash) => Argon2::default().verify_password(secret.as_bytes(), &hash).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn rand_salt() -> [u8; 16] {
+    use chacha20poly1305::aead::rand_core::RngCore;
+    let mut salt = [0; 16];
+    chacha20poly1305::aead::OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,50 @@
+//! Availability of the entities backed by a polled sensor.
+//!
+//! Every entity goes unavailable in Home Assistant with the daemon. Those
+//! showing a 1-Wire probe or an analog input also have an availability topic
+//! of their own, which goes offline once the sensor has failed a number of
+//! reads in a row and back online with the next good one, so a dead probe
+//! shows as unavailable rather than as its last reading.
+
+use std::collections::BTreeMap;
+
+/// A read that failed, sent by the polling tasks in place of a reading.
+#[derive(Debug, Clone)]
+pub struct SensorFailure {
+    pub id: String,
+    pub error: String,
+}
+
+/// Failed reads in a row, per sensor.
+#[derive(Debug)]
+pub struct SensorHealth {
+    unavailable_after: u32,
+    failures: BTreeMap<String, u32>,
+}
+
+impl SensorHealth {
+    /// Tracks sensors that go unavailable after `unavailable_after` failed
+    /// reads in a row.
+    pub fn new(unavailable_after: u32) -> SensorHealth {
+        SensorHealth { unavailable_after: unavailable_after.max(1), failures: BTreeMap::new() }
+    }
+
+    /// Records a good read, returning whether the sensor was unavailable.
+    pub fn read(&mut self, id: &str) -> bool {
+        let was_available = self.is_available(id);
+        self.failures.remove(id);
+        !was_available
+    }
+
+    /// Records a failed read, returning whether the sensor just became
+    /// unavailable.
+    pub fn failed(&mut self, id: &str) -> bool {
+        let count = self.failures.entry(id.to_owned()).or_default();
+        *count += 1;
+        *count == self.unavailable_after
+    }
+
+    pub fn is_available(&self, id: &str) -> bool {
+        self.failures.get(id).is_none_or(|&n| n < self.unavailable_after)
+    }
+}
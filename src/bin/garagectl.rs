@@ -0,0 +1,107 @@
+//! Pokes a running garaged over its control socket; see `garaged::ipc`.
+
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Error};
+use serde_json::Value;
+
+use garaged::ipc::{self, Request};
+
+const USAGE: &str = "usage: garagectl [--socket PATH] <command>
+
+commands:
+    status              show the door's state
+    open | close        move the door
+    lock [on|off]       switch the vacation lock
+    quarantine          list quarantined command sources
+    unblock <source>    release a quarantined source, or all of them with all
+    tail-events         follow the events topic
+
+Commands are sent as the identity behind the token in GARAGECTL_TOKEN, if
+set; unblock needs an admin token.";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("garagectl: {:#}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let mut args = std::env::args_os().skip(1).peekable();
+    let socket = match args.next_if(|a| a == "--socket") {
+        Some(_) => PathBuf::from(args.next().context("--socket requires a path")?),
+        None => PathBuf::from(ipc::DEFAULT_SOCKET),
+    };
+    let args: Vec<String> = args.map(OsString::into_string).collect::<Result<_, _>>()
+        .map_err(|a| anyhow::anyhow!("invalid argument {:?}", a))?;
+    let credential = std::env::var("GARAGECTL_TOKEN").ok();
+    let request = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["status"] => Request::Status,
+        ["open"] => Request::Command { command: "OPEN".to_owned(), credential },
+        ["close"] => Request::Command { command: "CLOSE".to_owned(), credential },
+        ["lock"] | ["lock", "on"] => Request::Lock { locked: true },
+        ["lock", "off"] => Request::Lock { locked: false },
+        ["quarantine"] => Request::Quarantine,
+        ["unblock", source] => Request::Unblock { source: source.to_owned(), token: credential },
+        ["tail-events"] => Request::Events,
+        _ => bail!("{}", USAGE),
+    };
+
+    let mut stream = UnixStream::connect(&socket)
+        .with_context(|| format!("failed to connect to {}", socket.display()))?;
+    let mut line = serde_json::to_string(&request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    let mut replies = BufReader::new(stream).lines();
+    let reply = read(&mut replies)?;
+    if reply["ok"] != Value::Bool(true) {
+        bail!("{}", reply["message"].as_str().unwrap_or("request failed"));
+    }
+    match request {
+        Request::Status => println!("{}", serde_json::to_string_pretty(&reply["status"])?),
+        Request::Command { .. } => println!("{} accepted", reply["command"].as_str().unwrap_or_default()),
+        Request::Lock { .. } => {
+            let locked = reply["vacation_lock"].as_bool().unwrap_or_default();
+            println!("vacation lock {}", if locked { "on" } else { "off" });
+        }
+        Request::Quarantine => {
+            let sources = reply["quarantine"].as_array().cloned().unwrap_or_default();
+            if sources.is_empty() {
+                println!("nothing quarantined");
+            }
+            for source in sources {
+                println!(
+                    "{} ({}) until {}",
+                    source["source"].as_str().unwrap_or_default(),
+                    source["reason"].as_str().unwrap_or_default(),
+                    source["until"].as_str().unwrap_or_default(),
+                );
+            }
+        }
+        Request::Unblock { .. } => {
+            let released = reply["released"].as_array().cloned().unwrap_or_default();
+            if released.is_empty() {
+                println!("nothing to release");
+            }
+            for source in released {
+                println!("released {}", source.as_str().unwrap_or_default());
+            }
+        }
+        Request::Events => loop {
+            println!("{}", read(&mut replies)?);
+        },
+    }
+    Ok(())
+}
+
+fn read(replies: &mut impl Iterator<Item = std::io::Result<String>>) -> Result<Value, Error> {
+    let line = match replies.next() {
+        Some(line) => line.context("failed to read from garaged")?,
+        None => bail!("garaged closed the connection"),
+    };
+    serde_json::from_str(&line).context("garaged sent an invalid reply")
+}
@@ -0,0 +1,45 @@
+//! Wall-clock time for schedules.
+//!
+//! Timers already run on tokio's clock, which tests can pause and advance.
+//! Lockout windows, day rollovers and journaled actions are reckoned in wall
+//! time, though, so they take it from a [`Clock`] instead of the system: in
+//! tests a clock started with [`Clock::tokio`] moves with tokio's, letting
+//! hours of schedule run in milliseconds. Timestamps that only label reports
+//! still come from the system clock.
+
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Clock {
+    /// The system clock, following NTP corrections.
+    #[default]
+    System,
+    /// `epoch` plus however far tokio's clock has moved since `start`.
+    Tokio { epoch: DateTime<Utc>, start: Instant },
+}
+
+impl Clock {
+    /// A clock reading `epoch` now and advancing with tokio's clock.
+    pub fn tokio(epoch: DateTime<Utc>) -> Clock {
+        Clock::Tokio { epoch, start: Instant::now() }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            Clock::Tokio { epoch, start } => {
+                *epoch + chrono::Duration::from_std(start.elapsed()).unwrap_or_else(|_| chrono::Duration::zero())
+            }
+        }
+    }
+
+    pub fn local_now(&self) -> DateTime<Local> {
+        self.now().with_timezone(&Local)
+    }
+
+    /// The local calendar day.
+    pub fn today(&self) -> NaiveDate {
+        self.local_now().date_naive()
+    }
+}
@@ -0,0 +1,1652 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{Utc, Weekday};
+use serde::Deserialize;
+use strum::Display;
+
+use crate::actuation::{self, Stage};
+use crate::error::ConfigError;
+use crate::hardware;
+use crate::journal::{ActionKind, CatchUp};
+use crate::lockout::LockoutSchedule;
+use crate::schedule::ScheduleEntry;
+use crate::secrets::{Secret, SecretKey};
+use crate::templates::Templates;
+use crate::warning::WarningPattern;
+use crate::webhooks::WebhookEvent;
+
+pub const DEFAULT_PATH: &str = "/etc/garaged.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mqtt: MqttConfig,
+    pub credentials: CredentialsConfig,
+    pub gpio: GpioConfig,
+    pub automated_close: AutomatedCloseConfig,
+    /// Close the door after it has been left open, disabled unless configured.
+    pub auto_close: Option<AutoCloseConfig>,
+    /// Notify when the door stays open, disabled unless configured.
+    pub left_open_alert: Option<LeftOpenAlertConfig>,
+    /// Named partial-open positions, disabled unless configured.
+    pub presets: Option<PresetsConfig>,
+    pub log: LogConfig,
+    pub motor: MotorConfig,
+    /// How the percent-open position is estimated between the sensors.
+    pub position: PositionConfig,
+    /// Local HTTP API, disabled unless configured.
+    pub http: Option<HttpConfig>,
+    /// Local control socket for `garagectl`, disabled unless configured.
+    pub socket: Option<SocketConfig>,
+    pub auth: AuthConfig,
+    pub health_check: HealthCheckConfig,
+    /// Periodic heartbeat on `<base>/health`, disabled unless configured.
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// The Iono Pi's hardware watchdog, disabled unless configured.
+    pub watchdog: Option<WatchdogConfig>,
+    pub rate_limit: RateLimitConfig,
+    /// The stages each relay press goes through; see [`crate::actuation`].
+    pub actuation: ActuationConfig,
+    /// Blocking of abusive command sources, disabled unless configured.
+    pub quarantine: Option<QuarantineConfig>,
+    pub locale: LocaleConfig,
+    /// Recurring windows during which remote commands need an admin.
+    pub lockout: LockoutSchedule,
+    /// Remote opens only while someone is home, disabled unless configured.
+    pub presence: Option<PresenceConfig>,
+    /// Actions run at set times.
+    pub schedule: Vec<ScheduleEntry>,
+    pub storage: StorageConfig,
+    /// The user to switch to once the hardware is set up, kept as started
+    /// unless configured.
+    pub privileges: Option<PrivilegesConfig>,
+    pub catch_up: CatchUpConfig,
+    /// Other controllers whose state drives rules here.
+    pub links: Vec<LinkConfig>,
+    /// 1-Wire temperature probes, disabled unless configured.
+    pub onewire: Option<OneWireConfig>,
+    /// Wiegand keypad by the door, disabled unless configured.
+    pub keypad: Option<KeypadConfig>,
+    /// 433 MHz remote receiver, disabled unless configured.
+    pub rf: Option<RfConfig>,
+    /// Webhook receiving access events, disabled unless configured.
+    pub audit: Option<AuditConfig>,
+    /// Webhooks notified of door events.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Analog inputs published as sensors, disabled unless configured.
+    pub analog: Option<AnalogConfig>,
+    pub privacy: PrivacyConfig,
+    /// Local SQLite record of door activity, disabled unless configured.
+    pub history: Option<HistoryConfig>,
+    /// The virtual door used with `--simulate`.
+    pub simulation: SimulationConfig,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Read(path.to_owned(), e))?;
+        let mut config: Config = toml::from_str(&text)
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        config.validate()?;
+        config.decrypt_secrets()?;
+        Ok(config)
+    }
+
+    /// Loads the config file for a runtime reload, with `overrides` (from the
+    /// remote config topic) merged over it. A missing file means defaults.
+    pub fn reload(path: &Path, overrides: Option<&serde_json::Value>) -> Result<Config, ConfigError> {
+        let overrides = match overrides {
+            Some(o) => o,
+            None if path.exists() => return Config::load(path),
+            None => return Ok(Config::default()),
+        };
+        let text = if path.exists() {
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_owned(), e))?
+        } else {
+            String::new()
+        };
+        let base: toml::Value = toml::from_str(&text)
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))?;
+        let mut merged = serde_json::to_value(base).map_err(ConfigError::Override)?;
+        merge(&mut merged, overrides);
+        let mut config: Config = serde_json::from_value(merged).map_err(ConfigError::Override)?;
+        config.validate()?;
+        config.decrypt_secrets()?;
+        Ok(config)
+    }
+
+    /// Checks settings that parse fine but can't work.
+    fn validate(&self) -> Result<(), ConfigError> {
+        for (name, prefix) in [("topic_prefix", &self.mqtt.topic_prefix), ("discovery_prefix", &self.mqtt.discovery_prefix)] {
+            if prefix.is_empty() || prefix.contains(['+', '#']) || prefix.starts_with('/') || prefix.ends_with('/') {
+                return Err(ConfigError::Invalid(format!("mqtt.{} {:?} must be a plain topic without slashes at either end", name, prefix)));
+            }
+        }
+        if let Some(topic) = &self.mqtt.options_topic {
+            if topic.is_empty() || topic.contains(['+', '#']) {
+                return Err(ConfigError::Invalid(format!("mqtt.options_topic {:?} must be a topic without wildcards", topic)));
+            }
+        }
+        if self.mqtt.qos > 2 || self.mqtt.topic_qos.values().any(|&q| q > 2) {
+            return Err(ConfigError::Invalid("mqtt qos levels must be 0, 1 or 2".to_owned()));
+        }
+        if self.mqtt.state_expiry_secs.is_some() && self.mqtt.protocol != MqttProtocol::V5 {
+            return Err(ConfigError::Invalid("mqtt.state_expiry_secs needs protocol = \"5\"".to_owned()));
+        }
+        for provider in &self.auth.providers {
+            if let ProviderConfig::Ldap { url, keypad_filter, token_filter, .. } = provider {
+                if !url.starts_with("ldap://") && !url.starts_with("ldaps://") {
+                    return Err(ConfigError::Invalid(format!("ldap provider url {:?} must start with ldap:// or ldaps://", url)));
+                }
+                let filters = [keypad_filter.as_ref(), token_filter.as_ref()];
+                if filters.iter().all(|f| f.is_none()) || filters.iter().flatten().any(|f| !f.contains("{secret}")) {
+                    return Err(ConfigError::Invalid("ldap provider needs a keypad_filter or token_filter containing {secret}".to_owned()));
+                }
+            }
+        }
+        let outputs = [("relay", Some(&self.gpio.relay)), ("led", self.gpio.led.as_ref()), ("maintenance", self.gpio.maintenance.as_ref())];
+        for (name, output) in outputs {
+            if output.map(|o| o.driver().is_none()).unwrap_or(false) {
+                return Err(ConfigError::Invalid(format!("gpio.{} needs exactly one of pin, sysfs_led or pwm", name)));
+            }
+        }
+        let inputs = [
+            ("gpio.status", Some(&self.gpio.status)),
+            ("gpio.open", self.gpio.open.as_ref()),
+            ("gpio.input", Some(&self.gpio.input)),
+            ("gpio.vehicle", self.gpio.vehicle.as_ref()),
+            ("gpio.obstruction", self.gpio.obstruction.as_ref()),
+            ("gpio.encoder", self.gpio.encoder.as_ref()),
+            ("keypad.d0", self.keypad.as_ref().map(|k| &k.d0)),
+            ("keypad.d1", self.keypad.as_ref().map(|k| &k.d1)),
+            ("rf.data", self.rf.as_ref().map(|r| &r.data)),
+        ];
+        for (name, input) in inputs {
+            if input.is_some_and(|i| i.line().is_none()) {
+                return Err(ConfigError::Invalid(format!("{} needs either pin or chip and line", name)));
+            }
+        }
+        let rf_on_ionopi = self.rf.as_ref().and_then(|r| r.data.pin)
+            .is_some_and(|pin| hardware::ionopi_line(&self.gpio.ionopi_dir, pin).is_some());
+        if rf_on_ionopi && self.gpio.backends.contains(&HardwareBackend::IonoPi) {
+            return Err(ConfigError::Invalid("rf.data can't be one of the iono pi's lines, which are only polled every 10ms; wire the receiver to a plain gpio pin".to_owned()));
+        }
+        if self.gpio.zones.iter().any(|z| z.pin_config().line().is_none()) {
+            return Err(ConfigError::Invalid("each gpio.zones sensor needs either pin or chip and line".to_owned()));
+        }
+        let mut extra = BTreeSet::new();
+        for output in &self.gpio.extra.outputs {
+            if !valid_id(&output.id) || !extra.insert(output.id.as_str()) {
+                return Err(ConfigError::Invalid(format!("gpio.extra id {:?} is invalid or used twice", output.id)));
+            }
+            if output.output.driver().is_none() {
+                return Err(ConfigError::Invalid(format!("gpio.extra output {} needs exactly one of pin, sysfs_led or pwm", output.id)));
+            }
+        }
+        for input in &self.gpio.extra.inputs {
+            if !valid_id(&input.id) || !extra.insert(input.id.as_str()) {
+                return Err(ConfigError::Invalid(format!("gpio.extra id {:?} is invalid or used twice", input.id)));
+            }
+            if input.input.line().is_none() {
+                return Err(ConfigError::Invalid(format!("gpio.extra input {} needs either pin or chip and line", input.id)));
+            }
+        }
+        if self.socket.as_ref().is_some_and(|s| s.mode > 0o777) {
+            return Err(ConfigError::Invalid("socket.mode must be permission bits, at most 0o777".to_owned()));
+        }
+        if self.gpio.backends.is_empty() {
+            return Err(ConfigError::Invalid("gpio.backends must list at least one backend".to_owned()));
+        }
+        actuation::check(&self.actuation.stages).map_err(ConfigError::Invalid)?;
+        if self.actuation.warning_ms > 10_000 {
+            return Err(ConfigError::Invalid("actuation.warning_ms must be at most 10000".to_owned()));
+        }
+        if self.actuation.spacing_ms > 10_000 {
+            return Err(ConfigError::Invalid("actuation.spacing_ms must be at most 10000".to_owned()));
+        }
+        if !(self.motor.anomaly_tolerance > 0.0 && self.motor.anomaly_tolerance < 1.0) || self.motor.anomaly_learn_runs == 0 {
+            return Err(ConfigError::Invalid("motor.anomaly_tolerance must be between 0 and 1, and motor.anomaly_learn_runs at least 1".to_owned()));
+        }
+        if !(self.simulation.travel_secs > 0.0 && self.simulation.travel_secs.is_finite()) {
+            return Err(ConfigError::Invalid("simulation.travel_secs must be positive".to_owned()));
+        }
+        let mut percents = BTreeSet::new();
+        for zone in &self.gpio.zones {
+            if !(1..=99).contains(&zone.percent) || !percents.insert(zone.percent) {
+                return Err(ConfigError::Invalid(format!("zone sensor at {}% must be between 1 and 99 and unique", zone.percent)));
+            }
+        }
+        let mut codes = BTreeSet::new();
+        for code in self.rf.iter().flat_map(|r| &r.codes) {
+            if code.code > 0xff_ffff || !codes.insert(code.code) {
+                return Err(ConfigError::Invalid(format!("rf code {:#x} must fit in 24 bits and be listed once", code.code)));
+            }
+        }
+        let mut ids = BTreeSet::new();
+        for sensor in self.onewire.iter().flat_map(|o| &o.sensors) {
+            if !valid_id(&sensor.id) || !ids.insert(sensor.id.as_str()) {
+                return Err(ConfigError::Invalid(format!("1-wire sensor id {:?} is invalid or used twice", sensor.id)));
+            }
+        }
+        let mut inputs = BTreeSet::new();
+        for input in self.analog.iter().flat_map(|a| &a.inputs) {
+            if !valid_id(&input.id) || !inputs.insert(input.id.as_str()) {
+                return Err(ConfigError::Invalid(format!("analog input id {:?} is invalid or used twice", input.id)));
+            }
+            if input.channel.is_some() == input.path.is_some() {
+                return Err(ConfigError::Invalid(format!("analog input {} needs exactly one of channel or path", input.id)));
+            }
+        }
+        match &self.position {
+            PositionConfig::Encoder { pulses_per_travel } => {
+                if self.gpio.encoder.is_none() || *pulses_per_travel == 0 {
+                    return Err(ConfigError::Invalid("the encoder estimator needs gpio.encoder and a non-zero pulses_per_travel".to_owned()));
+                }
+            }
+            PositionConfig::Current { input, .. } => {
+                if !inputs.contains(input.as_str()) {
+                    return Err(ConfigError::Invalid(format!("the current estimator's input {:?} is not an analog input", input)));
+                }
+            }
+            PositionConfig::Switches if self.gpio.zones.is_empty() => {
+                return Err(ConfigError::Invalid("the switches estimator needs zone sensors".to_owned()));
+            }
+            PositionConfig::Time | PositionConfig::Switches => (),
+        }
+        for webhook in &self.webhooks {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                return Err(ConfigError::Invalid(format!("webhook url {:?} must be http or https", webhook.url)));
+            }
+            if webhook.events.is_empty() || webhook.max_attempts == 0 {
+                return Err(ConfigError::Invalid(format!("webhook {} needs at least one event and attempt", webhook.url)));
+            }
+        }
+        let mut names = BTreeSet::new();
+        for link in &self.links {
+            if link.name.is_empty() || !names.insert(link.name.as_str()) {
+                return Err(ConfigError::Invalid(format!("link name {:?} is empty or used twice", link.name)));
+            }
+            for topic in std::iter::once(&link.topic).chain(&link.availability_topic) {
+                if topic.is_empty() || topic.contains(['+', '#']) {
+                    return Err(ConfigError::Invalid(format!("link {} topic {:?} must be a plain topic", link.name, topic)));
+                }
+                if topic.starts_with(&self.mqtt.topic_prefix) {
+                    return Err(ConfigError::Invalid(format!("link {} points back at this door's own topics", link.name)));
+                }
+            }
+            if link.rules.is_empty() {
+                return Err(ConfigError::Invalid(format!("link {} has no rules", link.name)));
+            }
+        }
+        if self.heartbeat.is_some_and(|h| h.interval_secs == 0) {
+            return Err(ConfigError::Invalid("heartbeat.interval_secs must be positive".to_owned()));
+        }
+        if self.watchdog.as_ref().is_some_and(|w| w.feed_secs == 0) {
+            return Err(ConfigError::Invalid("watchdog.feed_secs must be positive".to_owned()));
+        }
+        if let Some(presence) = &self.presence {
+            if presence.topics.is_empty() || presence.home.is_empty() {
+                return Err(ConfigError::Invalid("presence needs at least one topic and home payload".to_owned()));
+            }
+            if let Some(topic) = presence.topics.iter().find(|t| t.is_empty() || t.contains(['+', '#'])) {
+                return Err(ConfigError::Invalid(format!("presence topic {:?} must be a plain topic", topic)));
+            }
+        }
+        let mut names = BTreeSet::new();
+        for entry in &self.schedule {
+            if entry.name.is_empty() || !names.insert(entry.name.as_str()) {
+                return Err(ConfigError::Invalid(format!("schedule name {:?} is empty or used twice", entry.name)));
+            }
+            if entry.next_after(Utc::now()).is_none() {
+                return Err(ConfigError::Invalid(format!("schedule {} never runs: {}", entry.name, entry.cron)));
+            }
+        }
+        if self.locale.templates.is_some() {
+            Templates::load(&self.locale)?;
+        }
+        Ok(())
+    }
+
+    fn secrets_mut(&mut self) -> impl Iterator<Item = &mut Secret> {
+        let ldap = self.auth.providers.iter_mut().filter_map(|p| match p {
+            ProviderConfig::Ldap { bind_password, .. } => Some(bind_password),
+            _ => None,
+        });
+        self.mqtt.password.iter_mut().chain(self.audit.iter_mut().map(|a| &mut a.secret)).chain(ldap)
+    }
+
+    fn decrypt_secrets(&mut self) -> Result<(), ConfigError> {
+        if !self.secrets_mut().any(|s| s.is_encrypted()) {
+            return Ok(());
+        }
+        let key = SecretKey::load(&self.credentials)?;
+        for secret in self.secrets_mut() {
+            secret.decrypt(&key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `id` can be used in a topic and an entity id.
+fn valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Settings that may be changed through the remote config topic, as
+/// `(section, key)` with `None` allowing the whole section. Anything touching
+/// credentials, the broker connection or pin assignments is excluded.
+const REMOTE_SETTINGS: &[(&str, Option<&str>)] = &[
+    ("automated_close", None),
+    ("auto_close", None),
+    ("left_open_alert", None),
+    ("presets", None),
+    ("catch_up", None),
+    ("motor", None),
+    ("health_check", None),
+    ("rate_limit", None),
+    ("locale", Some("language")),
+    ("locale", Some("temperature_unit")),
+    ("locale", Some("clock")),
+    ("locale", Some("first_day_of_week")),
+    ("locale", Some("names")),
+    ("gpio", Some("pulse_ms")),
+];
+
+/// Checks that a remote override only touches settings in `REMOTE_SETTINGS`.
+pub fn check_remote_overrides(overrides: &serde_json::Value) -> Result<(), ConfigError> {
+    let sections = overrides.as_object()
+        .ok_or_else(|| ConfigError::Forbidden("<non-object>".to_owned()))?;
+    for (section, value) in sections {
+        let allowed: Vec<_> = REMOTE_SETTINGS.iter()
+            .filter(|(s, _)| s == section)
+            .map(|(_, key)| *key)
+            .collect();
+        if allowed.contains(&None) {
+            continue;
+        }
+        let keys = value.as_object()
+            .ok_or_else(|| ConfigError::Forbidden(section.clone()))?;
+        for key in keys.keys() {
+            if !allowed.contains(&Some(key.as_str())) {
+                return Err(ConfigError::Forbidden(format!("{}.{}", section, key)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deep-merges `overrides` into `base`, replacing everything but objects.
+pub fn merge(base: &mut serde_json::Value, overrides: &serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(base.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, value) => *base = value.clone(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    /// Defaults to the hostname.
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<Secret>,
+    pub keep_alive_secs: u64,
+    /// Accept config overrides published to the `set_config` topic.
+    pub remote_config: bool,
+    /// Accept runtime options published to the options topic; see
+    /// [`crate::options`].
+    pub remote_options: bool,
+    /// Where runtime options are read from, `<base>/options` by default.
+    /// Doors sharing a topic share their options.
+    pub options_topic: Option<String>,
+    /// Publish the state as JSON with when and why it last changed.
+    pub json_state: bool,
+    /// Where the daemon's own state and command topics live.
+    pub topic_prefix: String,
+    /// Where Home Assistant looks for discovery configs.
+    pub discovery_prefix: String,
+    /// QoS for publishes and subscriptions without a `topic_qos` entry.
+    pub qos: u8,
+    /// QoS by full topic name. Without an entry the command topic is
+    /// subscribed at 2 and other devices' topics at 0.
+    pub topic_qos: BTreeMap<String, u8>,
+    /// Retain state publishes. Availability and the shutdown record are
+    /// retained regardless, as the daemon and Home Assistant rely on them.
+    pub retain: bool,
+    /// Discard the broker session on connecting. Without a clean session
+    /// the broker keeps the subscriptions, and queues QoS 1 and 2 messages
+    /// while the daemon is away.
+    pub clean_session: bool,
+    /// MQTT 3.1.1 unless the broker is known to speak 5.
+    pub protocol: MqttProtocol,
+    /// With MQTT 5, how long the broker keeps a state publish for clients
+    /// that haven't received it yet. Kept until replaced without it.
+    pub state_expiry_secs: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum MqttProtocol {
+    #[serde(rename = "3.1.1")]
+    V311,
+    /// Adds replies on a command's response topic, user properties naming
+    /// the door and version, and expiring state messages.
+    #[serde(rename = "5")]
+    V5,
+}
+
+impl MqttConfig {
+    /// QoS level for `topic`, or `default` without a `topic_qos` entry.
+    pub fn qos_for(&self, topic: &str, default: u8) -> u8 {
+        self.topic_qos.get(topic).copied().unwrap_or(default)
+    }
+}
+
+impl Default for MqttConfig {
+    fn default() -> MqttConfig {
+        MqttConfig {
+            host: "10.44.0.15".to_owned(),
+            port: 1883,
+            client_id: None,
+            username: None,
+            password: None,
+            keep_alive_secs: 5,
+            remote_config: false,
+            remote_options: false,
+            options_topic: None,
+            json_state: false,
+            topic_prefix: "garaged/garage".to_owned(),
+            discovery_prefix: "homeassistant".to_owned(),
+            qos: 1,
+            topic_qos: BTreeMap::new(),
+            retain: true,
+            clean_session: true,
+            protocol: MqttProtocol::V311,
+            state_expiry_secs: None,
+        }
+    }
+}
+
+/// Where the key for `enc:v1:` secrets comes from.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CredentialsConfig {
+    pub key_file: Option<PathBuf>,
+    /// Command printing the base64 key, e.g. `["tpm2_unseal", "-c", "0x81000001"]`.
+    pub key_command: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpioConfig {
+    /// How long the relay is held closed for each trigger.
+    pub pulse_ms: u64,
+    pub relay: OutputConfig,
+    /// Closed sensor, reading high while the door is fully closed.
+    pub status: PinConfig,
+    /// Optional second sensor reading high while the door is fully open.
+    pub open: Option<PinConfig>,
+    pub input: PinConfig,
+    /// Optional status light; see [`crate::led`].
+    pub led: Option<OutputConfig>,
+    /// Optional buzzer or strobe, sounded before the door closes unattended.
+    pub warning: Option<OutputConfig>,
+    /// Optional light or sign relay, switched on while in maintenance mode.
+    pub maintenance: Option<OutputConfig>,
+    /// Optional vehicle presence sensor, reading high while a car is parked.
+    pub vehicle: Option<PinConfig>,
+    /// Optional safety beam, reading high while something is in the doorway.
+    pub obstruction: Option<PinConfig>,
+    /// Sensors part way along the track.
+    pub zones: Vec<ZoneConfig>,
+    /// Optional rotary encoder on the opener, pulsing as the door moves.
+    pub encoder: Option<PinConfig>,
+    /// Spare relays and inputs, unrelated to the door, shown in Home
+    /// Assistant as switches and binary sensors.
+    pub extra: ExtraIoConfig,
+    /// Backends tried at startup, in order; see [`crate::hardware::Hardware::detect`].
+    pub backends: Vec<HardwareBackend>,
+    /// Where the Iono Pi kernel module shows its lines.
+    pub ionopi_dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtraIoConfig {
+    pub outputs: Vec<ExtraOutputConfig>,
+    pub inputs: Vec<ExtraInputConfig>,
+}
+
+/// A spare output switched from Home Assistant, e.g. the garage lights.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtraOutputConfig {
+    /// Used in the topics and entity id.
+    pub id: String,
+    /// Friendly name in Home Assistant.
+    pub name: String,
+    pub output: OutputConfig,
+    /// Home Assistant icon, e.g. `mdi:lightbulb`.
+    pub icon: Option<String>,
+}
+
+/// A spare input reported to Home Assistant, e.g. a motion sensor.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtraInputConfig {
+    /// Used in the topics and entity id.
+    pub id: String,
+    /// Friendly name in Home Assistant.
+    pub name: String,
+    pub input: PinConfig,
+    /// Home Assistant device class, e.g. `motion` or `door`.
+    pub device_class: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum HardwareBackend {
+    /// The Iono Pi's relays, LED and digital inputs through its kernel
+    /// module, other pins through sysfs.
+    IonoPi,
+    /// Every pin through the kernel's sysfs GPIO interface.
+    Sysfs,
+    /// The virtual door, as with `--simulate`.
+    Simulated,
+}
+
+impl GpioConfig {
+    pub fn pulse(&self) -> Duration {
+        Duration::from_millis(self.pulse_ms)
+    }
+}
+
+impl Default for GpioConfig {
+    fn default() -> GpioConfig {
+        GpioConfig {
+            pulse_ms: 200,
+            relay: OutputConfig::gpio(17),
+            status: PinConfig::new(6),
+            open: None,
+            input: PinConfig::new(12),
+            led: None,
+            warning: None,
+            maintenance: None,
+            vehicle: None,
+            obstruction: None,
+            zones: Vec::new(),
+            encoder: None,
+            extra: ExtraIoConfig::default(),
+            backends: vec![HardwareBackend::IonoPi, HardwareBackend::Sysfs],
+            ionopi_dir: PathBuf::from("/sys/class/ionopi"),
+        }
+    }
+}
+
+/// An input line, given either by its sysfs GPIO number as `pin` or as
+/// `line` on the GPIO chip named `chip`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PinConfig {
+    pub pin: Option<u64>,
+    /// The chip's label, e.g. `pinctrl-bcm2711`, or device name, e.g. `gpiochip0`.
+    pub chip: Option<String>,
+    pub line: Option<u32>,
+    /// Treat the pin as active-low, for relay boards and switches wired that way.
+    #[serde(default)]
+    pub invert: bool,
+    /// Which changes wake the daemon; unset keeps the input's usual edges.
+    pub edge: Option<EdgeConfig>,
+    /// Bias resistor, left as the board sets it at boot when unset.
+    pub pull: Option<Pull>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeConfig {
+    Rising,
+    Falling,
+    Both,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pull {
+    Up,
+    Down,
+    None,
+}
+
+/// Where an input is, as configured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpioLine<'a> {
+    Number(u64),
+    Chip { chip: &'a str, line: u32 },
+}
+
+impl PinConfig {
+    pub fn new(pin: u64) -> PinConfig {
+        PinConfig { pin: Some(pin), chip: None, line: None, invert: false, edge: None, pull: None }
+    }
+
+    /// The line selected, or `None` unless exactly one of `pin` or
+    /// `chip` and `line` is set.
+    pub fn line(&self) -> Option<GpioLine<'_>> {
+        match (self.pin, &self.chip, self.line) {
+            (Some(pin), None, None) => Some(GpioLine::Number(pin)),
+            (None, Some(chip), Some(line)) => Some(GpioLine::Chip { chip, line }),
+            _ => None,
+        }
+    }
+}
+
+/// An output, normally a GPIO line. Relay HATs that expose their channels
+/// as LED class devices or PWM channels set `sysfs_led` or `pwm` instead of
+/// `pin`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    pub pin: Option<u64>,
+    /// Device name under `/sys/class/leds`.
+    pub sysfs_led: Option<String>,
+    pub pwm: Option<PwmConfig>,
+    #[serde(default)]
+    pub invert: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PwmConfig {
+    pub chip: u32,
+    pub channel: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputDriver<'a> {
+    Gpio(u64),
+    Led(&'a str),
+    Pwm { chip: u32, channel: u32 },
+}
+
+impl OutputConfig {
+    pub fn gpio(pin: u64) -> OutputConfig {
+        OutputConfig { pin: Some(pin), sysfs_led: None, pwm: None, invert: false }
+    }
+
+    /// The driver selected, or `None` unless exactly one is.
+    pub fn driver(&self) -> Option<OutputDriver<'_>> {
+        match (self.pin, &self.sysfs_led, self.pwm) {
+            (Some(pin), None, None) => Some(OutputDriver::Gpio(pin)),
+            (None, Some(device), None) => Some(OutputDriver::Led(device)),
+            (None, None, Some(pwm)) => Some(OutputDriver::Pwm { chip: pwm.chip, channel: pwm.channel }),
+            _ => None,
+        }
+    }
+}
+
+/// A position sensor reading high while the door is `percent` open.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneConfig {
+    pub pin: Option<u64>,
+    pub chip: Option<String>,
+    pub line: Option<u32>,
+    #[serde(default)]
+    pub invert: bool,
+    pub edge: Option<EdgeConfig>,
+    pub pull: Option<Pull>,
+    pub percent: u8,
+}
+
+impl ZoneConfig {
+    pub fn pin_config(&self) -> PinConfig {
+        PinConfig {
+            pin: self.pin,
+            chip: self.chip.clone(),
+            line: self.line,
+            invert: self.invert,
+            edge: self.edge,
+            pull: self.pull,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutomatedCloseConfig {
+    /// Warning period before any automated close, during which CANCEL aborts it.
+    pub countdown_secs: u64,
+    /// Hold remote CLOSE commands for `warning_secs` too, cancellable like
+    /// an automated close.
+    pub remote_close_warning: bool,
+    /// How long before the close the warning output starts.
+    pub warning_secs: u64,
+    pub warning_beeps: u32,
+    pub warning_beep_ms: u64,
+}
+
+impl AutomatedCloseConfig {
+    pub fn countdown(&self) -> Duration {
+        Duration::from_secs(self.countdown_secs)
+    }
+
+    pub fn warning(&self) -> WarningPattern {
+        WarningPattern {
+            length: Duration::from_secs(self.warning_secs),
+            beeps: self.warning_beeps,
+            beep: Duration::from_millis(self.warning_beep_ms),
+        }
+    }
+}
+
+impl Default for AutomatedCloseConfig {
+    fn default() -> AutomatedCloseConfig {
+        AutomatedCloseConfig {
+            countdown_secs: 30,
+            remote_close_warning: false,
+            warning_secs: 5,
+            warning_beeps: 5,
+            warning_beep_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoCloseConfig {
+    /// How long the door may stay open before the close countdown starts.
+    pub after_mins: u64,
+}
+
+impl AutoCloseConfig {
+    pub fn after(&self) -> Duration {
+        Duration::from_secs(self.after_mins * 60)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LeftOpenAlertConfig {
+    /// Topic for alert messages, defaults to `<base>/notifications`.
+    pub topic: Option<String>,
+    /// How long the door may stay open before the first alert.
+    pub after_mins: u64,
+    /// Delay before the first repeat.
+    pub repeat_mins: u64,
+    /// Factor the repeat delay grows by after each alert.
+    pub backoff: f64,
+    pub max_repeat_mins: u64,
+}
+
+impl LeftOpenAlertConfig {
+    pub fn after(&self) -> Duration {
+        Duration::from_secs(self.after_mins * 60)
+    }
+
+    pub fn repeat(&self) -> Duration {
+        Duration::from_secs(self.repeat_mins * 60)
+    }
+
+    pub fn max_repeat(&self) -> Duration {
+        Duration::from_secs(self.max_repeat_mins * 60)
+    }
+}
+
+impl Default for LeftOpenAlertConfig {
+    fn default() -> LeftOpenAlertConfig {
+        LeftOpenAlertConfig {
+            topic: None,
+            after_mins: 10,
+            repeat_mins: 10,
+            backoff: 2.0,
+            max_repeat_mins: 120,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresetsConfig {
+    /// Topic carrying the current wind speed as a bare number, e.g. from a
+    /// Home Assistant weather sensor.
+    pub wind_topic: Option<String>,
+    /// Wind readings older than this count as unknown.
+    pub wind_max_age_mins: u64,
+    pub positions: Vec<PresetConfig>,
+}
+
+impl PresetsConfig {
+    pub fn wind_max_age(&self) -> Duration {
+        Duration::from_secs(self.wind_max_age_mins * 60)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PresetConfig> {
+        self.positions.iter().find(|p| p.name == name)
+    }
+}
+
+impl Default for PresetsConfig {
+    fn default() -> PresetsConfig {
+        PresetsConfig {
+            wind_topic: None,
+            wind_max_age_mins: 30,
+            positions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresetConfig {
+    pub name: String,
+    /// How long the door runs up from closed before it is stopped.
+    pub open_secs: f64,
+    /// Highest wind speed, in the wind topic's unit, at which the position
+    /// may be used. Positions without a limit ignore the wind.
+    pub max_wind: Option<f64>,
+}
+
+impl PresetConfig {
+    pub fn open_time(&self) -> Duration {
+        Duration::from_secs_f64(self.open_secs.max(0.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Filter directive in `RUST_LOG` syntax. `RUST_LOG` takes precedence if set.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig { level: "info".to_owned(), format: LogFormat::Text }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotorConfig {
+    /// Nominal time for the door to travel fully open or closed.
+    pub travel_secs: f64,
+    /// A closing run longer than `travel_secs` times this factor is flagged.
+    pub long_cycle_factor: f64,
+    /// How far, as a fraction of the learned baseline, a full run may be
+    /// off before it is reported as an anomaly.
+    pub anomaly_tolerance: f64,
+    /// Runs timed in each direction to learn the baseline from.
+    pub anomaly_learn_runs: u32,
+}
+
+impl MotorConfig {
+    pub fn travel(&self) -> Duration {
+        Duration::from_secs_f64(self.travel_secs)
+    }
+}
+
+impl Default for MotorConfig {
+    fn default() -> MotorConfig {
+        MotorConfig { travel_secs: 12.0, long_cycle_factor: 1.5, anomaly_tolerance: 0.3, anomaly_learn_runs: 5 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkConfig {
+    pub name: String,
+    /// Topic carrying the other controller's state, e.g. its alarm panel.
+    pub topic: String,
+    /// Top-level field to read from JSON payloads. The whole payload is
+    /// compared otherwise.
+    pub field: Option<String>,
+    /// The other controller's availability topic, for health checks.
+    pub availability_topic: Option<String>,
+    /// Flag the link unhealthy after this long without a message on `topic`.
+    pub max_silence_mins: Option<u64>,
+    pub rules: Vec<LinkRule>,
+}
+
+impl LinkConfig {
+    pub fn max_silence(&self) -> Option<Duration> {
+        self.max_silence_mins.map(|m| Duration::from_secs(m * 60))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkRule {
+    /// Value that triggers the rule when the link changes to it.
+    pub when: String,
+    pub action: LinkAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkAction {
+    /// Close the door after the usual countdown.
+    #[strum(serialize = "close")]
+    Close,
+    /// Publish an alert on the notifications topic.
+    #[strum(serialize = "alert")]
+    Alert,
+    /// Turn the vacation lock on.
+    #[strum(serialize = "lock")]
+    Lock,
+    /// Turn the vacation lock off.
+    #[strum(serialize = "unlock")]
+    Unlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalogConfig {
+    pub poll_secs: u64,
+    /// Failed reads in a row before an input is shown unavailable.
+    pub unavailable_after: u32,
+    pub inputs: Vec<AnalogInputConfig>,
+}
+
+impl AnalogConfig {
+    pub fn poll(&self) -> Duration {
+        Duration::from_secs(self.poll_secs.max(1))
+    }
+}
+
+impl Default for AnalogConfig {
+    fn default() -> AnalogConfig {
+        AnalogConfig { poll_secs: 30, unavailable_after: 3, inputs: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnalogInputConfig {
+    /// Used in the topic and entity id.
+    pub id: String,
+    /// Friendly name in Home Assistant.
+    pub name: String,
+    /// An Iono Pi input, read from the kernel module's sysfs files.
+    pub channel: Option<AnalogChannel>,
+    /// Any other file holding a reading, e.g. an IIO `in_voltage0_raw`.
+    pub path: Option<PathBuf>,
+    /// The value published is `raw * scale + offset`.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+    pub unit: Option<String>,
+    /// Home Assistant device class, e.g. `voltage` or `current`.
+    pub device_class: Option<String>,
+}
+
+impl AnalogInputConfig {
+    pub fn path(&self) -> PathBuf {
+        match (&self.path, self.channel) {
+            (Some(path), _) => path.clone(),
+            (None, Some(channel)) => PathBuf::from("/sys/class/ionopi/analog_in").join(channel.to_string()),
+            (None, None) => PathBuf::new(),
+        }
+    }
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// The Iono Pi's analog inputs: AV in millivolts, AI in microamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalogChannel {
+    #[strum(serialize = "av1")]
+    Av1,
+    #[strum(serialize = "av2")]
+    Av2,
+    #[strum(serialize = "av3")]
+    Av3,
+    #[strum(serialize = "av4")]
+    Av4,
+    #[strum(serialize = "ai1")]
+    Ai1,
+    #[strum(serialize = "ai2")]
+    Ai2,
+    #[strum(serialize = "ai3")]
+    Ai3,
+    #[strum(serialize = "ai4")]
+    Ai4,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    pub url: String,
+    /// Key for the HMAC in the `X-Signature` header.
+    pub secret: Secret,
+    /// Deliveries tried before an event goes to the dead-letter file.
+    #[serde(default = "default_audit_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubling after each one.
+    #[serde(default = "default_audit_backoff")]
+    pub backoff_secs: f64,
+    #[serde(default = "default_audit_max_backoff")]
+    pub max_backoff_secs: f64,
+    #[serde(default = "default_provider_timeout")]
+    pub timeout_secs: u64,
+    /// Defaults to `audit_dead_letter.jsonl` in the storage directory.
+    pub dead_letter: Option<PathBuf>,
+}
+
+impl AuditConfig {
+    pub fn backoff(&self) -> Duration {
+        Duration::from_secs_f64(self.backoff_secs.max(0.0))
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_secs_f64(self.max_backoff_secs.max(0.0))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+fn default_audit_attempts() -> u32 {
+    8
+}
+
+fn default_audit_backoff() -> f64 {
+    2.0
+}
+
+fn default_audit_max_backoff() -> f64 {
+    300.0
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Events POSTed to this URL, all of them unless given.
+    #[serde(default = "WebhookEvent::all")]
+    pub events: BTreeSet<WebhookEvent>,
+    /// Deliveries tried before an event is dropped.
+    #[serde(default = "default_webhook_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubling after each one.
+    #[serde(default = "default_audit_backoff")]
+    pub backoff_secs: f64,
+    #[serde(default = "default_webhook_max_backoff")]
+    pub max_backoff_secs: f64,
+    #[serde(default = "default_provider_timeout")]
+    pub timeout_secs: u64,
+}
+
+impl WebhookConfig {
+    pub fn backoff(&self) -> Duration {
+        Duration::from_secs_f64(self.backoff_secs.max(0.0))
+    }
+
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_secs_f64(self.max_backoff_secs.max(0.0))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+fn default_webhook_attempts() -> u32 {
+    5
+}
+
+fn default_webhook_max_backoff() -> f64 {
+    60.0
+}
+
+/// A Wiegand keypad. Codes are checked against the auth providers as
+/// `keypad` credentials.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeypadConfig {
+    /// Wiegand data lines, pulsed low for 0 and 1 bits respectively.
+    pub d0: PinConfig,
+    pub d1: PinConfig,
+    /// Keys typed further apart than this start a new code.
+    #[serde(default = "default_entry_timeout")]
+    pub entry_timeout_secs: u64,
+    /// Wrong codes in a row before the keypad is ignored for `block_secs`.
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+    #[serde(default = "default_block")]
+    pub block_secs: u64,
+}
+
+impl KeypadConfig {
+    pub fn entry_timeout(&self) -> Duration {
+        Duration::from_secs(self.entry_timeout_secs)
+    }
+
+    pub fn block(&self) -> Duration {
+        Duration::from_secs(self.block_secs)
+    }
+}
+
+/// A 433 MHz receiver for EV1527 remotes. Buttons are learned by pressing
+/// them and copying the code from the `rf_unknown` event into `codes`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RfConfig {
+    /// The receiver's data output. Not one of the Iono Pi's lines, as
+    /// those are polled far too slowly to time its pulses.
+    pub data: PinConfig,
+    #[serde(default)]
+    pub codes: Vec<RfCodeConfig>,
+}
+
+/// A learned remote button.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RfCodeConfig {
+    /// The 24-bit code the button sends, address and button bits together.
+    pub code: u32,
+    /// Who or what the button belongs to, for the events topic.
+    pub name: Option<String>,
+    #[serde(default)]
+    pub action: RfAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum RfAction {
+    /// Whatever one press of the wall button would do.
+    #[default]
+    Toggle,
+    Open,
+    Close,
+    /// Stops a moving door, or cancels a pending automated close.
+    Stop,
+}
+
+fn default_entry_timeout() -> u64 {
+    10
+}
+
+fn default_max_failures() -> u32 {
+    5
+}
+
+fn default_block() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OneWireConfig {
+    /// Where the kernel's w1 driver lists bus devices.
+    pub devices_dir: PathBuf,
+    pub poll_secs: u64,
+    /// Failed reads in a row before a probe is shown unavailable.
+    pub unavailable_after: u32,
+    pub sensors: Vec<TemperatureSensorConfig>,
+}
+
+impl OneWireConfig {
+    pub fn poll(&self) -> Duration {
+        Duration::from_secs(self.poll_secs.max(1))
+    }
+}
+
+impl Default for OneWireConfig {
+    fn default() -> OneWireConfig {
+        OneWireConfig {
+            devices_dir: PathBuf::from("/sys/bus/w1/devices"),
+            poll_secs: 60,
+            unavailable_after: 3,
+            sensors: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemperatureSensorConfig {
+    /// Bus id, the device's directory name, e.g. `28-0316a2797dff`.
+    pub id: String,
+    /// Friendly name in Home Assistant.
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// Leave identities out of events and attributes and keep no more than
+    /// the day anything happened on.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Defaults to `history.db` in the storage directory.
+    pub path: Option<PathBuf>,
+    /// How long entries are kept.
+    pub retention_days: u32,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> HistoryConfig {
+        HistoryConfig { path: None, retention_days: 90 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    /// Time for the virtual door to travel fully open or closed.
+    pub travel_secs: f64,
+    /// A scenario script played against the virtual door from startup; see
+    /// [`crate::scenario`].
+    pub scenario: Option<PathBuf>,
+}
+
+impl SimulationConfig {
+    pub fn travel(&self) -> Duration {
+        Duration::from_secs_f64(self.travel_secs)
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> SimulationConfig {
+        SimulationConfig { travel_secs: 12.0, scenario: None }
+    }
+}
+
+/// How actions that came due while the daemon was down are handled.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CatchUpConfig {
+    pub auto_close: CatchUp,
+    pub close_countdown: CatchUp,
+}
+
+impl CatchUpConfig {
+    pub fn policy(&self, kind: ActionKind) -> CatchUp {
+        match kind {
+            ActionKind::AutoClose => self.auto_close,
+            ActionKind::CloseCountdown => self.close_countdown,
+        }
+    }
+}
+
+impl Default for CatchUpConfig {
+    fn default() -> CatchUpConfig {
+        CatchUpConfig { auto_close: CatchUp::Alert, close_countdown: CatchUp::Alert }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// Where state that outlives a restart, like usage counters, is kept.
+    pub dir: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> StorageConfig {
+        StorageConfig { dir: PathBuf::from("/var/lib/garaged") }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrivilegesConfig {
+    pub user: String,
+    /// Defaults to the user's primary group.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    pub bind: SocketAddr,
+    /// Require an `Authorization: Bearer` token checked by the auth providers.
+    #[serde(default)]
+    pub require_token: bool,
+}
+
+/// A Unix socket taking JSON requests; see [`crate::ipc`]. Anyone who can
+/// connect can use it, so access comes down to `mode` and `group`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SocketConfig {
+    pub path: PathBuf,
+    /// Permission bits, written in octal like `0o660`.
+    pub mode: u32,
+    /// Group to give the socket to, so its members can connect.
+    pub group: Option<String>,
+}
+
+impl Default for SocketConfig {
+    fn default() -> SocketConfig {
+        SocketConfig { path: PathBuf::from(crate::ipc::DEFAULT_SOCKET), mode: 0o660, group: None }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Identity providers, tried in order.
+    pub providers: Vec<ProviderConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ProviderConfig {
+    /// Salted hashes in a local TOML file.
+    File { path: PathBuf },
+    /// An external directory service answering validation requests.
+    Http {
+        url: String,
+        #[serde(default = "default_provider_timeout")]
+        timeout_secs: u64,
+    },
+    /// An LDAP directory, searched for the entry holding the credential
+    /// after binding as a service account.
+    Ldap {
+        /// `ldap://` or `ldaps://`.
+        url: String,
+        bind_dn: String,
+        bind_password: Secret,
+        /// Where entries are searched for, e.g. `ou=people,dc=example,dc=com`.
+        base_dn: String,
+        /// Filters finding the entry for a keypad code or a token, with
+        /// `{secret}` standing for the escaped credential. A kind without
+        /// one isn't checked here.
+        keypad_filter: Option<String>,
+        token_filter: Option<String>,
+        /// The attribute naming the person.
+        #[serde(default = "default_ldap_id_attribute")]
+        id_attribute: String,
+        /// Members of this group, by DN in `memberOf`, are admins.
+        admin_group: Option<String>,
+        #[serde(default = "default_provider_timeout")]
+        timeout_secs: u64,
+    },
+}
+
+fn default_ldap_id_attribute() -> String {
+    "uid".to_owned()
+}
+
+fn default_provider_timeout() -> u64 {
+    5
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthCheckConfig {
+    /// Close time deviation from the baseline, in percent, that warns.
+    pub warn_pct: f64,
+    /// Close time deviation from the baseline, in percent, that fails.
+    pub fail_pct: f64,
+    /// Extra wait after the nominal travel time before closing again.
+    pub settle_secs: u64,
+}
+
+impl HealthCheckConfig {
+    pub fn settle(&self) -> Duration {
+        Duration::from_secs(self.settle_secs)
+    }
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> HealthCheckConfig {
+        HealthCheckConfig { warn_pct: 15.0, fail_pct: 30.0, settle_secs: 3 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeartbeatConfig {
+    pub interval_secs: u64,
+}
+
+impl HeartbeatConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> HeartbeatConfig {
+        HeartbeatConfig { interval_secs: 60 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchdogConfig {
+    /// The kernel module's watchdog attributes.
+    pub dir: PathBuf,
+    /// How often to feed it, well within the board's timeout.
+    pub feed_secs: u64,
+    /// Only feed it while the broker is reachable.
+    pub require_mqtt: bool,
+}
+
+impl WatchdogConfig {
+    pub fn feed_interval(&self) -> Duration {
+        Duration::from_secs(self.feed_secs)
+    }
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> WatchdogConfig {
+        WatchdogConfig { dir: PathBuf::from("/sys/class/ionopi/watchdog"), feed_secs: 5, require_mqtt: true }
+    }
+}
+
+/// Limits on how fast commands move the door, to protect the opener motor.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    /// Minimum time between a relay press and the next command that would
+    /// press it. Presses that stop the door are exempt.
+    pub relay_cooldown_secs: f64,
+    /// Commands accepted from MQTT within `window_secs`.
+    pub max_commands: usize,
+    pub window_secs: u64,
+}
+
+impl RateLimitConfig {
+    pub fn relay_cooldown(&self) -> Duration {
+        Duration::from_secs_f64(self.relay_cooldown_secs)
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> RateLimitConfig {
+        RateLimitConfig { relay_cooldown_secs: 2.0, max_commands: 10, window_secs: 60 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActuationConfig {
+    pub stages: Vec<Stage>,
+    /// How long the warning stage sounds the warning output before pressing.
+    pub warning_ms: u64,
+    /// Least time between two presses; a press asked for sooner waits.
+    pub spacing_ms: u64,
+}
+
+impl ActuationConfig {
+    pub fn warning(&self) -> Duration {
+        Duration::from_millis(self.warning_ms)
+    }
+
+    pub fn spacing(&self) -> Duration {
+        Duration::from_millis(self.spacing_ms)
+    }
+}
+
+impl Default for ActuationConfig {
+    fn default() -> ActuationConfig {
+        ActuationConfig { stages: vec![Stage::Interlock, Stage::Pulse, Stage::Verify], warning_ms: 2000, spacing_ms: 1000 }
+    }
+}
+
+/// Picks the [`crate::estimator`] for the percent-open position.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "estimator", rename_all = "lowercase", deny_unknown_fields)]
+pub enum PositionConfig {
+    /// From the time spent moving, relative to `motor.travel_secs`.
+    #[default]
+    Time,
+    /// From pulses on `gpio.encoder`, `pulses_per_travel` to a full run.
+    Encoder { pulses_per_travel: u32 },
+    /// From the time the motor draws at least `running_amps`, read on the
+    /// analog input with id `input`.
+    Current { input: String, running_amps: f64 },
+    /// Only from the end and zone sensors.
+    Switches,
+}
+
+impl PositionConfig {
+    /// The analog input the estimator reads, if any.
+    pub fn current_input(&self) -> Option<&str> {
+        match self {
+            PositionConfig::Current { input, .. } => Some(input),
+            _ => None,
+        }
+    }
+}
+
+/// When a command source is quarantined, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuarantineConfig {
+    /// Strikes are counted over this sliding window.
+    pub window_secs: u64,
+    /// Unknown tokens or wrong keypad codes within the window.
+    pub max_failed_auth: u32,
+    /// Rejected commands within the window.
+    pub max_rejections: u32,
+    /// How long a quarantine lasts unless it is released by hand.
+    pub cooldown_secs: u64,
+}
+
+impl QuarantineConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.cooldown_secs)
+    }
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> QuarantineConfig {
+        QuarantineConfig { window_secs: 600, max_failed_auth: 5, max_rejections: 10, cooldown_secs: 3600 }
+    }
+}
+
+/// Where the presence lock learns whether anyone is home.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresenceConfig {
+    /// Topics carrying someone's presence, e.g. Home Assistant person or
+    /// device tracker states.
+    pub topics: Vec<String>,
+    /// Payloads meaning home, compared ignoring case; anything else is away.
+    pub home: Vec<String>,
+    /// Remote opens are still accepted this long after the last person
+    /// left.
+    pub grace_secs: u64,
+}
+
+impl PresenceConfig {
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace_secs)
+    }
+
+    pub fn is_home(&self, payload: &[u8]) -> bool {
+        let payload = String::from_utf8_lossy(payload);
+        self.home.iter().any(|h| h.eq_ignore_ascii_case(payload.trim()))
+    }
+}
+
+impl Default for PresenceConfig {
+    fn default() -> PresenceConfig {
+        PresenceConfig { topics: Vec::new(), home: vec!["home".to_owned(), "on".to_owned()], grace_secs: 300 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocaleConfig {
+    /// Language for entity friendly names: en, de, fr, es or nl.
+    pub language: String,
+    pub temperature_unit: TemperatureUnit,
+    pub clock: ClockFormat,
+    pub first_day_of_week: Weekday,
+    /// Friendly name overrides keyed by entity, for other languages or taste.
+    pub names: BTreeMap<String, String>,
+    /// Directory of alert message templates overriding the built-in ones.
+    pub templates: Option<PathBuf>,
+}
+
+impl Default for LocaleConfig {
+    fn default() -> LocaleConfig {
+        LocaleConfig {
+            language: "en".to_owned(),
+            temperature_unit: TemperatureUnit::Celsius,
+            clock: ClockFormat::H24,
+            first_day_of_week: Weekday::Mon,
+            names: BTreeMap::new(),
+            templates: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TemperatureUnit {
+    #[serde(rename = "C")]
+    Celsius,
+    #[serde(rename = "F")]
+    Fahrenheit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ClockFormat {
+    #[serde(rename = "24h")]
+    H24,
+    #[serde(rename = "12h")]
+    H12,
+}
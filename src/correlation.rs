@@ -0,0 +1,189 @@
+//! Following each relay press through to what the door physically did.
+//!
+//! A press is correlated with the first sign of motion the sensors give
+//! (leaving an end or a zone sensor, an encoder pulse) and with the door
+//! reaching the end it was heading for. A press that shows neither within
+//! twice the travel time never moved the door, which is how a failing
+//! opener receiver shows up long before it stops working altogether.
+//!
+//! Where the sensors can't see a run start, as from a partway stop away
+//! from the zone sensors or the open end without an open sensor, a press
+//! that doesn't end at a sensor is left unconfirmed rather than counted as
+//! a failure. Presses that stop a moving door aren't followed at all.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tokio::time::Instant;
+
+use crate::door::Trigger;
+
+/// Outcomes kept for the success rate.
+const WINDOW: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Outcome {
+    /// The door reached the end it was heading for.
+    Completed,
+    /// The door started moving but wasn't seen reaching an end, because it
+    /// was stopped or the sensors can't see that end.
+    Moved,
+    /// Nothing moved.
+    NeverMoved,
+    /// The sensors couldn't have seen the door move.
+    Unconfirmed,
+}
+
+impl Outcome {
+    /// Whether the outcome says anything about the opener responding.
+    fn counts(self) -> bool {
+        self != Outcome::Unconfirmed
+    }
+}
+
+/// One press and what came of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Correlation {
+    pub trigger: String,
+    /// When the relay was pressed.
+    pub timestamp: DateTime<Utc>,
+    pub outcome: Outcome,
+    /// From the press to the sensors first seeing the door move.
+    pub started_ms: Option<u64>,
+    /// From the press to the door reaching an end.
+    pub completed_secs: Option<f64>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    trigger: Trigger,
+    timestamp: DateTime<Utc>,
+    at: Instant,
+    deadline: Instant,
+    /// Whether the sensors will see the run start.
+    visible: bool,
+    started: Option<Duration>,
+}
+
+impl Pending {
+    fn finish(self, outcome: Outcome, completed: Option<Duration>) -> Correlation {
+        Correlation {
+            trigger: self.trigger.to_string(),
+            timestamp: self.timestamp,
+            outcome,
+            started_ms: self.started.map(|d| d.as_millis() as u64),
+            completed_secs: completed.map(|d| (d.as_secs_f64() * 10.0).round() / 10.0),
+        }
+    }
+
+    /// How a press ends without the door being seen at an end.
+    fn unfinished(self) -> Correlation {
+        let outcome = match (self.started, self.visible) {
+            (Some(_), _) => Outcome::Moved,
+            (None, true) => Outcome::NeverMoved,
+            (None, false) => Outcome::Unconfirmed,
+        };
+        self.finish(outcome, None)
+    }
+}
+
+/// Follows the press in progress, if any.
+#[derive(Debug, Default)]
+pub struct CommandTracker {
+    pending: Option<Pending>,
+}
+
+impl CommandTracker {
+    /// Starts following a press that should set the door moving, allowing
+    /// it `timeout` to reach an end. `visible` is whether the sensors will
+    /// see the run start. Returns the press this one cut short, if any.
+    pub fn pressed(&mut self, trigger: Trigger, visible: bool, timeout: Duration, now: DateTime<Utc>) -> Option<Correlation> {
+        let at = Instant::now();
+        let previous = self.pending.take().map(Pending::unfinished);
+        self.pending = Some(Pending { trigger, timestamp: now, at, deadline: at + timeout, visible, started: None });
+        previous
+    }
+
+    /// A press that stopped the moving door ends the run being followed.
+    pub fn stopped(&mut self) -> Option<Correlation> {
+        self.pending.take().map(Pending::unfinished)
+    }
+
+    /// The sensors saw the door move.
+    pub fn moved(&mut self) {
+        if let Some(pending) = &mut self.pending {
+            pending.started.get_or_insert_with(|| pending.at.elapsed());
+        }
+    }
+
+    /// The sensors saw the door reach an end.
+    pub fn arrived(&mut self) -> Option<Correlation> {
+        let mut pending = self.pending.take()?;
+        let elapsed = pending.at.elapsed();
+        // Arriving without having been seen leaving still shows it moved,
+        // but not when it started.
+        if pending.started.is_none() && pending.visible {
+            pending.started = Some(elapsed);
+        }
+        Some(pending.finish(Outcome::Completed, Some(elapsed)))
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|p| p.deadline)
+    }
+
+    /// Called at the deadline: the door didn't reach an end in time.
+    pub fn timed_out(&mut self) -> Option<Correlation> {
+        self.stopped()
+    }
+}
+
+/// Recent outcomes, persisted with the usage counters.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutcomeStats {
+    /// Whether the door moved, for the latest presses that could tell,
+    /// oldest first.
+    recent: VecDeque<bool>,
+    pub never_moved: u64,
+    pub last: Option<Correlation>,
+}
+
+/// What the command success sensor shows.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutcomeReport {
+    /// Percent of the recent presses that moved the door, `None` before
+    /// any could tell.
+    pub success_rate: Option<f64>,
+    /// Presses the rate is taken over.
+    pub presses: usize,
+    /// Presses that never moved the door, since the counters were created.
+    pub never_moved: u64,
+    pub last: Option<Correlation>,
+}
+
+impl OutcomeStats {
+    pub fn record(&mut self, correlation: &Correlation) {
+        if correlation.outcome.counts() {
+            if self.recent.len() == WINDOW {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(correlation.outcome != Outcome::NeverMoved);
+        }
+        if correlation.outcome == Outcome::NeverMoved {
+            self.never_moved += 1;
+        }
+        self.last = Some(correlation.clone());
+    }
+
+    pub fn report(&self) -> OutcomeReport {
+        let moved = self.recent.iter().filter(|&&m| m).count();
+        let success_rate = (!self.recent.is_empty())
+            .then(|| (moved as f64 * 1000.0 / self.recent.len() as f64).round() / 10.0);
+        OutcomeReport { success_rate, presses: self.recent.len(), never_moved: self.never_moved, last: self.last.clone() }
+    }
+}
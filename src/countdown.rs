@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tokio::time::Instant;
+
+/// What started a close countdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    #[strum(serialize = "auto_close")]
+    AutoClose,
+    #[strum(serialize = "sweep")]
+    Sweep,
+    #[strum(serialize = "schedule")]
+    Schedule,
+    /// The wind rose above the limit of the preset the door is stopped at.
+    #[strum(serialize = "wind")]
+    Wind,
+    /// A rule on a linked controller's state.
+    #[strum(serialize = "link")]
+    Link,
+    /// A CLOSE command held back for the warning.
+    #[strum(serialize = "mqtt")]
+    Mqtt,
+    #[strum(serialize = "http")]
+    Http,
+}
+
+/// A pending automated close that can still be cancelled.
+#[derive(Debug, Clone, Copy)]
+pub struct Countdown {
+    pub reason: CloseReason,
+    pub deadline: Instant,
+}
+
+impl Countdown {
+    pub fn start(reason: CloseReason, length: Duration) -> Countdown {
+        Countdown { reason, deadline: Instant::now() + length }
+    }
+
+    /// Whole seconds left before the door is closed, rounded up.
+    pub fn remaining_secs(&self) -> u64 {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        (remaining.as_millis() as u64).div_ceil(1000)
+    }
+
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
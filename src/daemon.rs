@@ -0,0 +1,3296 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{stream, StreamExt};
+use rumqttc::QoS;
+use serde_json::{json, to_vec, Value};
+use strum::IntoEnumIterator;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, sleep_until, timeout, Instant, MissedTickBehavior};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+use crate::acl::AclProbe;
+use crate::actuation::{Enqueued, Held, Press, PressQueue, Queued, Stage, Verdict};
+use crate::alerts::LeftOpenAlerts;
+use crate::anomaly::AnomalyDetector;
+use crate::analog;
+use crate::api::{self, ApiHandle, CommandRequest, Decision, Failure, MaintenanceRequest, QuarantineRequest, QueryRequest, Snapshot, VacationLockRequest};
+use crate::audit::Audit;
+use crate::auth::{Authenticator, Credential, CredentialKind, Identity};
+use crate::availability::SensorHealth;
+use crate::clock::Clock;
+use crate::config::{self, AutoCloseConfig, Config, LinkAction, LocaleConfig, PresetConfig, RfAction, WatchdogConfig};
+use crate::correlation::{CommandTracker, Correlation, Outcome};
+use crate::countdown::{CloseReason, Countdown};
+use crate::door::{check_command, parse_command_message, parse_door_status, Command, Position, Source, StateRecord, Status, Trigger};
+use crate::error::{BrokerError, Error, GpioError, RejectReason, StateMachineError};
+use crate::estimator;
+use crate::hardware::Hardware;
+use crate::journal::{ActionKind, CatchUp, Journal, ScheduledAction};
+use crate::keypad::{Frame, PinEntry, Wiegand};
+use crate::led::{Indication, StatusLed};
+use crate::links::Links;
+use crate::locale::Locale;
+use crate::lockout::ActiveLockout;
+use crate::machine::{self, Guards};
+use crate::maintenance::{self, MaintenanceMode};
+use crate::health::{HealthCheck, HealthReport, Step};
+use crate::heartbeat::{Heartbeat, SensorRead};
+use crate::history::{History, HistoryEntry, HistoryQuery};
+use crate::motor::MotorRuntime;
+use crate::mqtt::{self, Topics};
+use crate::mqtt_client::{Client, Event, EventLoop, Reply};
+use crate::outbox::{Outbox, Priority};
+use crate::onewire::{self, Reading};
+use crate::options::{Options, RuntimeOptions};
+use crate::position::{PositionTracker, Readings};
+use crate::presence::Presence;
+use crate::presets::{self, Presets};
+use crate::privacy;
+use crate::privileges;
+use crate::quarantine::{self, Quarantine, Strike};
+use crate::ratelimit::RateLimiter;
+use crate::rf;
+use crate::schedule::{ScheduleAction, Scheduler};
+use crate::shutdown::{ShutdownReason, ShutdownRecord};
+use crate::signals::{SignalEvent, Signals};
+use crate::state::StateStore;
+use crate::stats::{StatsStore, UsageStats};
+use crate::subsystems::{self, Condition, StatusReporter, Subsystem, SubsystemStatus, Subsystems};
+use crate::systemd;
+use crate::templates::Templates;
+use crate::vacation::VacationLock;
+use crate::vehicle::{VehicleEvent, VehicleTracker};
+use crate::watchdog::HardwareWatchdog;
+use crate::webhooks::{WebhookEvent, Webhooks};
+
+/// First wait before reconnecting to the broker, doubled after each failed
+/// attempt up to `MAX_RECONNECT_DELAY`.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Gap between replayed history entries, so a replay doesn't crowd the door's
+/// own publishes out of the request queue.
+const REPLAY_PACE: Duration = Duration::from_millis(10);
+
+pub struct Daemon {
+    config: Config,
+    config_path: PathBuf,
+    hw: Hardware,
+    client: Client,
+    topics: Topics,
+    locale: Locale,
+    templates: Templates,
+    position: PositionTracker,
+    presets: Presets,
+    links: Links,
+    links_problem: bool,
+    countdown: Option<Countdown>,
+    /// Whether the warning output is on.
+    warning_on: bool,
+    /// A press waiting for the warning stage to finish sounding.
+    held: Option<Held>,
+    /// Started with the loop when an LED is configured.
+    led: Option<StatusLed>,
+    rate_limiter: RateLimiter,
+    quarantine: Quarantine,
+    /// When the relay was last pressed, for the cooldown.
+    last_press: Option<Instant>,
+    /// Presses waiting their turn at the relay.
+    presses: PressQueue,
+    /// What the last press was for.
+    press_trigger: Option<Trigger>,
+    /// The last published position, when it changed and why.
+    state_record: Option<StateRecord>,
+    /// Why the door state is unknown, while a sensor can't be read.
+    sensor_fault: Option<String>,
+    /// Why the wall button is being ignored, while it can't be read.
+    button_fault: Option<String>,
+    /// For the heartbeat.
+    last_sensor_read: Option<SensorRead>,
+    /// Connections to the broker so far, for the heartbeat.
+    connects: u32,
+    started: Instant,
+    /// Shared with `publish`, which only borrows the daemon.
+    outbox: Mutex<Outbox>,
+    /// Whether the subscriptions are still to be sent.
+    subscribe_pending: bool,
+    clock: Clock,
+    /// How the previous instance stopped, once its record has been seen.
+    previous_shutdown: Option<ShutdownRecord>,
+    /// When the door was last seen opening, for auto-close.
+    open_since: Option<Instant>,
+    left_open: LeftOpenAlerts,
+    vehicle: VehicleTracker,
+    /// Whether the safety beam is broken, or can't be read.
+    obstructed: bool,
+    /// States of `gpio.extra`'s outputs and inputs, in config order, the
+    /// inputs `None` until read.
+    extra_outputs: Vec<bool>,
+    extra_inputs: Vec<Option<bool>>,
+    wiegand: Wiegand,
+    pin_entry: PinEntry,
+    motor: MotorRuntime,
+    anomalies: AnomalyDetector,
+    /// Follows relay presses through to the door moving.
+    commands: CommandTracker,
+    stats: UsageStats,
+    stats_store: StatsStore,
+    state_store: StateStore,
+    journal: Journal,
+    health: Option<HealthCheck>,
+    acl: Option<AclProbe>,
+    overrides: Option<Value>,
+    auth: Arc<Authenticator>,
+    /// Lockout window in effect as of the last check.
+    lockout: Option<ActiveLockout>,
+    presence: Presence,
+    scheduler: Scheduler,
+    temperature_health: SensorHealth,
+    analog_health: SensorHealth,
+    vacation: VacationLock,
+    maintenance: MaintenanceMode,
+    options: Options,
+    subsystems: Subsystems,
+    /// Handed to tasks outside the loop that report a subsystem's status.
+    status_reporter: StatusReporter,
+    subsystem_updates: Option<mpsc::UnboundedReceiver<(Subsystem, SubsystemStatus)>>,
+    /// Started with the loop when the audit webhook is configured.
+    audit: Option<Audit>,
+    /// Started with the loop when any webhooks are configured.
+    webhooks: Option<Webhooks>,
+    /// Enabled with the loop when configured.
+    watchdog: Option<HardwareWatchdog>,
+    /// Running when history is configured.
+    history: Option<History>,
+    /// Answers to MQTT history queries, from the tasks running them.
+    history_replies: mpsc::Sender<Value>,
+    history_results: Option<mpsc::Receiver<Value>>,
+    /// The history replay in progress, if any.
+    replay: Option<JoinHandle<()>>,
+    last_rejection: Option<Value>,
+    api: ApiHandle,
+    snapshot: watch::Sender<Snapshot>,
+    api_commands: Option<mpsc::Receiver<CommandRequest>>,
+    api_queries: Option<mpsc::Receiver<QueryRequest>>,
+    api_quarantine: Option<mpsc::Receiver<QuarantineRequest>>,
+    api_maintenance: Option<mpsc::Receiver<MaintenanceRequest>>,
+    api_vacation_lock: Option<mpsc::Receiver<VacationLockRequest>>,
+    /// Feeds [`ApiHandle::events`].
+    events: broadcast::Sender<Value>,
+    /// Set once the door is being monitored and the button answered.
+    local_ready: watch::Sender<bool>,
+}
+
+impl Daemon {
+    /// `clock` supplies wall time for schedules; pass [`Clock::System`]
+    /// outside of tests.
+    pub fn new(config: Config, config_path: PathBuf, hw: Hardware, client: Client, clock: Clock) -> Daemon {
+        let motor = MotorRuntime::new(config.motor.travel(), config.motor.long_cycle_factor, clock.today());
+        let anomalies = AnomalyDetector::new(config.motor.anomaly_tolerance, config.motor.anomaly_learn_runs);
+        let locale = Locale::new(config.locale.clone());
+        let templates = load_templates(&config.locale);
+        let auth = Arc::new(Authenticator::from_config(&config.auth));
+        let stats_store = StatsStore::new(&config.storage.dir);
+        let state_store = StateStore::new(&config.storage.dir);
+        let extra_outputs = vec![false; config.gpio.extra.outputs.len()];
+        let extra_inputs = vec![None; config.gpio.extra.inputs.len()];
+        let journal = Journal::new(&config.storage.dir);
+        let vacation = VacationLock::load(&config.storage.dir);
+        let maintenance = MaintenanceMode::load(&config.storage.dir);
+        let options = Options::load(&config.storage.dir);
+        let zones = config.gpio.zones.iter().map(|z| z.percent).collect();
+        let estimator = estimator::build(&config.position, config.motor.travel());
+        let position = PositionTracker::with_estimator(config.gpio.open.is_some(), zones, config.motor.travel(), estimator);
+        let (status_reporter, subsystem_updates) = subsystems::channel();
+        let history = config.history.as_ref()
+            .map(|c| History::spawn(c, &config.storage.dir, status_reporter.clone()));
+        let (api, api_server) = api::channel(history.clone());
+        let (history_replies, history_results) = mpsc::channel(4);
+        let mut subsystems = Subsystems::default();
+        subsystems.set(Subsystem::Gpio, SubsystemStatus::ok());
+        subsystems.set(Subsystem::Mqtt, SubsystemStatus::failing("not connected yet"));
+        subsystems.set(Subsystem::Http, SubsystemStatus::enabled(config.http.is_some()));
+        subsystems.set(Subsystem::Socket, SubsystemStatus::enabled(config.socket.is_some()));
+        subsystems.set(Subsystem::Scheduler, SubsystemStatus::ok());
+        subsystems.set(Subsystem::Storage, SubsystemStatus::ok());
+        subsystems.set(Subsystem::Notifications, SubsystemStatus::enabled(config.audit.is_some() || !config.webhooks.is_empty()));
+        subsystems.set(Subsystem::Links, SubsystemStatus::enabled(!config.links.is_empty()));
+        subsystems.set(Subsystem::Acl, SubsystemStatus::ok());
+        subsystems.set(Subsystem::History, SubsystemStatus::enabled(config.history.is_some()));
+        subsystems.set(Subsystem::Watchdog, SubsystemStatus::disabled());
+        let topics = Topics::new(&config.mqtt.topic_prefix, &config.mqtt.discovery_prefix);
+        let scheduler = Scheduler::new(&config.schedule, clock.now());
+        let temperature_health = SensorHealth::new(config.onewire.clone().unwrap_or_default().unavailable_after);
+        let analog_health = SensorHealth::new(config.analog.clone().unwrap_or_default().unavailable_after);
+        Daemon {
+            config,
+            config_path,
+            hw,
+            client,
+            topics,
+            locale,
+            templates,
+            position,
+            presets: Presets::default(),
+            links: Links::default(),
+            links_problem: false,
+            countdown: None,
+            warning_on: false,
+            held: None,
+            led: None,
+            rate_limiter: RateLimiter::default(),
+            quarantine: Quarantine::default(),
+            last_press: None,
+            presses: PressQueue::default(),
+            press_trigger: None,
+            state_record: None,
+            sensor_fault: None,
+            button_fault: None,
+            last_sensor_read: None,
+            connects: 0,
+            started: Instant::now(),
+            outbox: Mutex::new(Outbox::default()),
+            subscribe_pending: false,
+            clock,
+            previous_shutdown: None,
+            open_since: None,
+            left_open: LeftOpenAlerts::default(),
+            vehicle: VehicleTracker::default(),
+            obstructed: false,
+            extra_outputs,
+            extra_inputs,
+            wiegand: Wiegand::default(),
+            pin_entry: PinEntry::default(),
+            motor,
+            anomalies,
+            commands: CommandTracker::default(),
+            stats: stats_store.load(),
+            stats_store,
+            state_store,
+            journal,
+            health: None,
+            acl: None,
+            overrides: None,
+            auth,
+            lockout: None,
+            presence: Presence::default(),
+            scheduler,
+            temperature_health,
+            analog_health,
+            vacation,
+            maintenance,
+            options,
+            subsystems,
+            status_reporter,
+            subsystem_updates: Some(subsystem_updates),
+            audit: None,
+            webhooks: None,
+            watchdog: None,
+            history,
+            history_replies,
+            history_results: Some(history_results),
+            replay: None,
+            last_rejection: None,
+            api,
+            snapshot: api_server.snapshot,
+            api_commands: Some(api_server.commands),
+            api_queries: Some(api_server.queries),
+            api_quarantine: Some(api_server.quarantine),
+            api_maintenance: Some(api_server.maintenance),
+            api_vacation_lock: Some(api_server.vacation_lock),
+            events: api_server.events,
+            local_ready: watch::channel(false).0,
+        }
+    }
+
+    /// Handle for local front ends to query state and send commands.
+    pub fn api(&self) -> ApiHandle {
+        self.api.clone()
+    }
+
+    /// Identity providers shared with front ends that authenticate users.
+    pub fn authenticator(&self) -> Arc<Authenticator> {
+        self.auth.clone()
+    }
+
+    /// Handle for front ends running outside the loop to report how they
+    /// are doing.
+    pub fn status_reporter(&self) -> StatusReporter {
+        self.status_reporter.clone()
+    }
+
+    /// Becomes true once local control is up and, with `[privileges]`, root
+    /// has been given up, for front ends that should start only after it.
+    pub fn local_ready(&self) -> watch::Receiver<bool> {
+        self.local_ready.subscribe()
+    }
+
+    pub async fn run(&mut self, mut event_loop: EventLoop) -> Result<(), Error> {
+        let span = info_span!("door", id = mqtt::DOOR_ID);
+        let result = self.run_loop(&mut event_loop).instrument(span.clone()).await;
+        let record = match &result {
+            Ok((reason, detail)) => ShutdownRecord::new(*reason, detail.clone(), self.started.elapsed()),
+            Err(Error::StateMachine(StateMachineError::StreamEnded(name))) => {
+                ShutdownRecord::new(ShutdownReason::StreamEnded, Some((*name).to_owned()), self.started.elapsed())
+            }
+            Err(e) => ShutdownRecord::new(ShutdownReason::Error, Some(e.to_string()), self.started.elapsed()),
+        };
+        self.shutdown(&mut event_loop, &record).instrument(span).await;
+        result.map(|_| ())
+    }
+
+    /// Runs until a signal or an error stops it, returning why.
+    ///
+    /// Startup is split so that after a power cut the door is monitored and
+    /// the wall button works as soon as the pins are set up: everything
+    /// else, including connecting to the broker, is left to the first turn
+    /// of the loop, and a network that takes minutes to come back only
+    /// delays that.
+    async fn run_loop(&mut self, event_loop: &mut EventLoop) -> Result<(ShutdownReason, Option<String>), Error> {
+        let mut status_changes = self.hw.status_stream()?;
+        let mut open_changes = match self.hw.open_stream()? {
+            Some(s) => s.boxed(),
+            None => stream::pending().boxed(),
+        };
+        let zone_streams = self.hw.zone_streams()?;
+        let mut zone_changes = match zone_streams.is_empty() {
+            true => stream::pending().boxed(),
+            false => stream::select_all(zone_streams).boxed(),
+        };
+        let mut obstruction_changes = match self.hw.obstruction_stream()? {
+            Some(s) => s.boxed(),
+            None => stream::pending().boxed(),
+        };
+        let mut encoder_pulses = match self.hw.encoder_stream()? {
+            Some(s) => s.boxed(),
+            None => stream::pending().boxed(),
+        };
+        let extra_streams = self.hw.extra_streams()?.into_iter()
+            .enumerate()
+            .map(|(index, s)| s.map(move |value| (index, value)).boxed());
+        let mut extra_changes = stream::select_all(extra_streams);
+        let mut input_triggers = self.hw.input_stream()?;
+        let mut keypad_bits = match self.hw.keypad_stream()? {
+            Some(s) => s.boxed(),
+            None => stream::pending().boxed(),
+        };
+        let mut rf_codes = match self.hw.rf_codes()? {
+            Some(s) => s.boxed(),
+            None => stream::pending().boxed(),
+        };
+        let mut api_commands = self.api_commands.take()
+            .expect("daemon loop can only be run once");
+        let mut api_queries = self.api_queries.take()
+            .expect("daemon loop can only be run once");
+        let mut api_quarantine = self.api_quarantine.take()
+            .expect("daemon loop can only be run once");
+        let mut api_maintenance = self.api_maintenance.take()
+            .expect("daemon loop can only be run once");
+        let mut api_vacation_lock = self.api_vacation_lock.take()
+            .expect("daemon loop can only be run once");
+        let mut signals = Signals::new()?;
+        let mut temperatures = onewire::spawn(self.config.onewire.clone());
+        let mut analog_readings = analog::spawn(self.config.analog.clone());
+        let mut subsystem_updates = self.subsystem_updates.take()
+            .expect("daemon loop can only be run once");
+        let mut history_results = self.history_results.take()
+            .expect("daemon loop can only be run once");
+        self.led = self.hw.take_led().map(StatusLed::spawn);
+        // Only spawned here, so alerts from catching up aren't lost; they
+        // connect in the background.
+        let reporter = self.status_reporter.clone();
+        self.audit = self.config.audit.clone().map(|c| Audit::spawn(c, &self.config.storage.dir, reporter.clone()));
+        if !self.config.webhooks.is_empty() {
+            self.webhooks = Some(Webhooks::spawn(&self.config.webhooks, reporter));
+        }
+
+        // Without a first reading there is nothing to go on, so unreadable
+        // sensors are only fatal here; systemd restarts the daemon.
+        let status = self.hw.door_status().await?;
+        let position = self.position.resume(&self.readings(status == Status::Closed).await?);
+        if let Some(saved) = self.state_store.load() {
+            if self.position.restore(&saved) {
+                info!(percent = self.position.percent(), heading = ?saved.heading, "restored the saved door position");
+            }
+        }
+        self.last_sensor_read = Some(SensorRead::ok(self.clock.now()));
+        info!(%position, "initial door state");
+        self.track_open(status).await?;
+        self.publish_state(position).await?;
+        self.obstructed = self.read_obstruction().await;
+        match self.hw.extra_readings().await {
+            Ok(readings) => self.extra_inputs = readings.into_iter().map(Some).collect(),
+            Err(e) => warn!(error = %e, source = %e.source, "failed to read extra inputs"),
+        }
+        self.catch_up(status).await?;
+        self.stats.resume(status, self.clock.now());
+        self.lockout = self.config.lockout.active_at(self.clock.local_now());
+        if self.vacation.is_locked() {
+            info!("vacation lock is on");
+        }
+        if self.maintenance.is_active() {
+            warn!("maintenance mode is on, the relay won't be pressed");
+        }
+        self.enable_watchdog();
+        info!(elapsed_ms = self.started.elapsed().as_millis() as u64, "local control ready");
+        if self.config.privileges.is_none() {
+            self.local_ready.send_replace(true);
+        }
+
+        let mut timer = interval(Duration::from_secs(60));
+        let mut countdown_timer = interval(Duration::from_secs(1));
+        countdown_timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut travel_timer = interval(Duration::from_secs(1));
+        travel_timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let watchdog_period = systemd::watchdog_interval();
+        let mut watchdog_timer = interval(watchdog_period.unwrap_or(Duration::from_secs(60)));
+        let heartbeat_period = self.config.heartbeat.map(|h| h.interval());
+        let feed_period = self.config.watchdog.as_ref().map(WatchdogConfig::feed_interval);
+        let mut feed_timer = interval(feed_period.unwrap_or(Duration::from_secs(5)));
+        feed_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut heartbeat_timer = interval(heartbeat_period.unwrap_or(Duration::from_secs(60)));
+        heartbeat_timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut deferred = true;
+        let mut reconnect_at: Option<Instant> = None;
+        let mut reconnect_delay = RECONNECT_DELAY;
+
+        info!("beginning monitor loop");
+        loop {
+            self.sync_journal();
+            self.sync_state();
+            self.flush_outbox()?;
+            if self.subscribe_pending {
+                self.subscribe()?;
+            }
+            self.publish_subsystems().await?;
+            self.update_led();
+            let health_deadline = self.health.as_ref().map(HealthCheck::deadline);
+            let acl_deadline = self.acl.as_ref().map(AclProbe::deadline);
+            let auto_close_deadline = self.auto_close_deadline();
+            let alert_deadline = self.left_open_deadline();
+            let motion_deadline = self.position.deadline();
+            let command_deadline = self.commands.deadline();
+            let preset_deadline = self.presets.deadline();
+            let keypad_deadline = self.wiegand.deadline();
+            let warning_deadline = self.warning_deadline();
+            let quarantine_deadline = self.quarantine.deadline();
+            let schedule_deadline = self.schedule_deadline();
+            let held_deadline = self.held.map(|h| h.until);
+            let press_deadline = self.presses.deadline(self.config.actuation.spacing()).filter(|_| self.held.is_none());
+            tokio::select! {
+                _ = std::future::ready(()), if deferred => {
+                    deferred = false;
+                    self.start_deferred().await?;
+                },
+                _ = sleep_until(reconnect_at.unwrap_or_else(Instant::now)), if reconnect_at.is_some() => {
+                    reconnect_at = None;
+                },
+                _next_timer = timer.tick() => {
+                    if let Some(status) = self.read_status().await? {
+                        self.update_position(status == Status::Closed).await?;
+                        self.publish_state(self.position.position()).await?;
+                    }
+                    self.publish_motor().await?;
+                    self.publish_stats().await?;
+                    self.publish_presets().await?;
+                    self.publish_links().await?;
+                    self.refresh_lockout().await?;
+                },
+                _ = watchdog_timer.tick(), if watchdog_period.is_some() => {
+                    systemd::notify_watchdog();
+                },
+                _ = heartbeat_timer.tick(), if heartbeat_period.is_some() => {
+                    self.publish_heartbeat().await?;
+                },
+                _ = feed_timer.tick(), if self.watchdog.is_some() => {
+                    self.feed_watchdog();
+                },
+                _ = travel_timer.tick(), if self.position.is_moving() => {
+                    self.publish_percent().await?;
+                },
+                _ = countdown_timer.tick(), if self.countdown.is_some() => {
+                    self.countdown_tick().await?;
+                },
+                _ = sleep_until(auto_close_deadline.unwrap_or_else(Instant::now)), if auto_close_deadline.is_some() => {
+                    // Restart the clock so a cancelled close waits another full period.
+                    self.open_since = Some(Instant::now());
+                    self.start_automated_close(CloseReason::AutoClose).await?;
+                },
+                _ = sleep_until(alert_deadline.unwrap_or_else(Instant::now)), if alert_deadline.is_some() => {
+                    self.send_left_open_alert().await?;
+                },
+                _ = sleep_until(motion_deadline.unwrap_or_else(Instant::now)), if motion_deadline.is_some() => {
+                    let from = self.position.position();
+                    if let Some(position) = self.position.timed_out() {
+                        warn!("door did not reach the other end in time, presuming it stopped");
+                        self.follow_run(from, position).await?;
+                        self.publish_state(position).await?;
+                    }
+                },
+                _ = sleep_until(command_deadline.unwrap_or_else(Instant::now)), if command_deadline.is_some() => {
+                    if let Some(correlation) = self.commands.timed_out() {
+                        self.command_finished(correlation).await?;
+                    }
+                },
+                _ = sleep_until(preset_deadline.unwrap_or_else(Instant::now)), if preset_deadline.is_some() => {
+                    self.preset_reached().await?;
+                },
+                _ = sleep_until(warning_deadline.unwrap_or_else(Instant::now)), if warning_deadline.is_some() => {
+                    self.update_warning();
+                },
+                _ = sleep_until(quarantine_deadline.unwrap_or_else(Instant::now)), if quarantine_deadline.is_some() => {
+                    self.expire_quarantine().await?;
+                },
+                _ = sleep_until(held_deadline.unwrap_or_else(Instant::now)), if held_deadline.is_some() => {
+                    self.resume_press().await?;
+                },
+                _ = sleep_until(press_deadline.unwrap_or_else(Instant::now)), if press_deadline.is_some() => {
+                    self.run_presses().await?;
+                },
+                _ = sleep_until(schedule_deadline.unwrap_or_else(Instant::now)), if schedule_deadline.is_some() => {
+                    self.run_schedule().await?;
+                },
+                _ = sleep_until(keypad_deadline.unwrap_or_else(Instant::now)), if keypad_deadline.is_some() => {
+                    let frame = self.wiegand.finish();
+                    self.keypad_frame(frame).await?;
+                },
+                _ = sleep_until(acl_deadline.unwrap_or_else(Instant::now)), if acl_deadline.is_some() => {
+                    self.finish_acl_probe().await?;
+                },
+                _ = sleep_until(health_deadline.unwrap_or_else(Instant::now)), if health_deadline.is_some() => {
+                    if let Some(step) = self.health.as_mut().map(HealthCheck::timeout) {
+                        self.health_step(step).await?;
+                    }
+                },
+                next_status = status_changes.next() => {
+                    match next_status {
+                        Some(Ok(x)) => {
+                            let status = parse_door_status(x);
+                            info!(%status, "detected door status");
+                            self.track_open(status).await?;
+                            self.update_position(status == Status::Closed).await?;
+                            if status == Status::Closed && self.presets.clear() {
+                                self.publish_presets().await?;
+                            }
+                            self.track_vehicle(status).await?;
+                            if let Some(step) = self.health.as_mut().map(|h| h.door_changed(status)) {
+                                self.health_step(step).await?;
+                            }
+                            self.track_motor(status).await?;
+                            if self.stats.door_changed(status, self.clock.now()) {
+                                self.redact_stats();
+                                self.save_stats();
+                                self.publish_stats().await?;
+                                if status == Status::Open {
+                                    self.publish_heatmap().await?;
+                                }
+                            }
+                        },
+                        Some(Err(e)) => self.sensor_failed(GpioError::new("status", "stream", e)).await?,
+                        None => return Err(StateMachineError::StreamEnded("status").into()),
+                    }
+                },
+                next_open = open_changes.next() => {
+                    match next_open {
+                        Some(Ok(_)) => {
+                            if let Some(status) = self.read_status().await? {
+                                self.update_position(status == Status::Closed).await?;
+                            }
+                        },
+                        Some(Err(e)) => self.sensor_failed(GpioError::new("open", "stream", e)).await?,
+                        None => return Err(StateMachineError::StreamEnded("open").into()),
+                    }
+                },
+                next_obstruction = obstruction_changes.next() => {
+                    match next_obstruction {
+                        Some(Ok(_)) => self.update_obstruction().await?,
+                        Some(Err(e)) => {
+                            warn!(error = %e, "safety beam stream failed");
+                            self.update_obstruction().await?;
+                        },
+                        None => return Err(StateMachineError::StreamEnded("obstruction").into()),
+                    }
+                },
+                next_zone = zone_changes.next() => {
+                    match next_zone {
+                        Some(Ok(_)) => {
+                            if let Some(status) = self.read_status().await? {
+                                self.update_position(status == Status::Closed).await?;
+                            }
+                        },
+                        Some(Err(e)) => self.sensor_failed(GpioError::new("zone", "stream", e)).await?,
+                        None => return Err(StateMachineError::StreamEnded("zone").into()),
+                    }
+                },
+                Some((index, value)) = extra_changes.next() => {
+                    match value {
+                        Ok(value) => self.extra_input_changed(index, value != 0).await?,
+                        Err(e) => warn!(error = %e, "failed to read extra input"),
+                    }
+                },
+                next_pulse = encoder_pulses.next() => {
+                    match next_pulse {
+                        Some(Ok(_)) => {
+                            self.position.encoder_pulse();
+                            self.commands.moved();
+                        },
+                        Some(Err(e)) => warn!(error = %e, "failed to read encoder"),
+                        None => return Err(StateMachineError::StreamEnded("encoder").into()),
+                    }
+                },
+                next_input = input_triggers.next() => {
+                    if matches!(next_input, Some(Ok(_))) {
+                        self.button_recovered().await?;
+                    }
+                    match next_input {
+                        Some(Ok(x)) if x != 0 && self.maintenance.is_active() => {
+                            warn!("ignoring input trigger, maintenance mode is on");
+                            self.publish_blocked("PRESS", Source::Button).await?;
+                        },
+                        Some(Ok(x)) if x != 0 && self.vacation.is_locked() => {
+                            warn!("ignoring input trigger, vacation lock is on");
+                            self.publish_blocked("PRESS", Source::Button).await?;
+                        },
+                        Some(Ok(x)) if x != 0 => {
+                            info!("detected input trigger");
+                            self.record_history("button", Some("button"), None, json!({}));
+                            self.abort_health_check().await?;
+                            self.actuate(Trigger::Button, None).await?;
+                        },
+                        Some(Ok(_)) => (),
+                        Some(Err(e)) => self.button_failed(GpioError::new("input", "stream", e)).await?,
+                        None => return Err(StateMachineError::StreamEnded("input").into()),
+                    }
+                },
+                next_bit = keypad_bits.next() => {
+                    match next_bit {
+                        Some(Ok(one)) => self.wiegand.bit(one),
+                        Some(Err(e)) => warn!(error = %e, "failed to read keypad data line"),
+                        None => return Err(StateMachineError::StreamEnded("keypad").into()),
+                    }
+                },
+                next_code = rf_codes.next() => {
+                    match next_code {
+                        Some(Ok(code)) => self.rf_code(code).await?,
+                        Some(Err(e)) => warn!(error = %e, "failed to read rf data line"),
+                        None => return Err(StateMachineError::StreamEnded("rf").into()),
+                    }
+                },
+                Some(request) = api_commands.recv() => {
+                    let result = self.execute(request.command, request.source, request.identity.as_ref()).await;
+                    let _ = request.reply.send(result.as_ref().map_err(Failure::from).copied());
+                    match result {
+                        Err(Error::CommandRejected { .. }) | Ok(()) => (),
+                        Err(e) => return Err(e),
+                    }
+                },
+                Some(reading) = temperatures.recv() => {
+                    match reading {
+                        Ok(reading) => {
+                            if self.temperature_health.read(&reading.id) {
+                                info!(sensor = %reading.id, "1-wire probe readable again");
+                                self.publish(&self.topics.temperature_availability(&reading.id), true, mqtt::ONLINE).await?;
+                            }
+                            self.publish_temperature(&reading).await?;
+                        },
+                        Err(failure) if self.temperature_health.failed(&failure.id) => {
+                            warn!(sensor = %failure.id, error = %failure.error, "1-wire probe keeps failing, marking it unavailable");
+                            self.publish(&self.topics.temperature_availability(&failure.id), true, mqtt::OFFLINE).await?;
+                        },
+                        Err(_) => (),
+                    }
+                },
+                Some(reading) = analog_readings.recv() => {
+                    match reading {
+                        Ok(reading) => {
+                            if self.config.position.current_input() == Some(reading.id.as_str()) {
+                                self.position.motor_current(reading.value);
+                            }
+                            if self.analog_health.read(&reading.id) {
+                                info!(input = %reading.id, "analog input readable again");
+                                self.publish(&self.topics.analog_availability(&reading.id), true, mqtt::ONLINE).await?;
+                            }
+                            let topic = self.topics.analog(&reading.id);
+                            self.publish(&topic, true, format!("{:.3}", reading.value)).await?;
+                        },
+                        Err(failure) if self.analog_health.failed(&failure.id) => {
+                            warn!(input = %failure.id, error = %failure.error, "analog input keeps failing, marking it unavailable");
+                            self.publish(&self.topics.analog_availability(&failure.id), true, mqtt::OFFLINE).await?;
+                        },
+                        Err(_) => (),
+                    }
+                },
+                Some((subsystem, status)) = subsystem_updates.recv() => {
+                    self.set_subsystem(subsystem, status);
+                },
+                Some(reply) = history_results.recv() => {
+                    self.publish_json(&self.topics.history_result, false, &reply).await?;
+                },
+                Some(request) = api_quarantine.recv() => {
+                    match request {
+                        QuarantineRequest::FailedAuth(source) => self.strike(&source, Strike::FailedAuth).await?,
+                        QuarantineRequest::Release { source, by, reply } => {
+                            let released = self.release_quarantine(&source, &by).await?;
+                            let _ = reply.send(released);
+                        }
+                    }
+                },
+                Some(request) = api_maintenance.recv() => {
+                    let by = match &request.identity {
+                        Some(identity) => format!("http:{}", identity.id),
+                        None => "http".to_owned(),
+                    };
+                    let result = self.set_maintenance(request.active, &by, request.reason, request.identity.as_ref()).await;
+                    let reply = result.as_ref().map(|()| self.maintenance.state().clone()).map_err(Failure::from);
+                    let _ = request.reply.send(reply);
+                    match result {
+                        Err(Error::CommandRejected { .. }) | Ok(()) => (),
+                        Err(e) => return Err(e),
+                    }
+                },
+                Some(request) = api_vacation_lock.recv() => {
+                    self.set_vacation_lock(request.locked, &request.by).await?;
+                    let _ = request.reply.send(self.vacation.is_locked());
+                },
+                Some(request) = api_queries.recv() => {
+                    let result = self.decide(request.command, Source::Http, request.identity.as_ref());
+                    let _ = request.reply.send(result.as_ref().map_err(Failure::from).cloned());
+                    result?;
+                },
+                next_msg = event_loop.poll(), if !deferred && reconnect_at.is_none() => {
+                    if !*self.local_ready.borrow() {
+                        self.drop_privileges()?;
+                    }
+                    match next_msg {
+                        Ok(Event::Connected { session_present }) => {
+                            info!(session_present, "connected to mqtt broker");
+                            reconnect_delay = RECONNECT_DELAY;
+                            self.connects += 1;
+                            if self.connects == 1 {
+                                systemd::notify_ready();
+                            }
+                            self.set_subsystem(Subsystem::Mqtt, SubsystemStatus::ok());
+                            self.connected().await?;
+                        },
+                        Ok(Event::SubscribeRefused { pkid }) => {
+                            warn!(pkid, "broker refused a subscription");
+                            if let Some(acl) = self.acl.as_mut() {
+                                acl.subscribe_refused();
+                            }
+                        },
+                        Ok(Event::Message(packet)) => {
+                            let probe = self.acl.as_mut()
+                                .map(|acl| acl.received(&packet.topic, &packet.payload))
+                                .unwrap_or(false);
+                            if probe {
+                                self.client.try_unsubscribe(&packet.topic)?;
+                                if self.acl.as_ref().map(AclProbe::is_complete).unwrap_or(false) {
+                                    self.finish_acl_probe().await?;
+                                }
+                            } else if packet.topic == self.topics.command {
+                                self.handle_command(packet.payload.as_ref(), packet.reply.as_ref()).await?;
+                            } else if packet.topic == self.topics.query {
+                                self.handle_query(packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.preset_set {
+                                let name = String::from_utf8_lossy(&packet.payload).trim().to_owned();
+                                self.select_preset(&name).await?;
+                            } else if packet.topic == self.topics.set_position {
+                                self.handle_set_position(packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.vacation_lock_set {
+                                self.handle_vacation_lock(packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.maintenance_set {
+                                self.handle_maintenance(packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.quarantine_release {
+                                self.handle_quarantine_release(packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.history {
+                                self.handle_history(packet.payload.as_ref());
+                            } else if packet.topic == self.topics.history_replay_request {
+                                self.handle_history_replay(packet.payload.as_ref());
+                            } else if packet.topic == self.topics.last_shutdown {
+                                self.handle_last_shutdown(packet.payload.as_ref()).await?;
+                            } else if Some(packet.topic.as_str()) == self.wind_topic() {
+                                self.handle_wind(packet.payload.as_ref()).await?;
+                            } else if Links::topics(&self.config.links).any(|t| t == packet.topic) {
+                                self.handle_link(&packet.topic, packet.payload.as_ref()).await?;
+                            } else if self.config.presence.as_ref().is_some_and(|p| p.topics.contains(&packet.topic)) {
+                                self.handle_presence(&packet.topic, packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.set_config && self.config.mqtt.remote_config {
+                                self.handle_set_config(packet.payload.as_ref()).await?;
+                            } else if Some(&packet.topic) == self.options_topic() {
+                                self.handle_options(packet.payload.as_ref()).await?;
+                            } else if let Some(index) = self.extra_output_index(&packet.topic) {
+                                self.handle_extra_output(index, packet.payload.as_ref()).await?;
+                            } else if packet.topic == self.topics.simulate && self.hw.simulator().is_some() {
+                                self.handle_simulate(packet.payload.as_ref());
+                            } else {
+                                warn!(topic = %packet.topic, "unrecognized topic");
+                            }
+                        },
+                        Err(e) => {
+                            error!(error = %e, retry_secs = reconnect_delay.as_secs(), "mqtt error");
+                            self.set_subsystem(Subsystem::Mqtt, SubsystemStatus::failing(e.to_string()));
+                            // The next poll reconnects straight away, which
+                            // would spin while the network is down.
+                            reconnect_at = Some(Instant::now() + reconnect_delay);
+                            reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                        }
+                        _ => (),
+                    }
+                },
+                signal = signals.recv() => {
+                    match signal {
+                        SignalEvent::Shutdown(name) => {
+                            info!(signal = name, "shutdown signal received");
+                            return Ok((ShutdownReason::Signal, Some(name.to_owned())));
+                        },
+                        SignalEvent::Reload => self.reload_config().await?,
+                    }
+                }
+            }
+        }
+    }
+
+    /// The rest of startup, run from the loop once local control is up:
+    /// stored stats and everything published.
+    async fn start_deferred(&mut self) -> Result<(), Error> {
+        self.publish_obstruction().await?;
+        self.redact_stats();
+        self.save_stats();
+        self.publish_stats().await?;
+        self.publish_heatmap().await?;
+        self.publish_commands().await?;
+        self.publish_countdown().await?;
+        self.publish_motor().await?;
+        self.publish_presets().await?;
+        self.publish_links().await?;
+        self.publish_vacation_lock().await?;
+        self.publish_maintenance().await?;
+        self.publish_quarantine().await?;
+        info!(elapsed_ms = self.started.elapsed().as_millis() as u64, "startup finished");
+        Ok(())
+    }
+
+    /// Sets up a fresh broker session. A restarted broker may have lost our
+    /// subscriptions and retained messages, so everything is sent again on
+    /// every connection, not just the first.
+    async fn connected(&mut self) -> Result<(), Error> {
+        self.publish(&self.topics.availability, true, mqtt::ONLINE).await?;
+        self.subscribe()?;
+        self.publish_discovery().await?;
+        self.publish_sensor_availability().await?;
+        self.publish_state(self.position.position()).await?;
+        self.publish_countdown().await?;
+        self.publish_motor().await?;
+        self.publish_motor_anomaly().await?;
+        self.publish_stats().await?;
+        self.publish_presets().await?;
+        self.publish_links().await?;
+        self.publish_vacation_lock().await?;
+        self.publish_maintenance().await?;
+        self.publish_quarantine().await?;
+        self.publish_extra_io().await?;
+        self.publish_json(&self.topics.subsystems, true, &self.subsystems_report()?).await?;
+        self.start_acl_probe()
+    }
+
+    /// Subscribes to every topic the daemon listens on in one request,
+    /// trying again from the loop if the request queue is full.
+    fn subscribe(&mut self) -> Result<(), Error> {
+        let io_sets: Vec<_> = self.config.gpio.extra.outputs.iter().map(|o| self.topics.io_set(&o.id)).collect();
+        let mut own = vec![
+            &self.topics.query, &self.topics.preset_set, &self.topics.set_position, &self.topics.vacation_lock_set,
+            &self.topics.maintenance_set, &self.topics.last_shutdown, &self.topics.quarantine_release,
+        ];
+        if self.config.mqtt.remote_config {
+            own.push(&self.topics.set_config);
+        }
+        if self.history.is_some() {
+            own.push(&self.topics.history);
+            own.push(&self.topics.history_replay_request);
+        }
+        if let Some(topic) = self.options_topic() {
+            own.push(topic);
+        }
+        if self.hw.simulator().is_some() {
+            own.push(&self.topics.simulate);
+        }
+        own.extend(&io_sets);
+        let command_qos = mqtt::qos(self.config.mqtt.qos_for(&self.topics.command, 2));
+        let filters: Vec<_> = std::iter::once((self.topics.command.clone(), command_qos))
+            .chain(own.into_iter().map(|t| (t.clone(), self.qos(t))))
+            .chain(external_topics(&self.config).into_iter()
+                .map(|t| (t.to_owned(), mqtt::qos(self.config.mqtt.qos_for(t, 0)))))
+            .collect();
+        match self.client.try_subscribe_many(filters) {
+            Ok(()) => self.subscribe_pending = false,
+            Err(BrokerError::QueueFull) => {
+                debug!("request queue full, subscribing later");
+                self.subscribe_pending = true;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// Records why the daemon is stopping, marks it offline and flushes the
+    /// disconnect to the broker. GPIO pins are unexported when the hardware
+    /// is dropped afterwards.
+    async fn shutdown(&mut self, event_loop: &mut EventLoop, record: &ShutdownRecord) {
+        systemd::notify_stopping();
+        self.drop_held_press("shutdown");
+        let dropped = self.presses.clear();
+        if dropped > 0 {
+            warn!(dropped, "dropping relay presses still waiting");
+        }
+        if let Some(watchdog) = self.watchdog.take() {
+            if let Err(e) = watchdog.disable() {
+                warn!(error = %e, "failed to disable the hardware watchdog");
+            }
+        }
+        self.stats.advance(self.clock.now());
+        self.save_stats();
+        self.sync_journal();
+        match serde_json::to_vec(record) {
+            Ok(payload) => {
+                if let Err(e) = self.publish(&self.topics.last_shutdown, true, payload).await {
+                    warn!(error = %e, "failed to publish shutdown record");
+                }
+            }
+            Err(e) => warn!(error = %e, "failed to encode shutdown record"),
+        }
+        if let Err(e) = self.publish(&self.topics.availability, true, mqtt::OFFLINE).await {
+            warn!(error = %e, "failed to publish offline availability");
+        }
+        if self.client.try_disconnect().is_err() {
+            return;
+        }
+        let flush = async {
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Disconnected) | Err(_) => break,
+                    Ok(_) => (),
+                }
+            }
+        };
+        if timeout(Duration::from_secs(2), flush).await.is_err() {
+            warn!("timed out disconnecting from mqtt broker");
+        }
+    }
+
+    async fn publish_discovery(&self) -> Result<(), Error> {
+        debug!(topic = %self.topics.config, "publishing device config");
+        self.publish_json(&self.topics.config, false, &mqtt::cover_discovery(&self.topics, &self.locale, self.config.mqtt.json_state)).await?;
+        self.publish_json(&self.topics.countdown_config, false, &mqtt::countdown_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.motor_config, false, &mqtt::motor_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.motor_anomaly_config, false, &mqtt::motor_anomaly_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.health_config, false, &mqtt::health_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.health_button_config, false, &mqtt::health_button_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.acl_config, false, &mqtt::acl_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.vacation_lock_config, false, &mqtt::vacation_lock_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.maintenance_config, false, &mqtt::maintenance_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.cycles_config, false, &mqtt::cycles_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.last_opened_config, false, &mqtt::last_opened_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.heatmap_config, false, &mqtt::heatmap_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.command_success_config, false, &mqtt::command_success_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.open_today_config, false, &mqtt::open_today_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.open_duration_config, false, &mqtt::open_duration_discovery(&self.topics, &self.locale)).await?;
+        self.publish_json(&self.topics.last_changed_config, false, &mqtt::last_changed_discovery(&self.topics, &self.locale)).await?;
+        if self.config.links.is_empty() {
+            self.publish(&self.topics.links_config, false, "").await?;
+        } else {
+            self.publish_json(&self.topics.links_config, false, &mqtt::links_discovery(&self.topics, &self.locale)).await?;
+        }
+        match &self.config.presets {
+            Some(presets) => {
+                let config = mqtt::preset_discovery(&self.topics, &self.locale, presets);
+                self.publish_json(&self.topics.preset_config, false, &config).await?;
+            }
+            None => self.publish(&self.topics.preset_config, false, "").await?,
+        }
+        self.publish_json(&self.topics.subsystems_config, false, &mqtt::subsystems_discovery(&self.topics, &self.locale)).await?;
+        if self.hw.has_obstruction_sensor() {
+            self.publish_json(&self.topics.obstruction_config, false, &mqtt::obstruction_discovery(&self.topics, &self.locale)).await?;
+        }
+        if self.hw.has_vehicle_sensor() {
+            self.publish_json(&self.topics.vehicle_config, false, &mqtt::vehicle_discovery(&self.topics, &self.locale)).await?;
+            for event in VehicleEvent::iter() {
+                let config = mqtt::vehicle_trigger_discovery(&self.topics, &self.locale, event);
+                self.publish_json(&self.topics.vehicle_trigger_config(event), false, &config).await?;
+            }
+        }
+        for output in &self.config.gpio.extra.outputs {
+            let config = mqtt::extra_output_discovery(&self.topics, &self.locale, output);
+            self.publish_json(&self.topics.io_switch_config(&output.id), false, &config).await?;
+        }
+        for input in &self.config.gpio.extra.inputs {
+            let config = mqtt::extra_input_discovery(&self.topics, &self.locale, input);
+            self.publish_json(&self.topics.io_binary_sensor_config(&input.id), false, &config).await?;
+        }
+        for input in self.config.analog.iter().flat_map(|a| &a.inputs) {
+            let config = mqtt::analog_discovery(&self.topics, &self.locale, input);
+            self.publish_json(&self.topics.analog_config(&input.id), false, &config).await?;
+        }
+        for sensor in self.config.onewire.iter().flat_map(|o| &o.sensors) {
+            let config = mqtt::temperature_discovery(&self.topics, &self.locale, sensor);
+            self.publish_json(&self.topics.temperature_config(&sensor.id), false, &config).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_set_config(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let overrides: Value = match serde_json::from_slice(payload) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(error = %e, "invalid json on config topic");
+                return Ok(());
+            }
+        };
+        if let Err(e) = config::check_remote_overrides(&overrides) {
+            warn!(error = %e, "rejecting remote config change");
+            return Ok(());
+        }
+        let mut merged = self.overrides.clone().unwrap_or_else(|| json!({}));
+        config::merge(&mut merged, &overrides);
+        match Config::reload(&self.config_path, Some(&merged)) {
+            Ok(config) => {
+                info!("applying remote config change");
+                self.overrides = Some(merged);
+                self.apply_config(config).await
+            }
+            Err(e) => {
+                warn!(error = %e, "rejecting remote config change");
+                Ok(())
+            }
+        }
+    }
+
+    /// Re-reads the config file, keeping any remote overrides on top.
+    async fn reload_config(&mut self) -> Result<(), Error> {
+        match Config::reload(&self.config_path, self.overrides.as_ref()) {
+            Ok(config) => {
+                info!(path = %self.config_path.display(), "reloaded config");
+                self.apply_config(config).await
+            }
+            Err(e) => {
+                error!(error = %e, "failed to reload config, keeping current settings");
+                Ok(())
+            }
+        }
+    }
+
+    /// Switches to a new configuration without dropping the broker session.
+    /// Broker, pin assignment, HTTP and logging changes still need a restart.
+    async fn apply_config(&mut self, config: Config) -> Result<(), Error> {
+        let old = &self.config;
+        let pins_changed = config.gpio.relay != old.gpio.relay
+            || config.gpio.status != old.gpio.status
+            || config.gpio.input != old.gpio.input
+            || config.gpio.led != old.gpio.led
+            || config.gpio.warning != old.gpio.warning
+            || config.gpio.vehicle != old.gpio.vehicle
+            || config.gpio.obstruction != old.gpio.obstruction
+            || config.gpio.zones != old.gpio.zones
+            || config.gpio.encoder != old.gpio.encoder
+            || config.gpio.maintenance != old.gpio.maintenance;
+        let restart_needed = pins_changed || config.mqtt != old.mqtt || config.http != old.http || config.socket != old.socket
+            || config.log != old.log || config.auth != old.auth || config.storage != old.storage
+            || config.onewire != old.onewire || config.keypad != old.keypad || config.audit != old.audit
+            || config.rf.as_ref().map(|r| &r.data) != old.rf.as_ref().map(|r| &r.data)
+            || config.analog != old.analog || config.history != old.history
+            || config.position != old.position || config.webhooks != old.webhooks
+            || config.simulation != old.simulation || config.heartbeat != old.heartbeat || config.watchdog != old.watchdog;
+        if restart_needed {
+            warn!("changes to mqtt, gpio pin, http, socket, log, auth, storage, onewire, keypad, rf data pin, audit, webhooks, analog, history, position, simulation, heartbeat or watchdog settings take effect after a restart");
+        }
+
+        self.hw.set_pulse(config.gpio.pulse());
+        self.motor.set_thresholds(config.motor.travel(), config.motor.long_cycle_factor);
+        self.anomalies.set_thresholds(config.motor.anomaly_tolerance, config.motor.anomaly_learn_runs);
+        self.position.set_travel(config.motor.travel());
+        let rediscover = config.locale != old.locale || config.presets != old.presets
+            || config.links.is_empty() != old.links.is_empty();
+        let (old_topics, new_topics) = (external_topics(old), external_topics(&config));
+        for topic in old_topics.difference(&new_topics) {
+            self.client.try_unsubscribe(topic)?;
+        }
+        for topic in new_topics.difference(&old_topics) {
+            let qos = mqtt::qos(config.mqtt.qos_for(topic, 0));
+            self.client.try_subscribe(topic, qos)?;
+        }
+        self.links.retain(&config.links);
+        if config.schedule != old.schedule {
+            self.scheduler = Scheduler::new(&config.schedule, self.clock.now());
+        }
+        self.locale = Locale::new(config.locale.clone());
+        self.templates = load_templates(&config.locale);
+        self.config = config;
+        if rediscover {
+            self.publish_discovery().await?;
+            self.publish_presets().await?;
+        }
+        self.publish_links().await?;
+        self.refresh_lockout().await
+    }
+
+    async fn handle_command(&mut self, payload: &[u8], reply: Option<&Reply>) -> Result<(), Error> {
+        let (command, credential) = match parse_command_message(payload) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(topic = %self.topics.command, error = %e, "invalid payload on command topic");
+                return self.reply(reply, &json!(Failure::from(&e)));
+            }
+        };
+        let identity = match credential {
+            Some(token) => self.identify(&token).await,
+            None => Ok(None),
+        };
+        let result = match identity {
+            Ok(identity) => self.execute(command, Source::Mqtt, identity.as_ref()).await,
+            Err(Error::CommandRejected { reason }) => {
+                self.record_rejection(command.to_string(), reason, Source::Mqtt).await?;
+                Err(Error::rejected(reason))
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => self.reply(reply, &json!({ "ok": true, "command": command.to_string() })),
+            Err(e @ Error::CommandRejected { .. }) => {
+                warn!(%command, code = e.code(), "ignoring command: {}", e);
+                self.reply(reply, &json!(Failure::from(&e)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Answers an MQTT 5 command on the response topic it gave, in the
+    /// same shape as the HTTP API's replies.
+    fn reply(&self, reply: Option<&Reply>, payload: &Value) -> Result<(), Error> {
+        let reply = match reply {
+            Some(r) => r,
+            None => return Ok(()),
+        };
+        let payload = to_vec(payload).map_err(BrokerError::from)?;
+        match self.client.try_reply(reply, payload) {
+            Ok(()) => Ok(()),
+            Err(BrokerError::QueueFull) => {
+                debug!(topic = %reply.topic, "request queue full, dropping command reply");
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Acts on a frame from the keypad once a code has been entered: a
+    /// valid code moves the door the way a press of the wall button would,
+    /// subject to the same rules as remote commands from that identity.
+    async fn keypad_frame(&mut self, frame: Frame) -> Result<(), Error> {
+        let config = match &self.config.keypad {
+            Some(c) => c.clone(),
+            None => return Ok(()),
+        };
+        let code = match frame {
+            Frame::Key(key) => match self.pin_entry.key(key, &config) {
+                Some(code) => code,
+                None => return Ok(()),
+            },
+            Frame::Card(card) => {
+                info!(card, "ignoring card read, only keypad codes are supported");
+                return Ok(());
+            }
+            Frame::Invalid(bits) => {
+                debug!(bits, "discarding invalid wiegand frame");
+                return Ok(());
+            }
+        };
+        if self.pin_entry.is_blocked() {
+            warn!("ignoring keypad code, too many wrong codes");
+            return self.publish_access(None, Some("blocked")).await;
+        }
+        if self.quarantine.is_quarantined(quarantine::KEYPAD) {
+            warn!("ignoring keypad code, keypad is quarantined");
+            return self.publish_access(None, Some(RejectReason::Quarantined.code())).await;
+        }
+        let identity = match self.auth.validate(&Credential::new(CredentialKind::Keypad, code)).await {
+            Ok(Some(identity)) => identity,
+            Ok(None) => {
+                warn!("wrong keypad code");
+                if self.pin_entry.failed(&config) {
+                    warn!(block_secs = config.block_secs, "too many wrong keypad codes, blocking keypad");
+                }
+                self.strike(quarantine::KEYPAD, Strike::FailedAuth).await?;
+                return self.publish_access(None, Some("unknown_code")).await;
+            }
+            Err(e) => {
+                warn!(error = %e, "failed to validate keypad code");
+                return self.publish_access(None, Some("auth_error")).await;
+            }
+        };
+        self.pin_entry.succeeded();
+        let command = self.press_command();
+        info!(%command, identity = %identity.id, "keypad code accepted");
+        match self.execute(command, Source::Keypad, Some(&identity)).await {
+            Ok(()) => self.publish_access(Some(&identity.id), None).await,
+            Err(Error::CommandRejected { reason }) => {
+                warn!(%command, code = reason.code(), "keypad command rejected");
+                self.publish_access(Some(&identity.id), Some(reason.code())).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// What one press would do to the door, as a command.
+    fn press_command(&self) -> Command {
+        match self.position.position() {
+            Position::Closed => Command::Open,
+            Position::Open => Command::Close,
+            Position::Opening | Position::Closing => Command::Cancel,
+            Position::Stopped if self.position.check_heading(Command::Open).is_ok() => Command::Open,
+            Position::Stopped => Command::Close,
+        }
+    }
+
+    /// Acts on a code from a 433 MHz remote: a learned button moves the door
+    /// as configured, subject to the same rules as the wall button's, while
+    /// an unknown one is reported on the events topic so it can be learned.
+    async fn rf_code(&mut self, code: u32) -> Result<(), Error> {
+        let hex = rf::format_code(code);
+        let learned = self.config.rf.iter().flat_map(|r| &r.codes).find(|c| c.code == code).cloned();
+        let learned = match learned {
+            Some(l) => l,
+            None => {
+                info!(code = %hex, "unknown rf code");
+                return self.publish_event("rf_unknown", json!({ "code": hex })).await;
+            }
+        };
+        let command = match learned.action {
+            RfAction::Toggle => self.press_command(),
+            RfAction::Open => Command::Open,
+            RfAction::Close => Command::Close,
+            RfAction::Stop => Command::Cancel,
+        };
+        info!(code = %hex, name = ?learned.name, %command, "rf code received");
+        let rejected = match self.execute(command, Source::Rf, None).await {
+            Ok(()) => None,
+            Err(Error::CommandRejected { reason }) => {
+                warn!(%command, code = reason.code(), "rf command rejected");
+                Some(reason.code())
+            }
+            Err(e) => return Err(e),
+        };
+        let details = json!({
+            "code": hex,
+            "name": learned.name,
+            "command": command.to_string(),
+            "result": if rejected.is_some() { "rejected" } else { "accepted" },
+            "reason": rejected,
+        });
+        self.publish_event("rf", details).await
+    }
+
+    /// Reports a keypad code on the events topic: granted without a reason,
+    /// denied with one.
+    async fn publish_access(&self, code_id: Option<&str>, denied: Option<&str>) -> Result<(), Error> {
+        let details = json!({
+            "result": if denied.is_some() { "denied" } else { "granted" },
+            "code_id": code_id,
+            "reason": denied,
+        });
+        self.publish_event("access", details).await
+    }
+
+    /// Looks up the identity behind a credential sent with an MQTT command
+    /// or query. Unknown credentials are treated like none at all, but count
+    /// towards quarantining them; quarantined ones are rejected outright.
+    async fn identify(&mut self, token: &str) -> Result<Option<Identity>, Error> {
+        let source = quarantine::token_key(token);
+        if self.quarantine.is_quarantined(&source) {
+            return Err(Error::rejected(RejectReason::Quarantined));
+        }
+        match self.auth.validate(&Credential::new(CredentialKind::Token, token)).await {
+            Ok(Some(identity)) => Ok(Some(identity)),
+            Ok(None) => {
+                warn!(%source, "ignoring unknown credential");
+                self.strike(&source, Strike::FailedAuth).await?;
+                Ok(None)
+            }
+            Err(e) => {
+                warn!(error = %e, "failed to validate credential");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Carries out a command from a remote source, recording any rejection
+    /// in the door attributes so Home Assistant can show why.
+    async fn execute(&mut self, command: Command, source: Source, identity: Option<&Identity>) -> Result<(), Error> {
+        let result = match self.admit(source) {
+            Ok(()) => self.dispatch(command, identity, source).await,
+            Err(e) => Err(e),
+        };
+        let outcome = match &result {
+            Ok(()) => json!({ "command": command.to_string(), "result": "accepted" }),
+            Err(Error::CommandRejected { reason }) => {
+                json!({ "command": command.to_string(), "result": "rejected", "reason": reason.code() })
+            }
+            Err(e) => json!({ "command": command.to_string(), "result": "error", "error": e.to_string() }),
+        };
+        self.record_history("command", Some(&source.to_string()), identity.map(|i| i.id.as_str()), outcome);
+        self.snapshot.send_modify(|s| s.metrics.command(source, &result));
+        if let Err(Error::CommandRejected { reason }) = &result {
+            self.record_rejection(command.to_string(), *reason, source).await?;
+            if let (Some(identity), false) = (identity, *reason == RejectReason::Quarantined) {
+                self.strike(&quarantine::identity_key(&identity.id), Strike::Rejected).await?;
+            }
+        }
+        result
+    }
+
+    /// Applies the rate limit to commands from MQTT.
+    fn admit(&mut self, source: Source) -> Result<(), Error> {
+        match source {
+            Source::Mqtt => self.rate_limiter.admit(&self.config.rate_limit),
+            Source::Http | Source::Socket | Source::Button | Source::Keypad | Source::Rf => Ok(()),
+        }
+    }
+
+    async fn record_rejection(&mut self, command: String, reason: RejectReason, source: Source) -> Result<(), Error> {
+        if reason == RejectReason::VacationLock {
+            self.publish_blocked(&command, source).await?;
+        }
+        let mut rejection = json!({
+            "command": command,
+            "reason": reason.code(),
+            "source": source,
+            "timestamp": self.clock.now(),
+        });
+        self.notify_webhooks(WebhookEvent::Rejected, &rejection);
+        if self.config.privacy.enabled {
+            privacy::redact(&mut rejection);
+        }
+        self.last_rejection = Some(rejection);
+        self.publish_attributes().await
+    }
+
+    /// Moves to a preset position picked over MQTT, recording rejections
+    /// like any other command.
+    async fn select_preset(&mut self, name: &str) -> Result<(), Error> {
+        let result = match self.admit(Source::Mqtt) {
+            Ok(()) => self.move_to_preset(name).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(Error::CommandRejected { reason }) => {
+                warn!(preset = %name, code = reason.code(), "ignoring preset command");
+                self.record_rejection(format!("PRESET {}", name), reason, Source::Mqtt).await
+            }
+            result => result,
+        }
+    }
+
+    async fn move_to_preset(&mut self, name: &str) -> Result<(), Error> {
+        let preset = self.check_preset(name)?;
+        self.evaluate(Command::Open, Source::Mqtt, None)?;
+        if self.position.position() != Position::Closed {
+            return Err(Error::rejected(RejectReason::NotClosed));
+        }
+        self.start_preset(preset, Trigger::Mqtt).await
+    }
+
+    /// Handles Home Assistant's position slider: the ends open or close the
+    /// door fully, anything in between picks the nearest preset.
+    async fn handle_set_position(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let percent = match std::str::from_utf8(payload).ok().and_then(|s| s.trim().parse::<u8>().ok()) {
+            Some(p) if p <= 100 => p,
+            _ => {
+                warn!(topic = %self.topics.set_position, "invalid payload on position topic");
+                return Ok(());
+            }
+        };
+        let command = match percent {
+            0 => Command::Close,
+            100 => Command::Open,
+            _ => {
+                let nearest = self.config.presets.as_ref()
+                    .and_then(|c| Presets::nearest(c, percent, self.config.motor.travel()))
+                    .map(|p| p.name.clone());
+                return match nearest {
+                    Some(name) => self.select_preset(&name).await,
+                    None => {
+                        warn!(percent, "no preset positions configured for partial opening");
+                        let reason = RejectReason::UnknownPreset;
+                        self.record_rejection(format!("POSITION {}", percent), reason, Source::Mqtt).await
+                    }
+                };
+            }
+        };
+        match self.execute(command, Source::Mqtt, None).await {
+            Err(e @ Error::CommandRejected { .. }) => {
+                warn!(%command, code = e.code(), "ignoring position command: {}", e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Looks up a preset and checks the wind allows it.
+    fn check_preset(&self, name: &str) -> Result<PresetConfig, Error> {
+        let config = self.config.presets.as_ref().ok_or(Error::rejected(RejectReason::UnknownPreset))?;
+        self.presets.check(config, name).cloned()
+    }
+
+    /// Opens the door from closed and schedules the press that stops it at
+    /// the preset position.
+    async fn start_preset(&mut self, preset: PresetConfig, cause: Trigger) -> Result<(), Error> {
+        info!(preset = %preset.name, open_secs = preset.open_secs, "moving to preset position");
+        self.trigger(cause, None).await?;
+        self.presets.start(&preset);
+        self.publish_presets().await
+    }
+
+    async fn preset_reached(&mut self) -> Result<(), Error> {
+        self.presets.reached();
+        let moving = match self.position.position() {
+            Position::Opening => true,
+            Position::Open => !self.hw.has_open_sensor(),
+            _ => false,
+        };
+        if !moving {
+            warn!("door was not opening when the preset position was due, leaving it");
+            self.presets.clear();
+            return self.publish_presets().await;
+        }
+        self.trigger(self.press_trigger.unwrap_or(Trigger::External), None).await
+    }
+
+    async fn handle_wind(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let config = match &self.config.presets {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let speed = match self.presets.wind_reading(payload) {
+            Some(s) => s,
+            None => {
+                warn!("invalid payload on wind topic");
+                return Ok(());
+            }
+        };
+        debug!(speed, "wind reading");
+        if self.presets.wind_exceeded(config) {
+            warn!(speed, "wind above the active preset's limit, closing");
+            self.start_automated_close(CloseReason::Wind).await?;
+        }
+        self.publish_presets().await
+    }
+
+    async fn handle_link(&mut self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let fired = self.links.message(&self.config.links, topic, payload);
+        for (link, value, action) in fired {
+            info!(%link, %value, %action, "link rule fired");
+            match action {
+                LinkAction::Close => self.start_automated_close(CloseReason::Link).await?,
+                LinkAction::Alert => {
+                    let details = json!({ "link": link, "value": value });
+                    self.publish_alert(&self.topics.notifications, "link", details).await?;
+                }
+                LinkAction::Lock => self.set_vacation_lock(true, &format!("link:{}", link)).await?,
+                LinkAction::Unlock => self.set_vacation_lock(false, &format!("link:{}", link)).await?,
+            }
+        }
+        self.publish_links().await
+    }
+
+    /// Publishes link health, logging when it changes.
+    async fn publish_links(&mut self) -> Result<(), Error> {
+        if self.config.links.is_empty() {
+            return Ok(());
+        }
+        let report = self.links.report(&self.config.links);
+        if report.problem != self.links_problem {
+            self.links_problem = report.problem;
+            let unhealthy: Vec<&str> = report.links.iter()
+                .filter(|(_, l)| !l.healthy)
+                .map(|(name, _)| name.as_str())
+                .collect();
+            if report.problem {
+                warn!(?unhealthy, "linked controller unhealthy");
+                let detail = format!("unhealthy: {}", unhealthy.join(", "));
+                self.set_subsystem(Subsystem::Links, SubsystemStatus::degraded(detail));
+            } else {
+                info!("linked controllers healthy again");
+                self.set_subsystem(Subsystem::Links, SubsystemStatus::ok());
+            }
+        }
+        let payload = serde_json::to_value(&report).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.links, true, &payload).await
+    }
+
+    /// Takes the retained record left by the previous instance, then clears
+    /// it so that if this one crashes, no record is left behind.
+    async fn handle_last_shutdown(&mut self, payload: &[u8]) -> Result<(), Error> {
+        if payload.is_empty() {
+            return Ok(());
+        }
+        match serde_json::from_slice::<ShutdownRecord>(payload) {
+            Ok(record) => {
+                info!(reason = %record.reason, detail = record.detail.as_deref(), at = %record.timestamp,
+                    uptime_secs = record.uptime_secs, "previous run shut down cleanly");
+                self.previous_shutdown = Some(record);
+            }
+            Err(e) => warn!(error = %e, "ignoring unreadable shutdown record"),
+        }
+        self.publish(&self.topics.last_shutdown, true, "").await?;
+        self.publish_attributes().await
+    }
+
+    /// Which of `gpio.extra`'s outputs `topic` switches, if any.
+    fn extra_output_index(&self, topic: &str) -> Option<usize> {
+        self.config.gpio.extra.outputs.iter().position(|o| self.topics.io_set(&o.id) == topic)
+    }
+
+    async fn handle_extra_output(&mut self, index: usize, payload: &[u8]) -> Result<(), Error> {
+        let id = self.config.gpio.extra.outputs[index].id.clone();
+        let active = match payload {
+            b"ON" => true,
+            b"OFF" => false,
+            _ => {
+                warn!(topic = %self.topics.io_set(&id), "invalid payload on extra output topic");
+                return Ok(());
+            }
+        };
+        match self.hw.set_extra(index, active) {
+            Ok(()) => {
+                info!(%id, active, "extra output switched");
+                self.extra_outputs[index] = active;
+            }
+            Err(e) => warn!(%id, error = %e, source = %e.source, "failed to switch extra output"),
+        }
+        // Published either way, so Home Assistant's switch snaps back after
+        // a failure.
+        self.publish(&self.topics.io(&id), true, on_off(self.extra_outputs[index])).await
+    }
+
+    async fn extra_input_changed(&mut self, index: usize, active: bool) -> Result<(), Error> {
+        if self.extra_inputs[index] == Some(active) {
+            return Ok(());
+        }
+        self.extra_inputs[index] = Some(active);
+        let id = &self.config.gpio.extra.inputs[index].id;
+        info!(%id, active, "extra input changed");
+        self.publish(&self.topics.io(id), true, on_off(active)).await
+    }
+
+    /// Publishes the state of every extra output and every extra input read
+    /// so far.
+    async fn publish_extra_io(&self) -> Result<(), Error> {
+        for (output, active) in self.config.gpio.extra.outputs.iter().zip(&self.extra_outputs) {
+            self.publish(&self.topics.io(&output.id), true, on_off(*active)).await?;
+        }
+        for (input, active) in self.config.gpio.extra.inputs.iter().zip(&self.extra_inputs) {
+            if let Some(active) = active {
+                self.publish(&self.topics.io(&input.id), true, on_off(*active)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_vacation_lock(&mut self, payload: &[u8]) -> Result<(), Error> {
+        match payload {
+            b"ON" => self.set_vacation_lock(true, "mqtt").await,
+            b"OFF" => self.set_vacation_lock(false, "mqtt").await,
+            _ => {
+                warn!(topic = %self.topics.vacation_lock_set, "invalid payload on vacation lock topic");
+                Ok(())
+            }
+        }
+    }
+
+    async fn set_vacation_lock(&mut self, locked: bool, by: &str) -> Result<(), Error> {
+        match self.vacation.set(locked, by) {
+            Ok(false) => return Ok(()),
+            Ok(true) => (),
+            Err(e) => {
+                warn!(dir = %self.config.storage.dir.display(), error = %e, "failed to save vacation lock state");
+                let detail = format!("failed to save vacation lock state: {}", e);
+                self.set_subsystem(Subsystem::Storage, SubsystemStatus::degraded(detail));
+            }
+        }
+        if locked {
+            info!(by, "vacation lock turned on");
+        } else {
+            info!(by, "vacation lock turned off");
+        }
+        let event = if locked { "locked" } else { "unlocked" };
+        self.publish_event(event, json!({ "by": by })).await?;
+        self.publish_vacation_lock().await?;
+        self.publish_attributes().await
+    }
+
+    async fn handle_maintenance(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let message = match maintenance::parse_message(payload) {
+            Ok(m) => m,
+            Err(e) => {
+                warn!(topic = %self.topics.maintenance_set, error = %e, "invalid payload on maintenance topic");
+                return Ok(());
+            }
+        };
+        let identity = match &message.credential {
+            Some(token) => self.identify(token).await,
+            None => Ok(None),
+        };
+        let result = match identity {
+            Ok(identity) => {
+                let by = match &identity {
+                    Some(identity) => format!("mqtt:{}", identity.id),
+                    None => "mqtt".to_owned(),
+                };
+                self.set_maintenance(message.active, &by, message.reason, identity.as_ref()).await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Err(e @ Error::CommandRejected { .. }) => {
+                warn!(active = message.active, code = e.code(), "ignoring maintenance request: {}", e);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Turns maintenance mode on for anyone, or off for an admin. Turning
+    /// it on also drops a pending automated close and any health check.
+    async fn set_maintenance(&mut self, active: bool, by: &str, reason: Option<String>, identity: Option<&Identity>) -> Result<(), Error> {
+        if !active && self.maintenance.is_active() && !identity.is_some_and(|i| i.admin) {
+            return Err(Error::rejected(RejectReason::AdminRequired));
+        }
+        match self.maintenance.set(active, by, reason) {
+            Ok(false) => return Ok(()),
+            Ok(true) => (),
+            Err(e) => {
+                warn!(dir = %self.config.storage.dir.display(), error = %e, "failed to save maintenance state");
+                let detail = format!("failed to save maintenance state: {}", e);
+                self.set_subsystem(Subsystem::Storage, SubsystemStatus::degraded(detail));
+            }
+        }
+        let reason = self.maintenance.state().reason.clone();
+        if active {
+            warn!(by, reason = reason.as_deref(), "maintenance mode turned on");
+            if let Some(countdown) = self.countdown.take() {
+                info!(reason = %countdown.reason, "automated close cancelled for maintenance");
+                self.publish_countdown().await?;
+            }
+            self.drop_held_press("maintenance");
+            self.abort_health_check().await?;
+        } else {
+            info!(by, "maintenance mode turned off");
+        }
+        let event = if active { "maintenance_started" } else { "maintenance_ended" };
+        self.publish_event(event, json!({ "by": by, "reason": reason })).await?;
+        self.publish_maintenance().await?;
+        self.publish_attributes().await
+    }
+
+    /// Publishes the maintenance state and switches the sign to match.
+    async fn publish_maintenance(&self) -> Result<(), Error> {
+        let active = self.maintenance.is_active();
+        if let Err(e) = self.hw.set_maintenance(active) {
+            warn!(error = %e, source = %e.source, "failed to switch maintenance output");
+        }
+        self.snapshot.send_modify(|s| s.maintenance = active);
+        let payload = serde_json::to_value(self.maintenance.state()).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.maintenance, true, &payload).await
+    }
+
+    async fn publish_vacation_lock(&self) -> Result<(), Error> {
+        self.snapshot.send_modify(|s| s.vacation_lock = self.vacation.is_locked());
+        let payload = serde_json::to_value(self.vacation.state()).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.vacation_lock, true, &payload).await
+    }
+
+    /// Counts a strike against a command source, quarantining it and
+    /// alerting once it has too many.
+    async fn strike(&mut self, source: &str, strike: Strike) -> Result<(), Error> {
+        let config = match self.config.quarantine {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let entry = match self.quarantine.strike(source, strike, &config, self.clock.now()) {
+            Some(e) => e,
+            None => return Ok(()),
+        };
+        warn!(source, reason = %strike, until = %entry.until, "quarantining command source");
+        let details = serde_json::to_value(&entry).map_err(BrokerError::from)?;
+        self.publish_event("quarantined", details.clone()).await?;
+        self.publish_alert(&self.topics.notifications, "quarantine", details).await?;
+        self.publish_quarantine().await
+    }
+
+    /// Releases a quarantine asked for on the release topic. Only an admin
+    /// credential may, as otherwise a quarantined sender could lift its own
+    /// quarantine; anything else is refused and recorded on the events topic.
+    async fn handle_quarantine_release(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let message = match quarantine::parse_release_message(payload) {
+            Ok(m) => m,
+            Err(e) => {
+                warn!(topic = %self.topics.quarantine_release, error = %e, "invalid payload on quarantine release topic");
+                return Ok(());
+            }
+        };
+        let identity = match &message.credential {
+            Some(token) => self.identify(token).await,
+            None => Ok(None),
+        };
+        let (reason, by) = match identity {
+            Ok(Some(identity)) if identity.admin => {
+                self.release_quarantine(&message.source, &format!("mqtt:{}", identity.id)).await?;
+                return Ok(());
+            }
+            Ok(Some(identity)) => {
+                self.strike(&quarantine::identity_key(&identity.id), Strike::Rejected).await?;
+                (RejectReason::AdminRequired, format!("mqtt:{}", identity.id))
+            }
+            Ok(None) => (RejectReason::AdminRequired, "mqtt".to_owned()),
+            Err(Error::CommandRejected { reason }) => (reason, "mqtt".to_owned()),
+            Err(e) => return Err(e),
+        };
+        warn!(source = %message.source, by, code = reason.code(), "refused to release quarantine");
+        self.publish_event("release_refused", json!({ "source": message.source, "by": by, "reason": reason.code() })).await
+    }
+
+    /// Lifts the quarantine on `source`, or on every source for `all`,
+    /// returning the sources released.
+    async fn release_quarantine(&mut self, source: &str, by: &str) -> Result<Vec<String>, Error> {
+        let released = match source {
+            "all" => self.quarantine.release_all(),
+            source if self.quarantine.release(source) => vec![source.to_owned()],
+            source => {
+                warn!(source, by, "no such quarantined source to release");
+                Vec::new()
+            }
+        };
+        for source in &released {
+            info!(source, by, "released command source from quarantine");
+            self.publish_event("released", json!({ "source": source, "by": by })).await?;
+        }
+        if !released.is_empty() {
+            self.publish_quarantine().await?;
+        }
+        Ok(released)
+    }
+
+    async fn expire_quarantine(&mut self) -> Result<(), Error> {
+        for source in self.quarantine.expire() {
+            info!(%source, "command source quarantine ran out");
+            self.publish_event("released", json!({ "source": source, "by": "cooldown" })).await?;
+        }
+        self.publish_quarantine().await
+    }
+
+    async fn publish_quarantine(&self) -> Result<(), Error> {
+        let list = self.quarantine.list();
+        let payload = serde_json::to_value(&list).map_err(BrokerError::from)?;
+        self.snapshot.send_modify(|s| s.quarantine = list);
+        self.publish_json(&self.topics.quarantine, true, &payload).await
+    }
+
+    /// Reads the safety beam. One that can't be read counts as broken, so
+    /// closes stay blocked until it can be.
+    async fn read_obstruction(&self) -> bool {
+        match self.hw.obstructed().await {
+            Ok(obstructed) => obstructed.unwrap_or(false),
+            Err(e) => {
+                warn!(error = %e, source = %e.source, "safety beam unreadable, treating as obstructed");
+                true
+            }
+        }
+    }
+
+    /// Follows a change on the safety beam, warning on the events topic
+    /// when it is broken.
+    async fn update_obstruction(&mut self) -> Result<(), Error> {
+        let obstructed = self.read_obstruction().await;
+        if obstructed == self.obstructed {
+            return Ok(());
+        }
+        self.obstructed = obstructed;
+        match obstructed {
+            true => warn!("doorway obstructed, blocking close commands"),
+            false => info!("doorway clear"),
+        }
+        if obstructed && self.held.is_some_and(|h| h.press.closes) {
+            self.drop_held_press("obstructed");
+        }
+        self.publish_obstruction().await?;
+        self.publish_attributes().await?;
+        self.publish_event("obstruction", json!({ "active": obstructed })).await
+    }
+
+    async fn publish_obstruction(&self) -> Result<(), Error> {
+        if !self.hw.has_obstruction_sensor() {
+            return Ok(());
+        }
+        let payload = on_off(self.obstructed);
+        self.publish(&self.topics.obstruction, true, payload).await
+    }
+
+    /// Reports an attempt to move the door while the vacation lock is on.
+    async fn publish_blocked(&self, command: &str, source: Source) -> Result<(), Error> {
+        let details = json!({ "command": command, "source": source });
+        self.publish_event("blocked", details).await
+    }
+
+    /// Adds an entry to the history, leaving out the identity and the time
+    /// of day in privacy mode.
+    fn record_history(&self, event: &str, source: Option<&str>, identity: Option<&str>, mut detail: Value) {
+        let history = match &self.history {
+            Some(h) => h,
+            None => return,
+        };
+        let mut timestamp = self.clock.now();
+        let mut identity = identity.map(str::to_owned);
+        if self.config.privacy.enabled {
+            privacy::redact(&mut detail);
+            timestamp = privacy::start_of_day(timestamp);
+            identity = None;
+        }
+        history.record(HistoryEntry {
+            id: None,
+            door: mqtt::DOOR_ID.to_owned(),
+            event: event.to_owned(),
+            source: source.map(str::to_owned),
+            identity,
+            detail,
+            timestamp,
+        });
+    }
+
+    /// Passes a poke on to the virtual door.
+    fn handle_simulate(&self, payload: &[u8]) {
+        let door = match self.hw.simulator() {
+            Some(door) => door,
+            None => return,
+        };
+        match String::from_utf8_lossy(payload).parse() {
+            Ok(poke) => door.poke(poke),
+            Err(e) => warn!(error = %e, "ignoring simulator poke"),
+        }
+    }
+
+    /// Runs a history query from MQTT off the loop, answering on the result
+    /// topic once it is done. An empty payload asks for the latest entries.
+    fn handle_history(&self, payload: &[u8]) {
+        let history = match &self.history {
+            Some(h) => h.clone(),
+            None => return,
+        };
+        let query = match parse_history_query(payload) {
+            Ok(q) => q,
+            Err(e) => {
+                warn!(topic = %self.topics.history, error = %e, "invalid payload on history topic");
+                return;
+            }
+        };
+        let replies = self.history_replies.clone();
+        tokio::spawn(async move {
+            let request_id = query.request_id.clone();
+            let reply = match history.query(query).await {
+                Ok(entries) => json!({ "request_id": request_id, "entries": entries }),
+                Err(e) => json!({ "request_id": request_id, "error": Failure::from(&Error::from(e)) }),
+            };
+            let _ = replies.send(reply).await;
+        });
+    }
+
+    /// Starts re-publishing the entries matching a query, for consumers
+    /// that missed them. One replay runs at a time.
+    fn handle_history_replay(&mut self, payload: &[u8]) {
+        let history = match &self.history {
+            Some(h) => h.clone(),
+            None => return,
+        };
+        if self.replay.as_ref().is_some_and(|r| !r.is_finished()) {
+            warn!("a history replay is already running, ignoring request");
+            return;
+        }
+        let query = match parse_history_query(payload) {
+            Ok(q) => q,
+            Err(e) => {
+                warn!(topic = %self.topics.history_replay_request, error = %e, "invalid payload on history replay topic");
+                return;
+            }
+        };
+        let topic = self.topics.history_replay.clone();
+        let qos = self.qos(&topic);
+        self.replay = Some(tokio::spawn(replay_history(history, query, self.client.clone(), topic, qos)));
+    }
+
+    async fn publish_event(&self, event: &str, details: Value) -> Result<(), Error> {
+        let mut payload = json!({
+            "door": mqtt::DOOR_ID,
+            "event": event,
+            "timestamp": self.clock.now(),
+        });
+        config::merge(&mut payload, &details);
+        let source = details.get("source").and_then(Value::as_str);
+        let identity = details.get("code_id").and_then(Value::as_str);
+        self.record_history(event, source, identity, details.clone());
+        if self.config.privacy.enabled {
+            privacy::redact(&mut payload);
+        }
+        if let Some(audit) = &self.audit {
+            audit.send(payload.clone());
+        }
+        let _ = self.events.send(payload.clone());
+        self.publish_json(&self.topics.events, false, &payload).await
+    }
+
+    /// POSTs `event` to the webhooks subscribed to it, in the same shape as
+    /// the events topic.
+    fn notify_webhooks(&self, event: WebhookEvent, details: &Value) {
+        let webhooks = match &self.webhooks {
+            Some(w) => w,
+            None => return,
+        };
+        if !self.options.get().webhooks_enabled(self.clock.local_now()) {
+            debug!(%event, "webhooks off or quiet hours, not notifying");
+            return;
+        }
+        let mut payload = json!({
+            "door": mqtt::DOOR_ID,
+            "event": event.to_string(),
+            "timestamp": self.clock.now(),
+        });
+        config::merge(&mut payload, details);
+        if self.config.privacy.enabled {
+            privacy::redact(&mut payload);
+        }
+        webhooks.send(event, &payload);
+    }
+
+    /// Where runtime options are read from, if they are accepted.
+    fn options_topic(&self) -> Option<&String> {
+        if !self.config.mqtt.remote_options {
+            return None;
+        }
+        Some(self.config.mqtt.options_topic.as_ref().unwrap_or(&self.topics.options))
+    }
+
+    async fn handle_options(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let (options, raw) = match RuntimeOptions::parse(payload) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!(error = %e, "rejecting runtime options, keeping the current ones");
+                return self.publish_event("options_rejected", json!({ "error": e.to_string() })).await;
+            }
+        };
+        match self.options.set(options, raw) {
+            Ok(false) => return Ok(()),
+            Ok(true) => (),
+            Err(e) => {
+                warn!(dir = %self.config.storage.dir.display(), error = %e, "failed to save runtime options");
+                let detail = format!("failed to save runtime options: {}", e);
+                self.set_subsystem(Subsystem::Storage, SubsystemStatus::degraded(detail));
+            }
+        }
+        info!(options = %self.options.raw(), "runtime options changed");
+        self.publish_event("options_changed", json!({ "options": self.options.raw() })).await?;
+        self.publish_attributes().await
+    }
+
+    async fn handle_presence(&mut self, topic: &str, payload: &[u8]) -> Result<(), Error> {
+        let config = match &self.config.presence {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        if !self.presence.report(config, topic, payload, Instant::now()) {
+            return Ok(());
+        }
+        if self.presence.anyone_home(config) {
+            info!(topic, "someone is home, remote opens allowed");
+        } else {
+            info!(topic, grace_secs = config.grace_secs, "nobody is home, remote opens refused after the grace period");
+        }
+        self.publish_attributes().await
+    }
+
+    fn wind_topic(&self) -> Option<&str> {
+        self.config.presets.as_ref()?.wind_topic.as_deref()
+    }
+
+    async fn publish_presets(&self) -> Result<(), Error> {
+        let config = match &self.config.presets {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let report = serde_json::to_value(self.presets.report(config)).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.preset, true, &report).await
+    }
+
+    /// Rejects commands that don't make sense for the current door state or
+    /// arrive during a lockout window without an admin identity.
+    async fn dispatch(&mut self, command: Command, identity: Option<&Identity>, source: Source) -> Result<(), Error> {
+        self.evaluate(command, source, identity)?;
+        let cause = Trigger::from(source);
+        if let (Some(lockout), Some(id)) = (self.blocking_lockout(command), identity) {
+            info!(%command, identity = %id.id, reason = %lockout.reason, "admin override of lockout");
+        }
+
+        if self.health.is_some() {
+            return self.abort_health_check().await;
+        }
+        if command == Command::Cancel {
+            if let Some(countdown) = self.countdown.take() {
+                info!(reason = %countdown.reason, remaining = countdown.remaining_secs(), "automated close cancelled");
+                return self.publish_countdown().await;
+            }
+            if self.drop_held_press("cancelled") {
+                return Ok(());
+            }
+        }
+
+        info!(%command, identity = identity.map(|i| i.id.as_str()), "received command");
+        if command == Command::Close && self.config.automated_close.remote_close_warning {
+            let reason = match cause {
+                Trigger::Mqtt => Some(CloseReason::Mqtt),
+                Trigger::Http => Some(CloseReason::Http),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                return self.start_countdown(reason, self.config.automated_close.warning().length).await;
+            }
+        }
+        if command == Command::Vent {
+            let preset = self.check_preset(presets::VENT)?;
+            return self.start_preset(preset, cause).await;
+        }
+        if command == Command::HealthCheck {
+            let baseline = self.motor.baseline_close();
+            info!(baseline_secs = baseline.as_secs_f64(), "starting health check");
+            self.health = Some(HealthCheck::start(self.config.health_check, self.config.motor.travel(), baseline));
+        }
+        self.actuate(cause, Some(command)).await
+    }
+
+    /// Applies every rule that could block `command` without acting on it.
+    /// `dispatch` relies on only `Cancel` passing while a health check runs.
+    /// The vacation lock has no admin override; `Cancel` still gets through
+    /// since it can only stop the door.
+    fn evaluate(&self, command: Command, source: Source, identity: Option<&Identity>) -> Result<(), Error> {
+        if let (Some(identity), false) = (identity, command == Command::Cancel) {
+            if self.quarantine.is_quarantined(&quarantine::identity_key(&identity.id)) {
+                return Err(Error::rejected(RejectReason::Quarantined));
+            }
+        }
+        let guards = Guards {
+            maintenance: self.maintenance.is_active(),
+            vacation_lock: self.vacation.is_locked(),
+            locked_out: self.blocking_lockout(command).is_some() && !identity.map(|i| i.admin).unwrap_or(false),
+            nobody_home: source.is_remote() && self.nobody_home(),
+            health_check: self.health.is_some(),
+            obstructed: self.obstructed,
+            // A close held for its warning is as good as counting down.
+            countdown: self.countdown.is_some() || self.held.is_some(),
+            cooldown: self.cooldown_remaining().is_some(),
+            vent: match command {
+                Command::Vent => self.check_preset(presets::VENT).map(drop),
+                _ => Ok(()),
+            },
+        };
+        debug!(%command, position = %self.position.position(), "evaluating command");
+        machine::evaluate(command, &self.position, guards)
+    }
+
+    /// Whether the presence lock is configured and holding.
+    fn nobody_home(&self) -> bool {
+        self.config.presence.as_ref().is_some_and(|p| !self.presence.allows_remote_open(p, Instant::now()))
+    }
+
+    fn cooldown_remaining(&self) -> Option<Duration> {
+        let until = self.last_press? + self.config.rate_limit.relay_cooldown();
+        until.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())
+    }
+
+    /// The lockout window `command` falls under, if any. `Cancel` only ever
+    /// stops the door, so it is never locked out.
+    fn blocking_lockout(&self, command: Command) -> Option<ActiveLockout> {
+        match command {
+            Command::Cancel => None,
+            _ => self.config.lockout.active_at(self.clock.local_now()),
+        }
+    }
+
+    /// Dry run of `command` for the query topic and endpoint.
+    fn decide(&self, command: Command, source: Source, identity: Option<&Identity>) -> Result<Decision, Error> {
+        let lockout = self.blocking_lockout(command);
+        let (allowed, blocked_by, detail) = match self.evaluate(command, source, identity) {
+            Ok(()) => (true, None, None),
+            Err(Error::CommandRejected { reason }) => {
+                let detail = match reason {
+                    RejectReason::Lockout => lockout.as_ref().map(|l| l.reason.clone()),
+                    RejectReason::Cooldown => self.cooldown_remaining()
+                        .map(|d| format!("relay cooldown, {:.1}s left", d.as_secs_f64())),
+                    RejectReason::Maintenance => self.maintenance.state().reason.clone(),
+                    RejectReason::Quarantined => identity
+                        .and_then(|i| self.quarantine.get(&quarantine::identity_key(&i.id)))
+                        .map(|q| format!("quarantined until {}", q.until)),
+                    _ => None,
+                };
+                (false, Some(reason.code()), detail)
+            }
+            Err(e) => return Err(e),
+        };
+        Ok(Decision {
+            command: command.to_string(),
+            allowed,
+            blocked_by,
+            detail,
+            lockout_override: allowed && lockout.is_some(),
+        })
+    }
+
+    async fn handle_query(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let (command, credential) = match parse_command_message(payload) {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(topic = %self.topics.query, error = %e, "invalid payload on query topic");
+                return Ok(());
+            }
+        };
+        let identity = match credential {
+            Some(token) => self.identify(&token).await,
+            None => Ok(None),
+        };
+        let decision = match identity {
+            Ok(identity) => self.decide(command, Source::Mqtt, identity.as_ref())?,
+            Err(Error::CommandRejected { reason }) => Decision {
+                command: command.to_string(),
+                allowed: false,
+                blocked_by: Some(reason.code()),
+                detail: None,
+                lockout_override: false,
+            },
+            Err(e) => return Err(e),
+        };
+        debug!(%command, allowed = decision.allowed, blocked_by = decision.blocked_by, "answered command query");
+        let payload = serde_json::to_value(&decision).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.query_result, false, &payload).await
+    }
+
+    fn start_acl_probe(&mut self) -> Result<(), Error> {
+        let probe = AclProbe::start(self.topics.all());
+        let sent = probe.probe_topics().try_for_each(|topic| {
+            self.client.try_subscribe(topic, QoS::AtLeastOnce)?;
+            self.client.try_publish(topic, QoS::AtLeastOnce, false, probe.payload().into(), None)
+        });
+        match sent {
+            Ok(()) => (),
+            // Only a self-test, so not worth holding back for.
+            Err(BrokerError::QueueFull) => {
+                debug!("request queue full, skipping broker acl self-test");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        debug!("started broker acl self-test");
+        self.acl = Some(probe);
+        Ok(())
+    }
+
+    async fn finish_acl_probe(&mut self) -> Result<(), Error> {
+        let probe = match self.acl.take() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let leftover: Vec<String> = probe.probe_topics().map(str::to_owned).collect();
+        let report = probe.report();
+        if report.problem {
+            warn!(gaps = ?report.gaps, subscribe_refused = report.subscribe_refused, "broker acl self-test found gaps");
+            self.set_subsystem(Subsystem::Acl, SubsystemStatus::degraded("broker acl self-test found gaps"));
+        } else {
+            info!("broker acl self-test passed");
+            self.set_subsystem(Subsystem::Acl, SubsystemStatus::ok());
+        }
+        for topic in leftover {
+            self.client.try_unsubscribe(&topic)?;
+        }
+        let payload = serde_json::to_value(&report).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.acl, true, &payload).await
+    }
+
+    async fn health_step(&mut self, step: Step) -> Result<(), Error> {
+        match step {
+            Step::Wait => Ok(()),
+            Step::Actuate => self.actuate(Trigger::HealthCheck, None).await,
+            Step::Done(report) => {
+                self.health = None;
+                self.publish_health(&report).await
+            }
+        }
+    }
+
+    async fn abort_health_check(&mut self) -> Result<(), Error> {
+        match self.health.take() {
+            Some(check) => self.publish_health(&check.aborted()).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn publish_health(&self, report: &HealthReport) -> Result<(), Error> {
+        info!(result = %report.result, close_secs = report.close_secs, delta_pct = report.delta_pct,
+            detail = report.detail.as_deref(), "health check finished");
+        let payload = serde_json::to_value(report).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.health, true, &payload).await
+    }
+
+    /// Starts the warning countdown for an automated close. The relay is only
+    /// triggered once the countdown runs out without being cancelled.
+    pub async fn start_automated_close(&mut self, reason: CloseReason) -> Result<(), Error> {
+        self.start_countdown(reason, self.config.automated_close.countdown()).await
+    }
+
+    async fn start_countdown(&mut self, reason: CloseReason, length: Duration) -> Result<(), Error> {
+        if self.countdown.is_some() {
+            return Ok(());
+        }
+        if !self.can_close() {
+            return Ok(());
+        }
+        let countdown = Countdown::start(reason, length);
+        info!(%reason, remaining = countdown.remaining_secs(), "close countdown started");
+        self.countdown = Some(countdown);
+        self.publish_countdown().await
+    }
+
+    async fn track_open(&mut self, status: Status) -> Result<(), Error> {
+        match status {
+            Status::Open if self.open_since.is_none() => {
+                let now = Instant::now();
+                self.open_since = Some(now);
+                if let Some(config) = &self.config.left_open_alert {
+                    self.left_open.door_opened(now, config);
+                }
+            }
+            Status::Open => (),
+            Status::Closed => {
+                self.open_since = None;
+                if self.left_open.door_closed() > 0 {
+                    self.publish_left_open(json!({ "state": "resolved" })).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn left_open_deadline(&self) -> Option<Instant> {
+        self.config.left_open_alert.as_ref()?;
+        self.left_open.deadline()
+    }
+
+    async fn send_left_open_alert(&mut self) -> Result<(), Error> {
+        let (config, since) = match (&self.config.left_open_alert, self.open_since) {
+            (Some(c), Some(s)) => (c, s),
+            _ => return Ok(()),
+        };
+        let count = self.left_open.fire(config);
+        let open_for = since.elapsed();
+        let opened_at = self.clock.now() - chrono::Duration::from_std(open_for).unwrap_or_else(|_| chrono::Duration::zero());
+        warn!(open_secs = open_for.as_secs(), count, "door left open");
+        let details = json!({
+            "state": "open",
+            "open_secs": open_for.as_secs(),
+            "open_since": opened_at,
+            "count": count,
+        });
+        self.notify_webhooks(WebhookEvent::LeftOpen, &details);
+        self.publish_left_open(details).await
+    }
+
+    async fn publish_left_open(&self, details: Value) -> Result<(), Error> {
+        let topic = self.config.left_open_alert.as_ref()
+            .and_then(|c| c.topic.as_deref())
+            .unwrap_or(&self.topics.notifications);
+        self.publish_alert(topic, "left_open", details).await
+    }
+
+    async fn publish_alert(&self, topic: &str, alert: &str, details: Value) -> Result<(), Error> {
+        if !self.options.get().alerts_enabled(self.clock.local_now()) {
+            debug!(alert, "alerts off or quiet hours, not sending");
+            return Ok(());
+        }
+        let mut payload = json!({
+            "door": mqtt::DOOR_ID,
+            "alert": alert,
+            "timestamp": self.clock.now(),
+        });
+        config::merge(&mut payload, &details);
+        let state = self.position.position().to_string();
+        if let Some(message) = self.templates.render(alert, &payload, &state, self.clock.now()) {
+            payload["message"] = message.into();
+        }
+        self.publish_json(topic, false, &payload).await
+    }
+
+    /// Time-based actions that would be lost on a restart.
+    fn pending_actions(&self) -> Vec<ScheduledAction> {
+        let mut actions = Vec::new();
+        if let Some(deadline) = self.auto_close_deadline() {
+            actions.push(ScheduledAction::at(ActionKind::AutoClose, deadline, None, self.clock.now()));
+        }
+        if let Some(countdown) = self.countdown {
+            actions.push(ScheduledAction::at(ActionKind::CloseCountdown, countdown.deadline, Some(countdown.reason), self.clock.now()));
+        }
+        actions
+    }
+
+    fn sync_journal(&mut self) {
+        let status = match self.journal.update(self.pending_actions()) {
+            Ok(()) => SubsystemStatus::ok(),
+            Err(e) => {
+                warn!(dir = %self.config.storage.dir.display(), error = %e, "failed to save scheduled actions");
+                SubsystemStatus::failing(format!("failed to save scheduled actions: {}", e))
+            }
+        };
+        self.set_subsystem(Subsystem::Scheduler, status);
+    }
+
+    /// Saves the door's position once it is at rest. Failures are only
+    /// logged; a restart then guesses as it would without a saved state.
+    fn sync_state(&mut self) {
+        let saved = match self.position.saved() {
+            Some(saved) => saved,
+            None => return,
+        };
+        if let Err(e) = self.state_store.update(saved) {
+            warn!(dir = %self.config.storage.dir.display(), error = %e, "failed to save door state");
+            self.set_subsystem(Subsystem::Storage, SubsystemStatus::degraded(format!("failed to save door state: {}", e)));
+        }
+    }
+
+    /// Resumes the actions journaled by the previous run, applying the
+    /// catch-up policy to those that came due while the daemon was down.
+    async fn catch_up(&mut self, status: Status) -> Result<(), Error> {
+        let actions = self.journal.load();
+        if status == Status::Closed {
+            if !actions.is_empty() {
+                info!(count = actions.len(), "door closed while down, dropping scheduled actions");
+            }
+            return Ok(());
+        }
+        for action in actions {
+            if action.kind == ActionKind::AutoClose && self.auto_close().is_none() {
+                continue;
+            }
+            match action.remaining(self.clock.now()) {
+                Some(remaining) => self.resume_action(&action, remaining).await?,
+                None => self.missed_action(&action).await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn resume_action(&mut self, action: &ScheduledAction, remaining: Duration) -> Result<(), Error> {
+        info!(action = %action.kind, remaining_secs = remaining.as_secs(), "resuming scheduled action");
+        match action.kind {
+            ActionKind::AutoClose => {
+                if let Some(config) = self.auto_close() {
+                    let elapsed = config.after().saturating_sub(remaining);
+                    self.open_since = Instant::now().checked_sub(elapsed).or(self.open_since);
+                }
+                Ok(())
+            }
+            ActionKind::CloseCountdown => {
+                let reason = action.reason.unwrap_or(CloseReason::AutoClose);
+                self.countdown = Some(Countdown { reason, deadline: Instant::now() + remaining });
+                self.publish_countdown().await
+            }
+        }
+    }
+
+    async fn missed_action(&mut self, action: &ScheduledAction) -> Result<(), Error> {
+        let policy = self.config.catch_up.policy(action.kind);
+        warn!(action = %action.kind, due = %action.due, %policy, "scheduled action came due while down");
+        match policy {
+            CatchUp::Execute => {
+                self.start_automated_close(action.reason.unwrap_or(CloseReason::AutoClose)).await
+            }
+            CatchUp::Skip => Ok(()),
+            CatchUp::Alert => {
+                let details = json!({ "action": action.kind, "due": action.due });
+                self.publish_alert(&self.topics.notifications, "missed_action", details).await
+            }
+        }
+    }
+
+    /// The auto-close settings, unless the runtime options turn it off.
+    fn auto_close(&self) -> Option<&AutoCloseConfig> {
+        self.config.auto_close.as_ref().filter(|_| self.options.get().auto_close_enabled())
+    }
+
+    /// When the auto-close countdown should start, if it is enabled and the
+    /// door is open with nothing else in progress.
+    fn auto_close_deadline(&self) -> Option<Instant> {
+        if self.countdown.is_some() || self.health.is_some() {
+            return None;
+        }
+        let after = self.auto_close()?.after();
+        self.open_since.map(|since| since + after)
+    }
+
+    async fn countdown_tick(&mut self) -> Result<(), Error> {
+        let countdown = match self.countdown {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        if !countdown.expired() {
+            return self.publish_countdown().await;
+        }
+
+        self.countdown = None;
+        self.publish_countdown().await?;
+        if self.can_close() {
+            info!(reason = %countdown.reason, "countdown finished, closing door");
+            self.actuate(countdown.reason.into(), None).await?;
+        } else if self.obstructed {
+            warn!(reason = %countdown.reason, "countdown finished but the doorway is obstructed, not closing");
+        }
+        Ok(())
+    }
+
+    /// When the warning output should next be switched: right away if it is
+    /// out of step with the countdown, or at the pattern's next change.
+    fn warning_deadline(&self) -> Option<Instant> {
+        if !self.hw.has_warning() || self.held.is_some() {
+            return None;
+        }
+        let now = Instant::now();
+        let pattern = self.config.automated_close.warning();
+        let left = self.countdown.map(|c| c.deadline.saturating_duration_since(now));
+        let active = left.is_some_and(|left| pattern.active(left));
+        if active != self.warning_on {
+            return Some(now);
+        }
+        left.and_then(|left| pattern.next_change(left)).map(|d| now + d)
+    }
+
+    /// Switches the warning output to follow the countdown's pattern.
+    fn update_warning(&mut self) {
+        let pattern = self.config.automated_close.warning();
+        let active = self.countdown
+            .map(|c| pattern.active(c.deadline.saturating_duration_since(Instant::now())))
+            .unwrap_or(false);
+        if let Err(e) = self.hw.set_warning(active) {
+            warn!(error = %e, source = %e.source, "failed to switch warning output");
+        }
+        self.warning_on = active;
+    }
+
+    /// Whether one press would close the door, for automated closes.
+    fn can_close(&self) -> bool {
+        !self.obstructed && !self.maintenance.is_active() && self.press_closes()
+    }
+
+    /// Whether one press would set the door closing.
+    fn press_closes(&self) -> bool {
+        check_command(Command::Close, self.position.position())
+            .and_then(|()| self.position.check_heading(Command::Close))
+            .is_ok()
+    }
+
+    /// Triggers the relay for anything but a preset move, which leaves the
+    /// preset position behind.
+    async fn actuate(&mut self, cause: Trigger, command: Option<Command>) -> Result<(), Error> {
+        if self.presets.clear() {
+            self.publish_presets().await?;
+        }
+        self.trigger(cause, command).await
+    }
+
+    /// Queues a press, running it straight away if the relay is free.
+    async fn trigger(&mut self, cause: Trigger, command: Option<Command>) -> Result<(), Error> {
+        match self.presses.push(Queued { cause, command }) {
+            Enqueued::Added => (),
+            Enqueued::Coalesced => info!(%cause, ?command, "same command already waiting for the relay, pressing once"),
+            Enqueued::Full => {
+                warn!(%cause, queued = self.presses.len(), "too many presses waiting, not pressing the relay");
+                self.record_history("relay_refused", Some(&cause.to_string()), None, json!({ "reason": "queue_full" }));
+            }
+        }
+        self.run_presses().await
+    }
+
+    /// Runs the queued presses whose turn has come, unless one is held.
+    async fn run_presses(&mut self) -> Result<(), Error> {
+        while self.held.is_none() {
+            match self.presses.pop(self.config.actuation.spacing()) {
+                Some(queued) => self.press(queued.cause).await?,
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a press through the configured [`crate::actuation`] stages.
+    async fn press(&mut self, cause: Trigger) -> Result<(), Error> {
+        let stops = matches!(self.position.position(), Position::Opening | Position::Closing) || self.position.is_moving();
+        let press = Press { cause, stops, visible: self.position.sees_departure(), closes: !stops && self.press_closes() };
+        self.run_stages(press, 0).await
+    }
+
+    /// Runs `press` through the stages from the `from`th on, until one
+    /// refuses or holds it.
+    async fn run_stages(&mut self, press: Press, from: usize) -> Result<(), Error> {
+        let cause = press.cause;
+        for (index, stage) in self.config.actuation.stages.clone().into_iter().enumerate().skip(from) {
+            match self.run_stage(stage, press).await? {
+                Verdict::Proceed => (),
+                Verdict::Refuse(reason) => {
+                    warn!(%cause, %stage, reason, "not pressing the relay");
+                    self.record_history("relay_refused", Some(&cause.to_string()), None, json!({ "stage": stage, "reason": reason }));
+                    return Ok(());
+                }
+                Verdict::Hold(wait) => {
+                    self.held = Some(Held { press, next: index + 1, until: Instant::now() + wait });
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Carries on with the held press once its hold is over, then with any
+    /// presses that queued up behind it.
+    async fn resume_press(&mut self) -> Result<(), Error> {
+        let held = match self.held.take() {
+            Some(h) => h,
+            None => return Ok(()),
+        };
+        self.restore_warning();
+        self.run_stages(held.press, held.next).await?;
+        self.run_presses().await
+    }
+
+    /// Drops a press held for the warning, as CANCEL, maintenance mode or a
+    /// broken safety beam should stop it, returning whether there was one.
+    fn drop_held_press(&mut self, reason: &'static str) -> bool {
+        let held = match self.held.take() {
+            Some(h) => h,
+            None => return false,
+        };
+        let cause = held.press.cause;
+        info!(%cause, reason, "not pressing the relay after the warning");
+        self.record_history("relay_refused", Some(&cause.to_string()), None, json!({ "stage": Stage::Warning, "reason": reason }));
+        self.restore_warning();
+        true
+    }
+
+    async fn run_stage(&mut self, stage: Stage, press: Press) -> Result<Verdict, Error> {
+        match stage {
+            Stage::RateLimit if !press.stops && self.cooldown_remaining().is_some() => return Ok(Verdict::Refuse("cooldown")),
+            Stage::RateLimit => (),
+            // Commands are rejected well before this; it catches anything
+            // automated that doesn't go through them. Someone at the wall
+            // button can see the doorway for themselves.
+            Stage::Interlock if self.maintenance.is_active() => return Ok(Verdict::Refuse("maintenance")),
+            Stage::Interlock if self.obstructed && press.closes && press.cause != Trigger::Button => {
+                return Ok(Verdict::Refuse("obstructed"));
+            }
+            Stage::Interlock => (),
+            Stage::Warning => {
+                // A countdown has warned already, and someone at the wall
+                // button can see the door for themselves.
+                let warned = match press.cause {
+                    Trigger::AutoClose | Trigger::Sweep | Trigger::Schedule | Trigger::Wind | Trigger::Link => true,
+                    Trigger::Mqtt | Trigger::Http => self.config.automated_close.remote_close_warning,
+                    Trigger::Button => true,
+                    _ => false,
+                };
+                if press.closes && !warned && self.hw.has_warning() {
+                    return Ok(self.sound_warning());
+                }
+            }
+            Stage::Pulse => {
+                self.last_press = Some(Instant::now());
+                self.presses.pressed();
+                self.press_trigger = Some(press.cause);
+                self.motor.relay_triggered();
+                self.hw.trigger_relay().await?;
+                let from = self.position.position();
+                if let Some(position) = self.position.relay_triggered() {
+                    self.follow_run(from, position).await?;
+                    info!(%position, "door position changed");
+                    self.publish_state(position).await?;
+                }
+            }
+            Stage::Verify => {
+                // A press while moving stops the door rather than starting a run.
+                let cut_short = if press.stops {
+                    self.commands.stopped()
+                } else {
+                    let timeout = self.config.motor.travel() * 2;
+                    self.commands.pressed(press.cause, press.visible, timeout, self.clock.now())
+                };
+                if let Some(correlation) = cut_short {
+                    self.command_finished(correlation).await?;
+                }
+            }
+        }
+        Ok(Verdict::Proceed)
+    }
+
+    /// Switches the warning output on, holding the press for
+    /// `actuation.warning_ms`. A warning that can't be sounded doesn't hold
+    /// up the press.
+    fn sound_warning(&mut self) -> Verdict {
+        info!(warning_ms = self.config.actuation.warning_ms, "sounding warning before pressing the relay");
+        if let Err(e) = self.hw.set_warning(true) {
+            warn!(error = %e, source = %e.source, "failed to switch warning output");
+            return Verdict::Proceed;
+        }
+        Verdict::Hold(self.config.actuation.warning())
+    }
+
+    /// Leaves the warning output as the countdown had it.
+    fn restore_warning(&mut self) {
+        if let Err(e) = self.hw.set_warning(self.warning_on) {
+            warn!(error = %e, source = %e.source, "failed to switch warning output");
+        }
+    }
+
+    /// Feeds the tracker the closed sensor reading along with the open
+    /// sensor's, publishing the position if it changed.
+    async fn update_position(&mut self, closed: bool) -> Result<(), Error> {
+        let readings = match self.readings(closed).await {
+            Ok(r) => r,
+            Err(e) => return self.sensor_failed(e).await,
+        };
+        self.last_sensor_read = Some(SensorRead::ok(self.clock.now()));
+        let before = (self.position.position(), self.position.zone());
+        let changed = self.position.sensors_changed(&readings);
+        if (self.position.position(), self.position.zone()) != before {
+            self.commands.moved();
+        }
+        if let Some(position) = changed {
+            self.follow_run(before.0, position).await?;
+        }
+        if changed.is_some() && self.position.at_sensed_end() {
+            if let Some(correlation) = self.commands.arrived() {
+                self.command_finished(correlation).await?;
+            }
+        }
+        if self.sensor_recovered() {
+            self.publish_attributes().await?;
+            return self.publish_state(self.position.position()).await;
+        }
+        if let Some(position) = changed {
+            info!(%position, "door position changed");
+            self.publish_state(position).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the closed sensor, marking the state unknown if it can't be.
+    async fn read_status(&mut self) -> Result<Option<Status>, Error> {
+        match self.hw.door_status().await {
+            Ok(status) => {
+                self.last_sensor_read = Some(SensorRead::ok(self.clock.now()));
+                Ok(Some(status))
+            }
+            Err(e) => {
+                self.sensor_failed(e).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Publishes the door state as unknown until the sensors can be read
+    /// again. The loop keeps running, so commands and presence still work.
+    async fn sensor_failed(&mut self, e: GpioError) -> Result<(), Error> {
+        self.last_sensor_read = Some(SensorRead::failed(self.clock.now(), e.to_string()));
+        if self.sensor_fault.is_some() {
+            debug!(error = %e, source = %e.source, "sensor still unreadable");
+            return Ok(());
+        }
+        error!(error = %e, source = %e.source, "sensor unreadable, door state unknown");
+        self.sensor_fault = Some(e.to_string());
+        self.update_gpio_status();
+        self.snapshot.send_modify(|s| s.state = None);
+        // Home Assistant's payload for an unknown cover state; the JSON
+        // state's template renders null the same way.
+        let unknown = if self.config.mqtt.json_state { r#"{"state":null}"# } else { "None" };
+        self.publish(&self.topics.state, true, unknown).await?;
+        self.publish_attributes().await
+    }
+
+    /// Clears a sensor fault after a good reading, returning whether there
+    /// was one.
+    fn sensor_recovered(&mut self) -> bool {
+        if self.sensor_fault.take().is_none() {
+            return false;
+        }
+        info!("sensors readable again");
+        self.update_gpio_status();
+        true
+    }
+
+    /// Marks the wall button unavailable after its input failed. The loop
+    /// keeps running, so the door, commands and the other inputs still
+    /// work.
+    async fn button_failed(&mut self, e: GpioError) -> Result<(), Error> {
+        if self.button_fault.is_some() {
+            debug!(error = %e, source = %e.source, "wall button still unreadable");
+            return Ok(());
+        }
+        warn!(error = %e, source = %e.source, "wall button unreadable, ignoring it until it reads again");
+        self.button_fault = Some(e.to_string());
+        self.update_gpio_status();
+        self.publish_attributes().await
+    }
+
+    /// Clears a wall button fault after a good reading.
+    async fn button_recovered(&mut self) -> Result<(), Error> {
+        if self.button_fault.take().is_none() {
+            return Ok(());
+        }
+        info!("wall button readable again");
+        self.update_gpio_status();
+        self.publish_attributes().await
+    }
+
+    /// The GPIO subsystem's status from the sensor and wall button faults.
+    /// The door can still be read and driven without the button, so that
+    /// alone only degrades it.
+    fn update_gpio_status(&mut self) {
+        let status = match (&self.sensor_fault, &self.button_fault) {
+            (Some(e), _) => SubsystemStatus::failing(e.clone()),
+            (None, Some(e)) => SubsystemStatus::degraded(format!("wall button: {}", e)),
+            (None, None) => SubsystemStatus::ok(),
+        };
+        self.set_subsystem(Subsystem::Gpio, status);
+    }
+
+    async fn readings(&self, closed: bool) -> Result<Readings, GpioError> {
+        Ok(Readings { closed, open: self.hw.fully_open().await?, zones: self.hw.zone_readings().await? })
+    }
+
+    async fn track_motor(&mut self, status: Status) -> Result<(), Error> {
+        if let Some(cycle) = self.motor.door_changed(status, self.clock.today()) {
+            if cycle.long {
+                warn!(secs = cycle.runtime.as_secs_f64(), "door took unusually long to close");
+            }
+            self.publish_motor().await?;
+        }
+        Ok(())
+    }
+
+    /// Times runs between the end sensors, reporting one that is off its
+    /// baseline or a door that reversed while closing.
+    async fn follow_run(&mut self, from: Position, to: Position) -> Result<(), Error> {
+        let had_problem = self.anomalies.problem().is_some();
+        match self.anomalies.position_changed(from, to) {
+            Some(anomaly) => {
+                warn!(kind = %anomaly.kind, direction = %anomaly.direction, secs = anomaly.secs,
+                    baseline_secs = anomaly.baseline_secs, "motor anomaly");
+                let details = serde_json::to_value(anomaly).map_err(BrokerError::from)?;
+                self.publish_event("motor_anomaly", details).await?;
+            }
+            None if had_problem && self.anomalies.problem().is_none() => info!("door ran normally again"),
+            None => return Ok(()),
+        }
+        self.publish_motor_anomaly().await
+    }
+
+    async fn publish_motor_anomaly(&self) -> Result<(), Error> {
+        let payload = json!({ "problem": self.anomalies.problem().is_some(), "anomaly": self.anomalies.problem() });
+        self.publish_json(&self.topics.motor_anomaly, true, &payload).await
+    }
+
+    async fn track_vehicle(&mut self, status: Status) -> Result<(), Error> {
+        let present = match self.hw.vehicle_present().await {
+            Ok(Some(p)) => p,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                warn!(error = %e, source = %e.source, "vehicle sensor unreadable, skipping vehicle event");
+                return Ok(());
+            }
+        };
+        if let Some(record) = self.vehicle.door_changed(status, present) {
+            info!(event = %record.event, "vehicle event");
+            let mut payload = serde_json::to_value(&record).map_err(BrokerError::from)?;
+            if self.config.privacy.enabled {
+                privacy::redact(&mut payload);
+            }
+            self.publish_json(&self.topics.vehicle, false, &payload).await?;
+        }
+        Ok(())
+    }
+
+    /// Records what a relay press did, alerting when it never moved the door.
+    async fn command_finished(&mut self, correlation: Correlation) -> Result<(), Error> {
+        info!(trigger = %correlation.trigger, outcome = %correlation.outcome, started_ms = correlation.started_ms,
+            completed_secs = correlation.completed_secs, "relay press finished");
+        let details = serde_json::to_value(&correlation).map_err(BrokerError::from)?;
+        self.record_history("outcome", Some(&correlation.trigger), None, details);
+        if correlation.outcome == Outcome::NeverMoved {
+            warn!(trigger = %correlation.trigger, "relay press never moved the door");
+            let details = json!({ "trigger": correlation.trigger });
+            self.publish_alert(&self.topics.notifications, "never_moved", details).await?;
+        }
+        self.stats.outcomes.record(&correlation);
+        self.redact_stats();
+        self.save_stats();
+        self.publish_commands().await
+    }
+
+    async fn publish_commands(&self) -> Result<(), Error> {
+        let report = serde_json::to_value(self.stats.outcomes.report()).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.commands, true, &report).await
+    }
+
+    async fn publish_motor(&mut self) -> Result<(), Error> {
+        let report = serde_json::to_value(self.motor.report(self.clock.today())).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.motor, true, &report).await
+    }
+
+    /// Cuts the last opening and the last relay press down to their day in
+    /// privacy mode, including ones recorded before it was turned on.
+    fn redact_stats(&mut self) {
+        if self.config.privacy.enabled {
+            self.stats.last_opened = self.stats.last_opened.map(privacy::start_of_day);
+            self.stats.last_changed = self.stats.last_changed.map(privacy::start_of_day);
+            if let Some(last) = &mut self.stats.outcomes.last {
+                last.timestamp = privacy::start_of_day(last.timestamp);
+            }
+        }
+    }
+
+    /// Persists the usage counters. Failures are only logged, the counters
+    /// keep running in memory.
+    fn save_stats(&mut self) {
+        let status = match self.stats_store.save(&self.stats) {
+            Ok(()) => SubsystemStatus::ok(),
+            Err(e) => {
+                warn!(dir = %self.config.storage.dir.display(), error = %e, "failed to save usage stats");
+                SubsystemStatus::degraded(format!("failed to save usage stats: {}", e))
+            }
+        };
+        self.set_subsystem(Subsystem::Storage, status);
+    }
+
+    async fn publish_stats(&mut self) -> Result<(), Error> {
+        let report = serde_json::to_value(self.stats.report(self.clock.now())).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.stats, true, &report).await
+    }
+
+    async fn publish_heatmap(&self) -> Result<(), Error> {
+        self.snapshot.send_modify(|s| s.heatmap = self.stats.heatmap.clone());
+        let report = serde_json::to_value(self.stats.heatmap.report()).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.heatmap, true, &report).await
+    }
+
+    async fn publish_state(&mut self, position: Position) -> Result<(), Error> {
+        if self.sensor_fault.is_some() {
+            return Ok(());
+        }
+        let changed = self.state_record.map(|r| r.state != position).unwrap_or(true);
+        if changed {
+            let trigger = self.state_record.map(|_| self.change_trigger());
+            self.state_record = Some(StateRecord { state: position, since: self.clock.now(), trigger });
+            // The position found at startup is no change, for the stats or
+            // the webhooks; `stats.resume` notices one made while down.
+            if trigger.is_some() {
+                self.stats.position_changed(self.clock.now());
+                self.redact_stats();
+                self.save_stats();
+                self.publish_stats().await?;
+            }
+            let source = trigger.map(|t| t.to_string());
+            self.record_history("state", source.as_deref(), None, json!({ "state": position }));
+            let event = match position {
+                Position::Open => Some(WebhookEvent::Open),
+                Position::Closed => Some(WebhookEvent::Close),
+                _ => None,
+            };
+            if let (Some(event), Some(_)) = (event, trigger) {
+                self.notify_webhooks(event, &json!({ "state": position, "source": source }));
+            }
+        }
+        self.snapshot.send_modify(|s| s.state = Some(position));
+        match self.state_record {
+            Some(record) if self.config.mqtt.json_state => {
+                let payload = serde_json::to_value(record).map_err(BrokerError::from)?;
+                self.publish_json(&self.topics.state, true, &payload).await?;
+            }
+            _ => self.publish(&self.topics.state, true, position.to_string()).await?,
+        }
+        if changed {
+            self.publish_attributes().await?;
+        }
+        self.publish_percent().await
+    }
+
+    /// What a change of position is put down to: the last relay press if
+    /// the door could still be moving from it, otherwise something else.
+    fn change_trigger(&self) -> Trigger {
+        match (self.last_press, self.press_trigger) {
+            (Some(at), Some(cause)) if at.elapsed() <= self.config.motor.travel() * 2 => cause,
+            _ => Trigger::External,
+        }
+    }
+
+    /// Publishes whether each probe and analog input is being read, each on
+    /// its own retained availability topic so Home Assistant shows a failed
+    /// one as unavailable rather than its last value.
+    async fn publish_sensor_availability(&self) -> Result<(), Error> {
+        let availability = |available| if available { mqtt::ONLINE } else { mqtt::OFFLINE };
+        for sensor in self.config.onewire.iter().flat_map(|o| &o.sensors) {
+            let payload = availability(self.temperature_health.is_available(&sensor.id));
+            self.publish(&self.topics.temperature_availability(&sensor.id), true, payload).await?;
+        }
+        for input in self.config.analog.iter().flat_map(|a| &a.inputs) {
+            let payload = availability(self.analog_health.is_available(&input.id));
+            self.publish(&self.topics.analog_availability(&input.id), true, payload).await?;
+        }
+        Ok(())
+    }
+
+    /// Publishes a probe reading in the locale's unit, retained so Home
+    /// Assistant has a value straight after a restart.
+    async fn publish_temperature(&self, reading: &Reading) -> Result<(), Error> {
+        let value = format!("{:.1}", self.locale.temperature(reading.celsius));
+        self.publish(&self.topics.temperature(&reading.id), true, value).await
+    }
+
+    async fn publish_percent(&self) -> Result<(), Error> {
+        let percent = self.position.percent();
+        self.snapshot.send_modify(|s| s.position = Some(percent));
+        self.publish(&self.topics.position, true, percent.to_string()).await
+    }
+
+    async fn publish_countdown(&self) -> Result<(), Error> {
+        let remaining = self.countdown.map(|c| c.remaining_secs());
+        self.snapshot.send_modify(|s| {
+            s.close_countdown = remaining;
+            s.close_reason = self.countdown.map(|c| c.reason);
+        });
+        self.publish(&self.topics.countdown, true, remaining.unwrap_or(0).to_string()).await?;
+        self.publish_attributes().await
+    }
+
+    /// When the next scheduled action is due on tokio's clock. Taken afresh
+    /// every loop turn, so it follows the wall clock being set.
+    fn schedule_deadline(&self) -> Option<Instant> {
+        let wait = self.scheduler.next_due()? - self.clock.now();
+        Some(Instant::now() + wait.to_std().unwrap_or_default())
+    }
+
+    async fn run_schedule(&mut self) -> Result<(), Error> {
+        let entries = self.config.schedule.clone();
+        for entry in self.scheduler.due(&entries, self.clock.now()) {
+            info!(name = %entry.name, action = %entry.action, "running scheduled action");
+            let by = format!("schedule:{}", entry.name);
+            match entry.action {
+                ScheduleAction::Close => self.start_automated_close(CloseReason::Schedule).await?,
+                ScheduleAction::Lock => self.set_vacation_lock(true, &by).await?,
+                ScheduleAction::Unlock => self.set_vacation_lock(false, &by).await?,
+            }
+        }
+        self.publish_attributes().await
+    }
+
+    /// Re-evaluates the lockout schedule, publishing when a window starts or
+    /// ends.
+    async fn refresh_lockout(&mut self) -> Result<(), Error> {
+        let lockout = self.config.lockout.active_at(self.clock.local_now());
+        if lockout == self.lockout {
+            return Ok(());
+        }
+        match &lockout {
+            Some(l) => info!(reason = %l.reason, "lockout window started"),
+            None => info!("lockout window ended"),
+        }
+        self.lockout = lockout;
+        self.publish_attributes().await
+    }
+
+    async fn publish_attributes(&self) -> Result<(), Error> {
+        self.snapshot.send_modify(|s| s.lockout = self.lockout.clone());
+        let attributes = json!({
+            "close_countdown": self.countdown.map(|c| c.remaining_secs()),
+            "close_reason": self.countdown.map(|c| c.reason.to_string()),
+            "lockout": self.lockout.is_some(),
+            "lockout_reason": self.lockout.as_ref().map(|l| &l.reason),
+            "someone_home": self.config.presence.as_ref().map(|p| self.presence.anyone_home(p)),
+            "next_scheduled": self.scheduler.upcoming(&self.config.schedule)
+                .map(|(entry, at)| json!({ "name": entry.name, "action": entry.action, "at": at })),
+            "vacation_lock": self.vacation.is_locked(),
+            "maintenance": self.maintenance.is_active(),
+            "options": self.options.raw(),
+            "obstructed": self.obstructed,
+            "sensor_fault": self.sensor_fault,
+            "button_fault": self.button_fault,
+            "previous_shutdown": self.previous_shutdown,
+            "state_since": self.state_record.map(|r| r.since),
+            "trigger": self.state_record.and_then(|r| r.trigger),
+            "last_rejection": self.last_rejection,
+            "dropped_publishes": self.outbox.lock().expect("outbox lock poisoned").dropped(),
+        });
+        self.publish_json(&self.topics.attributes, true, &attributes).await
+    }
+
+    /// Switches to the `[privileges]` user, once the first broker connection
+    /// has been made or has failed, then lets the HTTP API and the control
+    /// socket start answering.
+    fn drop_privileges(&mut self) -> Result<(), Error> {
+        if let Some(config) = &self.config.privileges {
+            let files: Vec<_> = [self.config.history.as_ref().and_then(|h| h.path.clone()),
+                self.config.audit.as_ref().and_then(|a| a.dead_letter.clone())]
+                .into_iter()
+                .flatten()
+                .collect();
+            privileges::drop_to(config, &self.config.storage.dir, &files)?;
+        }
+        self.local_ready.send_replace(true);
+        Ok(())
+    }
+
+    /// Turns on the hardware watchdog if configured. There's no board to
+    /// reset when simulating.
+    fn enable_watchdog(&mut self) {
+        let config = match &self.config.watchdog {
+            Some(c) if self.hw.simulator().is_none() => c,
+            _ => return,
+        };
+        match HardwareWatchdog::enable(config) {
+            Ok(watchdog) => {
+                self.watchdog = Some(watchdog);
+                self.set_subsystem(Subsystem::Watchdog, SubsystemStatus::ok());
+            }
+            Err(e) => {
+                error!(dir = %config.dir.display(), error = %e, "failed to enable the hardware watchdog");
+                self.set_subsystem(Subsystem::Watchdog, SubsystemStatus::failing(e.to_string()));
+            }
+        }
+    }
+
+    /// Feeds the hardware watchdog while the door can be monitored and, if
+    /// required, reached over MQTT.
+    fn feed_watchdog(&mut self) {
+        let require_mqtt = self.config.watchdog.as_ref().is_some_and(|c| c.require_mqtt);
+        let failing = |subsystem| self.subsystems.get(subsystem).is_some_and(|s| s.condition == Condition::Failing);
+        let unhealthy = if failing(Subsystem::Gpio) {
+            Some("gpio")
+        } else if require_mqtt && failing(Subsystem::Mqtt) {
+            Some("mqtt")
+        } else {
+            None
+        };
+        let result = match self.watchdog.as_mut() {
+            Some(watchdog) => watchdog.tick(unhealthy),
+            None => return,
+        };
+        match (result, unhealthy) {
+            (Ok(()), None) => self.set_subsystem(Subsystem::Watchdog, SubsystemStatus::ok()),
+            (Ok(()), Some(subsystem)) => {
+                let detail = format!("not fed while {} is failing", subsystem);
+                self.set_subsystem(Subsystem::Watchdog, SubsystemStatus::degraded(detail));
+            }
+            (Err(e), _) => {
+                warn!(error = %e, "failed to feed the hardware watchdog");
+                self.set_subsystem(Subsystem::Watchdog, SubsystemStatus::failing(e.to_string()));
+            }
+        }
+    }
+
+    async fn publish_heartbeat(&self) -> Result<(), Error> {
+        let cooldown = self.cooldown_remaining();
+        let heartbeat = Heartbeat {
+            door: mqtt::DOOR_ID,
+            timestamp: self.clock.now(),
+            uptime_secs: self.started.elapsed().as_secs(),
+            last_sensor_read: self.last_sensor_read.clone(),
+            mqtt_reconnects: self.connects.saturating_sub(1),
+            relay_ready: cooldown.is_none(),
+            relay_cooldown_secs: cooldown.map_or(0.0, |d| d.as_secs_f64()),
+            relay_queue: self.presses.len(),
+        };
+        let payload = serde_json::to_value(&heartbeat).map_err(BrokerError::from)?;
+        self.publish_json(&self.topics.heartbeat, false, &payload).await
+    }
+
+    async fn publish_json(&self, topic: &str, retain: bool, payload: &Value) -> Result<(), Error> {
+        let payload = to_vec(payload).map_err(BrokerError::from)?;
+        self.publish(topic, retain, payload).await
+    }
+
+    /// Queues a publish without waiting, so a broker outage can't stall the
+    /// loop. What happens to publishes that don't fit in the queue depends
+    /// on the topic's priority; see [`Outbox`].
+    /// With `retain` off in the config only the topics that need it are
+    /// retained.
+    async fn publish<P: Into<Vec<u8>>>(&self, topic: &str, retain: bool, payload: P) -> Result<(), Error> {
+        let priority = self.topics.priority(topic);
+        let mut outbox = self.outbox.lock().expect("outbox lock poisoned");
+        if !outbox.admit(priority) {
+            return Ok(());
+        }
+        let retain = retain && (self.config.mqtt.retain || self.topics.always_retained(topic));
+        let payload = payload.into();
+        match self.client.try_publish(topic, self.qos(topic), retain, payload.clone(), self.expiry(topic)) {
+            Ok(()) => {
+                outbox.sent(topic);
+                Ok(())
+            }
+            Err(BrokerError::QueueFull) => {
+                outbox.full(topic, retain, payload, priority);
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// With MQTT 5, how long the broker keeps a state message for clients
+    /// yet to receive it.
+    fn expiry(&self, topic: &str) -> Option<u32> {
+        self.config.mqtt.state_expiry_secs.filter(|_| topic == self.topics.state || topic == self.topics.position)
+    }
+
+    /// QoS for one of the daemon's own topics.
+    fn qos(&self, topic: &str) -> QoS {
+        mqtt::qos(self.config.mqtt.qos_for(topic, self.config.mqtt.qos))
+    }
+
+    /// Shows the most pressing state on the status LED: a sensor fault, then
+    /// a lost broker, then the door itself.
+    fn update_led(&self) {
+        let led = match &self.led {
+            Some(led) => led,
+            None => return,
+        };
+        let mqtt = self.subsystems.get(Subsystem::Mqtt).map(|s| s.condition);
+        let indication = if self.sensor_fault.is_some() {
+            Indication::SensorFault
+        } else if mqtt == Some(Condition::Failing) {
+            Indication::MqttDisconnected
+        } else {
+            match self.position.position() {
+                Position::Closed => Indication::Closed,
+                Position::Opening | Position::Closing => Indication::Moving,
+                Position::Open | Position::Stopped => Indication::Open,
+            }
+        };
+        led.show(indication);
+    }
+
+    /// Records a subsystem's status, logging changes and putting it in the
+    /// history as an error when it starts failing; the status topic is
+    /// brought up to date at the top of the next loop turn.
+    fn set_subsystem(&mut self, subsystem: Subsystem, status: SubsystemStatus) {
+        let condition = status.condition;
+        let was_failing = self.subsystems.get(subsystem).is_some_and(|s| s.condition == Condition::Failing);
+        let detail = json!({ "subsystem": subsystem, "detail": status.detail });
+        if self.subsystems.set(subsystem, status) {
+            match condition {
+                Condition::Degraded | Condition::Failing => warn!(%subsystem, %condition, "subsystem unhealthy"),
+                Condition::Ok | Condition::Disabled => info!(%subsystem, %condition, "subsystem status changed"),
+            }
+            if condition == Condition::Failing && !was_failing {
+                self.record_history("error", None, None, detail);
+            }
+        }
+    }
+
+    /// Publishes the subsystem statuses if any changed, marking MQTT
+    /// degraded first while the outbox sheds telemetry.
+    async fn publish_subsystems(&mut self) -> Result<(), Error> {
+        let shedding = self.outbox.lock().expect("outbox lock poisoned").is_shedding();
+        let mqtt = self.subsystems.get(Subsystem::Mqtt).map(|s| s.condition);
+        match (mqtt, shedding) {
+            (Some(Condition::Ok), true) => {
+                self.set_subsystem(Subsystem::Mqtt, SubsystemStatus::degraded("request queue full, shedding telemetry"));
+            }
+            (Some(Condition::Degraded), false) => self.set_subsystem(Subsystem::Mqtt, SubsystemStatus::ok()),
+            _ => (),
+        }
+        if !self.subsystems.take_changed() {
+            return Ok(());
+        }
+        let report = self.subsystems_report()?;
+        self.publish_json(&self.topics.subsystems, true, &report).await
+    }
+
+    fn subsystems_report(&self) -> Result<Value, Error> {
+        Ok(serde_json::to_value(self.subsystems.report()).map_err(BrokerError::from)?)
+    }
+
+    /// Retries held critical publishes while the queue has room.
+    fn flush_outbox(&self) -> Result<(), Error> {
+        let mut outbox = self.outbox.lock().expect("outbox lock poisoned");
+        while let Some((topic, retain, payload)) = outbox.next_held() {
+            match self.client.try_publish(topic.as_str(), self.qos(&topic), retain, payload.clone(), self.expiry(&topic)) {
+                Ok(()) => outbox.sent(&topic),
+                Err(BrokerError::QueueFull) => {
+                    outbox.full(&topic, retain, payload, Priority::Critical);
+                    break;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Home Assistant's payload for a switch or binary sensor.
+fn on_off(active: bool) -> &'static str {
+    if active { "ON" } else { "OFF" }
+}
+
+/// Loads the message templates, falling back to the built-in ones if the
+/// templates directory changed since the config was checked.
+fn load_templates(config: &LocaleConfig) -> Templates {
+    Templates::load(config).unwrap_or_else(|e| {
+        warn!(error = %e, "failed to load message templates, using the built-in ones");
+        Templates::builtin(config)
+    })
+}
+
+/// Reads a history query, an empty payload asking for the latest entries.
+fn parse_history_query(payload: &[u8]) -> Result<HistoryQuery, serde_json::Error> {
+    match payload {
+        b"" => Ok(HistoryQuery::default()),
+        payload => serde_json::from_slice(payload),
+    }
+}
+
+/// Publishes the entries matching `query` one per message, oldest first and
+/// tagged with the request id, then a message marking the end. Runs beside
+/// the loop, waiting for room in the request queue rather than dropping.
+async fn replay_history(history: History, query: HistoryQuery, client: Client, topic: String, qos: QoS) {
+    let request_id = query.request_id.clone();
+    let end = match history.query(query).await {
+        Ok(mut entries) => {
+            entries.reverse();
+            info!(count = entries.len(), request_id, "replaying history");
+            for entry in &entries {
+                let mut payload = json!(entry);
+                payload["request_id"] = json!(request_id);
+                if client.publish(topic.as_str(), qos, false, payload.to_string().into_bytes()).await.is_err() {
+                    return;
+                }
+                sleep(REPLAY_PACE).await;
+            }
+            json!({ "request_id": request_id, "end": true, "count": entries.len() })
+        }
+        Err(e) => json!({ "request_id": request_id, "end": true, "error": Failure::from(&Error::from(e)) }),
+    };
+    let _ = client.publish(topic.as_str(), qos, false, end.to_string().into_bytes()).await;
+}
+
+/// Topics owned by other devices that the config asks us to follow.
+fn external_topics(config: &Config) -> BTreeSet<&str> {
+    let wind = config.presets.as_ref().and_then(|p| p.wind_topic.as_deref());
+    let presence = config.presence.iter().flat_map(|p| p.topics.iter().map(String::as_str));
+    wind.into_iter().chain(Links::topics(&config.links)).chain(presence).collect()
+}
@@ -0,0 +1,90 @@
+//! Retrying JSON POSTs, shared by the audit webhook and the notification
+//! webhooks.
+//!
+//! Failures that might go away (timeouts, connection errors, 5xx, 408 and
+//! 429) are retried with exponential backoff; anything else is given up on
+//! straight away. How deliveries go is reported as the `notifications`
+//! subsystem.
+
+use std::time::Duration;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Request};
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+use crate::http_client::HttpsClient;
+use crate::subsystems::{StatusReporter, Subsystem, SubsystemStatus};
+
+/// Where to POST and how hard to try.
+pub struct Target<'a> {
+    /// Names the target in logs and subsystem details, e.g. `audit webhook`.
+    pub name: &'a str,
+    pub url: &'a str,
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub timeout: Duration,
+}
+
+/// A delivery given up on.
+#[derive(Debug)]
+pub struct Failure {
+    pub message: String,
+    pub attempts: u32,
+}
+
+/// POSTs `body` with `headers` until it is accepted, it fails in a way
+/// that retrying won't fix, or `target.max_attempts` run out.
+pub async fn post(client: &HttpsClient, target: &Target<'_>, headers: &[(&str, &str)], body: &str, status: &StatusReporter) -> Result<(), Failure> {
+    let mut delay = target.backoff;
+    let mut attempt = 1;
+    loop {
+        let error = match attempt_post(client, target, headers, body).await {
+            Ok(()) => {
+                debug!(target = target.name, attempt, "delivered");
+                status.report(Subsystem::Notifications, SubsystemStatus::ok());
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        if !error.retry || attempt >= target.max_attempts {
+            let detail = format!("{} failing: {}", target.name, error.message);
+            status.report(Subsystem::Notifications, SubsystemStatus::failing(detail));
+            return Err(Failure { message: error.message, attempts: attempt });
+        }
+        let detail = format!("{} retrying: {}", target.name, error.message);
+        status.report(Subsystem::Notifications, SubsystemStatus::degraded(detail));
+        warn!(target = target.name, attempt, retry_secs = delay.as_secs_f64(), error = %error.message, "delivery failed, retrying");
+        sleep(delay).await;
+        delay = (delay * 2).min(target.max_backoff);
+        attempt += 1;
+    }
+}
+
+struct AttemptError {
+    message: String,
+    retry: bool,
+}
+
+async fn attempt_post(client: &HttpsClient, target: &Target<'_>, headers: &[(&str, &str)], body: &str) -> Result<(), AttemptError> {
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri(target.url)
+        .header(CONTENT_TYPE, "application/json");
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = request.body(Body::from(body.to_owned()))
+        .map_err(|e| AttemptError { message: format!("invalid request: {}", e), retry: false })?;
+    let response = tokio::time::timeout(target.timeout, client.request(request)).await
+        .map_err(|_| AttemptError { message: "timed out".to_owned(), retry: true })?
+        .map_err(|e| AttemptError { message: e.to_string(), retry: true })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    // Other client errors won't go away by sending the same request again.
+    let retry = status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429;
+    Err(AttemptError { message: format!("webhook returned {}", status), retry })
+}
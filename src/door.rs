@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum::{EnumString, Display};
+
+use crate::countdown::CloseReason;
+use crate::error::{Error, RejectReason};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[strum(serialize = "open")]
+    Open,
+    #[strum(serialize = "closed")]
+    Closed,
+}
+
+/// Where the door is, as published to Home Assistant. Without an open sensor
+/// only `Open` (meaning "not closed") and `Closed` are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Position {
+    #[strum(serialize = "open")]
+    Open,
+    #[strum(serialize = "closed")]
+    Closed,
+    #[strum(serialize = "opening")]
+    Opening,
+    #[strum(serialize = "closing")]
+    Closing,
+    /// Stopped partway, between the two sensors.
+    #[strum(serialize = "stopped")]
+    Stopped,
+}
+
+impl Position {
+    /// The closed sensor's view of this position.
+    pub fn status(self) -> Status {
+        match self {
+            Position::Closed => Status::Closed,
+            _ => Status::Open,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+pub enum Command {
+    #[strum(serialize = "OPEN")]
+    Open,
+    #[strum(serialize = "CLOSE")]
+    Close,
+    /// Aborts a pending automated close, or stops a moving door when an open
+    /// sensor is fitted.
+    #[strum(serialize = "CANCEL")]
+    Cancel,
+    /// Runs a timed open/close cycle to check the door's balance.
+    #[strum(serialize = "HEALTH_CHECK")]
+    HealthCheck,
+    /// Opens the door partway, to the preset position named `vent`.
+    #[strum(serialize = "VENT")]
+    Vent,
+}
+
+impl Command {
+    /// Whether the command sets a closed or stopped door opening.
+    pub fn opens(self) -> bool {
+        matches!(self, Command::Open | Command::HealthCheck | Command::Vent)
+    }
+}
+
+/// Where a command came from. The physical button bypasses `execute`
+/// entirely, so lockout windows never apply to it; only the vacation lock
+/// does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    #[strum(serialize = "mqtt")]
+    Mqtt,
+    #[strum(serialize = "http")]
+    Http,
+    #[strum(serialize = "button")]
+    Button,
+    #[strum(serialize = "keypad")]
+    Keypad,
+    #[strum(serialize = "rf")]
+    Rf,
+    /// The local control socket, as used by `garagectl`.
+    #[strum(serialize = "socket")]
+    Socket,
+}
+
+impl Source {
+    /// Whether the command could have been sent from away from the door.
+    pub fn is_remote(self) -> bool {
+        matches!(self, Source::Mqtt | Source::Http | Source::Socket)
+    }
+}
+
+/// What set the door moving, so automations can tell a person at the wall
+/// button from a remote command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    #[strum(serialize = "mqtt")]
+    Mqtt,
+    #[strum(serialize = "http")]
+    Http,
+    #[strum(serialize = "button")]
+    Button,
+    #[strum(serialize = "keypad")]
+    Keypad,
+    #[strum(serialize = "rf")]
+    Rf,
+    #[strum(serialize = "socket")]
+    Socket,
+    #[strum(serialize = "auto_close")]
+    AutoClose,
+    #[strum(serialize = "sweep")]
+    Sweep,
+    #[strum(serialize = "schedule")]
+    Schedule,
+    #[strum(serialize = "wind")]
+    Wind,
+    #[strum(serialize = "link")]
+    Link,
+    #[strum(serialize = "health_check")]
+    HealthCheck,
+    /// Moved without garaged pressing the relay, e.g. by a handheld remote.
+    #[strum(serialize = "external")]
+    External,
+}
+
+impl From<Source> for Trigger {
+    fn from(source: Source) -> Trigger {
+        match source {
+            Source::Mqtt => Trigger::Mqtt,
+            Source::Http => Trigger::Http,
+            Source::Button => Trigger::Button,
+            Source::Keypad => Trigger::Keypad,
+            Source::Rf => Trigger::Rf,
+            Source::Socket => Trigger::Socket,
+        }
+    }
+}
+
+impl From<CloseReason> for Trigger {
+    fn from(reason: CloseReason) -> Trigger {
+        match reason {
+            CloseReason::AutoClose => Trigger::AutoClose,
+            CloseReason::Sweep => Trigger::Sweep,
+            CloseReason::Schedule => Trigger::Schedule,
+            CloseReason::Wind => Trigger::Wind,
+            CloseReason::Link => Trigger::Link,
+            CloseReason::Mqtt => Trigger::Mqtt,
+            CloseReason::Http => Trigger::Http,
+        }
+    }
+}
+
+/// The JSON state payload: the position and what led to it.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct StateRecord {
+    pub state: Position,
+    pub since: DateTime<Utc>,
+    /// `None` for the state found at startup.
+    pub trigger: Option<Trigger>,
+}
+
+pub fn parse_door_status(status: u8) -> Status {
+    match status {
+        0 => Status::Open,
+        _ => Status::Closed,
+    }
+}
+
+pub fn parse_command(payload: &[u8]) -> Result<Command, Error> {
+    std::str::from_utf8(payload)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(Error::rejected(RejectReason::InvalidPayload))
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandMessage {
+    command: String,
+    credential: Option<String>,
+}
+
+/// Parses a command topic payload: either a bare command, or JSON like
+/// `{"command": "OPEN", "credential": "<token>"}` to act as that identity.
+pub fn parse_command_message(payload: &[u8]) -> Result<(Command, Option<String>), Error> {
+    if payload.first() != Some(&b'{') {
+        return parse_command(payload).map(|c| (c, None));
+    }
+    let message: CommandMessage = serde_json::from_slice(payload)
+        .map_err(|_| Error::rejected(RejectReason::InvalidPayload))?;
+    let command = parse_command(message.command.as_bytes())?;
+    Ok((command, message.credential))
+}
+
+/// Checks whether `command` should trigger the relay for a door currently at
+/// `position`. `Cancel` only actuates to stop a moving door; the caller
+/// accepts it separately while an automated close is pending.
+pub fn check_command(command: Command, position: Position) -> Result<(), Error> {
+    match (command, position) {
+        (Command::Open, Position::Closed | Position::Stopped) |
+        (Command::Close, Position::Open | Position::Stopped) |
+        (Command::Cancel, Position::Opening | Position::Closing) |
+        (Command::HealthCheck | Command::Vent, Position::Closed) => Ok(()),
+        (Command::Open, Position::Open) => Err(Error::rejected(RejectReason::AlreadyOpen)),
+        (Command::Close, Position::Closed) => Err(Error::rejected(RejectReason::AlreadyClosed)),
+        (Command::Open | Command::Close, Position::Opening | Position::Closing) => {
+            Err(Error::rejected(RejectReason::InMotion))
+        }
+        (Command::Cancel, _) => Err(Error::rejected(RejectReason::NoPendingClose)),
+        (Command::HealthCheck | Command::Vent, _) => Err(Error::rejected(RejectReason::NotClosed)),
+    }
+}
@@ -0,0 +1,259 @@
+//! Typed errors for the garaged core.
+//!
+//! Every error exposes a stable `code()` so that acks, metrics labels and the
+//! HTTP API can report failure causes without clients parsing messages.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Gpio(#[from] GpioError),
+    #[error(transparent)]
+    Broker(#[from] BrokerError),
+    #[error("command rejected: {reason}")]
+    CommandRejected { reason: RejectReason },
+    #[error(transparent)]
+    StateMachine(#[from] StateMachineError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error("system error: {0}")]
+    System(#[from] io::Error),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+    #[error(transparent)]
+    History(#[from] HistoryError),
+    #[error(transparent)]
+    Privileges(#[from] PrivilegeError),
+}
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Gpio(_) => "gpio",
+            Error::Broker(_) => "broker",
+            Error::CommandRejected { .. } => "command_rejected",
+            Error::StateMachine(_) => "state_machine",
+            Error::Config(_) => "config",
+            Error::System(_) => "system",
+            Error::Auth(_) => "auth",
+            Error::History(_) => "history",
+            Error::Privileges(_) => "privileges",
+        }
+    }
+
+    pub fn rejected(reason: RejectReason) -> Error {
+        Error::CommandRejected { reason }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("gpio {op} failed on {pin} pin")]
+pub struct GpioError {
+    pub pin: &'static str,
+    pub op: &'static str,
+    #[source]
+    pub source: sysfs_gpio::Error,
+}
+
+impl GpioError {
+    pub fn new(pin: &'static str, op: &'static str, source: sysfs_gpio::Error) -> GpioError {
+        GpioError { pin, op, source }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BrokerError {
+    #[error("mqtt request queue full")]
+    QueueFull,
+    #[error("mqtt event loop stopped")]
+    Closed,
+    #[error("mqtt connection error: {0}")]
+    Connection(Box<rumqttc::ConnectionError>),
+    #[error("mqtt connection error: {0}")]
+    ConnectionV5(Box<rumqttc::v5::ConnectionError>),
+    #[error("failed to encode payload: {0}")]
+    Encode(#[from] serde_json::Error),
+}
+
+impl From<rumqttc::ConnectionError> for BrokerError {
+    fn from(e: rumqttc::ConnectionError) -> BrokerError {
+        BrokerError::Connection(Box::new(e))
+    }
+}
+
+impl From<rumqttc::v5::ConnectionError> for BrokerError {
+    fn from(e: rumqttc::v5::ConnectionError) -> BrokerError {
+        BrokerError::ConnectionV5(Box::new(e))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    InvalidPayload,
+    AlreadyOpen,
+    AlreadyClosed,
+    NoPendingClose,
+    NotClosed,
+    HealthCheckRunning,
+    /// A lockout window is active and the sender is not an admin.
+    Lockout,
+    /// The door is moving; stop it first.
+    InMotion,
+    /// The door is stopped partway and the next press would move it the
+    /// other way.
+    WrongDirection,
+    /// No preset position with the requested name.
+    UnknownPreset,
+    /// The wind is above the preset position's limit.
+    WindTooHigh,
+    /// The preset position has a wind limit but there is no recent reading.
+    WindUnknown,
+    /// The vacation lock is on.
+    VacationLock,
+    /// The relay was pressed too recently.
+    Cooldown,
+    /// Too many commands arrived within the rate limit window.
+    RateLimited,
+    /// The safety beam is broken, so the door must not close.
+    Obstructed,
+    /// The sender is quarantined after too many failed or rejected attempts.
+    Quarantined,
+    /// The door is in maintenance mode.
+    Maintenance,
+    /// Only an admin may do this, e.g. end maintenance mode.
+    AdminRequired,
+    /// The presence lock is on and nobody is home.
+    NobodyHome,
+}
+
+impl RejectReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::InvalidPayload => "invalid_payload",
+            RejectReason::AlreadyOpen => "already_open",
+            RejectReason::AlreadyClosed => "already_closed",
+            RejectReason::NoPendingClose => "no_pending_close",
+            RejectReason::NotClosed => "not_closed",
+            RejectReason::HealthCheckRunning => "health_check_running",
+            RejectReason::Lockout => "lockout",
+            RejectReason::InMotion => "in_motion",
+            RejectReason::WrongDirection => "wrong_direction",
+            RejectReason::UnknownPreset => "unknown_preset",
+            RejectReason::WindTooHigh => "wind_too_high",
+            RejectReason::WindUnknown => "wind_unknown",
+            RejectReason::VacationLock => "vacation_lock",
+            RejectReason::Cooldown => "cooldown",
+            RejectReason::RateLimited => "rate_limited",
+            RejectReason::Obstructed => "obstructed",
+            RejectReason::Quarantined => "quarantined",
+            RejectReason::Maintenance => "maintenance",
+            RejectReason::AdminRequired => "admin_required",
+            RejectReason::NobodyHome => "nobody_home",
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Commands the door's position rules turn down are reported as
+/// [`Error::CommandRejected`], with a reason clients already branch on.
+#[derive(Debug, Error)]
+pub enum StateMachineError {
+    /// A sensor or input event stream ended, which it never should.
+    #[error("event stream for {0} ended unexpectedly")]
+    StreamEnded(&'static str),
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("failed to parse config file {0}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("credentials key unavailable: {0}")]
+    Key(String),
+    #[error("failed to decrypt secret, wrong key or corrupted value")]
+    Decrypt,
+    #[error("invalid config override")]
+    Override(#[source] serde_json::Error),
+    #[error("setting {0} cannot be changed remotely")]
+    Forbidden(String),
+    #[error("invalid config: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+}
+
+#[derive(Debug, Error)]
+#[error("invalid cron expression {expr:?}: {reason}")]
+pub struct CronError {
+    pub expr: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("failed to read message template {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("invalid message template {0}")]
+    Syntax(String, #[source] minijinja::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("failed to read scenario {0}")]
+    Read(PathBuf, #[source] io::Error),
+    #[error("invalid scenario {0}")]
+    Parse(PathBuf, #[source] serde_yaml::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("identity provider error: {0}")]
+    Provider(String),
+}
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("history database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("failed to create history directory: {0}")]
+    Io(#[from] io::Error),
+    #[error("history is not being recorded")]
+    Unavailable,
+}
+
+#[derive(Debug, Error)]
+pub enum SocketError {
+    #[error("failed to set up control socket {0}")]
+    Bind(PathBuf, #[source] io::Error),
+    #[error("no group named {0}")]
+    NoGroup(String),
+    #[error("failed to look up group: {0}")]
+    Lookup(#[from] nix::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum PrivilegeError {
+    #[error("no user named {0}")]
+    NoUser(String),
+    #[error("no group named {0}")]
+    NoGroup(String),
+    #[error("must be started as root to switch to user {0}")]
+    NotRoot(String),
+    #[error("failed to hand {0} over to the unprivileged user")]
+    HandOver(PathBuf, #[source] io::Error),
+    #[error("failed to switch user: {0}")]
+    Switch(#[from] nix::Error),
+    #[error("root privileges could be regained after switching user")]
+    Regained,
+}
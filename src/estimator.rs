@@ -0,0 +1,275 @@
+//! Strategies for estimating how far open the door is between the sensors.
+//!
+//! The [`PositionTracker`](crate::position::PositionTracker) decides where
+//! the door is going and tells its estimator when runs start and stop and
+//! when the door is known to be somewhere, at either end or a zone sensor.
+//! What the percentage does in between is up to the estimator, picked with
+//! `estimator` in the `[position]` config section:
+//!
+//! - `time`: from the time spent moving, relative to the nominal travel
+//!   time. Needs nothing beyond the door sensors.
+//! - `encoder`: from pulses of a rotary encoder on the opener, counted on
+//!   `gpio.encoder`. Direction comes from the tracker.
+//! - `current`: from the time the motor spends drawing current, read on an
+//!   analog input, so a motor that stalls or stops early stops the estimate
+//!   too.
+//! - `switches`: only where the end and zone sensors last saw the door,
+//!   without interpolating.
+
+use std::fmt;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::PositionConfig;
+use crate::door::Status;
+
+pub trait PositionEstimator: Send + fmt::Debug {
+    /// A run towards `heading` started from wherever the door is now.
+    fn start(&mut self, heading: Status);
+    /// The door stopped moving, wherever it got to.
+    fn stop(&mut self);
+    /// The door is known to be at `percent`. A run in progress carries on
+    /// from there.
+    fn fix(&mut self, percent: f64);
+    /// Percent open, not yet clamped to 0-100.
+    fn estimate(&self) -> f64;
+    /// Which way the run in progress is going, if there is one.
+    fn heading(&self) -> Option<Status>;
+    /// The nominal time for a full run changed.
+    fn set_travel(&mut self, _travel: Duration) {}
+    /// A pulse from the rotary encoder.
+    fn encoder_pulse(&mut self) {}
+    /// A reading of the motor current, in amps.
+    fn motor_current(&mut self, _amps: f64) {}
+}
+
+/// Builds the estimator picked in `config`.
+pub fn build(config: &PositionConfig, travel: Duration) -> Box<dyn PositionEstimator> {
+    match config {
+        PositionConfig::Time => Box::new(TimeEstimator::new(travel)),
+        PositionConfig::Encoder { pulses_per_travel } => Box::new(EncoderEstimator::new(*pulses_per_travel)),
+        PositionConfig::Current { running_amps, .. } => Box::new(CurrentEstimator::new(travel, *running_amps)),
+        PositionConfig::Switches => Box::new(SwitchEstimator::default()),
+    }
+}
+
+/// Where an estimate settles when the door stops: clamped and rounded to
+/// the whole percent that was reported.
+fn settle(estimate: f64) -> f64 {
+    estimate.clamp(0.0, 100.0).round()
+}
+
+/// `base` moved `share` of a full run towards `heading`.
+fn advance(base: f64, heading: Option<Status>, share: f64) -> f64 {
+    match heading {
+        Some(Status::Open) => base + 100.0 * share,
+        Some(Status::Closed) => base - 100.0 * share,
+        None => base,
+    }
+}
+
+#[derive(Debug)]
+pub struct TimeEstimator {
+    travel: Duration,
+    /// Percent open when the current run started, or where the door stopped.
+    base: f64,
+    /// Direction and start of the run being estimated.
+    motion: Option<(Status, Instant)>,
+}
+
+impl TimeEstimator {
+    pub fn new(travel: Duration) -> TimeEstimator {
+        TimeEstimator { travel, base: 100.0, motion: None }
+    }
+}
+
+impl PositionEstimator for TimeEstimator {
+    fn start(&mut self, heading: Status) {
+        self.base = settle(self.estimate());
+        self.motion = Some((heading, Instant::now()));
+    }
+
+    fn stop(&mut self) {
+        self.base = settle(self.estimate());
+        self.motion = None;
+    }
+
+    fn fix(&mut self, percent: f64) {
+        self.base = percent;
+        self.motion = self.motion.map(|(heading, _)| (heading, Instant::now()));
+    }
+
+    fn estimate(&self) -> f64 {
+        let share = match self.motion {
+            Some((_, started)) => started.elapsed().as_secs_f64() / self.travel.as_secs_f64().max(0.1),
+            None => 0.0,
+        };
+        advance(self.base, self.heading(), share)
+    }
+
+    fn heading(&self) -> Option<Status> {
+        self.motion.map(|(heading, _)| heading)
+    }
+
+    fn set_travel(&mut self, travel: Duration) {
+        self.travel = travel;
+    }
+}
+
+#[derive(Debug)]
+pub struct EncoderEstimator {
+    pulses_per_travel: u32,
+    base: f64,
+    heading: Option<Status>,
+    /// Pulses since the run started or the door was last fixed.
+    pulses: u32,
+}
+
+impl EncoderEstimator {
+    pub fn new(pulses_per_travel: u32) -> EncoderEstimator {
+        EncoderEstimator { pulses_per_travel, base: 100.0, heading: None, pulses: 0 }
+    }
+}
+
+impl PositionEstimator for EncoderEstimator {
+    fn start(&mut self, heading: Status) {
+        self.base = settle(self.estimate());
+        self.heading = Some(heading);
+        self.pulses = 0;
+    }
+
+    fn stop(&mut self) {
+        self.base = settle(self.estimate());
+        self.heading = None;
+        self.pulses = 0;
+    }
+
+    fn fix(&mut self, percent: f64) {
+        self.base = percent;
+        self.pulses = 0;
+    }
+
+    fn estimate(&self) -> f64 {
+        let share = f64::from(self.pulses) / f64::from(self.pulses_per_travel.max(1));
+        advance(self.base, self.heading, share)
+    }
+
+    fn heading(&self) -> Option<Status> {
+        self.heading
+    }
+
+    /// Pulses without a run in progress can't be given a direction, so
+    /// they are ignored.
+    fn encoder_pulse(&mut self) {
+        if self.heading.is_some() {
+            self.pulses = self.pulses.saturating_add(1);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CurrentEstimator {
+    travel: Duration,
+    running_amps: f64,
+    base: f64,
+    heading: Option<Status>,
+    /// Motor time counted since the run started or the door was last fixed,
+    /// not counting the stretch still going on.
+    ran: Duration,
+    /// Since when the motor has been drawing current, if it is.
+    running_since: Option<Instant>,
+}
+
+impl CurrentEstimator {
+    pub fn new(travel: Duration, running_amps: f64) -> CurrentEstimator {
+        CurrentEstimator { travel, running_amps, base: 100.0, heading: None, ran: Duration::ZERO, running_since: None }
+    }
+
+    fn running(&self) -> Duration {
+        self.ran + self.running_since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+impl PositionEstimator for CurrentEstimator {
+    /// The motor is taken to be running from the press until a reading says
+    /// otherwise, as the next reading may be a poll interval away.
+    fn start(&mut self, heading: Status) {
+        self.base = settle(self.estimate());
+        self.heading = Some(heading);
+        self.ran = Duration::ZERO;
+        self.running_since = Some(Instant::now());
+    }
+
+    fn stop(&mut self) {
+        self.base = settle(self.estimate());
+        self.heading = None;
+        self.ran = Duration::ZERO;
+        self.running_since = None;
+    }
+
+    fn fix(&mut self, percent: f64) {
+        self.base = percent;
+        self.ran = Duration::ZERO;
+        self.running_since = self.running_since.map(|_| Instant::now());
+    }
+
+    fn estimate(&self) -> f64 {
+        let share = self.running().as_secs_f64() / self.travel.as_secs_f64().max(0.1);
+        advance(self.base, self.heading, share)
+    }
+
+    fn heading(&self) -> Option<Status> {
+        self.heading
+    }
+
+    fn set_travel(&mut self, travel: Duration) {
+        self.travel = travel;
+    }
+
+    fn motor_current(&mut self, amps: f64) {
+        if self.heading.is_none() {
+            return;
+        }
+        match (amps >= self.running_amps, self.running_since) {
+            (true, None) => self.running_since = Some(Instant::now()),
+            (false, Some(since)) => {
+                self.ran += since.elapsed();
+                self.running_since = None;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Only ever changes at a sensor, so it never reports a run in progress
+/// that would need republishing in between.
+#[derive(Debug)]
+pub struct SwitchEstimator {
+    /// Where a sensor last saw the door.
+    base: f64,
+}
+
+impl Default for SwitchEstimator {
+    fn default() -> SwitchEstimator {
+        SwitchEstimator { base: 100.0 }
+    }
+}
+
+impl PositionEstimator for SwitchEstimator {
+    fn start(&mut self, _heading: Status) {}
+
+    fn stop(&mut self) {}
+
+    fn fix(&mut self, percent: f64) {
+        self.base = percent;
+    }
+
+    fn estimate(&self) -> f64 {
+        self.base
+    }
+
+    fn heading(&self) -> Option<Status> {
+        None
+    }
+}
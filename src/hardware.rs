@@ -0,0 +1,754 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream, StreamExt};
+use sysfs_gpio::{Direction, Edge, Pin};
+
+use tokio::time::{interval, sleep, MissedTickBehavior};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, info, warn};
+
+use crate::config::{EdgeConfig, GpioConfig, GpioLine, HardwareBackend, KeypadConfig, PinConfig, Pull, RfConfig, SimulationConfig};
+use crate::door::{parse_door_status, Status};
+use crate::error::GpioError;
+use crate::output::Output;
+use crate::rf::Ev1527;
+use crate::simulate::{Poke, Simulator};
+
+/// Where the kernel lists GPIO chips and the sysfs numbers they start at.
+const GPIO_CLASS: &str = "/sys/class/gpio";
+
+/// The Iono Pi's lines by BCM number, as the attributes its kernel module
+/// drives them through. The module claims these, so sysfs can't export them.
+const IONOPI_LINES: [(u64, &str); 14] = [
+    (17, "relay/o1"), (27, "relay/o2"), (22, "relay/o3"), (23, "relay/o4"),
+    (7, "led/l1"),
+    (18, "open_coll/oc1"), (25, "open_coll/oc2"), (24, "open_coll/oc3"),
+    (16, "digital_in/di1"), (19, "digital_in/di2"), (20, "digital_in/di3"),
+    (21, "digital_in/di4"), (26, "digital_in/di5"), (4, "digital_in/di6"),
+];
+
+/// How often inputs read through the Iono Pi module are checked, as its
+/// attributes can't be waited on.
+const IONOPI_POLL: Duration = Duration::from_millis(10);
+
+/// Extra attempts at a failed read, 5ms, 20ms and 80ms apart.
+const READ_RETRIES: usize = 3;
+
+/// How long the RF thread waits on its line before checking whether
+/// anyone still wants its codes.
+const RF_IDLE_MS: isize = 1000;
+
+/// Values read from an input pin on each change.
+pub type PinStream = BoxStream<'static, Result<u8, sysfs_gpio::Error>>;
+
+/// Codes decoded from the RF receiver.
+pub type CodeStream = BoxStream<'static, Result<u32, sysfs_gpio::Error>>;
+
+pub struct Hardware {
+    backend: Backend,
+    pulse: Duration,
+    lock: Mutex<()>,
+}
+
+enum Backend {
+    Gpio(Box<Pins>),
+    /// The virtual door, with the config saying which sensors and outputs
+    /// it has.
+    Simulated(Simulator, Box<GpioConfig>),
+}
+
+struct Pins {
+    led: Option<Output>,
+    warning: Option<Output>,
+    maintenance: Option<Output>,
+    relay: Output,
+    status: Input,
+    open: Option<Input>,
+    input: Input,
+    vehicle: Option<Input>,
+    obstruction: Option<Input>,
+    encoder: Option<Input>,
+    /// Zone sensors as `(percent, pin)`.
+    zones: Vec<(u8, Input)>,
+    /// Wiegand keypad data lines D0 and D1.
+    keypad: Option<(Input, Input)>,
+    /// 433 MHz receiver's data line.
+    rf: Option<Input>,
+    /// `gpio.extra`'s outputs and inputs, in config order.
+    extra_outputs: Vec<Output>,
+    extra_inputs: Vec<Input>,
+}
+
+enum Input {
+    Gpio(Pin),
+    /// An Iono Pi digital input, read through the kernel module.
+    IonoPi { path: PathBuf, invert: bool, edge: Edge },
+}
+
+/// The Iono Pi module's attribute for BCM line `num`, if it drives it.
+pub(crate) fn ionopi_line(dir: &Path, num: u64) -> Option<PathBuf> {
+    IONOPI_LINES.iter().find(|(n, _)| *n == num).map(|(_, attr)| dir.join(attr))
+}
+
+/// Whether `backend` can be used here, or why not.
+pub fn probe(backend: HardwareBackend, config: &GpioConfig) -> Result<(), String> {
+    match backend {
+        HardwareBackend::IonoPi if !config.ionopi_dir.is_dir() => {
+            Err(format!("{} not found, is the ionopi module loaded?", config.ionopi_dir.display()))
+        }
+        HardwareBackend::IonoPi | HardwareBackend::Simulated => Ok(()),
+        HardwareBackend::Sysfs => {
+            let labels = gpiochip_labels(Path::new(GPIO_CLASS)).map_err(|e| format!("{}: {}", GPIO_CLASS, e))?;
+            if labels.is_empty() {
+                return Err(format!("no gpio chips under {}", GPIO_CLASS));
+            }
+            debug!(?labels, "found gpio chips");
+            Ok(())
+        }
+    }
+}
+
+/// The labels of the GPIO chips listed under `class`.
+fn gpiochip_labels(class: &Path) -> io::Result<Vec<String>> {
+    let mut labels = Vec::new();
+    for entry in std::fs::read_dir(class)? {
+        let dir = entry?.path();
+        if dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("gpiochip")) {
+            labels.push(std::fs::read_to_string(dir.join("label"))?.trim().to_owned());
+        }
+    }
+    Ok(labels)
+}
+
+/// Exports `pin`, first releasing any export left behind by a run that
+/// didn't shut down cleanly, so setup starts from the kernel's defaults.
+pub(crate) fn claim(name: &'static str, pin: Pin) -> Result<(), GpioError> {
+    if pin.is_exported() {
+        warn!(pin = name, num = pin.get_pin(), "pin still exported by a previous run, reclaiming");
+        pin.unexport().map_err(|e| GpioError::new(name, "unexport", e))?;
+    }
+    pin.export().map_err(|e| GpioError::new(name, "export", e))
+}
+
+/// Sets the direction of a freshly claimed pin. udev may still be fixing
+/// up the permissions of its files, so access errors are retried briefly.
+pub(crate) fn set_direction(name: &'static str, pin: Pin, direction: Direction) -> Result<(), GpioError> {
+    let mut attempts = 0;
+    loop {
+        match pin.set_direction(direction) {
+            Err(sysfs_gpio::Error::Io(e)) if e.kind() == io::ErrorKind::PermissionDenied && attempts < 20 => {
+                attempts += 1;
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            result => return result.map_err(|e| GpioError::new(name, "set_direction", e)),
+        }
+    }
+}
+
+/// Reads an input, retrying with backoff so a single glitch doesn't count
+/// as a failed sensor. The backoff sleeps on tokio's timer, leaving the
+/// daemon loop free in the meantime.
+async fn read(name: &'static str, input: &Input) -> Result<u8, GpioError> {
+    let mut delay = Duration::from_millis(5);
+    for _ in 0..READ_RETRIES {
+        match input.value() {
+            Ok(v) => return Ok(v),
+            Err(e) => {
+                debug!(pin = name, error = %e, "gpio read failed, retrying");
+                sleep(delay).await;
+                delay *= 4;
+            }
+        }
+    }
+    input.value().map_err(|e| GpioError::new(name, "read", e))
+}
+
+/// Reads an optional input as on or off.
+async fn read_flag(name: &'static str, input: Option<&Input>) -> Result<Option<bool>, GpioError> {
+    match input {
+        Some(input) => Ok(Some(read(name, input).await? != 0)),
+        None => Ok(None),
+    }
+}
+
+impl Input {
+    fn value(&self) -> Result<u8, sysfs_gpio::Error> {
+        match self {
+            Input::Gpio(pin) => pin.get_value(),
+            Input::IonoPi { path, invert, .. } => read_ionopi(path, *invert),
+        }
+    }
+
+    /// Values on each change, as far as the input's edges go.
+    fn stream(&self, name: &'static str) -> Result<PinStream, GpioError> {
+        match self {
+            Input::Gpio(pin) => pin.get_value_stream().map(StreamExt::boxed).map_err(|e| GpioError::new(name, "stream", e)),
+            Input::IonoPi { path, invert, edge } => {
+                let (path, invert, edge) = (path.clone(), *invert, *edge);
+                let last = read_ionopi(&path, invert).map_err(|e| GpioError::new(name, "read", e))?;
+                let mut ticks = interval(IONOPI_POLL);
+                ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                Ok(stream::unfold((ticks, last), move |(mut ticks, mut last)| {
+                    let path = path.clone();
+                    async move {
+                        loop {
+                            ticks.tick().await;
+                            let value = match read_ionopi(&path, invert) {
+                                Ok(v) => v,
+                                Err(e) => return Some((Err(e), (ticks, last))),
+                            };
+                            if value == last {
+                                continue;
+                            }
+                            last = value;
+                            let wanted = match edge {
+                                Edge::BothEdges => true,
+                                Edge::RisingEdge => value == 1,
+                                Edge::FallingEdge => value == 0,
+                                Edge::NoInterrupt => false,
+                            };
+                            if wanted {
+                                return Some((Ok(value), (ticks, last)));
+                            }
+                        }
+                    }
+                }).boxed())
+            }
+        }
+    }
+
+    fn release(&self) {
+        if let Input::Gpio(pin) = self {
+            let _ = pin.unexport();
+        }
+    }
+}
+
+fn read_ionopi(path: &Path, invert: bool) -> Result<u8, sysfs_gpio::Error> {
+    let value = std::fs::read_to_string(path)?;
+    match value.trim() {
+        "0" => Ok(u8::from(invert)),
+        "1" => Ok(u8::from(!invert)),
+        other => Err(sysfs_gpio::Error::Unexpected(format!("unexpected value {:?} in {}", other, path.display()))),
+    }
+}
+
+/// The sysfs GPIO number of `line` on `chip`, found by the chip's label or
+/// its device name among the chips listed under `class`.
+pub fn resolve_line(class: &Path, chip: &str, line: u32) -> io::Result<u64> {
+    for entry in std::fs::read_dir(class)? {
+        let dir = entry?.path();
+        if !dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with("gpiochip")) {
+            continue;
+        }
+        let read = |attr: &str| std::fs::read_to_string(dir.join(attr)).map(|s| s.trim().to_owned());
+        // Newer kernels number the sysfs entries from 512 up, so the device
+        // name behind the link is the one `gpiodetect` shows.
+        let device = std::fs::read_link(dir.join("device")).ok()
+            .and_then(|d| d.file_name().map(|n| n.to_string_lossy().into_owned()));
+        if read("label").ok().as_deref() != Some(chip) && device.as_deref() != Some(chip) {
+            continue;
+        }
+        let parse = |attr: &str| read(attr)?.parse::<u64>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        let (base, count) = (parse("base")?, parse("ngpio")?);
+        if u64::from(line) >= count {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} only has {} lines", chip, count)));
+        }
+        return Ok(base + u64::from(line));
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("no gpio chip named {}", chip)))
+}
+
+/// Sets the bias resistor with the Raspberry Pi's `pinctrl`, as the sysfs
+/// interface has no way to.
+fn set_pull(name: &'static str, line: GpioLine<'_>, pull: Pull) -> Result<(), GpioError> {
+    let mut command = Command::new("pinctrl");
+    match line {
+        GpioLine::Number(pin) => command.arg("set").arg(pin.to_string()),
+        GpioLine::Chip { chip, line } => command.args(["-c", chip, "set"]).arg(line.to_string()),
+    };
+    command.arg(match pull {
+        Pull::Up => "pu",
+        Pull::Down => "pd",
+        Pull::None => "pn",
+    });
+    let output = command.output().map_err(|e| GpioError::new(name, "set_pull", sysfs_gpio::Error::Io(e)))?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(GpioError::new(name, "set_pull", sysfs_gpio::Error::Unexpected(message)));
+    }
+    Ok(())
+}
+
+/// Sets up an input, listening on `edge` unless its config says otherwise.
+/// With `ionopi` set, the Iono Pi's own inputs go through its module.
+fn input_pin(name: &'static str, config: &PinConfig, edge: Edge, ionopi: Option<&Path>) -> Result<Input, GpioError> {
+    let line = config.line().ok_or_else(|| {
+        GpioError::new(name, "configure", sysfs_gpio::Error::Unexpected("neither a pin nor a chip and line set".to_owned()))
+    })?;
+    let num = match line {
+        GpioLine::Number(pin) => pin,
+        GpioLine::Chip { chip, line } => resolve_line(Path::new(GPIO_CLASS), chip, line)
+            .map_err(|e| GpioError::new(name, "resolve_line", sysfs_gpio::Error::Io(e)))?,
+    };
+    let edge = match config.edge {
+        Some(EdgeConfig::Rising) => Edge::RisingEdge,
+        Some(EdgeConfig::Falling) => Edge::FallingEdge,
+        Some(EdgeConfig::Both) => Edge::BothEdges,
+        Some(EdgeConfig::None) => Edge::NoInterrupt,
+        None => edge,
+    };
+    debug!(pin = name, num, invert = config.invert, ?edge, pull = ?config.pull, "initializing pin");
+    if let (GpioLine::Number(_), Some(path)) = (line, ionopi.and_then(|dir| ionopi_line(dir, num))) {
+        if config.pull.is_some() {
+            warn!(pin = name, "the iono pi's inputs have no pull to set, ignoring it");
+        }
+        read_ionopi(&path, config.invert).map_err(|e| GpioError::new(name, "read", e))?;
+        return Ok(Input::IonoPi { path, invert: config.invert, edge });
+    }
+    if let Some(pull) = config.pull {
+        set_pull(name, line, pull)?;
+    }
+    let pin = Pin::new(num);
+    claim(name, pin)?;
+    set_direction(name, pin, Direction::In)?;
+    pin.set_active_low(config.invert).map_err(|e| GpioError::new(name, "set_active_low", e))?;
+    pin.set_edge(edge).map_err(|e| GpioError::new(name, "set_edge", e))?;
+    Ok(Input::Gpio(pin))
+}
+
+impl Hardware {
+    /// Starts the first backend in `config.backends` that is usable here,
+    /// moving on to the next if one can't be found or fails to start.
+    pub fn detect(config: &GpioConfig, keypad: Option<&KeypadConfig>, rf: Option<&RfConfig>, simulation: &SimulationConfig) -> Result<Hardware, GpioError> {
+        let mut failure = None;
+        for &backend in &config.backends {
+            if let Err(reason) = probe(backend, config) {
+                info!(%backend, reason, "hardware backend not available");
+                continue;
+            }
+            let hw = match backend {
+                HardwareBackend::IonoPi => Hardware::init(config, keypad, rf, Some(&config.ionopi_dir)),
+                HardwareBackend::Sysfs => Hardware::init(config, keypad, rf, None),
+                HardwareBackend::Simulated => Ok(Hardware::simulate(config, simulation)),
+            };
+            match hw {
+                Ok(hw) => {
+                    info!(%backend, "using hardware backend");
+                    return Ok(hw);
+                }
+                Err(e) => {
+                    warn!(%backend, error = %e, source = %e.source, "hardware backend failed to start");
+                    failure = Some(e);
+                }
+            }
+        }
+        Err(failure.unwrap_or_else(|| {
+            GpioError::new("gpio", "detect", sysfs_gpio::Error::Unsupported("none of gpio.backends is available".to_owned()))
+        }))
+    }
+
+    /// Claims the pins, with the Iono Pi's own lines going through its
+    /// module under `ionopi` if set.
+    pub fn init(config: &GpioConfig, keypad: Option<&KeypadConfig>, rf: Option<&RfConfig>, ionopi: Option<&Path>) -> Result<Hardware, GpioError> {
+        let led_pin = match &config.led {
+            Some(led) => Some(Output::init("led", led, ionopi)?),
+            None => None,
+        };
+
+        let warning = match &config.warning {
+            Some(warning) => Some(Output::init("warning", warning, ionopi)?),
+            None => None,
+        };
+        let maintenance = match &config.maintenance {
+            Some(maintenance) => Some(Output::init("maintenance", maintenance, ionopi)?),
+            None => None,
+        };
+        let relay_pin = Output::init("relay", &config.relay, ionopi)?;
+        let status_pin = input_pin("status", &config.status, Edge::BothEdges, ionopi)?;
+        let open_pin = match &config.open {
+            Some(open) => Some(input_pin("open", open, Edge::BothEdges, ionopi)?),
+            None => None,
+        };
+        let vehicle_pin = match &config.vehicle {
+            Some(vehicle) => Some(input_pin("vehicle", vehicle, Edge::NoInterrupt, ionopi)?),
+            None => None,
+        };
+        let obstruction_pin = match &config.obstruction {
+            Some(obstruction) => Some(input_pin("obstruction", obstruction, Edge::BothEdges, ionopi)?),
+            None => None,
+        };
+        let encoder_pin = match &config.encoder {
+            Some(encoder) => Some(input_pin("encoder", encoder, Edge::RisingEdge, ionopi)?),
+            None => None,
+        };
+        let zones = config.zones.iter()
+            .map(|z| Ok((z.percent, input_pin("zone", &z.pin_config(), Edge::BothEdges, ionopi)?)))
+            .collect::<Result<Vec<_>, GpioError>>()?;
+        let keypad = match keypad {
+            Some(k) => Some((
+                input_pin("keypad_d0", &k.d0, Edge::FallingEdge, ionopi)?,
+                input_pin("keypad_d1", &k.d1, Edge::FallingEdge, ionopi)?,
+            )),
+            None => None,
+        };
+        let rf = match rf {
+            Some(r) => Some(input_pin("rf_data", &r.data, Edge::BothEdges, ionopi)?),
+            None => None,
+        };
+        let extra_outputs = config.extra.outputs.iter()
+            .map(|o| Output::init("extra_output", &o.output, ionopi))
+            .collect::<Result<Vec<_>, GpioError>>()?;
+        let extra_inputs = config.extra.inputs.iter()
+            .map(|i| input_pin("extra_input", &i.input, Edge::BothEdges, ionopi))
+            .collect::<Result<Vec<_>, GpioError>>()?;
+        let input_pin = input_pin("input", &config.input, Edge::RisingEdge, ionopi)?;
+
+        let pins = Pins {
+            led: led_pin,
+            warning,
+            maintenance,
+            relay: relay_pin,
+            status: status_pin,
+            open: open_pin,
+            input: input_pin,
+            vehicle: vehicle_pin,
+            obstruction: obstruction_pin,
+            encoder: encoder_pin,
+            zones,
+            keypad,
+            rf,
+            extra_outputs,
+            extra_inputs,
+        };
+        Ok(Hardware { backend: Backend::Gpio(Box::new(pins)), pulse: config.pulse(), lock: Mutex::new(()) })
+    }
+
+    /// A virtual door in place of the pins; see [`crate::simulate`]. The
+    /// sensors and outputs configured in `config` are fitted, the pin
+    /// numbers are ignored and there is no encoder, keypad or RF receiver.
+    pub fn simulate(config: &GpioConfig, simulation: &SimulationConfig) -> Hardware {
+        let door = Simulator::spawn(simulation);
+        Hardware { backend: Backend::Simulated(door, Box::new(config.clone())), pulse: config.pulse(), lock: Mutex::new(()) }
+    }
+
+    /// The virtual door, when simulating.
+    pub fn simulator(&self) -> Option<&Simulator> {
+        match &self.backend {
+            Backend::Gpio(_) => None,
+            Backend::Simulated(door, _) => Some(door),
+        }
+    }
+
+    pub fn status_stream(&self) -> Result<PinStream, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.status.stream("status"),
+            Backend::Simulated(door, _) => Ok(door.stream(|s| s.closed)),
+        }
+    }
+
+    /// Changes on the open sensor, if one is configured.
+    pub fn open_stream(&self) -> Result<Option<PinStream>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.open.as_ref().map(|pin| pin.stream("open")).transpose(),
+            Backend::Simulated(door, config) => Ok(config.open.as_ref().map(|_| door.stream(|s| s.open))),
+        }
+    }
+
+    /// Changes on the safety beam, if one is configured.
+    pub fn obstruction_stream(&self) -> Result<Option<PinStream>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.obstruction.as_ref().map(|pin| pin.stream("obstruction")).transpose(),
+            Backend::Simulated(door, config) => Ok(config.obstruction.as_ref().map(|_| door.stream(|s| s.obstructed))),
+        }
+    }
+
+    /// Pulses from the rotary encoder, if one is configured.
+    pub fn encoder_stream(&self) -> Result<Option<PinStream>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.encoder.as_ref().map(|pin| pin.stream("encoder")).transpose(),
+            Backend::Simulated(..) => Ok(None),
+        }
+    }
+
+    /// Changes on the zone sensors, one stream per sensor.
+    pub fn zone_streams(&self) -> Result<Vec<PinStream>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.zones.iter().map(|(_, pin)| pin.stream("zone")).collect(),
+            Backend::Simulated(door, config) => Ok(config.zones.iter()
+                .map(|z| {
+                    let percent = z.percent;
+                    door.stream(move |s| s.at_zone(percent))
+                })
+                .collect()),
+        }
+    }
+
+    /// Reads the zone sensors as `(percent, active)`.
+    pub async fn zone_readings(&self) -> Result<Vec<(u8, bool)>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => {
+                let mut readings = Vec::with_capacity(pins.zones.len());
+                for (percent, pin) in &pins.zones {
+                    readings.push((*percent, read("zone", pin).await? != 0));
+                }
+                Ok(readings)
+            }
+            Backend::Simulated(door, config) => {
+                let sensors = door.sensors();
+                Ok(config.zones.iter().map(|z| (z.percent, sensors.at_zone(z.percent))).collect())
+            }
+        }
+    }
+
+    /// Pulses on the keypad's data lines, as `false` for D0 and `true` for
+    /// D1, if a keypad is configured.
+    pub fn keypad_stream(&self) -> Result<Option<BoxStream<'static, Result<bool, sysfs_gpio::Error>>>, GpioError> {
+        let (d0, d1) = match &self.backend {
+            Backend::Gpio(pins) => match &pins.keypad {
+                Some(keypad) => keypad,
+                None => return Ok(None),
+            },
+            Backend::Simulated(..) => return Ok(None),
+        };
+        let d0 = d0.stream("keypad_d0")?;
+        let d1 = d1.stream("keypad_d1")?;
+        Ok(Some(stream::select(d0.map(|r| r.map(|_| false)), d1.map(|r| r.map(|_| true))).boxed()))
+    }
+
+    /// Codes from the RF receiver, if one is configured. Its line is
+    /// waited on and decoded by a thread of its own, so edges are timed as
+    /// they happen rather than whenever the daemon loop gets to them, and
+    /// a noisy receiver only wakes the loop for whole codes.
+    pub fn rf_codes(&self) -> Result<Option<CodeStream>, GpioError> {
+        let data = match &self.backend {
+            Backend::Gpio(pins) => match &pins.rf {
+                Some(data) => data,
+                None => return Ok(None),
+            },
+            Backend::Simulated(..) => return Ok(None),
+        };
+        let pin = match data {
+            Input::Gpio(pin) => pin,
+            Input::IonoPi { .. } => {
+                let e = sysfs_gpio::Error::Unexpected("iono pi lines are polled too slowly to time rf pulses".to_owned());
+                return Err(GpioError::new("rf_data", "stream", e));
+            }
+        };
+        let mut poller = pin.get_poller().map_err(|e| GpioError::new("rf_data", "stream", e))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::Builder::new()
+            .name("rf".to_owned())
+            .spawn(move || {
+                let mut decoder = Ev1527::default();
+                while !tx.is_closed() {
+                    let sent = match poller.poll(RF_IDLE_MS) {
+                        Ok(Some(value)) => match decoder.edge(value != 0, std::time::Instant::now()) {
+                            Some(code) => tx.send(Ok(code)),
+                            None => Ok(()),
+                        },
+                        Ok(None) => Ok(()),
+                        Err(e) => {
+                            let sent = tx.send(Err(e));
+                            std::thread::sleep(Duration::from_secs(1));
+                            sent
+                        }
+                    };
+                    if sent.is_err() {
+                        return;
+                    }
+                }
+            })
+            .map_err(|e| GpioError::new("rf_data", "spawn", sysfs_gpio::Error::Io(e)))?;
+        Ok(Some(stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|code| (code, rx)) }).boxed()))
+    }
+
+    /// Changes on `gpio.extra`'s inputs, one stream per input. The virtual
+    /// door's never change.
+    pub fn extra_streams(&self) -> Result<Vec<PinStream>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.extra_inputs.iter().map(|pin| pin.stream("extra_input")).collect(),
+            Backend::Simulated(_, config) => Ok(config.extra.inputs.iter().map(|_| stream::pending().boxed()).collect()),
+        }
+    }
+
+    /// Reads `gpio.extra`'s inputs, in config order.
+    pub async fn extra_readings(&self) -> Result<Vec<bool>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => {
+                let mut readings = Vec::with_capacity(pins.extra_inputs.len());
+                for pin in &pins.extra_inputs {
+                    readings.push(read("extra_input", pin).await? != 0);
+                }
+                Ok(readings)
+            }
+            Backend::Simulated(_, config) => Ok(vec![false; config.extra.inputs.len()]),
+        }
+    }
+
+    /// Switches the `index`th of `gpio.extra`'s outputs.
+    pub fn set_extra(&self, index: usize, active: bool) -> Result<(), GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.extra_outputs.get(index).map_or(Ok(()), |o| o.set("extra_output", active)),
+            Backend::Simulated(_, config) => {
+                if let Some(output) = config.extra.outputs.get(index) {
+                    info!(id = %output.id, active, "simulated extra output switched");
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn input_stream(&self) -> Result<PinStream, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.input.stream("input"),
+            Backend::Simulated(door, _) => Ok(door.button_stream()),
+        }
+    }
+
+    pub async fn door_status(&self) -> Result<Status, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => read("status", &pins.status).await.map(parse_door_status),
+            Backend::Simulated(door, _) => Ok(parse_door_status(u8::from(door.sensors().closed))),
+        }
+    }
+
+    /// Reads the open sensor, if one is configured.
+    pub async fn fully_open(&self) -> Result<Option<bool>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => read_flag("open", pins.open.as_ref()).await,
+            Backend::Simulated(door, config) => Ok(config.open.as_ref().map(|_| door.sensors().open)),
+        }
+    }
+
+    pub fn has_open_sensor(&self) -> bool {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.open.is_some(),
+            Backend::Simulated(_, config) => config.open.is_some(),
+        }
+    }
+
+    pub fn has_vehicle_sensor(&self) -> bool {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.vehicle.is_some(),
+            Backend::Simulated(_, config) => config.vehicle.is_some(),
+        }
+    }
+
+    pub fn has_obstruction_sensor(&self) -> bool {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.obstruction.is_some(),
+            Backend::Simulated(_, config) => config.obstruction.is_some(),
+        }
+    }
+
+    /// Reads the vehicle presence sensor, if one is configured.
+    pub async fn vehicle_present(&self) -> Result<Option<bool>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => read_flag("vehicle", pins.vehicle.as_ref()).await,
+            Backend::Simulated(door, config) => Ok(config.vehicle.as_ref().map(|_| door.sensors().vehicle)),
+        }
+    }
+
+    /// Reads the safety beam, if one is configured.
+    pub async fn obstructed(&self) -> Result<Option<bool>, GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => read_flag("obstruction", pins.obstruction.as_ref()).await,
+            Backend::Simulated(door, config) => Ok(config.obstruction.as_ref().map(|_| door.sensors().obstructed)),
+        }
+    }
+
+    pub fn has_warning(&self) -> bool {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.warning.is_some(),
+            Backend::Simulated(_, config) => config.warning.is_some(),
+        }
+    }
+
+    /// Switches the warning buzzer or strobe, if one is configured.
+    pub fn set_warning(&self, active: bool) -> Result<(), GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.warning.as_ref().map_or(Ok(()), |w| w.set("warning", active)),
+            Backend::Simulated(_, config) if config.warning.is_some() => {
+                info!(active, "simulated warning switched");
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Switches the maintenance light or sign, if one is configured.
+    pub fn set_maintenance(&self, active: bool) -> Result<(), GpioError> {
+        match &self.backend {
+            Backend::Gpio(pins) => pins.maintenance.as_ref().map_or(Ok(()), |m| m.set("maintenance", active)),
+            Backend::Simulated(_, config) if config.maintenance.is_some() => {
+                info!(active, "simulated maintenance output switched");
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_pulse(&mut self, pulse: Duration) {
+        self.pulse = pulse;
+    }
+
+    /// Hands over the indicator LED, if one is configured, to be driven as
+    /// a status light. The virtual door has none.
+    pub fn take_led(&mut self) -> Option<Output> {
+        match &mut self.backend {
+            Backend::Gpio(pins) => pins.led.take(),
+            Backend::Simulated(..) => None,
+        }
+    }
+
+    pub async fn trigger_relay(&self) -> Result<(), GpioError> {
+        let _guard = self.lock.lock().await;
+        info!(pulse_ms = self.pulse.as_millis() as u64, "triggering door relay");
+        match &self.backend {
+            Backend::Gpio(pins) => {
+                pins.relay.set("relay", true)?;
+                sleep(self.pulse).await;
+                pins.relay.set("relay", false)?;
+            }
+            Backend::Simulated(door, _) => {
+                sleep(self.pulse).await;
+                door.poke(Poke::Press);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Pins {
+    fn drop(&mut self) {
+        if let Some(led) = &self.led {
+            led.release("led");
+        }
+        if let Some(warning) = &self.warning {
+            warning.release("warning");
+        }
+        if let Some(maintenance) = &self.maintenance {
+            maintenance.release("maintenance");
+        }
+        self.relay.release("relay");
+        self.status.release();
+        self.open.iter().chain(self.vehicle.iter()).chain(self.obstruction.iter()).chain(self.encoder.iter()).for_each(Input::release);
+        self.input.release();
+        for (_, zone) in &self.zones {
+            zone.release();
+        }
+        if let Some((d0, d1)) = &self.keypad {
+            d0.release();
+            d1.release();
+        }
+        self.rf.iter().for_each(Input::release);
+        for output in &self.extra_outputs {
+            output.release("extra_output");
+        }
+        self.extra_inputs.iter().for_each(Input::release);
+    }
+}
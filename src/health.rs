@@ -0,0 +1,163 @@
+//! On-demand spring-balance health check.
+//!
+//! Starting from closed, the door is opened, left to settle, then closed
+//! again while the close run is timed and compared to the learned baseline.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use strum::Display;
+use tokio::time::Instant;
+
+use crate::config::HealthCheckConfig;
+use crate::door::Status;
+
+/// How long the door has to start moving after the relay is triggered.
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    #[strum(serialize = "pass")]
+    Pass,
+    #[strum(serialize = "warn")]
+    Warn,
+    #[strum(serialize = "fail")]
+    Fail,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub result: Verdict,
+    pub close_secs: Option<f64>,
+    pub baseline_secs: f64,
+    pub delta_secs: Option<f64>,
+    pub delta_pct: Option<f64>,
+    /// Current draw comparison, once a current sensor is available.
+    pub current_delta: Option<f64>,
+    pub detail: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Opening,
+    Settling,
+    Closing,
+}
+
+/// What the daemon should do after feeding the check an event.
+#[derive(Debug)]
+pub enum Step {
+    Wait,
+    Actuate,
+    Done(HealthReport),
+}
+
+#[derive(Debug)]
+pub struct HealthCheck {
+    config: HealthCheckConfig,
+    travel: Duration,
+    baseline: Duration,
+    phase: Phase,
+    phase_started: Instant,
+    deadline: Instant,
+}
+
+impl HealthCheck {
+    /// Begins a check. The caller is expected to trigger the relay to open.
+    pub fn start(config: HealthCheckConfig, travel: Duration, baseline: Duration) -> HealthCheck {
+        let now = Instant::now();
+        HealthCheck {
+            config,
+            travel,
+            baseline,
+            phase: Phase::Opening,
+            phase_started: now,
+            deadline: now + START_TIMEOUT,
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn door_changed(&mut self, status: Status) -> Step {
+        let now = Instant::now();
+        match (self.phase, status) {
+            (Phase::Opening, Status::Open) => {
+                self.phase = Phase::Settling;
+                self.phase_started = now;
+                self.deadline = now + self.travel + self.config.settle();
+                Step::Wait
+            }
+            (Phase::Closing, Status::Closed) => {
+                Step::Done(self.evaluate(now - self.phase_started))
+            }
+            (Phase::Settling, Status::Closed) => {
+                Step::Done(self.failed("door closed before the test close run"))
+            }
+            _ => Step::Wait,
+        }
+    }
+
+    pub fn timeout(&mut self) -> Step {
+        let now = Instant::now();
+        match self.phase {
+            Phase::Opening => Step::Done(self.failed("door did not start opening")),
+            Phase::Settling => {
+                self.phase = Phase::Closing;
+                self.phase_started = now;
+                self.deadline = now + self.travel * 3;
+                Step::Actuate
+            }
+            Phase::Closing => Step::Done(self.failed("door did not close")),
+        }
+    }
+
+    pub fn aborted(&self) -> HealthReport {
+        self.failed("health check cancelled")
+    }
+
+    fn evaluate(&self, close: Duration) -> HealthReport {
+        let baseline = self.baseline.as_secs_f64();
+        let close = close.as_secs_f64();
+        let delta = close - baseline;
+        let delta_pct = delta / baseline * 100.0;
+        let result = if delta_pct.abs() >= self.config.fail_pct {
+            Verdict::Fail
+        } else if delta_pct.abs() >= self.config.warn_pct {
+            Verdict::Warn
+        } else {
+            Verdict::Pass
+        };
+        HealthReport {
+            result,
+            close_secs: Some(round(close)),
+            baseline_secs: round(baseline),
+            delta_secs: Some(round(delta)),
+            delta_pct: Some(round(delta_pct)),
+            current_delta: None,
+            detail: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn failed(&self, detail: &str) -> HealthReport {
+        HealthReport {
+            result: Verdict::Fail,
+            close_secs: None,
+            baseline_secs: round(self.baseline.as_secs_f64()),
+            delta_secs: None,
+            delta_pct: None,
+            current_delta: None,
+            detail: Some(detail.to_owned()),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+fn round(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
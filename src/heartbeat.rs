@@ -0,0 +1,40 @@
+//! Periodic heartbeat on `<base>/health`, a positive sign that the daemon is
+//! up and can read its sensors, for monitoring that would otherwise only
+//! see the last will when it is too late.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// The outcome of the last door sensor read.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorRead {
+    pub at: DateTime<Utc>,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+impl SensorRead {
+    pub fn ok(at: DateTime<Utc>) -> SensorRead {
+        SensorRead { at, ok: true, error: None }
+    }
+
+    pub fn failed(at: DateTime<Utc>, error: String) -> SensorRead {
+        SensorRead { at, ok: false, error: Some(error) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heartbeat {
+    pub door: &'static str,
+    pub timestamp: DateTime<Utc>,
+    pub uptime_secs: u64,
+    /// None until the sensors have been read once.
+    pub last_sensor_read: Option<SensorRead>,
+    /// Connections to the broker since the first.
+    pub mqtt_reconnects: u32,
+    /// Whether the relay cooldown has run out, so a command could press it.
+    pub relay_ready: bool,
+    pub relay_cooldown_secs: f64,
+    /// Presses waiting their turn at the relay.
+    pub relay_queue: usize,
+}
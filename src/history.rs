@@ -0,0 +1,259 @@
+//! A local record of everything that happened at the door, kept in SQLite
+//! so "who opened it at 3am" can be answered without Home Assistant's
+//! recorder.
+//!
+//! State changes, commands (carried out or refused), button presses, errors
+//! and every event published on the events topic each become a row. The
+//! database is owned by a thread of its own, so a slow SD card never holds
+//! up the daemon loop; rows older than the retention period are pruned
+//! hourly.
+
+use std::path::Path;
+use std::sync::mpsc as std_mpsc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::ToSql;
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, warn};
+
+use crate::config::HistoryConfig;
+use crate::error::HistoryError;
+use crate::subsystems::{StatusReporter, Subsystem, SubsystemStatus};
+
+/// Entries waiting to be written before new ones are dropped.
+const QUEUE: usize = 256;
+
+/// Rows returned when a query doesn't say.
+pub const DEFAULT_LIMIT: u32 = 50;
+
+/// Most rows a single query returns.
+pub const MAX_LIMIT: u32 = 1000;
+
+const PRUNE_EVERY: Duration = Duration::from_secs(3600);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        door TEXT NOT NULL,
+        event TEXT NOT NULL,
+        source TEXT,
+        identity TEXT,
+        detail TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Row id, increasing in the order entries were recorded. Unset until
+    /// the entry has been written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub door: String,
+    /// `state`, `command`, `button`, `error`, or the name of an event from
+    /// the events topic such as `access`.
+    pub event: String,
+    /// Where a command or press came from, e.g. `mqtt` or `keypad`.
+    pub source: Option<String>,
+    /// Who was behind it, if they were identified.
+    pub identity: Option<String>,
+    pub detail: Value,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Which entries to return, most recent first. Every filter is optional.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryQuery {
+    /// Echoed back on MQTT, so a requester can pick out its own reply.
+    pub request_id: Option<String>,
+    /// Defaults to [`DEFAULT_LIMIT`], capped at [`MAX_LIMIT`].
+    pub limit: Option<u32>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub event: Option<String>,
+    pub source: Option<String>,
+    pub identity: Option<String>,
+}
+
+impl HistoryQuery {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+}
+
+/// The history database, used directly by the history thread and tests.
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    pub fn open(path: &Path) -> Result<HistoryDb, HistoryError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        // Keeps readers from blocking the writer, and the other way round.
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(HistoryDb { conn })
+    }
+
+    /// Writes an entry, returning its row id.
+    pub fn record(&self, entry: &HistoryEntry) -> Result<i64, HistoryError> {
+        self.conn.execute(
+            "INSERT INTO events (door, event, source, identity, detail, timestamp) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![entry.door, entry.event, entry.source, entry.identity, entry.detail, entry.timestamp.timestamp_millis()],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let mut conditions = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(since) = query.since {
+            conditions.push("timestamp >= ?");
+            values.push(Box::new(since.timestamp_millis()));
+        }
+        if let Some(until) = query.until {
+            conditions.push("timestamp < ?");
+            values.push(Box::new(until.timestamp_millis()));
+        }
+        for (column, value) in [("event = ?", &query.event), ("source = ?", &query.source), ("identity = ?", &query.identity)] {
+            if let Some(value) = value {
+                conditions.push(column);
+                values.push(Box::new(value.clone()));
+            }
+        }
+        let filter = match conditions.is_empty() {
+            true => String::new(),
+            false => format!("WHERE {}", conditions.join(" AND ")),
+        };
+        let sql = format!(
+            "SELECT id, door, event, source, identity, detail, timestamp FROM events {} ORDER BY id DESC LIMIT {}",
+            filter,
+            query.limit(),
+        );
+        let mut statement = self.conn.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(values), entry)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Deletes entries from before `before`, returning how many.
+    pub fn prune(&self, before: DateTime<Utc>) -> Result<usize, HistoryError> {
+        let deleted = self.conn.execute("DELETE FROM events WHERE timestamp < ?1", params![before.timestamp_millis()])?;
+        Ok(deleted)
+    }
+}
+
+fn entry(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    let millis: i64 = row.get(6)?;
+    Ok(HistoryEntry {
+        id: Some(row.get(0)?),
+        door: row.get(1)?,
+        event: row.get(2)?,
+        source: row.get(3)?,
+        identity: row.get(4)?,
+        detail: row.get(5)?,
+        timestamp: DateTime::from_timestamp_millis(millis).unwrap_or_default(),
+    })
+}
+
+#[derive(Debug)]
+enum Request {
+    Record(HistoryEntry),
+    Query(HistoryQuery, oneshot::Sender<Result<Vec<HistoryEntry>, HistoryError>>),
+}
+
+/// Handle for recording and querying history.
+#[derive(Debug, Clone)]
+pub struct History {
+    tx: std_mpsc::SyncSender<Request>,
+}
+
+impl History {
+    /// Starts the history thread, which reports how writes go as the
+    /// `history` subsystem. `storage_dir` holds the database unless the
+    /// config names one.
+    pub fn spawn(config: &HistoryConfig, storage_dir: &Path, status: StatusReporter) -> History {
+        let path = config.path.clone().unwrap_or_else(|| storage_dir.join("history.db"));
+        let retention = chrono::Duration::days(i64::from(config.retention_days));
+        let (tx, rx) = std_mpsc::sync_channel(QUEUE);
+        let spawned = std::thread::Builder::new()
+            .name("history".to_owned())
+            .spawn(move || match HistoryDb::open(&path) {
+                Ok(db) => {
+                    info!(path = %path.display(), "opened history database");
+                    run(db, retention, rx, status);
+                }
+                Err(e) => {
+                    error!(path = %path.display(), error = %e, "failed to open history database");
+                    status.report(Subsystem::History, SubsystemStatus::failing(e.to_string()));
+                }
+            });
+        if let Err(e) = spawned {
+            error!(error = %e, "failed to start history thread");
+        }
+        History { tx }
+    }
+
+    /// Queues `entry` without waiting.
+    pub fn record(&self, entry: HistoryEntry) {
+        match self.tx.try_send(Request::Record(entry)) {
+            Ok(()) => (),
+            Err(std_mpsc::TrySendError::Full(_)) => warn!("history writes backed up, dropping entry"),
+            Err(std_mpsc::TrySendError::Disconnected(_)) => debug!("history not running, dropping entry"),
+        }
+    }
+
+    pub async fn query(&self, query: HistoryQuery) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let (reply, response) = oneshot::channel();
+        self.tx.try_send(Request::Query(query, reply)).map_err(|_| HistoryError::Unavailable)?;
+        response.await.map_err(|_| HistoryError::Unavailable)?
+    }
+}
+
+fn run(db: HistoryDb, retention: chrono::Duration, rx: std_mpsc::Receiver<Request>, status: StatusReporter) {
+    let mut failing = false;
+    let mut next_prune = Instant::now();
+    loop {
+        if Instant::now() >= next_prune {
+            match db.prune(Utc::now() - retention) {
+                Ok(0) => (),
+                Ok(deleted) => info!(deleted, "pruned old history entries"),
+                Err(e) => warn!(error = %e, "failed to prune history"),
+            }
+            next_prune = Instant::now() + PRUNE_EVERY;
+        }
+        let request = match rx.recv_timeout(next_prune.saturating_duration_since(Instant::now())) {
+            Ok(r) => r,
+            Err(std_mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+        match request {
+            Request::Record(entry) => match db.record(&entry) {
+                Ok(_) if failing => {
+                    info!("history writes working again");
+                    failing = false;
+                    status.report(Subsystem::History, SubsystemStatus::ok());
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    warn!(error = %e, event = %entry.event, "failed to record history");
+                    if !failing {
+                        failing = true;
+                        status.report(Subsystem::History, SubsystemStatus::failing(e.to_string()));
+                    }
+                }
+            },
+            Request::Query(query, reply) => {
+                let _ = reply.send(db.query(&query));
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+use hyper::body::HttpBody;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::api::{ApiHandle, Failure};
+use crate::auth::{Authenticator, Credential, CredentialKind, Identity};
+use crate::door::{parse_command, Source};
+use crate::error::Error;
+use crate::history::HistoryQuery;
+use crate::quarantine;
+
+/// Largest command body accepted, far more than any valid payload.
+const MAX_BODY: u64 = 1024;
+
+/// Binds the HTTP API's port, done before root is given up so that a port
+/// below 1024 can be used.
+pub fn bind(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    TcpListener::bind(addr)
+}
+
+/// Serves the HTTP API. Bearer tokens are checked against `auth` whenever
+/// present, so an admin token can override lockouts; with `require_token`
+/// every request needs one.
+pub async fn serve(listener: TcpListener, api: ApiHandle, auth: Arc<Authenticator>, require_token: bool) -> Result<(), hyper::Error> {
+    let make_svc = make_service_fn(move |_conn| {
+        let api = api.clone();
+        let auth = auth.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| handle(api.clone(), auth.clone(), require_token, req)))
+        }
+    });
+    let server = Server::from_tcp(listener)?.serve(make_svc);
+    info!(addr = %server.local_addr(), "http api listening");
+    server.await
+}
+
+async fn handle(api: ApiHandle, auth: Arc<Authenticator>, require_token: bool, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    debug!(method = %req.method(), path = req.uri().path(), "http request");
+    let identity = match authorize(&api, &auth, require_token, &req).await {
+        Ok(identity) => identity,
+        Err(response) => return Ok(response),
+    };
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/status") => json_response(StatusCode::OK, &api.snapshot()),
+        (&Method::GET, "/heatmap") => json_response(StatusCode::OK, &api.snapshot().heatmap.report()),
+        (&Method::GET, "/history") => history(api, &req).await,
+        (&Method::GET, "/quarantine") => json_response(StatusCode::OK, &api.snapshot().quarantine),
+        (&Method::GET, "/metrics") => metrics(&api),
+        (&Method::POST, "/quarantine/release") => release(api, identity, req).await,
+        (&Method::POST, "/maintenance") => maintenance(api, identity, req).await,
+        (&Method::POST, "/command") => command(api, identity, req, false).await,
+        (&Method::POST, "/query") => command(api, identity, req, true).await,
+        (_, "/status") | (_, "/heatmap") | (_, "/history") | (_, "/quarantine") | (_, "/metrics") | (_, "/quarantine/release")
+            | (_, "/maintenance") | (_, "/command") | (_, "/query") => empty(StatusCode::METHOD_NOT_ALLOWED),
+        _ => empty(StatusCode::NOT_FOUND),
+    };
+    Ok(response)
+}
+
+/// Checks the bearer token, if any. Quarantined tokens are refused before
+/// they are looked at, and unknown ones count towards quarantining them.
+async fn authorize(api: &ApiHandle, auth: &Authenticator, require_token: bool, req: &Request<Body>) -> Result<Option<Identity>, Response<Body>> {
+    let token = req.headers().get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let token = match token {
+        Some(t) => t,
+        None if require_token => return Err(unauthorized()),
+        None => return Ok(None),
+    };
+    let source = quarantine::token_key(token);
+    if api.snapshot().quarantine.iter().any(|q| q.source == source) {
+        warn!(path = req.uri().path(), %source, "rejected http request with quarantined token");
+        let failure = Failure { error: "quarantined", reason: None, message: "token is quarantined".to_owned() };
+        return Err(json_response(StatusCode::FORBIDDEN, &failure));
+    }
+    match auth.validate(&Credential::new(CredentialKind::Token, token)).await {
+        Ok(Some(identity)) => Ok(Some(identity)),
+        Ok(None) => {
+            warn!(path = req.uri().path(), "rejected http request with unknown token");
+            api.failed_auth(token);
+            Err(unauthorized())
+        }
+        Err(e) => Err(json_response(StatusCode::SERVICE_UNAVAILABLE, &Failure::from(&Error::from(e)))),
+    }
+}
+
+fn unauthorized() -> Response<Body> {
+    let failure = Failure { error: "unauthorized", reason: None, message: "missing or invalid token".to_owned() };
+    json_response(StatusCode::UNAUTHORIZED, &failure)
+}
+
+/// Runs the command in the body, or with `dry_run` only reports whether it
+/// would be accepted.
+async fn command(api: ApiHandle, identity: Option<Identity>, req: Request<Body>, dry_run: bool) -> Response<Body> {
+    let body = match read_body(req.into_body()).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let command = match parse_command(body.trim_ascii()) {
+        Ok(c) => c,
+        Err(e) => return failure_response(&Failure::from(&e)),
+    };
+    if dry_run {
+        return match api.query(command, identity).await {
+            Ok(decision) => json_response(StatusCode::OK, &decision),
+            Err(f) => failure_response(&f),
+        };
+    }
+    match api.command(command, Source::Http, identity).await {
+        Ok(()) => json_response(StatusCode::OK, &json!({ "ok": true, "command": command.to_string() })),
+        Err(f) => failure_response(&f),
+    }
+}
+
+/// Answers a history query given in the query string, e.g.
+/// `/history?event=command&since=2026-03-14T00:00:00Z&limit=20`.
+async fn history(api: ApiHandle, req: &Request<Body>) -> Response<Body> {
+    let query: HistoryQuery = match serde_urlencoded::from_str(req.uri().query().unwrap_or_default()) {
+        Ok(q) => q,
+        Err(e) => {
+            let failure = Failure { error: "invalid_query", reason: None, message: e.to_string() };
+            return json_response(StatusCode::BAD_REQUEST, &failure);
+        }
+    };
+    match api.history(query).await {
+        Ok(entries) => json_response(StatusCode::OK, &entries),
+        Err(f) => failure_response(&f),
+    }
+}
+
+/// Lifts a quarantine, named in the body or `all`. Needs an admin token.
+async fn release(api: ApiHandle, identity: Option<Identity>, req: Request<Body>) -> Response<Body> {
+    let identity = match identity {
+        Some(i) if i.admin => i,
+        _ => {
+            let failure = Failure { error: "forbidden", reason: None, message: "releasing a quarantine needs an admin token".to_owned() };
+            return json_response(StatusCode::FORBIDDEN, &failure);
+        }
+    };
+    let body = match read_body(req.into_body()).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let source = String::from_utf8_lossy(body.trim_ascii()).into_owned();
+    match api.release(source, format!("http:{}", identity.id)).await {
+        Ok(released) => json_response(StatusCode::OK, &json!({ "released": released })),
+        Err(f) => failure_response(&f),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaintenanceBody {
+    active: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Turns maintenance mode on or off, e.g. `{"active": true, "reason":
+/// "spring replacement"}`. Turning it off needs an admin token.
+async fn maintenance(api: ApiHandle, identity: Option<Identity>, req: Request<Body>) -> Response<Body> {
+    let body = match read_body(req.into_body()).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+    let body: MaintenanceBody = match serde_json::from_slice(&body) {
+        Ok(b) => b,
+        Err(e) => {
+            let failure = Failure { error: "invalid_body", reason: None, message: e.to_string() };
+            return json_response(StatusCode::BAD_REQUEST, &failure);
+        }
+    };
+    match api.maintenance(body.active, body.reason, identity).await {
+        Ok(state) => json_response(StatusCode::OK, &state),
+        Err(f) => failure_response(&f),
+    }
+}
+
+/// Reads a request body, giving up with a 413 as soon as it runs past
+/// `MAX_BODY`. A chunked body announces no length, so the bytes are counted
+/// as they arrive rather than trusting the size hint.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, Response<Body>> {
+    if body.size_hint().lower() > MAX_BODY {
+        return Err(empty(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| empty(StatusCode::BAD_REQUEST))?;
+        if (bytes.len() + chunk.len()) as u64 > MAX_BODY {
+            return Err(empty(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Counters in the Prometheus text format.
+fn metrics(api: &ApiHandle) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(api.snapshot().metrics.render()))
+        .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+fn failure_response(failure: &Failure) -> Response<Body> {
+    let status = match (failure.error, failure.reason) {
+        ("command_rejected", Some("invalid_payload")) => StatusCode::BAD_REQUEST,
+        ("command_rejected", Some("admin_required")) => StatusCode::FORBIDDEN,
+        ("command_rejected", _) => StatusCode::CONFLICT,
+        ("unavailable", _) | ("history", _) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    json_response(status, failure)
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(_) => empty(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+fn empty(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
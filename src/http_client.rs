@@ -0,0 +1,16 @@
+//! Shared outbound HTTP(S) client.
+
+use hyper::client::HttpConnector;
+use hyper::Client;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+
+pub type HttpsClient = Client<HttpsConnector<HttpConnector>>;
+
+pub fn build() -> HttpsClient {
+    let connector = HttpsConnectorBuilder::new()
+        .with_webpki_roots()
+        .https_or_http()
+        .enable_http1()
+        .build();
+    Client::builder().build(connector)
+}
@@ -0,0 +1,204 @@
+//! Local control over a Unix socket, for `garagectl` and scripts on the
+//! controller itself.
+//!
+//! Each connection takes requests as JSON, one per line, and answers each
+//! with a line of JSON: the result with `"ok": true`, or a [`Failure`].
+//!
+//! - `{"request": "status"}` answers with the same snapshot as the HTTP
+//!   API's `/status`.
+//! - `{"request": "command", "command": "OPEN"}` runs a command, as the
+//!   identity behind `credential` if one is given.
+//! - `{"request": "lock", "locked": true}` switches the vacation lock.
+//! - `{"request": "quarantine"}` lists the quarantined command sources, as
+//!   `/quarantine` does.
+//! - `{"request": "unblock", "source": "identity:alice", "token": "..."}`
+//!   lifts a quarantine, or all of them for `all`. Like
+//!   `/quarantine/release`, it needs an admin token.
+//! - `{"request": "events"}` answers once, then sends every event from the
+//!   events topic as it happens until the connection is closed.
+//!
+//! Anyone who can connect to the socket can use it, the way anyone on the
+//! broker can publish commands.
+
+use std::fs::Permissions;
+use std::io;
+use std::os::unix::fs::{chown, FileTypeExt, PermissionsExt};
+use std::sync::Arc;
+
+use nix::unistd::Group;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::api::{ApiHandle, Failure};
+use crate::auth::{Authenticator, Credential, CredentialKind, Identity};
+use crate::config::SocketConfig;
+use crate::door::{parse_command, Source};
+use crate::error::{Error, SocketError};
+use crate::quarantine;
+
+pub const DEFAULT_SOCKET: &str = "/run/garaged/garaged.sock";
+
+/// Longest request line accepted, far more than any valid request.
+const MAX_REQUEST: u64 = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    Command {
+        command: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        credential: Option<String>,
+    },
+    Lock {
+        locked: bool,
+    },
+    Quarantine,
+    Unblock {
+        source: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    Events,
+}
+
+/// Creates the socket, replacing one left behind by an earlier run, and
+/// gives it `config.mode` and `config.group`.
+pub fn bind(config: &SocketConfig) -> Result<UnixListener, SocketError> {
+    let path = &config.path;
+    let failed = |e| SocketError::Bind(path.clone(), e);
+    if std::fs::symlink_metadata(path).map(|m| m.file_type().is_socket()).unwrap_or(false) {
+        std::fs::remove_file(path).map_err(failed)?;
+    }
+    let listener = UnixListener::bind(path).map_err(failed)?;
+    std::fs::set_permissions(path, Permissions::from_mode(config.mode)).map_err(failed)?;
+    if let Some(name) = &config.group {
+        let group = Group::from_name(name)?.ok_or_else(|| SocketError::NoGroup(name.clone()))?;
+        chown(path, None, Some(group.gid.as_raw())).map_err(failed)?;
+    }
+    Ok(listener)
+}
+
+/// Serves connections on `listener`. Credentials sent with commands are
+/// checked against `auth`, so an admin token can override lockouts.
+pub async fn serve(listener: UnixListener, api: ApiHandle, auth: Arc<Authenticator>) -> io::Result<()> {
+    info!("control socket listening");
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let api = api.clone();
+        let auth = auth.clone();
+        tokio::spawn(async move {
+            if let Err(e) = connection(stream, api, auth).await {
+                debug!(error = %e, "control socket connection failed");
+            }
+        });
+    }
+}
+
+async fn connection(stream: UnixStream, api: ApiHandle, auth: Arc<Authenticator>) -> io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut read = BufReader::new(read);
+    loop {
+        let mut line = Vec::new();
+        (&mut read).take(MAX_REQUEST).read_until(b'\n', &mut line).await?;
+        if line.is_empty() {
+            return Ok(());
+        }
+        if line.last() != Some(&b'\n') && line.len() as u64 == MAX_REQUEST {
+            let failure = Failure { error: "invalid_request", reason: None, message: "request too long".to_owned() };
+            return reply(&mut write, &json!(failure)).await;
+        }
+        let request: Request = match serde_json::from_slice(&line) {
+            Ok(r) => r,
+            Err(e) => {
+                let failure = Failure { error: "invalid_request", reason: None, message: e.to_string() };
+                reply(&mut write, &json!(failure)).await?;
+                continue;
+            }
+        };
+        let response = match request {
+            Request::Status => Ok(json!({ "ok": true, "status": api.snapshot() })),
+            Request::Command { command, credential } => run(&api, &auth, &command, credential.as_deref()).await,
+            Request::Lock { locked } => api.vacation_lock(locked, "socket".to_owned()).await
+                .map(|locked| json!({ "ok": true, "vacation_lock": locked })),
+            Request::Quarantine => Ok(json!({ "ok": true, "quarantine": api.snapshot().quarantine })),
+            Request::Unblock { source, token } => unblock(&api, &auth, source, token.as_deref()).await,
+            Request::Events => {
+                let events = api.events();
+                reply(&mut write, &json!({ "ok": true })).await?;
+                return forward(events, &mut write).await;
+            }
+        };
+        reply(&mut write, &response.unwrap_or_else(|failure| json!(failure))).await?;
+    }
+}
+
+async fn run(api: &ApiHandle, auth: &Authenticator, command: &str, credential: Option<&str>) -> Result<Value, Failure> {
+    let command = parse_command(command.as_bytes()).map_err(|e| Failure::from(&e))?;
+    let identity = match credential {
+        Some(token) => identify(api, auth, token).await?,
+        None => None,
+    };
+    api.command(command, Source::Socket, identity).await?;
+    Ok(json!({ "ok": true, "command": command.to_string() }))
+}
+
+/// Lifts a quarantine for an admin.
+async fn unblock(api: &ApiHandle, auth: &Authenticator, source: String, token: Option<&str>) -> Result<Value, Failure> {
+    let identity = match token {
+        Some(token) => identify(api, auth, token).await?,
+        None => None,
+    };
+    let identity = match identity {
+        Some(i) if i.admin => i,
+        _ => {
+            warn!(%source, "refused to release quarantine without an admin token");
+            let message = "releasing a quarantine needs an admin token".to_owned();
+            return Err(Failure { error: "forbidden", reason: None, message });
+        }
+    };
+    let released = api.release(source, format!("socket:{}", identity.id)).await?;
+    Ok(json!({ "ok": true, "released": released }))
+}
+
+/// Looks up the identity behind a credential. Quarantined ones are refused
+/// before they are looked at, and unknown ones count towards quarantining
+/// them.
+async fn identify(api: &ApiHandle, auth: &Authenticator, token: &str) -> Result<Option<Identity>, Failure> {
+    let source = quarantine::token_key(token);
+    if api.snapshot().quarantine.iter().any(|q| q.source == source) {
+        warn!(%source, "rejected control socket request with quarantined token");
+        return Err(Failure { error: "quarantined", reason: None, message: "token is quarantined".to_owned() });
+    }
+    match auth.validate(&Credential::new(CredentialKind::Token, token)).await {
+        Ok(Some(identity)) => Ok(Some(identity)),
+        Ok(None) => {
+            warn!("rejected control socket request with unknown token");
+            api.failed_auth(token);
+            Err(Failure { error: "unauthorized", reason: None, message: "invalid token".to_owned() })
+        }
+        Err(e) => Err(Failure::from(&Error::from(e))),
+    }
+}
+
+/// Passes on events until the client goes away or the daemon stops.
+async fn forward(mut events: broadcast::Receiver<Value>, write: &mut OwnedWriteHalf) -> io::Result<()> {
+    loop {
+        match events.recv().await {
+            Ok(event) => reply(write, &event).await?,
+            Err(RecvError::Lagged(missed)) => warn!(missed, "control socket client fell behind on events"),
+            Err(RecvError::Closed) => return Ok(()),
+        }
+    }
+}
+
+async fn reply(write: &mut OwnedWriteHalf, value: &Value) -> io::Result<()> {
+    let mut line = value.to_string();
+    line.push('\n');
+    write.write_all(line.as_bytes()).await
+}
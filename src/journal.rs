@@ -0,0 +1,124 @@
+//! Pending time-based actions, persisted so a restart doesn't forget them.
+//!
+//! The daemon rewrites the journal whenever its set of pending actions
+//! changes. At startup, actions that came due while it was down are handled
+//! according to the configured [`CatchUp`] policy; the rest are resumed.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::countdown::CloseReason;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    /// The auto-close timer running out while the door is open.
+    #[strum(serialize = "auto_close")]
+    AutoClose,
+    /// A warning countdown ending in the door being closed.
+    #[strum(serialize = "close_countdown")]
+    CloseCountdown,
+}
+
+/// What to do at startup with an action that came due while down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CatchUp {
+    /// Carry the action out now. Closes still get a fresh countdown.
+    #[strum(serialize = "execute")]
+    Execute,
+    /// Drop it; timers start over.
+    #[strum(serialize = "skip")]
+    Skip,
+    /// Drop it, but publish a notification saying it was missed.
+    #[strum(serialize = "alert")]
+    Alert,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledAction {
+    pub kind: ActionKind,
+    pub due: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<CloseReason>,
+}
+
+impl ScheduledAction {
+    /// An action due at the monotonic `deadline`, given the wall time `now`.
+    pub fn at(kind: ActionKind, deadline: Instant, reason: Option<CloseReason>, now: DateTime<Utc>) -> ScheduledAction {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let due = now + chrono::Duration::from_std(remaining).unwrap_or_else(|_| chrono::Duration::zero());
+        ScheduledAction { kind, due, reason }
+    }
+
+    /// Time left before the action is due, or `None` if it is overdue.
+    pub fn remaining(&self, now: DateTime<Utc>) -> Option<Duration> {
+        (self.due - now).to_std().ok()
+    }
+
+    /// Whether `other` is the same action, allowing for the clock jitter of
+    /// converting monotonic deadlines to wall-clock time.
+    fn same(&self, other: &ScheduledAction) -> bool {
+        self.kind == other.kind
+            && self.reason == other.reason
+            && (self.due - other.due).num_milliseconds().abs() < 1000
+    }
+}
+
+/// JSON file holding the pending actions.
+pub struct Journal {
+    path: PathBuf,
+    saved: Vec<ScheduledAction>,
+}
+
+impl Journal {
+    pub fn new(dir: &Path) -> Journal {
+        Journal { path: dir.join("schedule.json"), saved: Vec::new() }
+    }
+
+    /// Reads the actions left by the previous run.
+    pub fn load(&mut self) -> Vec<ScheduledAction> {
+        let text = match std::fs::read(&self.path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                warn!(path = %self.path.display(), error = %e, "failed to read scheduled actions");
+                return Vec::new();
+            }
+        };
+        match serde_json::from_slice(&text) {
+            Ok(actions) => {
+                self.saved = actions;
+                self.saved.clone()
+            }
+            Err(e) => {
+                warn!(path = %self.path.display(), error = %e, "corrupt scheduled actions, ignoring");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Writes `actions` if they differ from what was last saved. A failed
+    /// write isn't retried until the actions change again.
+    pub fn update(&mut self, actions: Vec<ScheduledAction>) -> io::Result<()> {
+        let unchanged = actions.len() == self.saved.len()
+            && actions.iter().zip(&self.saved).all(|(a, b)| a.same(b));
+        if unchanged {
+            return Ok(());
+        }
+        self.saved = actions;
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.saved)?)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
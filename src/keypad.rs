@@ -0,0 +1,137 @@
+//! A Wiegand keypad by the door.
+//!
+//! Wiegand readers pulse one data line low for a 0 bit and the other for a
+//! 1 bit, and a pause ends the frame. Keypads send each key as a 4-bit
+//! frame, or as 8 bits with the complement in the high nibble; codes are
+//! typed as digits followed by `#`, and `*` starts over.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::KeypadConfig;
+
+/// Silence that ends a frame. Bits within a frame are 1-2ms apart.
+const FRAME_GAP: Duration = Duration::from_millis(25);
+
+/// Longest frame kept; anything longer is line noise.
+const MAX_BITS: usize = 64;
+
+/// Longest code accepted, so a stuck key can't grow the buffer forever.
+const MAX_DIGITS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    /// `0`-`9`, `*` or `#`.
+    Key(char),
+    /// A 26-bit card read, as facility code and card number.
+    Card(u32),
+    /// Wrong length or parity, with the bit count.
+    Invalid(usize),
+}
+
+/// Collects bits from the data lines into frames.
+#[derive(Debug, Default)]
+pub struct Wiegand {
+    bits: Vec<bool>,
+    last_bit: Option<Instant>,
+}
+
+impl Wiegand {
+    pub fn bit(&mut self, one: bool) {
+        if self.bits.len() < MAX_BITS {
+            self.bits.push(one);
+        }
+        self.last_bit = Some(Instant::now());
+    }
+
+    /// When the frame in progress, if any, is complete.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.last_bit.map(|at| at + FRAME_GAP)
+    }
+
+    /// Decodes and clears the frame collected so far.
+    pub fn finish(&mut self) -> Frame {
+        self.last_bit = None;
+        let bits = std::mem::take(&mut self.bits);
+        let value = bits.iter().fold(0u64, |acc, &b| acc << 1 | u64::from(b));
+        match bits.len() {
+            4 => key(value as u8).map_or(Frame::Invalid(4), Frame::Key),
+            8 if (value >> 4) as u8 == !(value as u8) & 0x0f => {
+                key(value as u8 & 0x0f).map_or(Frame::Invalid(8), Frame::Key)
+            }
+            26 => {
+                let even = bits[..13].iter().filter(|&&b| b).count() % 2 == 0;
+                let odd = bits[13..].iter().filter(|&&b| b).count() % 2 == 1;
+                match even && odd {
+                    true => Frame::Card((value >> 1) as u32 & 0xff_ffff),
+                    false => Frame::Invalid(26),
+                }
+            }
+            n => Frame::Invalid(n),
+        }
+    }
+}
+
+fn key(value: u8) -> Option<char> {
+    match value {
+        0..=9 => Some(char::from(b'0' + value)),
+        10 => Some('*'),
+        11 => Some('#'),
+        _ => None,
+    }
+}
+
+/// Keys typed so far, and the block after too many wrong codes.
+#[derive(Debug, Default)]
+pub struct PinEntry {
+    digits: String,
+    last_key: Option<Instant>,
+    failures: u32,
+    blocked_until: Option<Instant>,
+}
+
+impl PinEntry {
+    /// Takes a key, returning the code once `#` is pressed. Keys typed after
+    /// a long pause start a new code.
+    pub fn key(&mut self, key: char, config: &KeypadConfig) -> Option<String> {
+        let now = Instant::now();
+        if self.last_key.map(|at| now - at > config.entry_timeout()).unwrap_or(false) {
+            self.digits.clear();
+        }
+        self.last_key = Some(now);
+        match key {
+            '#' => Some(std::mem::take(&mut self.digits)).filter(|code| !code.is_empty()),
+            '*' => {
+                self.digits.clear();
+                None
+            }
+            digit => {
+                if self.digits.len() < MAX_DIGITS {
+                    self.digits.push(digit);
+                }
+                None
+            }
+        }
+    }
+
+    /// Whether codes are being ignored after too many wrong ones.
+    pub fn is_blocked(&self) -> bool {
+        self.blocked_until.map(|until| Instant::now() < until).unwrap_or(false)
+    }
+
+    /// Counts a wrong code, returning whether that blocks the keypad.
+    pub fn failed(&mut self, config: &KeypadConfig) -> bool {
+        self.failures += 1;
+        if self.failures < config.max_failures {
+            return false;
+        }
+        self.failures = 0;
+        self.blocked_until = Some(Instant::now() + config.block());
+        true
+    }
+
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+    }
+}
@@ -0,0 +1,121 @@
+//! The indicator LED as a status light.
+//!
+//! A task of its own drives the LED through a blink pattern and switches
+//! patterns as soon as the daemon hands it a new [`Indication`]:
+//!
+//! - solid while the door is closed;
+//! - a slow blink while it is open or stopped part way;
+//! - a fast blink while it is moving;
+//! - blink codes, repeated after a pause, when something is wrong: two
+//!   blinks while the broker is unreachable, three while a door sensor
+//!   can't be read.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+use crate::output::Output;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indication {
+    Off,
+    Closed,
+    Open,
+    Moving,
+    MqttDisconnected,
+    SensorFault,
+}
+
+/// The steps of one round of the pattern, as `(lit, how long)`. Rounds
+/// repeat until the indication changes.
+pub fn pattern(indication: Indication) -> Vec<(bool, Duration)> {
+    let ms = Duration::from_millis;
+    match indication {
+        Indication::Off => vec![(false, Duration::from_secs(3600))],
+        Indication::Closed => vec![(true, Duration::from_secs(3600))],
+        Indication::Open => vec![(true, ms(1000)), (false, ms(1000))],
+        Indication::Moving => vec![(true, ms(150)), (false, ms(150))],
+        Indication::MqttDisconnected => code(2),
+        Indication::SensorFault => code(3),
+    }
+}
+
+/// `blinks` short blinks followed by a pause.
+fn code(blinks: usize) -> Vec<(bool, Duration)> {
+    let mut steps: Vec<_> = (0..blinks)
+        .flat_map(|_| [(true, Duration::from_millis(200)), (false, Duration::from_millis(300))])
+        .collect();
+    if let Some(last) = steps.last_mut() {
+        last.1 = Duration::from_millis(1500);
+    }
+    steps
+}
+
+/// Feeds the LED task.
+pub struct StatusLed {
+    tx: watch::Sender<Indication>,
+}
+
+impl StatusLed {
+    /// Starts driving `output`, dark until the first indication.
+    pub fn spawn(output: Output) -> StatusLed {
+        let (tx, rx) = watch::channel(Indication::Off);
+        tokio::spawn(run(Lit { output, failed: false }, rx));
+        StatusLed { tx }
+    }
+
+    pub fn show(&self, indication: Indication) {
+        self.tx.send_if_modified(|current| {
+            let changed = *current != indication;
+            if changed {
+                debug!(?indication, "status led changed");
+            }
+            *current = indication;
+            changed
+        });
+    }
+}
+
+async fn run(mut led: Lit, mut rx: watch::Receiver<Indication>) {
+    loop {
+        let steps = pattern(*rx.borrow_and_update());
+        for &(lit, duration) in steps.iter().cycle() {
+            led.set(lit);
+            tokio::select! {
+                _ = sleep(duration) => (),
+                changed = rx.changed() => match changed {
+                    Ok(()) => break,
+                    Err(_) => return,
+                },
+            }
+        }
+    }
+}
+
+/// The output, released when the task ends or is dropped with the runtime.
+struct Lit {
+    output: Output,
+    /// Whether the last write failed, so a dead LED is only logged once.
+    failed: bool,
+}
+
+impl Lit {
+    fn set(&mut self, lit: bool) {
+        match self.output.set("led", lit) {
+            Ok(()) => self.failed = false,
+            Err(e) if !self.failed => {
+                warn!(error = %e, "failed to switch status led");
+                self.failed = true;
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+impl Drop for Lit {
+    fn drop(&mut self) {
+        self.output.release("led");
+    }
+}
@@ -0,0 +1,65 @@
+pub mod acl;
+pub mod actuation;
+pub mod alerts;
+pub mod analog;
+pub mod anomaly;
+pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod availability;
+pub mod clock;
+pub mod config;
+pub mod correlation;
+pub mod countdown;
+pub mod daemon;
+pub mod delivery;
+pub mod door;
+pub mod error;
+pub mod estimator;
+pub mod hardware;
+pub mod health;
+pub mod heartbeat;
+pub mod history;
+pub mod http;
+pub mod http_client;
+pub mod ipc;
+pub mod journal;
+pub mod keypad;
+pub mod led;
+pub mod links;
+pub mod locale;
+pub mod lockout;
+pub mod machine;
+pub mod maintenance;
+pub mod metrics;
+pub mod motor;
+pub mod mqtt;
+pub mod mqtt_client;
+pub mod onewire;
+pub mod options;
+pub mod outbox;
+pub mod output;
+pub mod position;
+pub mod presence;
+pub mod presets;
+pub mod privacy;
+pub mod privileges;
+pub mod quarantine;
+pub mod ratelimit;
+pub mod rf;
+pub mod scenario;
+pub mod schedule;
+pub mod secrets;
+pub mod shutdown;
+pub mod signals;
+pub mod simulate;
+pub mod state;
+pub mod stats;
+pub mod subsystems;
+pub mod systemd;
+pub mod templates;
+pub mod vacation;
+pub mod vehicle;
+pub mod warning;
+pub mod watchdog;
+pub mod webhooks;
@@ -0,0 +1,114 @@
+//! Rules driven by the state of another controller on the same broker.
+//!
+//! Each link follows one topic (typically another garaged's state, or an
+//! alarm panel) and fires its rules when the value changes to a match. The
+//! link is healthy while its peer reports online and the topic hasn't gone
+//! quiet for longer than allowed.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::config::{LinkAction, LinkConfig};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkStatus {
+    pub value: Option<String>,
+    pub available: Option<bool>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LinksReport {
+    pub problem: bool,
+    pub links: BTreeMap<String, LinkStatus>,
+}
+
+#[derive(Debug, Default)]
+struct LinkState {
+    value: Option<String>,
+    available: Option<bool>,
+    last_seen: Option<(Instant, DateTime<Utc>)>,
+    started: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct Links {
+    states: BTreeMap<String, LinkState>,
+}
+
+impl Links {
+    /// Every topic the configured links follow.
+    pub fn topics(config: &[LinkConfig]) -> impl Iterator<Item = &str> {
+        config.iter()
+            .flat_map(|l| std::iter::once(&l.topic).chain(&l.availability_topic))
+            .map(String::as_str)
+    }
+
+    /// Drops state for links no longer configured.
+    pub fn retain(&mut self, config: &[LinkConfig]) {
+        self.states.retain(|name, _| config.iter().any(|l| &l.name == name));
+    }
+
+    /// Feeds a message to the links following `topic`, returning the
+    /// actions to run as `(link name, value, action)`.
+    pub fn message(&mut self, config: &[LinkConfig], topic: &str, payload: &[u8]) -> Vec<(String, String, LinkAction)> {
+        let mut fired = Vec::new();
+        for link in config {
+            let state = self.states.entry(link.name.clone()).or_default();
+            if link.availability_topic.as_deref() == Some(topic) {
+                state.available = Some(payload == b"online");
+            }
+            if link.topic != topic {
+                continue;
+            }
+            state.last_seen = Some((Instant::now(), Utc::now()));
+            let value = match extract(payload, link.field.as_deref()) {
+                Some(v) => v,
+                None => continue,
+            };
+            if state.value.as_ref() == Some(&value) {
+                continue;
+            }
+            fired.extend(link.rules.iter()
+                .filter(|r| r.when == value)
+                .map(|r| (link.name.clone(), value.clone(), r.action)));
+            state.value = Some(value);
+        }
+        fired
+    }
+
+    pub fn report(&mut self, config: &[LinkConfig]) -> LinksReport {
+        let mut links = BTreeMap::new();
+        for link in config {
+            let state = self.states.entry(link.name.clone()).or_default();
+            let started = *state.started.get_or_insert_with(Instant::now);
+            let silent_since = state.last_seen.map(|(at, _)| at).unwrap_or(started);
+            let quiet = link.max_silence().map(|max| silent_since.elapsed() > max).unwrap_or(false);
+            let healthy = state.available != Some(false) && !quiet;
+            links.insert(link.name.clone(), LinkStatus {
+                value: state.value.clone(),
+                available: state.available,
+                last_seen: state.last_seen.map(|(_, at)| at),
+                healthy,
+            });
+        }
+        LinksReport { problem: links.values().any(|l| !l.healthy), links }
+    }
+}
+
+fn extract(payload: &[u8], field: Option<&str>) -> Option<String> {
+    let field = match field {
+        Some(f) => f,
+        None => return std::str::from_utf8(payload).ok().map(|s| s.trim().to_owned()),
+    };
+    let json: Value = serde_json::from_slice(payload).ok()?;
+    match json.get(field)? {
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
@@ -0,0 +1,227 @@
+//! Per-site units, time format and translated entity names.
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Utc};
+
+use crate::config::{ClockFormat, LocaleConfig, TemperatureUnit};
+
+/// Entities with a user-visible friendly name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Door,
+    CloseCountdown,
+    VehicleEvent,
+    MotorRuntime,
+    HealthCheck,
+    HealthCheckButton,
+    AclProblem,
+    Cycles,
+    LastOpened,
+    OpenToday,
+    OpenDuration,
+    LastChanged,
+    Preset,
+    LinkProblem,
+    VacationLock,
+    Obstruction,
+    UsageHeatmap,
+    Subsystems,
+    CommandSuccess,
+    Maintenance,
+    MotorAnomaly,
+}
+
+impl Entity {
+    /// Key used for name overrides in the `[locale.names]` config table.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Entity::Door => "door",
+            Entity::CloseCountdown => "close_countdown",
+            Entity::VehicleEvent => "vehicle_event",
+            Entity::MotorRuntime => "motor_runtime",
+            Entity::HealthCheck => "health_check",
+            Entity::HealthCheckButton => "health_check_button",
+            Entity::AclProblem => "acl_problem",
+            Entity::Cycles => "cycles",
+            Entity::LastOpened => "last_opened",
+            Entity::OpenToday => "open_today",
+            Entity::OpenDuration => "open_duration",
+            Entity::LastChanged => "last_changed",
+            Entity::Preset => "preset",
+            Entity::LinkProblem => "link_problem",
+            Entity::VacationLock => "vacation_lock",
+            Entity::Obstruction => "obstruction",
+            Entity::UsageHeatmap => "usage_heatmap",
+            Entity::Subsystems => "subsystems",
+            Entity::CommandSuccess => "command_success",
+            Entity::Maintenance => "maintenance",
+            Entity::MotorAnomaly => "motor_anomaly",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Locale {
+    config: LocaleConfig,
+}
+
+impl Locale {
+    pub fn new(config: LocaleConfig) -> Locale {
+        Locale { config }
+    }
+
+    pub fn name(&self, entity: Entity) -> String {
+        if let Some(name) = self.config.names.get(entity.key()) {
+            return name.clone();
+        }
+        translate(&self.config.language, entity)
+            .unwrap_or_else(|| english(entity))
+            .to_owned()
+    }
+
+    /// Formats a timestamp in local time for human-readable messages.
+    pub fn format_time(&self, time: DateTime<Utc>) -> String {
+        let format = match self.config.clock {
+            ClockFormat::H24 => "%Y-%m-%d %H:%M",
+            ClockFormat::H12 => "%Y-%m-%d %-I:%M %p",
+        };
+        time.with_timezone(&Local).format(format).to_string()
+    }
+
+    /// Converts a reading in degrees Celsius to the configured unit.
+    pub fn temperature(&self, celsius: f64) -> f64 {
+        match self.config.temperature_unit {
+            TemperatureUnit::Celsius => celsius,
+            TemperatureUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn temperature_unit(&self) -> &'static str {
+        match self.config.temperature_unit {
+            TemperatureUnit::Celsius => "°C",
+            TemperatureUnit::Fahrenheit => "°F",
+        }
+    }
+
+    /// First day of the statistics week containing `date`.
+    pub fn week_start(&self, date: NaiveDate) -> NaiveDate {
+        let offset = date.weekday().days_since(self.config.first_day_of_week);
+        date - chrono::Duration::days(offset.into())
+    }
+}
+
+fn english(entity: Entity) -> &'static str {
+    match entity {
+        Entity::Door => "Garage",
+        Entity::CloseCountdown => "Garage Close Countdown",
+        Entity::VehicleEvent => "Garage Vehicle Event",
+        Entity::MotorRuntime => "Garage Motor Runtime Today",
+        Entity::HealthCheck => "Garage Balance Health",
+        Entity::HealthCheckButton => "Garage Run Health Check",
+        Entity::AclProblem => "Garage Broker Permissions",
+        Entity::Cycles => "Garage Door Cycles",
+        Entity::LastOpened => "Garage Last Opened",
+        Entity::OpenToday => "Garage Open Time Today",
+        Entity::OpenDuration => "Garage Open For",
+        Entity::LastChanged => "Garage Last Changed",
+        Entity::Preset => "Garage Position Preset",
+        Entity::LinkProblem => "Garage Linked Controllers",
+        Entity::VacationLock => "Garage Vacation Lock",
+        Entity::Obstruction => "Garage Obstruction",
+        Entity::UsageHeatmap => "Garage Usage Heatmap",
+        Entity::Subsystems => "Garage Controller Problem",
+        Entity::CommandSuccess => "Garage Command Success Rate",
+        Entity::Maintenance => "Garage Maintenance",
+        Entity::MotorAnomaly => "Garage Motor Problem",
+    }
+}
+
+fn translate(language: &str, entity: Entity) -> Option<&'static str> {
+    let name = match (language, entity) {
+        ("de", Entity::Door) => "Garage",
+        ("de", Entity::CloseCountdown) => "Garage Schließ-Countdown",
+        ("de", Entity::VehicleEvent) => "Garage Fahrzeugereignis",
+        ("de", Entity::MotorRuntime) => "Garage Motorlaufzeit heute",
+        ("de", Entity::HealthCheck) => "Garage Federausgleich",
+        ("de", Entity::HealthCheckButton) => "Garage Zustandsprüfung starten",
+        ("de", Entity::AclProblem) => "Garage Broker-Berechtigungen",
+        ("de", Entity::Cycles) => "Garage Torzyklen",
+        ("de", Entity::LastOpened) => "Garage zuletzt geöffnet",
+        ("de", Entity::OpenToday) => "Garage heute geöffnet",
+        ("de", Entity::OpenDuration) => "Garage geöffnet seit",
+        ("de", Entity::LastChanged) => "Garage zuletzt geändert",
+        ("de", Entity::Preset) => "Garage Torposition",
+        ("de", Entity::LinkProblem) => "Garage verknüpfte Steuerungen",
+        ("de", Entity::VacationLock) => "Garage Urlaubssperre",
+        ("de", Entity::Obstruction) => "Garage Hindernis",
+        ("de", Entity::UsageHeatmap) => "Garage Nutzungsmuster",
+        ("de", Entity::Subsystems) => "Garage Steuerungsproblem",
+        ("de", Entity::CommandSuccess) => "Garage Befehlserfolgsquote",
+        ("de", Entity::Maintenance) => "Garage Wartung",
+        ("de", Entity::MotorAnomaly) => "Garage Antriebsproblem",
+        ("fr", Entity::Door) => "Garage",
+        ("fr", Entity::CloseCountdown) => "Garage compte à rebours de fermeture",
+        ("fr", Entity::VehicleEvent) => "Garage événement véhicule",
+        ("fr", Entity::MotorRuntime) => "Garage durée moteur aujourd'hui",
+        ("fr", Entity::HealthCheck) => "Garage état de l'équilibrage",
+        ("fr", Entity::HealthCheckButton) => "Garage lancer le contrôle",
+        ("fr", Entity::AclProblem) => "Garage permissions du broker",
+        ("fr", Entity::Cycles) => "Garage cycles de la porte",
+        ("fr", Entity::LastOpened) => "Garage dernière ouverture",
+        ("fr", Entity::OpenToday) => "Garage durée d'ouverture aujourd'hui",
+        ("fr", Entity::OpenDuration) => "Garage ouverte depuis",
+        ("fr", Entity::LastChanged) => "Garage dernier changement",
+        ("fr", Entity::Preset) => "Garage position prédéfinie",
+        ("fr", Entity::LinkProblem) => "Garage contrôleurs liés",
+        ("fr", Entity::VacationLock) => "Garage verrouillage vacances",
+        ("fr", Entity::Obstruction) => "Garage obstacle",
+        ("fr", Entity::UsageHeatmap) => "Garage carte d'utilisation",
+        ("fr", Entity::Subsystems) => "Garage problème du contrôleur",
+        ("fr", Entity::CommandSuccess) => "Garage taux de réussite des commandes",
+        ("fr", Entity::Maintenance) => "Garage maintenance",
+        ("fr", Entity::MotorAnomaly) => "Garage problème de moteur",
+        ("es", Entity::Door) => "Garaje",
+        ("es", Entity::CloseCountdown) => "Garaje cuenta atrás de cierre",
+        ("es", Entity::VehicleEvent) => "Garaje evento de vehículo",
+        ("es", Entity::MotorRuntime) => "Garaje tiempo de motor hoy",
+        ("es", Entity::HealthCheck) => "Garaje estado del equilibrado",
+        ("es", Entity::HealthCheckButton) => "Garaje iniciar comprobación",
+        ("es", Entity::AclProblem) => "Garaje permisos del broker",
+        ("es", Entity::Cycles) => "Garaje ciclos de la puerta",
+        ("es", Entity::LastOpened) => "Garaje última apertura",
+        ("es", Entity::OpenToday) => "Garaje tiempo abierta hoy",
+        ("es", Entity::OpenDuration) => "Garaje abierta desde hace",
+        ("es", Entity::LastChanged) => "Garaje último cambio",
+        ("es", Entity::Preset) => "Garaje posición predefinida",
+        ("es", Entity::LinkProblem) => "Garaje controladores vinculados",
+        ("es", Entity::VacationLock) => "Garaje bloqueo de vacaciones",
+        ("es", Entity::Obstruction) => "Garaje obstrucción",
+        ("es", Entity::UsageHeatmap) => "Garaje mapa de uso",
+        ("es", Entity::Subsystems) => "Garaje problema del controlador",
+        ("es", Entity::CommandSuccess) => "Garaje tasa de éxito de órdenes",
+        ("es", Entity::Maintenance) => "Garaje mantenimiento",
+        ("es", Entity::MotorAnomaly) => "Garaje problema del motor",
+        ("nl", Entity::Door) => "Garage",
+        ("nl", Entity::CloseCountdown) => "Garage sluitaftelling",
+        ("nl", Entity::VehicleEvent) => "Garage voertuiggebeurtenis",
+        ("nl", Entity::MotorRuntime) => "Garage motorlooptijd vandaag",
+        ("nl", Entity::HealthCheck) => "Garage balansconditie",
+        ("nl", Entity::HealthCheckButton) => "Garage controle starten",
+        ("nl", Entity::AclProblem) => "Garage brokerrechten",
+        ("nl", Entity::Cycles) => "Garage deurcycli",
+        ("nl", Entity::LastOpened) => "Garage laatst geopend",
+        ("nl", Entity::OpenToday) => "Garage open vandaag",
+        ("nl", Entity::OpenDuration) => "Garage open sinds",
+        ("nl", Entity::LastChanged) => "Garage laatst gewijzigd",
+        ("nl", Entity::Preset) => "Garage voorkeurspositie",
+        ("nl", Entity::LinkProblem) => "Garage gekoppelde controllers",
+        ("nl", Entity::VacationLock) => "Garage vakantievergrendeling",
+        ("nl", Entity::Obstruction) => "Garage obstakel",
+        ("nl", Entity::UsageHeatmap) => "Garage gebruikspatroon",
+        ("nl", Entity::Subsystems) => "Garage controllerprobleem",
+        ("nl", Entity::CommandSuccess) => "Garage slagingspercentage opdrachten",
+        ("nl", Entity::Maintenance) => "Garage onderhoud",
+        ("nl", Entity::MotorAnomaly) => "Garage motorprobleem",
+        _ => return None,
+    };
+    Some(name)
+}
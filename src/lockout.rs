@@ -0,0 +1,66 @@
+//! Recurring lockout windows during which remote commands are refused.
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockoutWindow {
+    #[serde(deserialize_with = "time_of_day")]
+    pub start: NaiveTime,
+    /// May be earlier than `start` for windows spanning midnight.
+    #[serde(deserialize_with = "time_of_day")]
+    pub end: NaiveTime,
+    /// Days the window starts on; every day if empty.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+}
+
+impl LockoutWindow {
+    pub fn contains(&self, now: DateTime<Local>) -> bool {
+        let time = now.time();
+        let today = now.weekday();
+        let yesterday = today.pred();
+        let starts_on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if self.start <= self.end {
+            starts_on(today) && time >= self.start && time < self.end
+        } else {
+            (starts_on(today) && time >= self.start) || (starts_on(yesterday) && time < self.end)
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ActiveLockout {
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutSchedule {
+    pub windows: Vec<LockoutWindow>,
+    /// Dates locked all day.
+    pub holidays: Vec<NaiveDate>,
+}
+
+impl LockoutSchedule {
+    pub fn active_at(&self, now: DateTime<Local>) -> Option<ActiveLockout> {
+        let today = now.date_naive();
+        if self.holidays.contains(&today) {
+            return Some(ActiveLockout { reason: format!("holiday {}", today) });
+        }
+        self.windows.iter()
+            .find(|w| w.contains(now))
+            .map(|w| ActiveLockout { reason: format!("scheduled {}", w.describe()) })
+    }
+}
+
+fn time_of_day<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveTime, D::Error> {
+    let s = String::deserialize(d)?;
+    NaiveTime::parse_from_str(&s, "%H:%M").map_err(serde::de::Error::custom)
+}
+
@@ -0,0 +1,295 @@
+//! The door's decision rules as a pure state machine.
+//!
+//! [`evaluate`] is the single place that decides whether a command may press
+//! the relay; the daemon gathers the [`Guards`] from its own state and calls
+//! it. [`Machine`] drives the same rules and the position tracker from a
+//! sequence of [`Event`]s with a virtual clock, so property tests and the
+//! fuzz target under `fuzz/` can check invariants over arbitrary histories
+//! without GPIO, MQTT or real time.
+
+use std::time::Duration;
+
+use crate::door::{check_command, Command, Position};
+use crate::error::{Error, RejectReason};
+use crate::position::{PositionTracker, Readings};
+
+/// Everything besides the door position that can block a command.
+#[derive(Debug)]
+pub struct Guards {
+    /// Maintenance mode blocks everything, `Cancel` included.
+    pub maintenance: bool,
+    pub vacation_lock: bool,
+    /// A lockout window covers the command and the caller isn't an admin.
+    pub locked_out: bool,
+    /// The presence lock covers the command and nobody is home; only
+    /// consulted for commands that open the door.
+    pub nobody_home: bool,
+    pub health_check: bool,
+    /// The safety beam is broken; only consulted for `CLOSE`.
+    pub obstructed: bool,
+    /// An automated close is counting down.
+    pub countdown: bool,
+    /// The relay cooldown is still running.
+    pub cooldown: bool,
+    /// Whether the vent preset can be used; only consulted for `VENT`.
+    pub vent: Result<(), Error>,
+}
+
+/// Applies every rule that could block `command`, in order. Only `Cancel`
+/// passes while a health check runs, and it also passes while a countdown
+/// is pending, without the door having to be moving.
+pub fn evaluate(command: Command, position: &PositionTracker, guards: Guards) -> Result<(), Error> {
+    if guards.maintenance {
+        return Err(Error::rejected(RejectReason::Maintenance));
+    }
+    if guards.vacation_lock && command != Command::Cancel {
+        return Err(Error::rejected(RejectReason::VacationLock));
+    }
+    if guards.locked_out && command != Command::Cancel {
+        return Err(Error::rejected(RejectReason::Lockout));
+    }
+    if guards.nobody_home && command.opens() {
+        return Err(Error::rejected(RejectReason::NobodyHome));
+    }
+    if guards.health_check {
+        return match command {
+            Command::Cancel => Ok(()),
+            _ => Err(Error::rejected(RejectReason::HealthCheckRunning)),
+        };
+    }
+    if command == Command::Cancel && guards.countdown {
+        return Ok(());
+    }
+    check_command(command, position.position())?;
+    if command == Command::Close && guards.obstructed {
+        return Err(Error::rejected(RejectReason::Obstructed));
+    }
+    if command == Command::Vent {
+        guards.vent?;
+    }
+    position.check_heading(command)?;
+    match command {
+        Command::Cancel => Ok(()),
+        _ if guards.cooldown => Err(Error::rejected(RejectReason::Cooldown)),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Fresh sensor readings. `open` is ignored without an open sensor.
+    Sensors { closed: bool, open: bool },
+    /// A remote command, from an admin identity or not.
+    Command { command: Command, admin: bool },
+    /// The wall button, which only maintenance mode and the vacation lock
+    /// block.
+    Button,
+    /// The tracker's deadline for a moving door passed.
+    DeadlineReached,
+    Lockout(bool),
+    /// Whether nobody is home as far as the presence lock goes.
+    NobodyHome(bool),
+    VacationLock(bool),
+    Maintenance(bool),
+    Obstruction(bool),
+    Elapse(Duration),
+}
+
+/// What one event did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Outcome {
+    pub pulsed: bool,
+    pub rejected: Option<RejectReason>,
+    /// The new position, if it changed.
+    pub position: Option<Position>,
+}
+
+/// The door without hardware: the position tracker, the lockout, presence
+/// and vacation locks, maintenance mode, the safety beam and the relay cooldown, run
+/// against a virtual clock. Presets, health checks and automated closes
+/// aren't modelled.
+#[derive(Debug)]
+pub struct Machine {
+    dual: bool,
+    tracker: PositionTracker,
+    readings: Readings,
+    lockout: bool,
+    nobody_home: bool,
+    vacation_lock: bool,
+    maintenance: bool,
+    obstructed: bool,
+    cooldown: Duration,
+    now: Duration,
+    last_press: Option<Duration>,
+}
+
+impl Machine {
+    /// Starts from the given sensor readings, as the daemon does.
+    pub fn new(dual: bool, cooldown: Duration, closed: bool, open: bool) -> Machine {
+        let readings = Readings { closed, open: dual.then_some(open), zones: Vec::new() };
+        let mut tracker = PositionTracker::new(dual, Vec::new(), Duration::from_secs(15));
+        tracker.resume(&readings);
+        Machine {
+            dual,
+            tracker,
+            readings,
+            lockout: false,
+            nobody_home: false,
+            vacation_lock: false,
+            maintenance: false,
+            obstructed: false,
+            cooldown,
+            now: Duration::ZERO,
+            last_press: None,
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        self.tracker.position()
+    }
+
+    pub fn step(&mut self, event: Event) -> Outcome {
+        let before = self.position();
+        let mut outcome = Outcome::default();
+        match event {
+            Event::Sensors { closed, open } => {
+                self.readings = Readings { closed, open: self.dual.then_some(open), zones: Vec::new() };
+                self.tracker.sensors_changed(&self.readings);
+            }
+            Event::Command { command, admin } => {
+                let guards = Guards {
+                    maintenance: self.maintenance,
+                    vacation_lock: self.vacation_lock,
+                    locked_out: self.lockout && !admin,
+                    nobody_home: self.nobody_home,
+                    health_check: false,
+                    obstructed: self.obstructed,
+                    countdown: false,
+                    cooldown: self.cooling(),
+                    vent: Err(Error::rejected(RejectReason::UnknownPreset)),
+                };
+                match evaluate(command, &self.tracker, guards) {
+                    Ok(()) => self.press(&mut outcome),
+                    Err(Error::CommandRejected { reason }) => outcome.rejected = Some(reason),
+                    Err(e) => unreachable!("evaluate only rejects, got {}", e),
+                }
+            }
+            Event::Button if self.maintenance => outcome.rejected = Some(RejectReason::Maintenance),
+            Event::Button if self.vacation_lock => outcome.rejected = Some(RejectReason::VacationLock),
+            Event::Button => self.press(&mut outcome),
+            Event::DeadlineReached => {
+                if self.tracker.deadline().is_some() {
+                    self.tracker.timed_out();
+                }
+            }
+            Event::Lockout(active) => self.lockout = active,
+            Event::NobodyHome(away) => self.nobody_home = away,
+            Event::VacationLock(locked) => self.vacation_lock = locked,
+            Event::Maintenance(active) => self.maintenance = active,
+            Event::Obstruction(active) => self.obstructed = active,
+            Event::Elapse(by) => self.now += by,
+        }
+        let after = self.position();
+        outcome.position = (after != before).then_some(after);
+        outcome
+    }
+
+    fn cooling(&self) -> bool {
+        self.last_press.is_some_and(|at| self.now < at + self.cooldown)
+    }
+
+    fn press(&mut self, outcome: &mut Outcome) {
+        self.last_press = Some(self.now);
+        self.tracker.relay_triggered();
+        outcome.pulsed = true;
+    }
+}
+
+/// A broken invariant, with the index of the event that broke it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub step: usize,
+    pub event: Event,
+    pub message: &'static str,
+}
+
+/// Runs `events` through `machine`, checking after each one that:
+///
+/// - the relay never pulses for anything in maintenance mode;
+/// - the relay never pulses for a non-admin command during a lockout, nor
+///   for anything during the vacation lock, `Cancel` excepted;
+/// - the relay never pulses to open the door while nobody is home;
+/// - the relay never pulses for anything but `Cancel` within the cooldown;
+/// - the relay never pulses for `Close` while the safety beam is broken;
+/// - the door never leaves closed without the closed sensor releasing.
+pub fn check(machine: &mut Machine, events: &[Event]) -> Result<(), Violation> {
+    for (step, &event) in events.iter().enumerate() {
+        let was_closed = machine.position() == Position::Closed;
+        let sensor_was_closed = machine.readings.closed;
+        let cooling = machine.cooling();
+        let (lockout, vacation_lock, obstructed) = (machine.lockout, machine.vacation_lock, machine.obstructed);
+        let (maintenance, nobody_home) = (machine.maintenance, machine.nobody_home);
+        let outcome = machine.step(event);
+        let fail = |message| Err(Violation { step, event, message });
+
+        if outcome.pulsed && maintenance {
+            return fail("relay pulsed in maintenance mode");
+        }
+        if outcome.pulsed {
+            match event {
+                Event::Command { command: Command::Cancel, .. } => (),
+                Event::Command { admin, .. } if lockout && !admin => {
+                    return fail("relay pulsed during a lockout");
+                }
+                Event::Command { .. } | Event::Button if vacation_lock => {
+                    return fail("relay pulsed during the vacation lock");
+                }
+                Event::Command { command, .. } if nobody_home && command.opens() => {
+                    return fail("relay pulsed to open with nobody home");
+                }
+                Event::Command { .. } if cooling => return fail("relay pulsed within the cooldown"),
+                Event::Command { command: Command::Close, .. } if obstructed => {
+                    return fail("relay pulsed to close while obstructed");
+                }
+                _ => (),
+            }
+        }
+        if was_closed && machine.position() != Position::Closed {
+            let edge = matches!(event, Event::Sensors { closed: false, .. }) && sensor_was_closed;
+            if !edge {
+                return fail("door left closed without a sensor edge");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fuzzing entry point: decodes `data` into a start state and events and
+/// panics if any invariant breaks.
+pub fn fuzz(data: &[u8]) {
+    let (header, body) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut machine = Machine::new(header & 1 != 0, Duration::from_secs(2), header & 2 != 0, header & 4 != 0);
+    let events: Vec<Event> = body.chunks_exact(2).map(|pair| decode(pair[0], pair[1])).collect();
+    if let Err(v) = check(&mut machine, &events) {
+        panic!("{} at step {} ({:?})", v.message, v.step, v.event);
+    }
+}
+
+fn decode(op: u8, arg: u8) -> Event {
+    const COMMANDS: [Command; 5] = [Command::Open, Command::Close, Command::Cancel, Command::HealthCheck, Command::Vent];
+    match op % 10 {
+        0 => Event::Sensors { closed: arg & 1 != 0, open: arg & 2 != 0 },
+        1 => Event::Command { command: COMMANDS[usize::from(arg) % COMMANDS.len()], admin: arg & 0x80 != 0 },
+        2 => Event::Button,
+        3 => Event::DeadlineReached,
+        4 => Event::Lockout(arg & 1 != 0),
+        5 => Event::VacationLock(arg & 1 != 0),
+        6 => Event::Obstruction(arg & 1 != 0),
+        7 => Event::Maintenance(arg & 1 != 0),
+        8 => Event::NobodyHome(arg & 1 != 0),
+        _ => Event::Elapse(Duration::from_millis(u64::from(arg) * 50)),
+    }
+}
@@ -1,235 +1,182 @@
 
-use std::time::Duration;
-use std::str::{from_utf8, FromStr};
-
-use strum::{EnumString, Display};
-use sysfs_gpio::{Direction, Edge, Pin};
-
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event, Incoming};
-
-use serde_json::{json, to_vec};
-
-use tokio::time::{sleep, interval};
-use tokio::sync::Mutex;
-
-use futures::StreamExt;
-
-use anyhow::{Error, Context};
-
-#[derive(Debug, PartialEq, Display, EnumString)]
-enum Status {
-    #[strum(serialize = "open")]
-    Open,
-    #[strum(serialize = "closed")]
-    Closed,
+use std::ffi::OsString;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use garaged::auth;
+use garaged::clock::Clock;
+use garaged::config::{self, Config, LogConfig, LogFormat};
+use garaged::daemon::Daemon;
+use garaged::http;
+use garaged::ipc;
+use garaged::mqtt::Topics;
+use garaged::mqtt_client;
+use garaged::hardware::Hardware;
+use garaged::secrets::{self, SecretKey};
+use garaged::scenario::{self, BrokerLink, Scenario};
+use garaged::simulate;
+use garaged::subsystems::{Subsystem, SubsystemStatus};
+
+fn init_logging(config: &LogConfig) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(&config.level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match config.format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
 }
 
-#[derive(Debug, PartialEq, Display, EnumString)]
-enum Command {
-    #[strum(serialize = "OPEN")]
-    Open,
-    #[strum(serialize = "CLOSE")]
-    Close,
+fn generate_key(path: &Path) -> Result<(), Error> {
+    secrets::write_new_key(path).with_context(|| format!("failed to write key to {}", path.display()))?;
+    eprintln!("wrote new credentials key to {}", path.display());
+    Ok(())
 }
 
-struct Hardware {
-    led: Option<Pin>,
-    relay: Pin,
-    status: Pin,
-    input: Pin,
-    lock: Mutex<()>,
+/// Reads a secret from stdin and prints it encrypted for use in the config.
+fn encrypt_secret(key_path: &Path) -> Result<(), Error> {
+    let key = std::fs::read_to_string(key_path)
+        .with_context(|| format!("failed to read key from {}", key_path.display()))?;
+    let key = SecretKey::decode(key.trim())?;
+    let mut secret = String::new();
+    std::io::stdin().lock().read_line(&mut secret)?;
+    println!("{}", key.encrypt(secret.trim_end_matches(['\r', '\n'])));
+    Ok(())
 }
 
-impl Hardware {
-    fn init(enable_led: bool) -> Result<Hardware, Error> {
-        let led_pin = if enable_led {
-            println!("initalizing led pin");
-            let led_pin = Pin::new(7);
-            led_pin.export()?;
-            led_pin.set_direction(Direction::Low)?;
-            Some(led_pin)
-        } else {
-            None
-        };
-
-        println!("initalizing relay pin");
-        let relay_pin = Pin::new(17);
-        relay_pin.export()?;
-        relay_pin.set_direction(Direction::Low)?;
-
-        println!("initalizing status pin");
-        let status_pin = Pin::new(6);
-        status_pin.export()?;
-        status_pin.set_direction(Direction::In)?;
-        status_pin.set_edge(Edge::BothEdges)?;
-
-        println!("initalizing input pin");
-        let input_pin = Pin::new(12);
-        input_pin.export()?;
-        input_pin.set_direction(Direction::In)?;
-        input_pin.set_edge(Edge::RisingEdge)?;
-
-        Ok(Hardware {
-            led: led_pin,
-            relay: relay_pin,
-            status: status_pin,
-            input: input_pin,
-            lock: Mutex::new(()),
-        })
-    }
+/// Reads a keypad code or API token from stdin and prints its hash for the
+/// local credentials file.
+fn hash_secret() -> Result<(), Error> {
+    let mut secret = String::new();
+    std::io::stdin().lock().read_line(&mut secret)?;
+    println!("{}", auth::hash_secret(secret.trim_end_matches(['\r', '\n'])));
+    Ok(())
 }
 
-impl Drop for Hardware {
-    fn drop(&mut self) {
-        if let Some(led) = self.led {
-            let _ = led.unexport();
-        }
-        let _ = self.relay.unexport();
-        let _ = self.status.unexport();
-        let _ = self.input.unexport();
+fn required_path(arg: Option<OsString>, flag: &str) -> Result<PathBuf, Error> {
+    match arg {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => bail!("{} requires a key file path", flag),
     }
 }
 
-fn get_door_status(hw: &Hardware) -> Result<Status, Error> {
-    hw.status.get_value()
-        .map(parse_door_status)
-        .map_err(Error::from)
-}
-
-fn parse_door_status(status: u8) -> Status {
-    match status {
-        0 => Status::Open,
-        _ => Status::Closed,
+#[tokio::main]
+async fn main() -> Result<(), Error>  {
+    let mut args = std::env::args_os().skip(1).peekable();
+    let simulate = args.next_if(|a| a == "--simulate").is_some();
+    let config_path = match args.next() {
+        Some(flag) if flag == "--generate-key" => return generate_key(&required_path(args.next(), "--generate-key")?),
+        Some(flag) if flag == "--hash-secret" => return hash_secret(),
+        Some(flag) if flag == "--encrypt-secret" => return encrypt_secret(&required_path(args.next(), "--encrypt-secret")?),
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(config::DEFAULT_PATH),
+    };
+    let config_exists = config_path.exists();
+    let config = if config_exists {
+        Config::load(&config_path)?
+    } else {
+        Config::default()
+    };
+
+    init_logging(&config.log);
+    if config_exists {
+        info!(path = %config_path.display(), "loaded config");
+    } else {
+        info!(path = %config_path.display(), "no config file, using defaults");
     }
-}
 
-async fn trigger_relay(hw: &Hardware) -> Result<(), Error> {
-    let _ = hw.lock.lock().await;
-    println!("triggering door relay");
-    if let Some(led) = hw.led {
-        led.set_value(1)?;
+    let hw = if simulate {
+        Hardware::simulate(&config.gpio, &config.simulation)
+    } else {
+        info!("initializing gpio");
+        Hardware::detect(&config.gpio, config.keypad.as_ref(), config.rf.as_ref(), &config.simulation)?
+    };
+    if let Some(door) = hw.simulator() {
+        tokio::spawn(simulate::read_stdin(door.poker()));
     }
-    hw.relay.set_value(1)?;
-    sleep(Duration::from_millis(200)).await;
-    hw.relay.set_value(0)?;
-    if let Some(led) = hw.led {
-        led.set_value(0)?;
+    let scenario = match &config.simulation.scenario {
+        Some(path) if hw.simulator().is_some() => Some(Scenario::load(path)?),
+        Some(_) => {
+            warn!("simulation.scenario is only played when simulating");
+            None
+        }
+        None => None,
+    };
+
+    info!("initializing mqtt");
+    let topics = Topics::new(&config.mqtt.topic_prefix, &config.mqtt.discovery_prefix);
+    let mut mqtt_config = config.mqtt.clone();
+    let link = match &scenario {
+        Some(scenario) if scenario.cuts_broker() => {
+            let link = BrokerLink::spawn(&mqtt_config.host, mqtt_config.port).await
+                .context("failed to start the scenario's broker relay")?;
+            mqtt_config.host = link.addr().ip().to_string();
+            mqtt_config.port = link.addr().port();
+            Some(link)
+        }
+        _ => None,
+    };
+    let (client, event_loop) = mqtt_client::connect(&mqtt_config, &topics);
+    if let (Some(scenario), Some(door)) = (scenario, hw.simulator()) {
+        tokio::spawn(scenario::run(scenario, door.poker(), link));
+    }
+    let http_config = config.http.clone();
+    // Both bound before root is given up: /run/garaged belongs to root, and
+    // the HTTP port may be below 1024.
+    let http_listener = match &http_config {
+        Some(http_config) => Some(http::bind(http_config.bind)
+            .with_context(|| format!("failed to bind the http api to {}", http_config.bind))?),
+        None => None,
+    };
+    let socket = match &config.socket {
+        Some(socket_config) => Some(ipc::bind(socket_config)?),
+        None => None,
+    };
+    let mut daemon = Daemon::new(config, config_path, hw, client, Clock::System);
+
+    if let (Some(http_config), Some(listener)) = (http_config, http_listener) {
+        let api = daemon.api();
+        let auth = daemon.authenticator();
+        let status = daemon.status_reporter();
+        let mut local_ready = daemon.local_ready();
+        if http_config.require_token && auth.is_empty() {
+            bail!("http.require_token is set but no auth providers are configured");
+        }
+        tokio::spawn(async move {
+            // Leave the pins and the first door reading to the daemon.
+            if local_ready.wait_for(|ready| *ready).await.is_err() {
+                return;
+            }
+            if let Err(e) = http::serve(listener, api, auth, http_config.require_token).await {
+                error!(error = %e, "http api failed");
+                status.report(Subsystem::Http, SubsystemStatus::failing(e.to_string()));
+            }
+        });
     }
-    Ok(())
-}
 
-#[tokio::main]
-async fn main() -> Result<(), Error>  {
-    println!("initializing gpio");
-    let hw = Hardware::init(false)?;
-    let mut status_changes = hw.status.get_value_stream()?;
-    let mut input_triggers = hw.input.get_value_stream()?;
-
-    println!("initializing mqtt");
-    let hostname = gethostname::gethostname().into_string().expect("failed to get hostname");
-    let mut options = MqttOptions::new(hostname, "10.44.0.15", 1883);
-    options.set_keep_alive(Duration::from_secs(5));
-
-    let mqtt_path = "homeassistant/cover/garage";
-    let config_topic = format!("{}/config", mqtt_path);
-    let command_topic = format!("{}/command", mqtt_path);
-    let state_topic = format!("{}/state", mqtt_path);
-
-    let (client, mut event_loop) = AsyncClient::new(options, 10);
-    let config = json!({
-        "name": "Garage",
-        "unique_id": "garage_door",
-        "command_topic": command_topic,
-        "payload_close": Command::Close.to_string(),
-        "payload_open": Command::Open.to_string(),
-        "state_topic": state_topic,
-        "state_open": Status::Open.to_string(),
-        "state_closed": Status::Closed.to_string(),
-        "device_class": "garage",
-    });
-    println!("publishing device config");
-    client.publish(config_topic, QoS::AtLeastOnce, false, to_vec(&config)?).await?;
-    client.subscribe(&command_topic, QoS::ExactlyOnce).await?;
-
-    println!("publishing initial door state");
-    let status = get_door_status(&hw)?;
-    println!("initial door state = {}", status);
-    client.publish(&state_topic, QoS::AtLeastOnce, true, status.to_string()).await?;
-
-    let mut timer = interval(Duration::from_secs(60));
-
-    println!("beginning monitor loop");
-    loop {
-        tokio::select! {
-            _next_timer = timer.tick() => {
-                let status = get_door_status(&hw)?;
-                client.publish(&state_topic, QoS::AtLeastOnce, true, status.to_string()).await?;
-            },
-            next_status = status_changes.next() => {
-                match next_status {
-                    Some(Ok(x)) => {
-                        let status = parse_door_status(x);
-                        println!("detected door status = {}", status);
-                        client.publish(&state_topic, QoS::AtLeastOnce, true, status.to_string()).await?;
-                    },
-                    Some(Err(e)) => return Err(e).context("error reading door status events"),
-                    None => break,
-                }
-            },
-            next_input = input_triggers.next() => {
-                match next_input {
-                    Some(Ok(x)) if x != 0 => {
-                        println!("detected input trigger");
-                        trigger_relay(&hw).await?;
-                    },
-                    Some(Ok(_)) => (),
-                    Some(Err(e)) => return Err(e).context("error reading input trigger events"),
-                    None => break,
-                }
-            },
-            next_msg = event_loop.poll() => {
-                match next_msg.context("error reading mqtt events") {
-                    Ok(Event::Incoming(Incoming::Publish(packet))) => {
-                        if packet.topic == command_topic {
-                            let command = from_utf8(packet.payload.as_ref())
-                                .map_err(Error::from)
-                                .and_then(|s| Command::from_str(s).map_err(Error::from));
-                            let command = match command {
-                                Ok(c) => c,
-                                Err(_) => {
-                                    println!("invalid payload on command topic");
-                                    continue;
-                                }
-                            };
-                            let current_status = get_door_status(&hw)?;
-                            println!("command = {}, door status = {}", command, current_status);
-                            match (command, current_status) {
-                                (Command::Open, Status::Closed) |
-                                (Command::Close, Status::Open) => {
-                                    trigger_relay(&hw).await?;
-                                },
-                                _ => {
-                                    println!("invalid command, ignoring");
-                                }
-                            }
-                        } else {
-                            println!("unrecognized topic {}", packet.topic);
-                        }
-                        
-                    },
-                    Err(e) => {
-                        println!("mqtt error: {}", e);
-                    }
-                    _ => (),
-                }
-            },
-            _ = tokio::signal::ctrl_c() => {
-                println!("shutdown signal received");
-                break;
+    if let Some(listener) = socket {
+        let api = daemon.api();
+        let auth = daemon.authenticator();
+        let status = daemon.status_reporter();
+        let mut local_ready = daemon.local_ready();
+        tokio::spawn(async move {
+            if local_ready.wait_for(|ready| *ready).await.is_err() {
+                return;
             }
-        }
+            if let Err(e) = ipc::serve(listener, api, auth).await {
+                error!(error = %e, "control socket failed");
+                status.report(Subsystem::Socket, SubsystemStatus::failing(e.to_string()));
+            }
+        });
     }
 
-    println!("exiting program");
+    daemon.run(event_loop).await?;
+
+    info!("exiting program");
     Ok(())
 }
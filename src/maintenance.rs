@@ -0,0 +1,101 @@
+//! Maintenance mode, for while the door is being serviced.
+//!
+//! Unlike the vacation lock, nothing presses the relay while it is on: no
+//! command, `Cancel` included, nor the wall button, keypad or anything
+//! automated. Anyone may turn it on, but turning it off needs an admin
+//! credential, so the door can't be set moving under someone working on it.
+//! The state is persisted, and a file that can't be read starts in
+//! maintenance.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::error::{Error, RejectReason};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub active: bool,
+    /// When maintenance mode was last turned on or off.
+    pub since: Option<DateTime<Utc>>,
+    /// Who last turned it on or off, e.g. `mqtt` or `http:alice`.
+    pub by: Option<String>,
+    /// What the door is being serviced for, as given when turning it on.
+    pub reason: Option<String>,
+}
+
+/// A request to turn maintenance mode on or off, from the set topic or the
+/// HTTP API.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaintenanceMessage {
+    pub active: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Token to act as, needed to turn maintenance mode off.
+    #[serde(default)]
+    pub credential: Option<String>,
+}
+
+/// Parses a set topic payload: `ON`, `OFF`, or JSON like
+/// `{"active": false, "credential": "<token>"}`.
+pub fn parse_message(payload: &[u8]) -> Result<MaintenanceMessage, Error> {
+    let active = match payload {
+        b"ON" => true,
+        b"OFF" => false,
+        _ => return serde_json::from_slice(payload).map_err(|_| Error::rejected(RejectReason::InvalidPayload)),
+    };
+    Ok(MaintenanceMessage { active, reason: None, credential: None })
+}
+
+pub struct MaintenanceMode {
+    path: PathBuf,
+    state: MaintenanceState,
+}
+
+impl MaintenanceMode {
+    /// Loads the saved state, starting in normal operation if there is none.
+    pub fn load(dir: &Path) -> MaintenanceMode {
+        let path = dir.join("maintenance.json");
+        let state = match std::fs::read(&path) {
+            Ok(text) => serde_json::from_slice(&text).unwrap_or_else(|e| {
+                warn!(path = %path.display(), error = %e, "corrupt maintenance state, starting in maintenance");
+                MaintenanceState { active: true, ..MaintenanceState::default() }
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => MaintenanceState::default(),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "failed to read maintenance state, starting in maintenance");
+                MaintenanceState { active: true, ..MaintenanceState::default() }
+            }
+        };
+        MaintenanceMode { path, state }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.state.active
+    }
+
+    pub fn state(&self) -> &MaintenanceState {
+        &self.state
+    }
+
+    /// Turns maintenance mode on or off, returning whether that changed
+    /// anything. The new state applies even if it can't be saved.
+    pub fn set(&mut self, active: bool, by: &str, reason: Option<String>) -> io::Result<bool> {
+        if active == self.state.active {
+            return Ok(false);
+        }
+        let reason = reason.filter(|_| active);
+        self.state = MaintenanceState { active, since: Some(Utc::now()), by: Some(by.to_owned()), reason };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.state)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,41 @@
+//! Counters served on the HTTP API's `/metrics` in the Prometheus text
+//! format, labelled with the stable codes from [`crate::error`] so failures
+//! can be told apart without parsing messages.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::door::Source;
+use crate::error::Error;
+
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// Commands by source, result, error code and rejection reason.
+    commands: BTreeMap<(String, &'static str, &'static str, &'static str), u64>,
+}
+
+impl Metrics {
+    /// Counts what came of a command.
+    pub fn command(&mut self, source: Source, result: &Result<(), Error>) {
+        let labels = match result {
+            Ok(()) => (source.to_string(), "accepted", "", ""),
+            Err(e @ Error::CommandRejected { reason }) => (source.to_string(), "rejected", e.code(), reason.code()),
+            Err(e) => (source.to_string(), "error", e.code(), ""),
+        };
+        *self.commands.entry(labels).or_default() += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP garaged_commands_total Commands by source and result.\n");
+        out.push_str("# TYPE garaged_commands_total counter\n");
+        for ((source, result, error, reason), count) in &self.commands {
+            let _ = writeln!(
+                out,
+                "garaged_commands_total{{source=\"{}\",result=\"{}\",error=\"{}\",reason=\"{}\"}} {}",
+                source, result, error, reason, count,
+            );
+        }
+        out
+    }
+}
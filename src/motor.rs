@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::door::Status;
+
+/// A single motor run, from relay trigger to the door reaching its new state.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Cycle {
+    #[serde(rename = "direction")]
+    pub target: Status,
+    #[serde(rename = "secs", serialize_with = "as_secs")]
+    pub runtime: Duration,
+    /// Only closing runs are timed; with a single closed sensor the opening
+    /// run is assumed to take the nominal travel time.
+    pub measured: bool,
+    pub long: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeReport {
+    pub day: NaiveDate,
+    #[serde(serialize_with = "as_secs")]
+    pub today_secs: Duration,
+    pub cycles_today: u32,
+    pub last_cycle: Option<Cycle>,
+}
+
+/// Accumulates opener motor run time per local calendar day.
+#[derive(Debug)]
+pub struct MotorRuntime {
+    travel: Duration,
+    long_cycle_factor: f64,
+    pending: Option<Instant>,
+    day: NaiveDate,
+    total: Duration,
+    cycles: u32,
+    last_cycle: Option<Cycle>,
+    baseline: Option<Duration>,
+}
+
+/// Weight of each new measured close run in the learned baseline.
+const BASELINE_WEIGHT: f64 = 0.2;
+
+impl MotorRuntime {
+    pub fn new(travel: Duration, long_cycle_factor: f64, today: NaiveDate) -> MotorRuntime {
+        MotorRuntime {
+            travel,
+            long_cycle_factor,
+            pending: None,
+            day: today,
+            total: Duration::ZERO,
+            cycles: 0,
+            last_cycle: None,
+            baseline: None,
+        }
+    }
+
+    pub fn set_thresholds(&mut self, travel: Duration, long_cycle_factor: f64) {
+        self.travel = travel;
+        self.long_cycle_factor = long_cycle_factor;
+    }
+
+    pub fn relay_triggered(&mut self) {
+        self.pending = Some(Instant::now());
+    }
+
+    /// Records the end of a run when the door state changes after a relay
+    /// trigger. State changes without a recent trigger (the opener's own
+    /// remote, or a stale trigger that never moved the door) are ignored.
+    pub fn door_changed(&mut self, status: Status, today: NaiveDate) -> Option<Cycle> {
+        let elapsed = self.pending.take()?.elapsed();
+        if elapsed > self.travel.mul_f64(self.long_cycle_factor * 2.0) {
+            return None;
+        }
+
+        let (runtime, measured) = match status {
+            Status::Closed => (elapsed, true),
+            Status::Open => (self.travel, false),
+        };
+        let long = measured && runtime > self.travel.mul_f64(self.long_cycle_factor);
+        let cycle = Cycle { target: status, runtime, measured, long };
+
+        if measured {
+            self.baseline = Some(match self.baseline {
+                Some(b) => b.mul_f64(1.0 - BASELINE_WEIGHT) + runtime.mul_f64(BASELINE_WEIGHT),
+                None => runtime,
+            });
+        }
+
+        self.roll_day(today);
+        self.total += runtime;
+        self.cycles += 1;
+        self.last_cycle = Some(cycle);
+        Some(cycle)
+    }
+
+    /// Learned close time, falling back to the nominal travel time until a
+    /// close run has been measured.
+    pub fn baseline_close(&self) -> Duration {
+        self.baseline.unwrap_or(self.travel)
+    }
+
+    pub fn report(&mut self, today: NaiveDate) -> RuntimeReport {
+        self.roll_day(today);
+        RuntimeReport {
+            day: self.day,
+            today_secs: self.total,
+            cycles_today: self.cycles,
+            last_cycle: self.last_cycle,
+        }
+    }
+
+    fn roll_day(&mut self, today: NaiveDate) {
+        if today != self.day {
+            self.day = today;
+            self.total = Duration::ZERO;
+            self.cycles = 0;
+        }
+    }
+}
+
+fn as_secs<S: serde::Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64((d.as_secs_f64() * 10.0).round() / 10.0)
+}
@@ -0,0 +1,746 @@
+use std::time::Duration;
+
+use rumqttc::{LastWill, MqttOptions, QoS};
+use serde_json::{json, Value};
+
+use crate::config::{AnalogInputConfig, ExtraInputConfig, ExtraOutputConfig, MqttConfig, PresetsConfig, TemperatureSensorConfig};
+use crate::door::{Command, Position};
+use crate::locale::{Entity, Locale};
+use crate::outbox::Priority;
+use crate::vehicle::VehicleEvent;
+
+/// Identifies the door in payloads and logs.
+pub const DOOR_ID: &str = "garage";
+
+/// Capacity of the client's request queue. Every connection queues the
+/// discovery configs, state, subscriptions and ACL probes in one go, so this
+/// needs some headroom.
+pub const REQUEST_QUEUE: usize = 128;
+
+pub const ONLINE: &str = "online";
+pub const OFFLINE: &str = "offline";
+
+pub struct Topics {
+    base: String,
+    discovery: String,
+    pub availability: String,
+    pub config: String,
+    pub command: String,
+    pub set_config: String,
+    /// Takes the same payloads as `command`, but only answers on
+    /// `query_result` whether they would be accepted.
+    pub query: String,
+    pub query_result: String,
+    pub state: String,
+    /// Estimated percent open, 0 being closed.
+    pub position: String,
+    /// Percentages from Home Assistant's position slider.
+    pub set_position: String,
+    pub attributes: String,
+    pub countdown: String,
+    pub countdown_config: String,
+    pub vehicle: String,
+    pub vehicle_config: String,
+    pub motor: String,
+    pub motor_config: String,
+    /// The last motor anomaly, until a normal run clears it.
+    pub motor_anomaly: String,
+    pub motor_anomaly_config: String,
+    pub health: String,
+    pub health_config: String,
+    pub health_button_config: String,
+    pub acl: String,
+    pub acl_config: String,
+    pub notifications: String,
+    pub stats: String,
+    pub cycles_config: String,
+    pub last_opened_config: String,
+    pub open_today_config: String,
+    pub open_duration_config: String,
+    pub last_changed_config: String,
+    /// Openings by weekday and hour.
+    pub heatmap: String,
+    pub heatmap_config: String,
+    /// What recent relay presses did to the door.
+    pub commands: String,
+    pub command_success_config: String,
+    pub preset: String,
+    pub preset_set: String,
+    pub preset_config: String,
+    pub links: String,
+    pub links_config: String,
+    pub vacation_lock: String,
+    pub vacation_lock_set: String,
+    pub vacation_lock_config: String,
+    pub maintenance: String,
+    /// Takes `ON`, `OFF` or JSON with a credential, needed to turn it off.
+    pub maintenance_set: String,
+    pub maintenance_config: String,
+    /// Condition of each part of the controller.
+    pub subsystems: String,
+    pub subsystems_config: String,
+    /// Whether the safety beam is broken.
+    pub obstruction: String,
+    pub obstruction_config: String,
+    /// Commands refused by the vacation lock, and the lock being switched.
+    pub events: String,
+    /// Why the daemon last stopped, retained until the next instance is up.
+    pub last_shutdown: String,
+    /// Command sources currently quarantined.
+    pub quarantine: String,
+    /// Takes a quarantined source, or `all`, to release.
+    pub quarantine_release: String,
+    /// Takes a JSON history query and answers on `history_result`.
+    pub history: String,
+    pub history_result: String,
+    /// Takes a JSON history query and publishes the matching entries on
+    /// `history_replay`, oldest first.
+    pub history_replay_request: String,
+    pub history_replay: String,
+    /// Takes pokes for the virtual door when simulating; see
+    /// [`crate::simulate`].
+    pub simulate: String,
+    /// Runtime options, unless `mqtt.options_topic` puts them elsewhere.
+    pub options: String,
+    /// Periodic sign of life; see [`crate::heartbeat`].
+    pub heartbeat: String,
+}
+
+impl Topics {
+    /// Lays out the daemon's own topics under `base` and the Home Assistant
+    /// discovery configs under `discovery`.
+    pub fn new(base: &str, discovery: &str) -> Topics {
+        Topics {
+            base: base.to_owned(),
+            discovery: discovery.to_owned(),
+            availability: format!("{}/availability", base),
+            config: format!("{}/cover/garage/config", discovery),
+            command: format!("{}/command", base),
+            set_config: format!("{}/set_config", base),
+            query: format!("{}/query", base),
+            query_result: format!("{}/query/result", base),
+            state: format!("{}/state", base),
+            position: format!("{}/position", base),
+            set_position: format!("{}/position/set", base),
+            attributes: format!("{}/attributes", base),
+            countdown: format!("{}/countdown", base),
+            countdown_config: format!("{}/sensor/garage/close_countdown/config", discovery),
+            vehicle: format!("{}/vehicle", base),
+            vehicle_config: format!("{}/sensor/garage/vehicle_event/config", discovery),
+            motor: format!("{}/motor", base),
+            motor_config: format!("{}/sensor/garage/motor_runtime/config", discovery),
+            motor_anomaly: format!("{}/motor/anomaly", base),
+            motor_anomaly_config: format!("{}/binary_sensor/garage/motor_anomaly/config", discovery),
+            health: format!("{}/health_check", base),
+            health_config: format!("{}/sensor/garage/health_check/config", discovery),
+            health_button_config: format!("{}/button/garage/health_check/config", discovery),
+            acl: format!("{}/acl", base),
+            acl_config: format!("{}/binary_sensor/garage/acl/config", discovery),
+            notifications: format!("{}/notifications", base),
+            stats: format!("{}/stats", base),
+            cycles_config: format!("{}/sensor/garage/cycles/config", discovery),
+            last_opened_config: format!("{}/sensor/garage/last_opened/config", discovery),
+            open_today_config: format!("{}/sensor/garage/open_today/config", discovery),
+            open_duration_config: format!("{}/sensor/garage/open_duration/config", discovery),
+            last_changed_config: format!("{}/sensor/garage/last_changed/config", discovery),
+            heatmap: format!("{}/heatmap", base),
+            heatmap_config: format!("{}/sensor/garage/usage_heatmap/config", discovery),
+            commands: format!("{}/commands", base),
+            command_success_config: format!("{}/sensor/garage/command_success/config", discovery),
+            preset: format!("{}/preset", base),
+            preset_set: format!("{}/preset/set", base),
+            preset_config: format!("{}/select/garage/preset/config", discovery),
+            links: format!("{}/links", base),
+            links_config: format!("{}/binary_sensor/garage/links/config", discovery),
+            vacation_lock: format!("{}/vacation_lock", base),
+            vacation_lock_set: format!("{}/vacation_lock/set", base),
+            vacation_lock_config: format!("{}/switch/garage/vacation_lock/config", discovery),
+            maintenance: format!("{}/maintenance", base),
+            maintenance_set: format!("{}/maintenance/set", base),
+            maintenance_config: format!("{}/sensor/garage/maintenance/config", discovery),
+            subsystems: format!("{}/subsystems", base),
+            subsystems_config: format!("{}/binary_sensor/garage/subsystems/config", discovery),
+            obstruction: format!("{}/obstruction", base),
+            obstruction_config: format!("{}/binary_sensor/garage/obstruction/config", discovery),
+            events: format!("{}/events", base),
+            last_shutdown: format!("{}/last_shutdown", base),
+            quarantine: format!("{}/quarantine", base),
+            quarantine_release: format!("{}/quarantine/release", base),
+            history: format!("{}/history", base),
+            history_result: format!("{}/history/result", base),
+            history_replay_request: format!("{}/history/replay/request", base),
+            history_replay: format!("{}/history/replay", base),
+            simulate: format!("{}/simulate", base),
+            options: format!("{}/options", base),
+            heartbeat: format!("{}/health", base),
+        }
+    }
+
+    /// Every topic the daemon publishes or subscribes to, for the ACL self-test.
+    pub fn all(&self) -> Vec<&str> {
+        vec![
+            &self.availability, &self.config, &self.command, &self.set_config, &self.state, &self.position, &self.set_position,
+            &self.query, &self.query_result,
+            &self.attributes, &self.countdown, &self.countdown_config,
+            &self.vehicle, &self.vehicle_config, &self.motor, &self.motor_config, &self.motor_anomaly, &self.motor_anomaly_config,
+            &self.health, &self.health_config, &self.health_button_config,
+            &self.acl, &self.acl_config, &self.notifications,
+            &self.stats, &self.cycles_config, &self.last_opened_config, &self.open_today_config,
+            &self.open_duration_config, &self.last_changed_config,
+            &self.heatmap, &self.heatmap_config, &self.commands, &self.command_success_config,
+            &self.preset, &self.preset_set, &self.preset_config,
+            &self.links, &self.links_config,
+            &self.vacation_lock, &self.vacation_lock_set, &self.vacation_lock_config, &self.events,
+            &self.maintenance, &self.maintenance_set, &self.maintenance_config,
+            &self.obstruction, &self.obstruction_config, &self.subsystems, &self.subsystems_config,
+            &self.last_shutdown, &self.quarantine, &self.quarantine_release, &self.history, &self.history_result,
+            &self.history_replay_request, &self.history_replay,
+            &self.options, &self.heartbeat,
+        ]
+    }
+
+    pub fn vehicle_trigger_config(&self, event: VehicleEvent) -> String {
+        format!("{}/device_automation/garage/{}/config", self.discovery, event)
+    }
+
+    /// Topics that stay retained with `retain` off: Home Assistant needs the
+    /// availability topics after restarting, and the daemon reads its
+    /// shutdown record back on startup.
+    pub fn always_retained(&self, topic: &str) -> bool {
+        topic == self.availability || topic == self.last_shutdown || self.is_sensor_availability(topic)
+    }
+
+    fn is_sensor_availability(&self, topic: &str) -> bool {
+        (topic.starts_with(&self.temperature("")) || topic.starts_with(&self.analog(""))) && topic.ends_with("/availability")
+    }
+
+    /// How hard to try getting a publish on `topic` out when the request
+    /// queue is full.
+    pub fn priority(&self, topic: &str) -> Priority {
+        let critical = [
+            &self.availability, &self.state, &self.position, &self.attributes, &self.query_result,
+            &self.countdown, &self.vacation_lock, &self.maintenance, &self.obstruction, &self.subsystems, &self.events, &self.notifications, &self.last_shutdown,
+        ];
+        let telemetry = [&self.motor, &self.health, &self.acl, &self.stats, &self.heatmap, &self.commands, &self.preset, &self.links, &self.heartbeat];
+        if critical.iter().any(|t| *t == topic) || self.is_sensor_availability(topic) {
+            Priority::Critical
+        } else if telemetry.iter().any(|t| *t == topic)
+            || topic.starts_with(&self.temperature(""))
+            || topic.starts_with(&self.analog(""))
+        {
+            Priority::Telemetry
+        } else {
+            Priority::Normal
+        }
+    }
+
+    /// Readings from the 1-Wire probe with bus id `id`.
+    pub fn temperature(&self, id: &str) -> String {
+        format!("{}/temperature/{}", self.base, id)
+    }
+
+    /// Whether the probe `id` is being read.
+    pub fn temperature_availability(&self, id: &str) -> String {
+        format!("{}/temperature/{}/availability", self.base, id)
+    }
+
+    pub fn temperature_config(&self, id: &str) -> String {
+        format!("{}/sensor/garage/temperature_{}/config", self.discovery, id)
+    }
+
+    /// Readings from the analog input `id`.
+    pub fn analog(&self, id: &str) -> String {
+        format!("{}/analog/{}", self.base, id)
+    }
+
+    /// Whether the analog input `id` is being read.
+    pub fn analog_availability(&self, id: &str) -> String {
+        format!("{}/analog/{}/availability", self.base, id)
+    }
+
+    pub fn analog_config(&self, id: &str) -> String {
+        format!("{}/sensor/garage/analog_{}/config", self.discovery, id)
+    }
+
+    /// `ON` or `OFF` for the spare input or output `id` under `gpio.extra`.
+    pub fn io(&self, id: &str) -> String {
+        format!("{}/io/{}", self.base, id)
+    }
+
+    /// Takes `ON` or `OFF` for the spare output `id`.
+    pub fn io_set(&self, id: &str) -> String {
+        format!("{}/io/{}/set", self.base, id)
+    }
+
+    pub fn io_switch_config(&self, id: &str) -> String {
+        format!("{}/switch/garage/io_{}/config", self.discovery, id)
+    }
+
+    pub fn io_binary_sensor_config(&self, id: &str) -> String {
+        format!("{}/binary_sensor/garage/io_{}/config", self.discovery, id)
+    }
+}
+
+/// MQTT 3.1.1 connection options for the broker in `config`; see
+/// [`crate::mqtt_client`] for MQTT 5.
+pub fn options(config: &MqttConfig, topics: &Topics) -> MqttOptions {
+    let mut options = MqttOptions::new(client_id(config), &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(config.keep_alive_secs));
+    options.set_clean_session(config.clean_session);
+    // With the default of 100, the burst on connecting wraps the packet ids
+    // while earlier ones are still unacknowledged, and the collision that
+    // follows can leave the client out of step with the broker.
+    options.set_inflight(REQUEST_QUEUE as u16);
+    let will_qos = config.qos_for(&topics.availability, config.qos);
+    options.set_last_will(last_will(topics, qos(will_qos)));
+    if let Some(username) = &config.username {
+        let password = config.password.as_ref().map(|p| p.expose()).unwrap_or_default();
+        options.set_credentials(username, password);
+    }
+    options
+}
+
+/// The configured client id, or the hostname.
+pub fn client_id(config: &MqttConfig) -> String {
+    match &config.client_id {
+        Some(id) => id.clone(),
+        None => gethostname::gethostname().into_string().expect("failed to get hostname"),
+    }
+}
+
+/// Marks the daemon offline if it disconnects without saying goodbye.
+pub fn last_will(topics: &Topics, qos: QoS) -> LastWill {
+    LastWill::new(&topics.availability, OFFLINE, qos, true)
+}
+
+/// The QoS for a configured level, which validation keeps within 0 to 2.
+pub fn qos(level: u8) -> QoS {
+    match level {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Device block shared by every entity, so they are grouped together and
+/// device triggers have something to attach to.
+pub fn device(locale: &Locale) -> Value {
+    json!({
+        "identifiers": ["garaged_garage"],
+        "name": locale.name(Entity::Door),
+        "manufacturer": "garaged",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// The door itself. With `json_state` the state topic carries a
+/// [`StateRecord`](crate::door::StateRecord) rather than a bare position.
+pub fn cover_discovery(topics: &Topics, locale: &Locale, json_state: bool) -> Value {
+    let mut config = json!({
+        "name": locale.name(Entity::Door),
+        "unique_id": "garage_door",
+        "command_topic": topics.command,
+        "payload_close": Command::Close.to_string(),
+        "payload_open": Command::Open.to_string(),
+        "payload_stop": Command::Cancel.to_string(),
+        "state_topic": topics.state,
+        "state_open": Position::Open.to_string(),
+        "state_closed": Position::Closed.to_string(),
+        "state_opening": Position::Opening.to_string(),
+        "state_closing": Position::Closing.to_string(),
+        "state_stopped": Position::Stopped.to_string(),
+        "position_topic": topics.position,
+        "set_position_topic": topics.set_position,
+        "position_open": 100,
+        "position_closed": 0,
+        "json_attributes_topic": topics.attributes,
+        "device_class": "garage",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    });
+    if json_state {
+        config["value_template"] = json!("{{ value_json.state }}");
+    }
+    config
+}
+
+pub fn countdown_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::CloseCountdown),
+        "unique_id": "garage_door_close_countdown",
+        "state_topic": topics.countdown,
+        "unit_of_measurement": "s",
+        "icon": "mdi:timer-outline",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn vehicle_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::VehicleEvent),
+        "unique_id": "garage_door_vehicle_event",
+        "state_topic": topics.vehicle,
+        "value_template": "{{ value_json.event }}",
+        "json_attributes_topic": topics.vehicle,
+        "icon": "mdi:car",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn vehicle_trigger_discovery(topics: &Topics, locale: &Locale, event: VehicleEvent) -> Value {
+    json!({
+        "automation_type": "trigger",
+        "topic": topics.vehicle,
+        "value_template": "{{ value_json.event }}",
+        "payload": event.to_string(),
+        "type": event.to_string(),
+        "subtype": "vehicle",
+        "device": device(locale),
+    })
+}
+
+pub fn motor_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::MotorRuntime),
+        "unique_id": "garage_door_motor_runtime",
+        "state_topic": topics.motor,
+        "value_template": "{{ value_json.today_secs }}",
+        "json_attributes_topic": topics.motor,
+        "unit_of_measurement": "s",
+        "device_class": "duration",
+        "state_class": "total_increasing",
+        "icon": "mdi:engine",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// On after a run that was too slow, too fast or reversed, with what was
+/// wrong as attributes, until a normal run.
+pub fn motor_anomaly_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::MotorAnomaly),
+        "unique_id": "garage_door_motor_anomaly",
+        "state_topic": topics.motor_anomaly,
+        "value_template": "{{ 'ON' if value_json.problem else 'OFF' }}",
+        "json_attributes_topic": topics.motor_anomaly,
+        "device_class": "problem",
+        "entity_category": "diagnostic",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn cycles_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::Cycles),
+        "unique_id": "garage_door_cycles",
+        "state_topic": topics.stats,
+        "value_template": "{{ value_json.cycles }}",
+        "json_attributes_topic": topics.stats,
+        "json_attributes_template": "{{ {'cycles_today': value_json.cycles_today} | tojson }}",
+        "state_class": "total_increasing",
+        "icon": "mdi:counter",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn last_opened_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::LastOpened),
+        "unique_id": "garage_door_last_opened",
+        "state_topic": topics.stats,
+        "value_template": "{{ value_json.last_opened }}",
+        "device_class": "timestamp",
+        "icon": "mdi:garage-open",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn open_today_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::OpenToday),
+        "unique_id": "garage_door_open_today",
+        "state_topic": topics.stats,
+        "value_template": "{{ value_json.open_today_secs }}",
+        "unit_of_measurement": "s",
+        "device_class": "duration",
+        "state_class": "total_increasing",
+        "icon": "mdi:timer-outline",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// How long the door has been open for, 0 while closed. Kept by the
+/// daemon across restarts, so it doesn't reset like a template would.
+pub fn open_duration_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::OpenDuration),
+        "unique_id": "garage_door_open_duration",
+        "state_topic": topics.stats,
+        "value_template": "{{ value_json.open_secs }}",
+        "unit_of_measurement": "s",
+        "device_class": "duration",
+        "state_class": "measurement",
+        "icon": "mdi:timer-sand",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn last_changed_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::LastChanged),
+        "unique_id": "garage_door_last_changed",
+        "state_topic": topics.stats,
+        "value_template": "{{ value_json.last_changed }}",
+        "device_class": "timestamp",
+        "icon": "mdi:history",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// Total openings, with the weekday by hour matrix as attributes for
+/// dashboard heatmap cards.
+pub fn heatmap_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::UsageHeatmap),
+        "unique_id": "garage_door_usage_heatmap",
+        "state_topic": topics.heatmap,
+        "value_template": "{{ value_json.total }}",
+        "json_attributes_topic": topics.heatmap,
+        "json_attributes_template": "{{ {'days': value_json.days, 'counts': value_json.counts} | tojson }}",
+        "state_class": "total_increasing",
+        "icon": "mdi:calendar-clock",
+        "entity_category": "diagnostic",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// Share of recent relay presses that moved the door, with the last
+/// press's outcome as attributes.
+pub fn command_success_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::CommandSuccess),
+        "unique_id": "garage_door_command_success",
+        "state_topic": topics.commands,
+        "value_template": "{{ value_json.success_rate }}",
+        "json_attributes_topic": topics.commands,
+        "unit_of_measurement": "%",
+        "state_class": "measurement",
+        "icon": "mdi:remote",
+        "entity_category": "diagnostic",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// Select entity for the partial-open presets. Presets the wind currently
+/// rules out are listed in its `available` attribute.
+pub fn preset_discovery(topics: &Topics, locale: &Locale, presets: &PresetsConfig) -> Value {
+    let options: Vec<&str> = presets.positions.iter().map(|p| p.name.as_str()).collect();
+    json!({
+        "name": locale.name(Entity::Preset),
+        "unique_id": "garage_door_preset",
+        "command_topic": topics.preset_set,
+        "state_topic": topics.preset,
+        "value_template": "{{ value_json.preset }}",
+        "json_attributes_topic": topics.preset,
+        "options": options,
+        "icon": "mdi:garage-variant",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn links_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::LinkProblem),
+        "unique_id": "garage_door_links",
+        "state_topic": topics.links,
+        "value_template": "{{ 'ON' if value_json.problem else 'OFF' }}",
+        "json_attributes_topic": topics.links,
+        "json_attributes_template": "{{ value_json.links | tojson }}",
+        "device_class": "problem",
+        "entity_category": "diagnostic",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn vacation_lock_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::VacationLock),
+        "unique_id": "garage_door_vacation_lock",
+        "command_topic": topics.vacation_lock_set,
+        "state_topic": topics.vacation_lock,
+        "value_template": "{{ 'ON' if value_json.locked else 'OFF' }}",
+        "json_attributes_topic": topics.vacation_lock,
+        "payload_on": "ON",
+        "payload_off": "OFF",
+        "icon": "mdi:lock",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// Shows `maintenance` while the door is being serviced, `normal`
+/// otherwise. There is deliberately no switch: turning it off needs an
+/// admin credential.
+pub fn maintenance_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::Maintenance),
+        "unique_id": "garage_door_maintenance",
+        "state_topic": topics.maintenance,
+        "value_template": "{{ 'maintenance' if value_json.active else 'normal' }}",
+        "json_attributes_topic": topics.maintenance,
+        "device_class": "enum",
+        "options": ["normal", "maintenance"],
+        "icon": "mdi:wrench",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// On while any subsystem is degraded or failing, with each one's condition
+/// as attributes.
+pub fn subsystems_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::Subsystems),
+        "unique_id": "garage_door_subsystems",
+        "state_topic": topics.subsystems,
+        "value_template": "{{ 'OFF' if value_json.overall == 'ok' else 'ON' }}",
+        "json_attributes_topic": topics.subsystems,
+        "json_attributes_template": "{{ value_json.subsystems | tojson }}",
+        "device_class": "problem",
+        "entity_category": "diagnostic",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn obstruction_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::Obstruction),
+        "unique_id": "garage_door_obstruction",
+        "state_topic": topics.obstruction,
+        "device_class": "safety",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn health_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::HealthCheck),
+        "unique_id": "garage_door_health_check",
+        "state_topic": topics.health,
+        "value_template": "{{ value_json.result }}",
+        "json_attributes_topic": topics.health,
+        "icon": "mdi:stethoscope",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn health_button_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::HealthCheckButton),
+        "unique_id": "garage_door_health_check_button",
+        "command_topic": topics.command,
+        "payload_press": Command::HealthCheck.to_string(),
+        "icon": "mdi:stethoscope",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+pub fn acl_discovery(topics: &Topics, locale: &Locale) -> Value {
+    json!({
+        "name": locale.name(Entity::AclProblem),
+        "unique_id": "garage_door_acl",
+        "state_topic": topics.acl,
+        "value_template": "{{ 'ON' if value_json.problem else 'OFF' }}",
+        "json_attributes_topic": topics.acl,
+        "device_class": "problem",
+        "entity_category": "diagnostic",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    })
+}
+
+/// A 1-Wire temperature probe, reported in the locale's unit.
+pub fn temperature_discovery(topics: &Topics, locale: &Locale, sensor: &TemperatureSensorConfig) -> Value {
+    json!({
+        "name": sensor.name,
+        "unique_id": format!("garage_door_temperature_{}", sensor.id.replace('-', "_")),
+        "state_topic": topics.temperature(&sensor.id),
+        "unit_of_measurement": locale.temperature_unit(),
+        "device_class": "temperature",
+        "state_class": "measurement",
+        "availability": [{ "topic": topics.availability }, { "topic": topics.temperature_availability(&sensor.id) }],
+        "availability_mode": "all",
+        "device": device(locale),
+    })
+}
+
+/// A spare output under `gpio.extra`, switched like any other.
+pub fn extra_output_discovery(topics: &Topics, locale: &Locale, output: &ExtraOutputConfig) -> Value {
+    let mut config = json!({
+        "name": output.name,
+        "unique_id": format!("garage_door_io_{}", output.id.replace('-', "_")),
+        "command_topic": topics.io_set(&output.id),
+        "state_topic": topics.io(&output.id),
+        "payload_on": "ON",
+        "payload_off": "OFF",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    });
+    if let Some(icon) = &output.icon {
+        config["icon"] = json!(icon);
+    }
+    config
+}
+
+/// A spare input under `gpio.extra`.
+pub fn extra_input_discovery(topics: &Topics, locale: &Locale, input: &ExtraInputConfig) -> Value {
+    let mut config = json!({
+        "name": input.name,
+        "unique_id": format!("garage_door_io_{}", input.id.replace('-', "_")),
+        "state_topic": topics.io(&input.id),
+        "payload_on": "ON",
+        "payload_off": "OFF",
+        "availability_topic": topics.availability,
+        "device": device(locale),
+    });
+    if let Some(class) = &input.device_class {
+        config["device_class"] = json!(class);
+    }
+    config
+}
+
+pub fn analog_discovery(topics: &Topics, locale: &Locale, input: &AnalogInputConfig) -> Value {
+    let mut config = json!({
+        "name": input.name,
+        "unique_id": format!("garage_door_analog_{}", input.id.replace('-', "_")),
+        "state_topic": topics.analog(&input.id),
+        "state_class": "measurement",
+        "availability": [{ "topic": topics.availability }, { "topic": topics.analog_availability(&input.id) }],
+        "availability_mode": "all",
+        "device": device(locale),
+    });
+    if let Some(unit) = &input.unit {
+        config["unit_of_measurement"] = json!(unit);
+    }
+    if let Some(class) = &input.device_class {
+        config["device_class"] = json!(class);
+    }
+    config
+}
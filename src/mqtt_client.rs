@@ -0,0 +1,221 @@
+//! The broker connection, over MQTT 3.1.1 or, with `mqtt.protocol = "5"`,
+//! MQTT 5. rumqttc has a separate client and event loop for each; these
+//! wrap both so the daemon deals in one set of calls and events.
+//!
+//! With MQTT 5 every publish carries user properties naming the door and
+//! the daemon's version, commands can ask for a reply on a response topic,
+//! and state messages can be given an expiry.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use rumqttc::v5::mqttbytes::v5::{ConnectProperties, Filter, LastWill, Packet, PublishProperties, SubscribeReasonCode};
+use rumqttc::v5::mqttbytes::QoS as QoS5;
+use rumqttc::{v5, ClientError, Incoming, Outgoing, QoS, SubscribeFilter};
+
+use crate::config::{MqttConfig, MqttProtocol};
+use crate::error::BrokerError;
+use crate::mqtt::{self, Topics, DOOR_ID, OFFLINE, REQUEST_QUEUE};
+
+#[derive(Clone)]
+pub enum Client {
+    V311(rumqttc::AsyncClient),
+    V5(v5::AsyncClient),
+}
+
+pub enum EventLoop {
+    V311(Box<rumqttc::EventLoop>),
+    V5(Box<v5::EventLoop>),
+}
+
+/// What the daemon needs to know of what came from the event loop.
+#[derive(Debug)]
+pub enum Event {
+    Connected { session_present: bool },
+    /// The broker refused at least one filter of a subscription.
+    SubscribeRefused { pkid: u16 },
+    Message(Message),
+    /// Our goodbye has gone out.
+    Disconnected,
+    Other,
+}
+
+#[derive(Debug)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Bytes,
+    /// Where an MQTT 5 sender asked for the outcome to be published.
+    pub reply: Option<Reply>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub topic: String,
+    pub correlation: Option<Bytes>,
+}
+
+/// Sets up a client and event loop speaking `config.protocol`.
+pub fn connect(config: &MqttConfig, topics: &Topics) -> (Client, EventLoop) {
+    match config.protocol {
+        MqttProtocol::V311 => {
+            let (client, event_loop) = rumqttc::AsyncClient::new(mqtt::options(config, topics), REQUEST_QUEUE);
+            (Client::V311(client), EventLoop::V311(Box::new(event_loop)))
+        }
+        MqttProtocol::V5 => {
+            let (client, event_loop) = v5::AsyncClient::new(options_v5(config, topics), REQUEST_QUEUE);
+            (Client::V5(client), EventLoop::V5(Box::new(event_loop)))
+        }
+    }
+}
+
+fn options_v5(config: &MqttConfig, topics: &Topics) -> v5::MqttOptions {
+    let mut options = v5::MqttOptions::new(mqtt::client_id(config), &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(config.keep_alive_secs));
+    options.set_clean_start(config.clean_session);
+    options.set_outgoing_inflight_upper_limit(REQUEST_QUEUE as u16);
+    // MQTT 5 ends a session on disconnecting unless given an expiry, which
+    // would undo clean_session = false.
+    if !config.clean_session {
+        let mut properties = ConnectProperties::new();
+        properties.session_expiry_interval = Some(u32::MAX);
+        options.set_connect_properties(properties);
+    }
+    let will_qos = qos_v5(mqtt::qos(config.qos_for(&topics.availability, config.qos)));
+    options.set_last_will(LastWill::new(&topics.availability, OFFLINE, will_qos, true, None));
+    if let Some(username) = &config.username {
+        let password = config.password.as_ref().map(|p| p.expose()).unwrap_or_default();
+        options.set_credentials(username, password);
+    }
+    options
+}
+
+impl Client {
+    /// Queues a publish. `expiry` only has an effect with MQTT 5.
+    pub fn try_publish(&self, topic: &str, qos: QoS, retain: bool, payload: Vec<u8>, expiry: Option<u32>) -> Result<(), BrokerError> {
+        match self {
+            Client::V311(client) => client.try_publish(topic, qos, retain, payload).map_err(queue_error),
+            Client::V5(client) => {
+                let properties = PublishProperties { message_expiry_interval: expiry, ..properties() };
+                client.try_publish_with_properties(topic, qos_v5(qos), retain, payload, properties).map_err(queue_error_v5)
+            }
+        }
+    }
+
+    /// Publishes, waiting for room in the request queue.
+    pub async fn publish(&self, topic: &str, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<(), BrokerError> {
+        match self {
+            Client::V311(client) => client.publish(topic, qos, retain, payload).await.map_err(|_| BrokerError::Closed),
+            Client::V5(client) => client.publish_with_properties(topic, qos_v5(qos), retain, payload, properties()).await
+                .map_err(|_| BrokerError::Closed),
+        }
+    }
+
+    /// Publishes the outcome of a request to where it asked for it.
+    pub fn try_reply(&self, reply: &Reply, payload: Vec<u8>) -> Result<(), BrokerError> {
+        match self {
+            // Only MQTT 5 messages carry a response topic.
+            Client::V311(_) => Ok(()),
+            Client::V5(client) => {
+                let properties = PublishProperties { correlation_data: reply.correlation.clone(), ..properties() };
+                client.try_publish_with_properties(&reply.topic, QoS5::AtLeastOnce, false, payload, properties)
+                    .map_err(queue_error_v5)
+            }
+        }
+    }
+
+    pub fn try_subscribe(&self, topic: &str, qos: QoS) -> Result<(), BrokerError> {
+        match self {
+            Client::V311(client) => client.try_subscribe(topic, qos).map_err(queue_error),
+            Client::V5(client) => client.try_subscribe(topic, qos_v5(qos)).map_err(queue_error_v5),
+        }
+    }
+
+    /// Subscribes to every `(topic, qos)` in one request.
+    pub fn try_subscribe_many(&self, filters: Vec<(String, QoS)>) -> Result<(), BrokerError> {
+        match self {
+            Client::V311(client) => client
+                .try_subscribe_many(filters.into_iter().map(|(topic, qos)| SubscribeFilter::new(topic, qos)))
+                .map_err(queue_error),
+            Client::V5(client) => client
+                .try_subscribe_many(filters.into_iter().map(|(topic, qos)| Filter::new(topic, qos_v5(qos))))
+                .map_err(queue_error_v5),
+        }
+    }
+
+    pub fn try_unsubscribe(&self, topic: &str) -> Result<(), BrokerError> {
+        match self {
+            Client::V311(client) => client.try_unsubscribe(topic).map_err(queue_error),
+            Client::V5(client) => client.try_unsubscribe(topic).map_err(queue_error_v5),
+        }
+    }
+
+    pub fn try_disconnect(&self) -> Result<(), BrokerError> {
+        match self {
+            Client::V311(client) => client.try_disconnect().map_err(queue_error),
+            Client::V5(client) => client.try_disconnect().map_err(queue_error_v5),
+        }
+    }
+}
+
+impl EventLoop {
+    pub async fn poll(&mut self) -> Result<Event, BrokerError> {
+        match self {
+            EventLoop::V311(event_loop) => Ok(match event_loop.poll().await? {
+                rumqttc::Event::Incoming(Incoming::ConnAck(ack)) => Event::Connected { session_present: ack.session_present },
+                rumqttc::Event::Incoming(Incoming::SubAck(ack))
+                    if ack.return_codes.contains(&rumqttc::SubscribeReasonCode::Failure) => Event::SubscribeRefused { pkid: ack.pkid },
+                rumqttc::Event::Incoming(Incoming::Publish(packet)) => {
+                    Event::Message(Message { topic: packet.topic, payload: packet.payload, reply: None })
+                }
+                rumqttc::Event::Outgoing(Outgoing::Disconnect) => Event::Disconnected,
+                _ => Event::Other,
+            }),
+            EventLoop::V5(event_loop) => Ok(match event_loop.poll().await? {
+                v5::Event::Incoming(Packet::ConnAck(ack)) => Event::Connected { session_present: ack.session_present },
+                v5::Event::Incoming(Packet::SubAck(ack))
+                    if ack.return_codes.iter().any(|c| !matches!(c, SubscribeReasonCode::Success(_))) => {
+                    Event::SubscribeRefused { pkid: ack.pkid }
+                }
+                v5::Event::Incoming(Packet::Publish(packet)) => {
+                    let reply = packet.properties.as_ref().and_then(|p| {
+                        let topic = p.response_topic.clone()?;
+                        Some(Reply { topic, correlation: p.correlation_data.clone() })
+                    });
+                    let topic = String::from_utf8_lossy(&packet.topic).into_owned();
+                    Event::Message(Message { topic, payload: packet.payload, reply })
+                }
+                v5::Event::Outgoing(Outgoing::Disconnect) => Event::Disconnected,
+                _ => Event::Other,
+            }),
+        }
+    }
+}
+
+/// The user properties every MQTT 5 publish carries.
+fn properties() -> PublishProperties {
+    PublishProperties {
+        user_properties: vec![
+            ("door".to_owned(), DOOR_ID.to_owned()),
+            ("version".to_owned(), env!("CARGO_PKG_VERSION").to_owned()),
+        ],
+        ..PublishProperties::default()
+    }
+}
+
+fn qos_v5(qos: QoS) -> QoS5 {
+    match qos {
+        QoS::AtMostOnce => QoS5::AtMostOnce,
+        QoS::AtLeastOnce => QoS5::AtLeastOnce,
+        QoS::ExactlyOnce => QoS5::ExactlyOnce,
+    }
+}
+
+// rumqttc no longer says why a request couldn't be queued. The event loop
+// outlives the client, so it is the queue being full.
+fn queue_error(_: ClientError) -> BrokerError {
+    BrokerError::QueueFull
+}
+
+fn queue_error_v5(_: v5::ClientError) -> BrokerError {
+    BrokerError::QueueFull
+}
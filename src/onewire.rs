@@ -0,0 +1,100 @@
+//! Temperature probes on the 1-Wire bus, such as a DS18B20 on the Iono Pi,
+//! read through the kernel's w1 driver.
+//!
+//! A conversion takes up to 750ms per probe, so probes are read on the
+//! blocking pool by a task of their own and the readings handed to the
+//! daemon over a channel.
+
+use std::io;
+use std::path::Path;
+
+use tokio::sync::mpsc;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, warn};
+
+use crate::availability::SensorFailure;
+use crate::config::OneWireConfig;
+
+/// What a DS18B20 reports before its first conversion, rather than a real
+/// temperature.
+const POWER_ON_MILLIS: i64 = 85_000;
+
+#[derive(Debug, Clone)]
+pub struct Reading {
+    /// The probe's bus id, e.g. `28-0316a2797dff`.
+    pub id: String,
+    pub celsius: f64,
+}
+
+/// Starts polling the configured probes. Without any, the channel closes
+/// straight away.
+pub fn spawn(config: Option<OneWireConfig>) -> mpsc::Receiver<Result<Reading, SensorFailure>> {
+    let (tx, rx) = mpsc::channel(16);
+    let config = match config {
+        Some(c) if !c.sensors.is_empty() => c,
+        _ => return rx,
+    };
+    tokio::spawn(async move {
+        let mut timer = interval(config.poll());
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            timer.tick().await;
+            let poll = config.clone();
+            let readings = match tokio::task::spawn_blocking(move || read_all(&poll)).await {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!(error = %e, "1-wire poll failed");
+                    continue;
+                }
+            };
+            for reading in readings {
+                if tx.send(reading).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// Reads every probe, logging the ones that fail.
+fn read_all(config: &OneWireConfig) -> Vec<Result<Reading, SensorFailure>> {
+    config.sensors.iter()
+        .map(|sensor| match read_celsius(&config.devices_dir, &sensor.id) {
+            Ok(celsius) => {
+                debug!(sensor = %sensor.id, celsius, "read 1-wire temperature");
+                Ok(Reading { id: sensor.id.clone(), celsius })
+            }
+            Err(e) => {
+                warn!(sensor = %sensor.id, error = %e, "failed to read 1-wire temperature");
+                Err(SensorFailure { id: sensor.id.clone(), error: e.to_string() })
+            }
+        })
+        .collect()
+}
+
+/// Reads a probe's `w1_slave` file, which looks like:
+///
+/// ```text
+/// 72 01 4b 46 7f ff 0e 10 57 : crc=57 YES
+/// 72 01 4b 46 7f ff 0e 10 57 t=23125
+/// ```
+fn read_celsius(devices_dir: &Path, id: &str) -> io::Result<f64> {
+    let text = std::fs::read_to_string(devices_dir.join(id).join("w1_slave"))?;
+    let mut lines = text.lines();
+    if !lines.next().map(|l| l.trim_end().ends_with("YES")).unwrap_or(false) {
+        return Err(invalid("crc check failed"));
+    }
+    let millis: i64 = lines.next()
+        .and_then(|l| l.rsplit_once("t="))
+        .and_then(|(_, t)| t.trim().parse().ok())
+        .ok_or_else(|| invalid("no temperature in reading"))?;
+    if millis == POWER_ON_MILLIS {
+        return Err(invalid("probe returned its power-on value"));
+    }
+    Ok(millis as f64 / 1000.0)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,132 @@
+//! Runtime options: a few behaviours that can be flipped from the broker,
+//! through a retained JSON message on the options topic, without editing
+//! the config file. Several doors can share one topic for fleet-wide
+//! changes.
+//!
+//! The last accepted options are saved, so they still apply after a restart
+//! with the broker unreachable. A message that doesn't match the schema is
+//! rejected whole, keeping the options in effect; an empty one clears them.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::lockout::LockoutWindow;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeOptions {
+    /// Turns the configured auto-close off, or back on; as configured if
+    /// unset. Without an `[auto_close]` section there is nothing to enable.
+    pub auto_close: Option<bool>,
+    /// Windows, as in `[lockout]`, during which alerts and webhooks aren't
+    /// sent.
+    pub quiet_hours: Vec<LockoutWindow>,
+    pub notifications: NotificationOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationOptions {
+    /// Alerts on the notifications topics.
+    pub alerts: bool,
+    /// Webhook deliveries. The audit webhook is a record, not a
+    /// notification, and is always sent.
+    pub webhooks: bool,
+}
+
+impl Default for NotificationOptions {
+    fn default() -> NotificationOptions {
+        NotificationOptions { alerts: true, webhooks: true }
+    }
+}
+
+impl RuntimeOptions {
+    /// Checks a message from the options topic against the schema.
+    pub fn parse(payload: &[u8]) -> Result<(RuntimeOptions, Value), serde_json::Error> {
+        if payload.iter().all(u8::is_ascii_whitespace) {
+            return Ok((RuntimeOptions::default(), Value::Null));
+        }
+        let raw: Value = serde_json::from_slice(payload)?;
+        let options = RuntimeOptions::deserialize(&raw)?;
+        Ok((options, raw))
+    }
+
+    pub fn auto_close_enabled(&self) -> bool {
+        self.auto_close != Some(false)
+    }
+
+    pub fn is_quiet(&self, now: DateTime<Local>) -> bool {
+        self.quiet_hours.iter().any(|w| w.contains(now))
+    }
+
+    pub fn alerts_enabled(&self, now: DateTime<Local>) -> bool {
+        self.notifications.alerts && !self.is_quiet(now)
+    }
+
+    pub fn webhooks_enabled(&self, now: DateTime<Local>) -> bool {
+        self.notifications.webhooks && !self.is_quiet(now)
+    }
+}
+
+/// The options in effect, as last received.
+pub struct Options {
+    path: PathBuf,
+    options: RuntimeOptions,
+    /// As received, for display and saving; null when cleared.
+    raw: Value,
+}
+
+impl Options {
+    /// Loads the saved options, starting with none if there are none or
+    /// they can't be read.
+    pub fn load(dir: &Path) -> Options {
+        let path = dir.join("options.json");
+        let loaded = match std::fs::read(&path) {
+            Ok(text) => RuntimeOptions::parse(&text).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok((RuntimeOptions::default(), Value::Null)),
+            Err(e) => Err(e.to_string()),
+        };
+        let (options, raw) = loaded.unwrap_or_else(|e| {
+            warn!(path = %path.display(), error = %e, "failed to load runtime options, starting without");
+            (RuntimeOptions::default(), Value::Null)
+        });
+        Options { path, options, raw }
+    }
+
+    pub fn get(&self) -> &RuntimeOptions {
+        &self.options
+    }
+
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+
+    /// Switches to options checked by [`RuntimeOptions::parse`], returning
+    /// whether that changed anything. They apply even if they can't be
+    /// saved.
+    pub fn set(&mut self, options: RuntimeOptions, raw: Value) -> io::Result<bool> {
+        if options == self.options {
+            return Ok(false);
+        }
+        self.options = options;
+        self.raw = raw;
+        if self.raw.is_null() {
+            return match std::fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(true),
+            };
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.raw)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,111 @@
+//! What gives when the MQTT request queue fills up.
+//!
+//! A queue that is full for a moment, say while the discovery burst after a
+//! reconnect drains, only costs the publishes that didn't fit. Once it has
+//! stayed full for longer than [`SATURATED_AFTER`], telemetry isn't even
+//! attempted any more, so whatever room frees up goes to the door itself.
+//! Critical publishes are never dropped: those that don't fit are held,
+//! latest per topic, and retried until they go through.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// How long the queue has to stay full before telemetry is shed.
+pub const SATURATED_AFTER: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// State, availability and replies: held until there is room.
+    Critical,
+    /// Dropped if the queue is full at the time.
+    Normal,
+    /// Diagnostics and statistics: not attempted while the queue stays full.
+    Telemetry,
+}
+
+/// Publishes given up on since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DropCounts {
+    pub normal: u64,
+    pub telemetry: u64,
+    /// Held critical publishes replaced by a newer one on the same topic
+    /// before they could be sent.
+    pub superseded: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Outbox {
+    full_since: Option<Instant>,
+    saturated: bool,
+    held: BTreeMap<String, (bool, Vec<u8>)>,
+    dropped: DropCounts,
+}
+
+impl Outbox {
+    /// Whether a publish at `priority` should be attempted, counting it as
+    /// dropped if not.
+    pub fn admit(&mut self, priority: Priority) -> bool {
+        if priority == Priority::Telemetry && self.is_saturated() {
+            self.dropped.telemetry += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Records that `topic` went into the queue, so the queue has room and
+    /// anything held for the topic is out of date.
+    pub fn sent(&mut self, topic: &str) {
+        if self.held.remove(topic).is_some() {
+            self.dropped.superseded += 1;
+        }
+        self.full_since = None;
+        if self.saturated && self.held.is_empty() {
+            self.saturated = false;
+            info!(dropped = ?self.dropped, "mqtt request queue draining again");
+        }
+    }
+
+    /// Records a publish that didn't fit, holding it if it is critical.
+    pub fn full(&mut self, topic: &str, retain: bool, payload: Vec<u8>, priority: Priority) {
+        self.full_since.get_or_insert_with(Instant::now);
+        if self.is_saturated() && !self.saturated {
+            self.saturated = true;
+            warn!(secs = SATURATED_AFTER.as_secs(), "mqtt request queue full, shedding telemetry");
+        }
+        match priority {
+            Priority::Critical => {
+                if self.held.insert(topic.to_owned(), (retain, payload)).is_some() {
+                    self.dropped.superseded += 1;
+                }
+            }
+            Priority::Normal => {
+                warn!(topic, "mqtt request queue full, dropping publish");
+                self.dropped.normal += 1;
+            }
+            Priority::Telemetry => self.dropped.telemetry += 1,
+        }
+    }
+
+    /// Takes a held publish for another attempt. Hand it back with
+    /// [`full`](Outbox::full) if it still doesn't fit.
+    pub fn next_held(&mut self) -> Option<(String, bool, Vec<u8>)> {
+        self.held.pop_first().map(|(topic, (retain, payload))| (topic, retain, payload))
+    }
+
+    /// Whether telemetry is being shed.
+    pub fn is_shedding(&self) -> bool {
+        self.saturated
+    }
+
+    pub fn dropped(&self) -> DropCounts {
+        self.dropped
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.full_since.map(|at| at.elapsed() >= SATURATED_AFTER).unwrap_or(false)
+    }
+}
@@ -0,0 +1,124 @@
+//! Switched outputs for the relay and indicator LED.
+//!
+//! Besides plain GPIO lines, some relay HATs expose their channels through
+//! the kernel's LED class (`/sys/class/leds`) or as PWM channels. Both are
+//! driven fully on or off here, so they behave like a GPIO output.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sysfs_gpio::{Direction, Pin};
+use tracing::{debug, warn};
+
+use crate::config::{OutputConfig, OutputDriver};
+use crate::error::GpioError;
+use crate::hardware;
+
+/// PWM period used for on/off outputs, in nanoseconds.
+const PWM_PERIOD_NS: u64 = 1_000_000;
+
+pub enum Output {
+    Gpio(Pin),
+    /// A line owned by a kernel driver, switched through its attribute, as
+    /// the Iono Pi's relays and LED are.
+    Attribute { path: PathBuf, invert: bool },
+    /// LED class device directory and the brightness written for on.
+    Led { dir: PathBuf, on: String, invert: bool },
+    /// Chip and channel directories.
+    Pwm { chip: PathBuf, dir: PathBuf, channel: u32, invert: bool },
+}
+
+impl Output {
+    /// Sets up the output, starting inactive. With `ionopi` set, GPIO
+    /// lines the Iono Pi module drives go through it.
+    pub fn init(name: &'static str, config: &OutputConfig, ionopi: Option<&Path>) -> Result<Output, GpioError> {
+        debug!(output = name, driver = ?config.driver(), invert = config.invert, "initializing output");
+        let driver = config.driver().ok_or_else(|| {
+            GpioError::new(name, "configure", sysfs_gpio::Error::Unexpected("no single output driver set".to_owned()))
+        })?;
+        let output = match driver {
+            OutputDriver::Gpio(num) => match ionopi.and_then(|dir| hardware::ionopi_line(dir, num)) {
+                Some(path) => Output::Attribute { path, invert: config.invert },
+                None => {
+                    let pin = Pin::new(num);
+                    hardware::claim(name, pin)?;
+                    // sysfs applies the initial direction value raw, so an
+                    // inverted output must start high to come up inactive.
+                    let direction = if config.invert { Direction::High } else { Direction::Low };
+                    hardware::set_direction(name, pin, direction)?;
+                    pin.set_active_low(config.invert).map_err(|e| GpioError::new(name, "set_active_low", e))?;
+                    Output::Gpio(pin)
+                }
+            },
+            OutputDriver::Led(device) => {
+                let dir = PathBuf::from("/sys/class/leds").join(device);
+                let on = read(&dir.join("max_brightness")).map_err(|e| io_error(name, "read", e))?;
+                // Keep kernel triggers (heartbeat, disk activity...) off the relay.
+                write(&dir.join("trigger"), "none").map_err(|e| io_error(name, "set_trigger", e))?;
+                Output::Led { dir, on, invert: config.invert }
+            }
+            OutputDriver::Pwm { chip, channel } => {
+                let chip = PathBuf::from(format!("/sys/class/pwm/pwmchip{}", chip));
+                let dir = chip.join(format!("pwm{}", channel));
+                if dir.exists() {
+                    warn!(output = name, path = %dir.display(), "pwm channel still exported by a previous run, reclaiming");
+                    write(&chip.join("unexport"), &channel.to_string()).map_err(|e| io_error(name, "unexport", e))?;
+                }
+                write(&chip.join("export"), &channel.to_string()).map_err(|e| io_error(name, "export", e))?;
+                write(&dir.join("period"), &PWM_PERIOD_NS.to_string()).map_err(|e| io_error(name, "set_period", e))?;
+                Output::Pwm { chip, dir, channel, invert: config.invert }
+            }
+        };
+        output.set(name, false)?;
+        if let Output::Pwm { dir, .. } = &output {
+            write(&dir.join("enable"), "1").map_err(|e| io_error(name, "enable", e))?;
+        }
+        Ok(output)
+    }
+
+    pub fn set(&self, name: &'static str, active: bool) -> Result<(), GpioError> {
+        match self {
+            Output::Gpio(pin) => pin.set_value(u8::from(active)).map_err(|e| GpioError::new(name, "write", e)),
+            Output::Attribute { path, invert } => {
+                write(path, if active != *invert { "1" } else { "0" }).map_err(|e| io_error(name, "write", e))
+            }
+            Output::Led { dir, on, invert } => {
+                let value = if active != *invert { on.as_str() } else { "0" };
+                write(&dir.join("brightness"), value).map_err(|e| io_error(name, "write", e))
+            }
+            Output::Pwm { dir, invert, .. } => {
+                let duty = if active != *invert { PWM_PERIOD_NS } else { 0 };
+                write(&dir.join("duty_cycle"), &duty.to_string()).map_err(|e| io_error(name, "write", e))
+            }
+        }
+    }
+
+    /// Hands the output back to the kernel. Errors are ignored, this runs on
+    /// the way out.
+    pub fn release(&self, name: &'static str) {
+        let _ = self.set(name, false);
+        match self {
+            Output::Gpio(pin) => {
+                let _ = pin.unexport();
+            }
+            Output::Attribute { .. } | Output::Led { .. } => (),
+            Output::Pwm { chip, dir, channel, .. } => {
+                let _ = write(&dir.join("enable"), "0");
+                let _ = write(&chip.join("unexport"), &channel.to_string());
+            }
+        }
+    }
+}
+
+fn read(path: &Path) -> io::Result<String> {
+    fs::read_to_string(path).map(|s| s.trim().to_owned())
+}
+
+fn write(path: &Path, value: &str) -> io::Result<()> {
+    fs::write(path, value)
+}
+
+fn io_error(name: &'static str, op: &'static str, e: io::Error) -> GpioError {
+    GpioError::new(name, op, sysfs_gpio::Error::Io(e))
+}
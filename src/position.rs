@@ -0,0 +1,322 @@
+//! Door position from the closed sensor and an optional fully-open sensor.
+//!
+//! With both sensors, travel in between is reported as opening or closing
+//! depending on which end the door left, and as stopped when a relay press
+//! interrupts it or it fails to reach the other end in time. Single-button
+//! openers stop on a press while moving and reverse on the next one, which
+//! is what lets a press after a stop be predicted.
+//!
+//! A percentage is estimated alongside by a [`PositionEstimator`], by
+//! default from the time spent moving relative to the nominal travel time.
+//! With only the closed sensor just the opening run can be followed, since a
+//! press while open may equally have stopped the door.
+//!
+//! Zone sensors part way along the track pin the estimate down as the door
+//! passes them, and tell which way it is going. With the open sensor, a door
+//! that doesn't reach the next sensor in time is presumed stalled.
+//!
+//! Between sensors nothing but the tracker knows where the door is, so its
+//! [`SavedPosition`] is kept across restarts and laid over the first
+//! readings.
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::door::{Command, Position, Status};
+use crate::error::{Error, RejectReason};
+use crate::estimator::{PositionEstimator, TimeEstimator};
+
+/// One reading of every position sensor.
+#[derive(Debug, Clone, Default)]
+pub struct Readings {
+    pub closed: bool,
+    /// `None` without an open sensor.
+    pub open: Option<bool>,
+    /// Zone sensors as `(percent, active)`.
+    pub zones: Vec<(u8, bool)>,
+}
+
+impl Readings {
+    fn zone(&self) -> Option<u8> {
+        self.zones.iter().find(|(_, active)| *active).map(|(percent, _)| *percent)
+    }
+}
+
+/// What the tracker knew of a door at rest, as saved between runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedPosition {
+    pub position: Position,
+    pub percent: u8,
+    /// Which end the door was last moving towards.
+    pub heading: Option<Status>,
+}
+
+#[derive(Debug)]
+pub struct PositionTracker {
+    dual: bool,
+    /// Percentages of the zone sensors, in order.
+    zones: Vec<u8>,
+    /// The zone sensor the door is at, if any.
+    zone: Option<u8>,
+    position: Position,
+    /// Which end the door was last moving towards.
+    heading: Option<Status>,
+    deadline: Option<Instant>,
+    travel: Duration,
+    estimator: Box<dyn PositionEstimator>,
+}
+
+impl PositionTracker {
+    /// `dual` is whether an open sensor is fitted, `zones` the percentages
+    /// of any zone sensors. The percentage is estimated from the time spent
+    /// moving. Call [`resume`] with the first readings before anything else.
+    ///
+    /// [`resume`]: PositionTracker::resume
+    pub fn new(dual: bool, zones: Vec<u8>, travel: Duration) -> PositionTracker {
+        PositionTracker::with_estimator(dual, zones, travel, Box::new(TimeEstimator::new(travel)))
+    }
+
+    /// Like [`new`](PositionTracker::new), with the percentage estimated by
+    /// `estimator`.
+    pub fn with_estimator(dual: bool, mut zones: Vec<u8>, travel: Duration, estimator: Box<dyn PositionEstimator>) -> PositionTracker {
+        zones.sort_unstable();
+        PositionTracker {
+            dual,
+            zones,
+            zone: None,
+            position: Position::Open,
+            heading: None,
+            deadline: None,
+            travel,
+            estimator,
+        }
+    }
+
+    /// Takes the sensor readings at startup, when any motion in progress
+    /// can't be told apart from a door stopped partway. A partway door away
+    /// from the zone sensors is guessed to be half open.
+    pub fn resume(&mut self, readings: &Readings) -> Position {
+        self.zone = readings.zone();
+        let partway = f64::from(self.zone.unwrap_or(50));
+        let (position, percent) = match (readings.closed, readings.open) {
+            (true, _) => (Position::Closed, 0.0),
+            (false, Some(true)) => (Position::Open, 100.0),
+            (false, None) => (Position::Open, self.zone.map_or(100.0, f64::from)),
+            (false, Some(false)) => (Position::Stopped, partway),
+        };
+        self.position = position;
+        self.estimator.fix(percent);
+        self.position
+    }
+
+    /// Lays what the previous run knew over the startup readings, where
+    /// they leave the door somewhere between the sensors: the estimate
+    /// unless a zone sensor pins it down, and which way the door last went,
+    /// so a press from a partway stop is still predicted. Returns whether
+    /// anything was taken over.
+    pub fn restore(&mut self, saved: &SavedPosition) -> bool {
+        if self.at_sensed_end() || saved.position == Position::Closed || (self.dual && saved.position == Position::Open) {
+            return false;
+        }
+        if self.zone.is_none() {
+            self.estimator.fix(f64::from(saved.percent));
+        }
+        self.heading = saved.heading;
+        true
+    }
+
+    /// What to save for [`restore`](PositionTracker::restore), or `None`
+    /// while the door is moving and the estimate is bound to be stale by the
+    /// time it is read back.
+    pub fn saved(&self) -> Option<SavedPosition> {
+        if self.is_moving() || matches!(self.position, Position::Opening | Position::Closing) {
+            return None;
+        }
+        Some(SavedPosition { position: self.position, percent: self.percent(), heading: self.heading })
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Time allowed for a full run before a moving door is presumed stopped.
+    pub fn set_travel(&mut self, travel: Duration) {
+        self.travel = travel;
+        self.estimator.set_travel(travel);
+    }
+
+    /// A pulse from the rotary encoder, for the encoder estimator.
+    pub fn encoder_pulse(&mut self) {
+        self.estimator.encoder_pulse();
+    }
+
+    /// A motor current reading, for the current estimator.
+    pub fn motor_current(&mut self, amps: f64) {
+        self.estimator.motor_current(amps);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// The zone sensor the door is at, if any.
+    pub fn zone(&self) -> Option<u8> {
+        self.zone
+    }
+
+    /// Whether the sensors will see the door start moving from here: it is
+    /// closed, at a zone sensor, or open with the open sensor fitted.
+    pub fn sees_departure(&self) -> bool {
+        self.at_sensed_end() || self.zone.is_some()
+    }
+
+    /// Whether a sensor places the door at either end.
+    pub fn at_sensed_end(&self) -> bool {
+        match self.position {
+            Position::Closed => true,
+            Position::Open => self.dual,
+            _ => false,
+        }
+    }
+
+    /// Whether the percentage is changing, so it should be republished.
+    pub fn is_moving(&self) -> bool {
+        match self.estimator.heading() {
+            Some(Status::Open) => self.percent() < 100,
+            Some(Status::Closed) => self.percent() > 0,
+            None => false,
+        }
+    }
+
+    /// Estimated percent open, 0 being closed.
+    pub fn percent(&self) -> u8 {
+        self.estimator.estimate().clamp(0.0, 100.0).round() as u8
+    }
+
+    /// Updates from fresh sensor readings, returning the new position if it
+    /// changed.
+    pub fn sensors_changed(&mut self, readings: &Readings) -> Option<Position> {
+        let zone = readings.zone();
+        let reached = zone.filter(|_| zone != self.zone);
+        self.zone = zone;
+        let heading = reached.map(|at| self.heading_to(at));
+        let next = match (readings.closed, readings.open) {
+            (true, _) => Position::Closed,
+            (false, None | Some(true)) => Position::Open,
+            (false, Some(false)) => match (heading, self.position) {
+                (Some(Status::Open), _) => Position::Opening,
+                (Some(Status::Closed), _) => Position::Closing,
+                (None, Position::Closed) => Position::Opening,
+                (None, Position::Open) => Position::Closing,
+                (None, moving_or_stopped) => moving_or_stopped,
+            },
+        };
+        let changed = self.set(next);
+        if let (Some(at), Some(heading)) = (reached, heading) {
+            self.passed_zone(at, heading);
+        }
+        changed
+    }
+
+    /// Which way the door must be going to have reached the zone sensor at
+    /// `at`.
+    fn heading_to(&self, at: u8) -> Status {
+        match at.cmp(&self.percent()) {
+            Ordering::Greater => Status::Open,
+            Ordering::Less => Status::Closed,
+            Ordering::Equal => self.heading.unwrap_or(Status::Open),
+        }
+    }
+
+    /// Restarts the estimate from a zone sensor. A moving door then has its
+    /// share of the travel time to reach the next sensor along.
+    fn passed_zone(&mut self, at: u8, heading: Status) {
+        self.heading = Some(heading);
+        self.estimator.start(heading);
+        self.estimator.fix(f64::from(at));
+        if matches!(self.position, Position::Opening | Position::Closing) {
+            let next = match heading {
+                Status::Open => self.zones.iter().copied().find(|&p| p > at).unwrap_or(100),
+                Status::Closed => self.zones.iter().copied().rev().find(|&p| p < at).unwrap_or(0),
+            };
+            let share = f64::from(next.abs_diff(at)) / 100.0;
+            self.deadline = Some(Instant::now() + self.travel.mul_f64(share * 2.0));
+        }
+    }
+
+    /// Accounts for a relay press while the door sits between the sensors.
+    /// Presses at either end are picked up by the sensors instead.
+    pub fn relay_triggered(&mut self) -> Option<Position> {
+        if !self.dual {
+            // A press during the opening run stops the door partway, though
+            // the single sensor still only sees it as open.
+            if self.is_moving() {
+                self.estimator.stop();
+            }
+            return None;
+        }
+        let next = match self.position {
+            Position::Opening | Position::Closing => Position::Stopped,
+            Position::Stopped => match self.heading {
+                Some(Status::Open) => Position::Closing,
+                _ => Position::Opening,
+            },
+            Position::Open | Position::Closed => return None,
+        };
+        self.set(next)
+    }
+
+    /// Called at the deadline: the door didn't reach the other end in time.
+    pub fn timed_out(&mut self) -> Option<Position> {
+        self.set(Position::Stopped)
+    }
+
+    /// Rejects `command` if a press from a partway stop would move the door
+    /// away from where it was asked to go.
+    pub fn check_heading(&self, command: Command) -> Result<(), Error> {
+        let target = match command {
+            Command::Open => Status::Open,
+            Command::Close => Status::Closed,
+            _ => return Ok(()),
+        };
+        match (self.position, self.heading) {
+            (Position::Stopped, Some(heading)) if heading == target => {
+                Err(Error::rejected(RejectReason::WrongDirection))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn set(&mut self, next: Position) -> Option<Position> {
+        if next == self.position {
+            return None;
+        }
+        let from = self.position;
+        match next {
+            Position::Opening => self.estimator.start(Status::Open),
+            Position::Closing => self.estimator.start(Status::Closed),
+            // Without an open sensor, leaving closed is the start of a run.
+            Position::Open if !self.dual && from == Position::Closed => self.estimator.start(Status::Open),
+            _ => self.estimator.stop(),
+        }
+        match next {
+            Position::Closed => self.estimator.fix(0.0),
+            Position::Open if self.dual => self.estimator.fix(100.0),
+            _ => (),
+        }
+        self.position = next;
+        match next {
+            Position::Opening => self.heading = Some(Status::Open),
+            Position::Closing => self.heading = Some(Status::Closed),
+            _ => (),
+        }
+        self.deadline = match next {
+            Position::Opening | Position::Closing => Some(Instant::now() + self.travel * 2),
+            _ => None,
+        };
+        Some(next)
+    }
+}
@@ -0,0 +1,44 @@
+//! Presence lock: remote commands that would open the door are only
+//! accepted while someone is home, or for a grace period after the last
+//! person left, so a stolen phone can't open the door to an empty house.
+//!
+//! Nobody counts as home until one of the presence topics says so, so the
+//! lock holds after a restart until their retained states come in.
+
+use std::collections::BTreeMap;
+
+use tokio::time::Instant;
+
+use crate::config::PresenceConfig;
+
+#[derive(Debug, Default)]
+pub struct Presence {
+    /// The last report on each topic.
+    home: BTreeMap<String, bool>,
+    /// When the last person left.
+    left_at: Option<Instant>,
+}
+
+impl Presence {
+    /// Records a message on a presence topic, returning whether that changed
+    /// whether anyone is home.
+    pub fn report(&mut self, config: &PresenceConfig, topic: &str, payload: &[u8], now: Instant) -> bool {
+        let before = self.anyone_home(config);
+        self.home.insert(topic.to_owned(), config.is_home(payload));
+        let after = self.anyone_home(config);
+        if before && !after {
+            self.left_at = Some(now);
+        }
+        before != after
+    }
+
+    /// Whether any configured topic last reported home.
+    pub fn anyone_home(&self, config: &PresenceConfig) -> bool {
+        config.topics.iter().any(|t| self.home.get(t).copied().unwrap_or(false))
+    }
+
+    /// Whether remote commands may open the door at `now`.
+    pub fn allows_remote_open(&self, config: &PresenceConfig, now: Instant) -> bool {
+        self.anyone_home(config) || self.left_at.is_some_and(|left| now < left + config.grace())
+    }
+}
@@ -0,0 +1,111 @@
+//! Named partial-open positions, gated on wind speed.
+//!
+//! A preset is reached by starting the door from closed and pressing the
+//! relay again after the preset's run time, which stops a single-button
+//! opener where it is.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::config::{PresetConfig, PresetsConfig};
+use crate::error::{Error, RejectReason};
+
+/// Preset the `VENT` command moves to.
+pub const VENT: &str = "vent";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PresetReport {
+    /// The preset the door was last stopped at, until it moves again.
+    pub preset: Option<String>,
+    pub available: Vec<String>,
+    pub wind_speed: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Presets {
+    wind: Option<(f64, Instant)>,
+    active: Option<String>,
+    /// When to stop the door for the preset being moved to.
+    stop_at: Option<Instant>,
+}
+
+impl Presets {
+    /// Records a wind topic payload, returning the speed if it parsed.
+    pub fn wind_reading(&mut self, payload: &[u8]) -> Option<f64> {
+        let speed = std::str::from_utf8(payload).ok()?.trim().parse::<f64>().ok()?;
+        self.wind = Some((speed, Instant::now()));
+        Some(speed)
+    }
+
+    /// Latest wind speed, unless it is older than the configured age.
+    pub fn wind_speed(&self, config: &PresetsConfig) -> Option<f64> {
+        self.wind
+            .filter(|(_, at)| at.elapsed() <= config.wind_max_age())
+            .map(|(speed, _)| speed)
+    }
+
+    /// Looks up `name` and checks that the wind allows it.
+    pub fn check<'a>(&self, config: &'a PresetsConfig, name: &str) -> Result<&'a PresetConfig, Error> {
+        let preset = config.get(name).ok_or(Error::rejected(RejectReason::UnknownPreset))?;
+        match (preset.max_wind, self.wind_speed(config)) {
+            (None, _) => Ok(preset),
+            (Some(_), None) => Err(Error::rejected(RejectReason::WindUnknown)),
+            (Some(max), Some(speed)) if speed > max => Err(Error::rejected(RejectReason::WindTooHigh)),
+            (Some(_), Some(_)) => Ok(preset),
+        }
+    }
+
+    /// Whether the wind has picked up beyond the active preset's limit.
+    pub fn wind_exceeded(&self, config: &PresetsConfig) -> bool {
+        let limit = self.active.as_deref()
+            .and_then(|name| config.get(name))
+            .and_then(|p| p.max_wind);
+        match (limit, self.wind_speed(config)) {
+            (Some(max), Some(speed)) => speed > max,
+            _ => false,
+        }
+    }
+
+    /// Starts moving to `preset`; the caller triggers the relay to open.
+    pub fn start(&mut self, preset: &PresetConfig) {
+        self.active = Some(preset.name.clone());
+        self.stop_at = Some(Instant::now() + preset.open_time());
+    }
+
+    /// The preset whose estimated opening is closest to `percent`, given the
+    /// door's full travel time.
+    pub fn nearest(config: &PresetsConfig, percent: u8, travel: Duration) -> Option<&PresetConfig> {
+        let target = f64::from(percent) / 100.0 * travel.as_secs_f64();
+        config.positions.iter()
+            .min_by(|a, b| (a.open_secs - target).abs().total_cmp(&(b.open_secs - target).abs()))
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.stop_at
+    }
+
+    /// Called at the deadline, when the caller should stop the door.
+    pub fn reached(&mut self) {
+        self.stop_at = None;
+    }
+
+    /// Forgets the active preset once the door is moved any other way.
+    /// Returns whether there was one.
+    pub fn clear(&mut self) -> bool {
+        self.stop_at = None;
+        self.active.take().is_some()
+    }
+
+    pub fn report(&self, config: &PresetsConfig) -> PresetReport {
+        PresetReport {
+            preset: self.active.clone(),
+            available: config.positions.iter()
+                .filter(|p| self.check(config, &p.name).is_ok())
+                .map(|p| p.name.clone())
+                .collect(),
+            wind_speed: self.wind_speed(config),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Privacy mode, for households that want the door automated without
+//! keeping a log of who came and went when.
+//!
+//! Events still go out as they happen, so automations keep working, but
+//! without the identity behind them and with their timestamp cut to the
+//! local day. What is kept over time is the usage counters, which only ever
+//! held per-day totals.
+
+use chrono::{DateTime, Local, Utc};
+use serde_json::{json, Value};
+
+/// Keys naming who did something.
+const IDENTITY_KEYS: &[&str] = &["code_id", "identity"];
+
+/// Removes identities from an event or attribute payload and replaces its
+/// `timestamp` with the local `date`.
+pub fn redact(payload: &mut Value) {
+    let map = match payload {
+        Value::Object(map) => map,
+        _ => return,
+    };
+    for key in IDENTITY_KEYS {
+        map.remove(*key);
+    }
+    if let Some(timestamp) = map.remove("timestamp") {
+        let date = timestamp.as_str()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| t.with_timezone(&Local).date_naive());
+        map.insert("date".to_owned(), json!(date));
+    }
+}
+
+/// Local midnight at the start of the day `time` falls on, or `time` itself
+/// if that midnight doesn't exist.
+pub fn start_of_day(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.with_timezone(&Local)
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map(|midnight| midnight.with_timezone(&Utc))
+        .unwrap_or(time)
+}
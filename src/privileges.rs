@@ -0,0 +1,83 @@
+//! Giving up root once the hardware is set up. Exporting pins and enabling
+//! the watchdog need root, but the daemon then spends its life handling
+//! MQTT and HTTP input, so with `[privileges]` set it switches for good to
+//! an unprivileged user once the broker has first been tried and the HTTP
+//! port is bound, before either is listened to. Reconnects need nothing
+//! more, and files needed at shutdown are opened beforehand.
+
+use std::ffi::CString;
+use std::os::unix::fs::chown;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use nix::unistd::{self, Gid, Group, Uid, User};
+use tracing::info;
+
+use crate::config::PrivilegesConfig;
+use crate::error::PrivilegeError;
+
+/// Switches to `config.user`, with its own groups and primary group or
+/// `config.group`. The storage directory and any `files` kept elsewhere,
+/// such as a history database or dead-letter file, are handed over first so
+/// that they can still be written afterwards.
+pub fn drop_to(config: &PrivilegesConfig, storage: &Path, files: &[PathBuf]) -> Result<(), PrivilegeError> {
+    let user = User::from_name(&config.user)?.ok_or_else(|| PrivilegeError::NoUser(config.user.clone()))?;
+    let gid = match &config.group {
+        Some(name) => Group::from_name(name)?.ok_or_else(|| PrivilegeError::NoGroup(name.clone()))?.gid,
+        None => user.gid,
+    };
+    if unistd::geteuid() == user.uid {
+        info!(user = %user.name, "already running as the unprivileged user");
+        return Ok(());
+    }
+    if !unistd::geteuid().is_root() {
+        return Err(PrivilegeError::NotRoot(user.name));
+    }
+    hand_over(storage, user.uid, gid)?;
+    for file in files {
+        hand_over_file(file, user.uid, gid)?;
+    }
+    let name = CString::new(user.name.as_str()).map_err(|_| PrivilegeError::NoUser(user.name.clone()))?;
+    unistd::initgroups(&name, gid)?;
+    unistd::setgid(gid)?;
+    unistd::setuid(user.uid)?;
+    if unistd::setuid(Uid::from_raw(0)).is_ok() {
+        return Err(PrivilegeError::Regained);
+    }
+    info!(user = %user.name, uid = %user.uid, gid = %gid, "dropped root privileges");
+    Ok(())
+}
+
+/// Gives `dir` and the files directly in it to the user, creating it if
+/// need be.
+fn hand_over(dir: &Path, uid: Uid, gid: Gid) -> Result<(), PrivilegeError> {
+    let failed = |e| PrivilegeError::HandOver(dir.to_owned(), e);
+    std::fs::create_dir_all(dir).map_err(failed)?;
+    chown(dir, Some(uid.as_raw()), Some(gid.as_raw())).map_err(failed)?;
+    for entry in std::fs::read_dir(dir).map_err(failed)? {
+        let path = entry.map_err(failed)?.path();
+        if path.is_file() {
+            chown(&path, Some(uid.as_raw()), Some(gid.as_raw()))
+                .map_err(|e| PrivilegeError::HandOver(path.clone(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Gives `file` to the user, creating it if need be, along with the
+/// `-wal` and `-shm` files SQLite keeps beside a database.
+fn hand_over_file(file: &Path, uid: Uid, gid: Gid) -> Result<(), PrivilegeError> {
+    OpenOptions::new().append(true).create(true).open(file)
+        .and_then(|_| chown(file, Some(uid.as_raw()), Some(gid.as_raw())))
+        .map_err(|e| PrivilegeError::HandOver(file.to_owned(), e))?;
+    for suffix in ["-wal", "-shm"] {
+        let mut companion = file.as_os_str().to_owned();
+        companion.push(suffix);
+        let companion = PathBuf::from(companion);
+        if companion.is_file() {
+            chown(&companion, Some(uid.as_raw()), Some(gid.as_raw()))
+                .map_err(|e| PrivilegeError::HandOver(companion.clone(), e))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! Quarantine for command sources that look like they are being abused.
+//!
+//! A source is an authenticated identity (`identity:<id>`), a token nobody
+//! recognises (`token:<hash prefix>`, so the token itself is never shown) or
+//! the keypad as a whole (`keypad`, as wrong codes can't be told apart).
+//! Failed authentications and rejected commands count as strikes against
+//! their source; too many of either within the window quarantines it for
+//! the cooldown, or until an admin releases it. Sources sending no
+//! credential at all are shared by everything on the broker and the wall
+//! button, so they never are.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use strum::Display;
+use tokio::time::Instant;
+
+use crate::auth::hex;
+use crate::config::QuarantineConfig;
+use crate::error::{Error, RejectReason};
+
+/// The source for wrong keypad codes.
+pub const KEYPAD: &str = "keypad";
+
+pub fn identity_key(id: &str) -> String {
+    format!("identity:{}", id)
+}
+
+/// The source for an unrecognised token: enough of its hash to tell tokens
+/// apart in the quarantine list without revealing them.
+pub fn token_key(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    format!("token:{}", &hex(&digest)[..12])
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseMessage {
+    pub source: String,
+    /// Token to act as; releasing needs an admin.
+    pub credential: Option<String>,
+}
+
+/// Parses a release topic payload: either a bare source or `all`, which
+/// carries no credential and so is refused, or JSON like
+/// `{"source": "identity:alice", "credential": "<admin token>"}`.
+pub fn parse_release_message(payload: &[u8]) -> Result<ReleaseMessage, Error> {
+    if payload.first() != Some(&b'{') {
+        let source = std::str::from_utf8(payload).map_err(|_| Error::rejected(RejectReason::InvalidPayload))?;
+        return Ok(ReleaseMessage { source: source.trim().to_owned(), credential: None });
+    }
+    serde_json::from_slice(payload).map_err(|_| Error::rejected(RejectReason::InvalidPayload))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Strike {
+    /// An unknown token or a wrong keypad code.
+    FailedAuth,
+    /// A command from the source was rejected.
+    Rejected,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuarantinedSource {
+    pub source: String,
+    /// The kind of strike that tipped it over.
+    pub reason: Strike,
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct Quarantine {
+    /// When each strike still inside the window was counted, per source.
+    strikes: BTreeMap<(String, Strike), VecDeque<Instant>>,
+    quarantined: BTreeMap<String, (Instant, QuarantinedSource)>,
+}
+
+impl Quarantine {
+    /// Counts a strike against `source`, returning the quarantine if this
+    /// one started it. Strikes against a source already quarantined don't
+    /// count.
+    pub fn strike(&mut self, source: &str, strike: Strike, config: &QuarantineConfig, now: DateTime<Utc>) -> Option<QuarantinedSource> {
+        if self.is_quarantined(source) {
+            return None;
+        }
+        let at = Instant::now();
+        // Forget sources with nothing left in the window, so a stream of
+        // made-up tokens can't grow the map forever.
+        self.strikes.retain(|_, strikes| strikes.back().is_some_and(|&t| at.duration_since(t) < config.window()));
+        let strikes = self.strikes.entry((source.to_owned(), strike)).or_default();
+        while strikes.front().is_some_and(|&t| at.duration_since(t) >= config.window()) {
+            strikes.pop_front();
+        }
+        strikes.push_back(at);
+        let max = match strike {
+            Strike::FailedAuth => config.max_failed_auth,
+            Strike::Rejected => config.max_rejections,
+        };
+        if strikes.len() < max as usize {
+            return None;
+        }
+        self.strikes.retain(|(s, _), _| s != source);
+        let until = now + chrono::Duration::from_std(config.cooldown()).unwrap_or_else(|_| chrono::Duration::zero());
+        let entry = QuarantinedSource { source: source.to_owned(), reason: strike, since: now, until };
+        self.quarantined.insert(source.to_owned(), (at + config.cooldown(), entry.clone()));
+        Some(entry)
+    }
+
+    pub fn is_quarantined(&self, source: &str) -> bool {
+        self.quarantined.get(source).is_some_and(|(until, _)| Instant::now() < *until)
+    }
+
+    pub fn get(&self, source: &str) -> Option<&QuarantinedSource> {
+        self.quarantined.get(source).map(|(_, entry)| entry)
+    }
+
+    /// Lifts a quarantine by hand, returning whether there was one.
+    pub fn release(&mut self, source: &str) -> bool {
+        self.quarantined.remove(source).is_some()
+    }
+
+    /// Lifts every quarantine, returning the sources released.
+    pub fn release_all(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.quarantined).into_keys().collect()
+    }
+
+    /// Removes quarantines whose cooldown is over, returning their sources.
+    pub fn expire(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        let expired: Vec<String> = self.quarantined.iter()
+            .filter(|(_, (until, _))| *until <= now)
+            .map(|(source, _)| source.clone())
+            .collect();
+        for source in &expired {
+            self.quarantined.remove(source);
+        }
+        expired
+    }
+
+    /// When the next quarantine runs out.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.quarantined.values().map(|(until, _)| *until).min()
+    }
+
+    pub fn list(&self) -> Vec<QuarantinedSource> {
+        self.quarantined.values().map(|(_, entry)| entry.clone()).collect()
+    }
+}
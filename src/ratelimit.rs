@@ -0,0 +1,34 @@
+//! Sliding-window limit on commands from MQTT, so a misbehaving automation
+//! can't cycle the door continuously.
+
+use std::collections::VecDeque;
+
+use tokio::time::Instant;
+
+use crate::config::RateLimitConfig;
+use crate::error::{Error, RejectReason};
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// When each command still inside the window was accepted.
+    accepted: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    /// Counts a command against the limit, rejecting it once the window is
+    /// full. Rejected commands don't count.
+    pub fn admit(&mut self, config: &RateLimitConfig) -> Result<(), Error> {
+        let now = Instant::now();
+        while let Some(&at) = self.accepted.front() {
+            if now.duration_since(at) < config.window() {
+                break;
+            }
+            self.accepted.pop_front();
+        }
+        if self.accepted.len() >= config.max_commands {
+            return Err(Error::rejected(RejectReason::RateLimited));
+        }
+        self.accepted.push_back(now);
+        Ok(())
+    }
+}
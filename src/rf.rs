@@ -0,0 +1,91 @@
+//! A 433 MHz receiver's data line, decoded as EV1527 fixed-code remotes.
+//!
+//! EV1527 remotes send 24 bits, a 20-bit address burnt in at the factory and
+//! 4 button bits, as pulses a few hundred microseconds wide: a short high
+//! and a long low for a 0, a long high and a short low for a 1, and a short
+//! high and a very long low to sync before each frame. The frame is repeated
+//! for as long as the button is held, which counts as a single press.
+//!
+//! The decoder runs on a thread waiting on the data line, so edges are
+//! timed as the kernel reports them. A busy system can still drop frames;
+//! the remote's repeats usually make up for it.
+
+use std::time::{Duration, Instant};
+
+/// Bits in a frame.
+const FRAME_BITS: u8 = 24;
+
+/// How long a bit may take, high and low together. Remotes use pulses of
+/// 250-500µs, four to a bit.
+const MIN_BIT: Duration = Duration::from_micros(500);
+const MAX_BIT: Duration = Duration::from_millis(4);
+
+/// A low this many times the high before it is a sync; remotes send 31.
+const SYNC_RATIO: u32 = 15;
+
+/// The longest pulse of the pair must be at least this many times the
+/// shorter for the bit to count; remotes send 3.
+const BIT_RATIO: u32 = 2;
+
+/// A code received again within this long of the last is the same press.
+const REPEAT_HOLD: Duration = Duration::from_secs(1);
+
+/// Turns edges on the data line into codes.
+#[derive(Debug, Default)]
+pub struct Ev1527 {
+    /// The level the line changed to at `since`.
+    level: Option<(bool, Instant)>,
+    /// How long the line was high for the bit in progress.
+    high: Option<Duration>,
+    /// The bits so far and how many, once synced.
+    frame: Option<(u32, u8)>,
+    /// The last code received and when.
+    last: Option<(u32, Instant)>,
+}
+
+impl Ev1527 {
+    /// Takes the line changing to `high` at `at`, returning a code once a
+    /// frame is complete. Repeats of a held button are swallowed.
+    pub fn edge(&mut self, high: bool, at: Instant) -> Option<u32> {
+        let (was_high, since) = match self.level.replace((high, at)) {
+            Some((level, _)) if level == high => {
+                // An edge went missing, so the timings no longer line up.
+                self.high = None;
+                self.frame = None;
+                return None;
+            }
+            Some(level) => level,
+            None => return None,
+        };
+        let width = at - since;
+        if was_high {
+            self.high = Some(width);
+            return None;
+        }
+        let high = self.high.take()?;
+        if width >= high * SYNC_RATIO {
+            self.frame = Some((0, 0));
+            return None;
+        }
+        let (bits, count) = self.frame.take()?;
+        let bit = match high + width {
+            period if period < MIN_BIT || period > MAX_BIT => return None,
+            _ if high >= width * BIT_RATIO => 1,
+            _ if width >= high * BIT_RATIO => 0,
+            _ => return None,
+        };
+        let (bits, count) = (bits << 1 | bit, count + 1);
+        if count < FRAME_BITS {
+            self.frame = Some((bits, count));
+            return None;
+        }
+        let repeat = self.last.map(|(code, last)| code == bits && at - last < REPEAT_HOLD).unwrap_or(false);
+        self.last = Some((bits, at));
+        (!repeat).then_some(bits)
+    }
+}
+
+/// A code as it is written in `[[rf.codes]]`, six hex digits.
+pub fn format_code(code: u32) -> String {
+    format!("{:06x}", code)
+}
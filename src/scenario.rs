@@ -0,0 +1,208 @@
+//! Scripted runs of the virtual door, so regression tests and demos can
+//! play the same sequence every time. A scenario is YAML:
+//!
+//! ```yaml
+//! steps:
+//!   - { at: 5s, do: button }
+//!   - { at: 12s, do: jam }
+//!   - { at: 60s, do: drop_broker }
+//!   - { at: 90s, do: restore_broker }
+//! ```
+//!
+//! `at` counts from startup, in seconds or with an `ms`, `s` or `m` suffix.
+//! `do` is any poke [`crate::simulate`] takes, or `drop_broker` and
+//! `restore_broker`, which cut and restore the daemon's broker connection by
+//! relaying it through [`BrokerLink`].
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep_until, Instant};
+use tracing::{info, warn};
+
+use crate::error::ScenarioError;
+use crate::simulate::Poke;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    #[serde(deserialize_with = "deserialize_at")]
+    pub at: Duration,
+    #[serde(rename = "do")]
+    pub action: Action,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    Poke(Poke),
+    DropBroker,
+    RestoreBroker,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Action, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "drop_broker" => Ok(Action::DropBroker),
+            "restore_broker" => Ok(Action::RestoreBroker),
+            other => other.parse().map(Action::Poke),
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Action, String> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Poke(poke) => f.write_str(&format!("{:?}", poke).to_ascii_lowercase()),
+            Action::DropBroker => f.write_str("drop_broker"),
+            Action::RestoreBroker => f.write_str("restore_broker"),
+        }
+    }
+}
+
+fn deserialize_at<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum At {
+        Secs(f64),
+        Text(String),
+    }
+    let secs = match At::deserialize(deserializer)? {
+        At::Secs(secs) => Some(secs),
+        At::Text(text) => parse_secs(&text),
+    };
+    match secs {
+        Some(secs) if secs >= 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(D::Error::custom("at must be a time from startup like 5, 5s, 500ms or 2m")),
+    }
+}
+
+fn parse_secs(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (number, scale) = if let Some(n) = text.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = text.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = text.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        (text, 1.0)
+    };
+    number.trim().parse::<f64>().ok().map(|n| n * scale)
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Scenario, ScenarioError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ScenarioError::Read(path.to_owned(), e))?;
+        let mut scenario: Scenario = serde_yaml::from_str(&text)
+            .map_err(|e| ScenarioError::Parse(path.to_owned(), e))?;
+        // Stable, so steps at the same time keep their order in the file.
+        scenario.steps.sort_by_key(|step| step.at);
+        Ok(scenario)
+    }
+
+    /// Whether any step cuts or restores the broker connection, which
+    /// needs a [`BrokerLink`].
+    pub fn cuts_broker(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step.action, Action::DropBroker | Action::RestoreBroker))
+    }
+}
+
+/// Plays `scenario` from now, poking the door through `door` and cutting
+/// the broker through `link`.
+pub async fn run(scenario: Scenario, door: mpsc::UnboundedSender<Poke>, link: Option<BrokerLink>) {
+    let start = Instant::now();
+    info!(steps = scenario.steps.len(), "playing scenario");
+    for step in scenario.steps {
+        sleep_until(start + step.at).await;
+        info!(at_secs = step.at.as_secs_f64(), action = %step.action, "scenario step");
+        match (step.action, &link) {
+            (Action::Poke(poke), _) => {
+                if door.send(poke).is_err() {
+                    return;
+                }
+            }
+            (Action::DropBroker, Some(link)) => link.set_up(false),
+            (Action::RestoreBroker, Some(link)) => link.set_up(true),
+            (_, None) => warn!(action = %step.action, "no broker relay, skipping step"),
+        }
+    }
+    info!("scenario finished");
+}
+
+/// A relay on localhost between the daemon and the broker. While it is
+/// down its connections are closed and new ones are hung up on, which the
+/// daemon sees as the broker going away.
+pub struct BrokerLink {
+    addr: SocketAddr,
+    up: watch::Sender<bool>,
+}
+
+impl BrokerLink {
+    /// Starts relaying to the broker at `host`:`port`.
+    pub async fn spawn(host: &str, port: u16) -> io::Result<BrokerLink> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (up, rx) = watch::channel(true);
+        let broker = (host.to_owned(), port);
+        tokio::spawn(async move {
+            while let Ok((mut client, _)) = listener.accept().await {
+                if !*rx.borrow() {
+                    continue;
+                }
+                let mut rx = rx.clone();
+                let broker = broker.clone();
+                tokio::spawn(async move {
+                    let mut upstream = match TcpStream::connect(broker).await {
+                        Ok(s) => s,
+                        Err(e) => return warn!(error = %e, "broker relay failed to connect"),
+                    };
+                    let down = async {
+                        // The link going away isn't the broker dropping.
+                        if rx.wait_for(|up| !*up).await.is_err() {
+                            std::future::pending::<()>().await;
+                        }
+                    };
+                    tokio::select! {
+                        _ = tokio::io::copy_bidirectional(&mut client, &mut upstream) => (),
+                        _ = down => info!("broker connection cut"),
+                    }
+                });
+            }
+        });
+        Ok(BrokerLink { addr, up })
+    }
+
+    /// Where the daemon should connect instead of the broker.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn set_up(&self, up: bool) {
+        self.up.send_replace(up);
+    }
+}
@@ -0,0 +1,234 @@
+//! Cron-like scheduled actions, run by the daemon itself so rules like
+//! "close the door at night" don't depend on Home Assistant being up.
+//!
+//! Each `[[schedule]]` entry pairs a five-field cron expression (minute,
+//! hour, day of month, month, day of week) with an action, evaluated in the
+//! entry's timezone or else the system's. Fields take `*`, numbers, names
+//! (`Mon`, `Jan`), ranges, lists and steps, as in `*/15` or `Mon-Fri`; as in
+//! cron, when both days are restricted either may match. A time skipped by
+//! a DST change doesn't run that day, and one repeated runs once. Runs that
+//! came due while the daemon was down aren't made up.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer, Serialize};
+use strum::Display;
+
+use crate::error::CronError;
+
+/// How far ahead to look for an expression's next run, enough for any
+/// combination of days and months that occurs at all.
+const HORIZON_DAYS: u32 = 8 * 366;
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ScheduleAction {
+    /// Close the door after the usual countdown, if it is open.
+    Close,
+    /// Turn the vacation lock on.
+    Lock,
+    /// Turn the vacation lock off.
+    Unlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleEntry {
+    pub name: String,
+    pub cron: CronExpr,
+    pub action: ScheduleAction,
+    /// IANA timezone, e.g. `Europe/Oslo`; the system's if unset.
+    pub timezone: Option<Tz>,
+}
+
+impl ScheduleEntry {
+    /// The first time the entry runs after `after`.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.timezone {
+            Some(tz) => self.cron.next_after(after, &tz),
+            None => self.cron.next_after(after, &Local),
+        }
+    }
+}
+
+/// A parsed five-field cron expression, each field a bit set of the values
+/// it allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    text: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    /// Sunday is 0.
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronExpr {
+    /// The first minute matching the expression after `after`, reckoned in
+    /// `tz`.
+    pub fn next_after<Z: TimeZone>(&self, after: DateTime<Utc>, tz: &Z) -> Option<DateTime<Utc>> {
+        let start = after.with_timezone(tz).date_naive();
+        for date in start.iter_days().take(HORIZON_DAYS as usize) {
+            if !self.matches_day(date) {
+                continue;
+            }
+            for hour in (0..24).filter(|h| self.hours & 1 << h != 0) {
+                for minute in (0..60).filter(|m| self.minutes & 1 << m != 0) {
+                    let naive = date.and_hms_opt(hour, minute, 0)?;
+                    let time = match tz.from_local_datetime(&naive).earliest() {
+                        Some(t) => t.with_timezone(&Utc),
+                        None => continue,
+                    };
+                    if time > after {
+                        return Some(time);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & 1 << date.month() == 0 {
+            return false;
+        }
+        let day = self.days & 1 << date.day() != 0;
+        let weekday = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday,
+            (false, true) => day,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+impl FromStr for CronExpr {
+    type Err = CronError;
+
+    fn from_str(text: &str) -> Result<CronExpr, CronError> {
+        let error = |reason: String| CronError { expr: text.to_owned(), reason };
+        let fields: Vec<_> = text.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(error(format!("expected 5 fields, found {}", fields.len())));
+        };
+        let mut weekdays = field(weekday, 0, 7, &WEEKDAYS, 0).map_err(error)?;
+        // 7 is Sunday too.
+        if weekdays & 1 << 7 != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(CronExpr {
+            text: text.to_owned(),
+            minutes: field(minute, 0, 59, &[], 0).map_err(error)?,
+            hours: field(hour, 0, 23, &[], 0).map_err(error)?,
+            days: field(day, 1, 31, &[], 0).map_err(error)?,
+            months: field(month, 1, 12, &MONTHS, 1).map_err(error)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for CronExpr {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<CronExpr, D::Error> {
+        String::deserialize(d)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Parses one field into a bit set of the values between `min` and `max`
+/// it allows. `names` spell out the values from `first` on.
+fn field(text: &str, min: u32, max: u32, names: &[&str], first: u32) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let named = names.iter().position(|n| n.eq_ignore_ascii_case(s)).map(|i| i as u32 + first);
+        let value = named.or_else(|| s.parse().ok()).ok_or_else(|| format!("{:?} is not a value", s))?;
+        if !(min..=max).contains(&value) {
+            return Err(format!("{} is outside {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+    let mut bits = 0;
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("{:?} is not a step", step)),
+            },
+            None => (item, 1),
+        };
+        let (start, mut end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // A start with a step runs to the end, as in 5/15.
+            None if item.contains('/') => (value(range)?, max),
+            None => {
+                let v = value(range)?;
+                (v, v)
+            }
+        };
+        // Weekday ranges may end on Sunday, as in Fri-Sun.
+        if max == 7 && end == 0 && start > 0 {
+            end = 7;
+        }
+        if start > end {
+            return Err(format!("range {} is backwards", range));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+/// When each configured entry next runs.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    next: Vec<Option<DateTime<Utc>>>,
+}
+
+impl Scheduler {
+    pub fn new(entries: &[ScheduleEntry], now: DateTime<Utc>) -> Scheduler {
+        Scheduler { next: entries.iter().map(|e| e.next_after(now)).collect() }
+    }
+
+    /// The earliest upcoming run.
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.next.iter().flatten().min().copied()
+    }
+
+    /// The entry that runs next and when, for display.
+    pub fn upcoming<'a>(&self, entries: &'a [ScheduleEntry]) -> Option<(&'a ScheduleEntry, DateTime<Utc>)> {
+        entries.iter().zip(&self.next)
+            .filter_map(|(entry, next)| Some((entry, (*next)?)))
+            .min_by_key(|(_, next)| *next)
+    }
+
+    /// Entries due by `now`, in config order, each moved on to its next
+    /// run. An entry that fell several runs behind, as after the clock
+    /// jumped, runs once.
+    pub fn due<'a>(&mut self, entries: &'a [ScheduleEntry], now: DateTime<Utc>) -> Vec<&'a ScheduleEntry> {
+        let mut due = Vec::new();
+        for (entry, next) in entries.iter().zip(&mut self.next) {
+            if next.is_some_and(|n| n <= now) {
+                *next = entry.next_after(now);
+                due.push(entry);
+            }
+        }
+        due
+    }
+}
@@ -0,0 +1,125 @@
+//! Encryption at rest for credentials in the config file.
+//!
+//! Secret values may be written inline as `enc:v1:<base64>` (a random nonce
+//! followed by ChaCha20-Poly1305 ciphertext) and are decrypted at startup
+//! with a key read from a file or from the output of a command such as
+//! `tpm2_unseal`, so a copied SD card alone doesn't reveal them.
+
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::Deserialize;
+
+use crate::config::CredentialsConfig;
+use crate::error::ConfigError;
+
+const PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+/// A config value that may be stored encrypted. Never printed in full.
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.0.starts_with(PREFIX)
+    }
+
+    pub fn decrypt(&mut self, key: &SecretKey) -> Result<(), ConfigError> {
+        if let Some(encoded) = self.0.strip_prefix(PREFIX) {
+            self.0 = key.decrypt(encoded)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+pub struct SecretKey(Key);
+
+impl SecretKey {
+    pub fn generate() -> SecretKey {
+        SecretKey(ChaCha20Poly1305::generate_key(&mut OsRng))
+    }
+
+    pub fn load(config: &CredentialsConfig) -> Result<SecretKey, ConfigError> {
+        let text = match (&config.key_file, &config.key_command) {
+            (Some(path), None) => std::fs::read_to_string(path)
+                .map_err(|e| ConfigError::Key(format!("failed to read {}: {}", path.display(), e)))?,
+            (None, Some(command)) => run_key_command(command)?,
+            (Some(_), Some(_)) => return Err(ConfigError::Key("set only one of key_file and key_command".to_owned())),
+            (None, None) => return Err(ConfigError::Key("encrypted values need a key_file or key_command".to_owned())),
+        };
+        SecretKey::decode(text.trim())
+    }
+
+    pub fn decode(text: &str) -> Result<SecretKey, ConfigError> {
+        let bytes = base64::decode(text)
+            .map_err(|_| ConfigError::Key("key is not valid base64".to_owned()))?;
+        if bytes.len() != 32 {
+            return Err(ConfigError::Key("key must be 32 bytes".to_owned()));
+        }
+        Ok(SecretKey(*Key::from_slice(&bytes)))
+    }
+
+    pub fn encode(&self) -> String {
+        base64::encode(self.0)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> String {
+        let cipher = ChaCha20Poly1305::new(&self.0);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut data = nonce.to_vec();
+        data.extend(cipher.encrypt(&nonce, plaintext.as_bytes()).expect("encryption cannot fail"));
+        format!("{}{}", PREFIX, base64::encode(data))
+    }
+
+    fn decrypt(&self, encoded: &str) -> Result<String, ConfigError> {
+        let data = base64::decode(encoded).map_err(|_| ConfigError::Decrypt)?;
+        if data.len() < NONCE_LEN {
+            return Err(ConfigError::Decrypt);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let cipher = ChaCha20Poly1305::new(&self.0);
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| ConfigError::Decrypt)?;
+        String::from_utf8(plaintext).map_err(|_| ConfigError::Decrypt)
+    }
+}
+
+fn run_key_command(command: &[String]) -> Result<String, ConfigError> {
+    let (program, args) = command.split_first()
+        .ok_or_else(|| ConfigError::Key("key_command is empty".to_owned()))?;
+    let output = Command::new(program).args(args).output()
+        .map_err(|e| ConfigError::Key(format!("failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(ConfigError::Key(format!("{} exited with {}", program, output.status)));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| ConfigError::Key(format!("{} printed a non-utf8 key", program)))
+}
+
+/// Writes a freshly generated key to `path`, readable only by the owner.
+pub fn write_new_key(path: &Path) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    writeln!(file, "{}", SecretKey::generate().encode())
+}
@@ -0,0 +1,41 @@
+//! Why the daemon last stopped.
+//!
+//! A record is published retained on every controlled exit and cleared by
+//! the next instance once it is up, so a missing record while the daemon is
+//! down, or a `previous_shutdown` of null once it is back, points at a crash
+//! or power cut rather than a clean restart.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownReason {
+    /// SIGTERM or SIGINT, e.g. `systemctl stop` or a package upgrade.
+    #[strum(serialize = "signal")]
+    Signal,
+    /// A fatal error ended the main loop.
+    #[strum(serialize = "error")]
+    Error,
+    /// A GPIO event stream ended, which it never should.
+    #[strum(serialize = "stream_ended")]
+    StreamEnded,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownRecord {
+    pub reason: ShutdownReason,
+    /// Signal name, error message or stream name.
+    pub detail: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub uptime_secs: u64,
+}
+
+impl ShutdownRecord {
+    pub fn new(reason: ShutdownReason, detail: Option<String>, uptime: Duration) -> ShutdownRecord {
+        ShutdownRecord { reason, detail, timestamp: Utc::now(), uptime_secs: uptime.as_secs() }
+    }
+}
@@ -0,0 +1,37 @@
+//! Unix signal handling for the daemon loop.
+
+use std::io;
+
+use tokio::signal::unix::{signal, Signal, SignalKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalEvent {
+    /// SIGTERM or SIGINT: shut down cleanly.
+    Shutdown(&'static str),
+    /// SIGHUP: reload the configuration file.
+    Reload,
+}
+
+pub struct Signals {
+    term: Signal,
+    int: Signal,
+    hup: Signal,
+}
+
+impl Signals {
+    pub fn new() -> io::Result<Signals> {
+        Ok(Signals {
+            term: signal(SignalKind::terminate())?,
+            int: signal(SignalKind::interrupt())?,
+            hup: signal(SignalKind::hangup())?,
+        })
+    }
+
+    pub async fn recv(&mut self) -> SignalEvent {
+        tokio::select! {
+            _ = self.term.recv() => SignalEvent::Shutdown("SIGTERM"),
+            _ = self.int.recv() => SignalEvent::Shutdown("SIGINT"),
+            _ = self.hup.recv() => SignalEvent::Reload,
+        }
+    }
+}
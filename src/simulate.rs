@@ -0,0 +1,318 @@
+//! A virtual door standing in for the pins under `--simulate`, so the MQTT
+//! and Home Assistant side can be worked on without a Raspberry Pi.
+//!
+//! The door behaves like a single-button opener: a press starts it, stops
+//! it, or sends it back the way it came, and it takes the configured travel
+//! time end to end. Its sensors follow suit, only those configured under
+//! `[gpio]` are fitted, and outputs are logged. Pokes typed on stdin or
+//! published to `<base>/simulate` stand in for everything else:
+//!
+//! - `button`: the wall button
+//! - `press`: the opener's own remote
+//! - `open`, `closed`: put the door at one end at once
+//! - `obstruct`, `clear`: break or clear the safety beam
+//! - `arrive`, `leave`: park or drive off
+//! - `jam`, `unjam`: stop the door where it is and ignore presses, or free it
+//!
+//! [`crate::scenario`] plays these from a script.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures::stream::{self, StreamExt};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::{sleep, Instant};
+use tracing::{info, warn};
+
+use crate::config::SimulationConfig;
+use crate::hardware::PinStream;
+
+/// How often a moving door's position is updated.
+const TICK: Duration = Duration::from_millis(100);
+
+/// How near a zone sensor's percentage the door must be to trigger it.
+const ZONE_WIDTH: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poke {
+    Button,
+    Press,
+    Open,
+    Closed,
+    Obstruct,
+    Clear,
+    Arrive,
+    Leave,
+    Jam,
+    Unjam,
+}
+
+impl FromStr for Poke {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Poke, String> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "button" => Ok(Poke::Button),
+            "press" => Ok(Poke::Press),
+            "open" => Ok(Poke::Open),
+            "closed" => Ok(Poke::Closed),
+            "obstruct" => Ok(Poke::Obstruct),
+            "clear" => Ok(Poke::Clear),
+            "arrive" => Ok(Poke::Arrive),
+            "leave" => Ok(Poke::Leave),
+            "jam" => Ok(Poke::Jam),
+            "unjam" => Ok(Poke::Unjam),
+            other => Err(format!("unknown poke {:?}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Stopped,
+    Opening,
+    Closing,
+}
+
+/// What the sensors on the virtual door read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensors {
+    pub percent: f64,
+    pub closed: bool,
+    pub open: bool,
+    pub obstructed: bool,
+    pub vehicle: bool,
+}
+
+impl Sensors {
+    /// Whether a zone sensor at `percent` sees the door.
+    pub fn at_zone(&self, percent: u8) -> bool {
+        (self.percent - f64::from(percent)).abs() <= ZONE_WIDTH
+    }
+}
+
+/// The door itself, moved along by [`VirtualDoor::advance`].
+#[derive(Debug, Clone)]
+pub struct VirtualDoor {
+    travel: Duration,
+    /// 0 closed, 100 open.
+    percent: f64,
+    motion: Motion,
+    /// Which way the door last moved, so a press after a stop reverses it.
+    last: Motion,
+    obstructed: bool,
+    vehicle: bool,
+    /// Stuck on its track: stopped, and presses do nothing.
+    jammed: bool,
+}
+
+impl VirtualDoor {
+    /// A closed door taking `travel` end to end.
+    pub fn new(travel: Duration) -> VirtualDoor {
+        VirtualDoor {
+            travel,
+            percent: 0.0,
+            motion: Motion::Stopped,
+            last: Motion::Closing,
+            obstructed: false,
+            vehicle: false,
+            jammed: false,
+        }
+    }
+
+    pub fn motion(&self) -> Motion {
+        self.motion
+    }
+
+    pub fn sensors(&self) -> Sensors {
+        Sensors {
+            percent: self.percent,
+            closed: self.percent <= 0.0,
+            open: self.percent >= 100.0,
+            obstructed: self.obstructed,
+            vehicle: self.vehicle,
+        }
+    }
+
+    /// Applies a poke. [`Poke::Button`] is an input to the daemon, not to
+    /// the door, so it does nothing here.
+    pub fn poke(&mut self, poke: Poke) {
+        match poke {
+            Poke::Button => (),
+            Poke::Press => self.press(),
+            Poke::Open => self.stop_at(100.0),
+            Poke::Closed => self.stop_at(0.0),
+            Poke::Obstruct => {
+                self.obstructed = true;
+                // Openers reverse a closing door when the beam breaks.
+                if self.motion == Motion::Closing && !self.jammed {
+                    self.start(Motion::Opening);
+                }
+            }
+            Poke::Clear => self.obstructed = false,
+            Poke::Arrive => self.vehicle = true,
+            Poke::Leave => self.vehicle = false,
+            Poke::Jam => {
+                self.jammed = true;
+                self.motion = Motion::Stopped;
+            }
+            Poke::Unjam => self.jammed = false,
+        }
+    }
+
+    /// The relay or the opener's remote: starts a stopped door, reversing
+    /// the last direction, or stops a moving one. A jammed door stays put.
+    pub fn press(&mut self) {
+        if self.jammed {
+            return;
+        }
+        match self.motion {
+            Motion::Stopped if self.percent <= 0.0 => self.start(Motion::Opening),
+            Motion::Stopped if self.percent >= 100.0 => self.start(Motion::Closing),
+            Motion::Stopped if self.last == Motion::Opening => self.start(Motion::Closing),
+            Motion::Stopped => self.start(Motion::Opening),
+            _ => self.motion = Motion::Stopped,
+        }
+    }
+
+    /// Moves the door on by `elapsed`, stopping it at either end.
+    pub fn advance(&mut self, elapsed: Duration) {
+        let step = 100.0 * elapsed.as_secs_f64() / self.travel.as_secs_f64();
+        match self.motion {
+            Motion::Stopped => return,
+            Motion::Opening => self.percent = (self.percent + step).min(100.0),
+            Motion::Closing => self.percent = (self.percent - step).max(0.0),
+        }
+        if self.percent <= 0.0 || self.percent >= 100.0 {
+            self.motion = Motion::Stopped;
+        }
+    }
+
+    fn start(&mut self, motion: Motion) {
+        self.motion = motion;
+        self.last = motion;
+    }
+
+    fn stop_at(&mut self, percent: f64) {
+        self.percent = percent;
+        self.motion = Motion::Stopped;
+    }
+}
+
+/// The running virtual door, in place of the pins.
+pub struct Simulator {
+    pokes: mpsc::UnboundedSender<Poke>,
+    sensors: watch::Receiver<Sensors>,
+    buttons: broadcast::Sender<()>,
+}
+
+impl Simulator {
+    /// Starts the door on its own task, closed.
+    pub fn spawn(config: &SimulationConfig) -> Simulator {
+        let door = VirtualDoor::new(config.travel());
+        let (pokes, rx) = mpsc::unbounded_channel();
+        let (tx, sensors) = watch::channel(door.sensors());
+        let (buttons, _) = broadcast::channel(16);
+        tokio::spawn(run(door, rx, tx, buttons.clone()));
+        info!(travel_secs = config.travel_secs, "simulating the door");
+        Simulator { pokes, sensors, buttons }
+    }
+
+    pub fn poke(&self, poke: Poke) {
+        let _ = self.pokes.send(poke);
+    }
+
+    /// Handle for feeding in pokes from elsewhere.
+    pub fn poker(&self) -> mpsc::UnboundedSender<Poke> {
+        self.pokes.clone()
+    }
+
+    pub fn sensors(&self) -> Sensors {
+        *self.sensors.borrow()
+    }
+
+    /// Changes in one sensor, read as a pin would be.
+    pub fn stream<F>(&self, read: F) -> PinStream
+    where
+        F: Fn(&Sensors) -> bool + Copy + Send + Sync + 'static,
+    {
+        let rx = self.sensors.clone();
+        let last = read(&rx.borrow());
+        stream::unfold((rx, last), move |(mut rx, last)| async move {
+            loop {
+                rx.changed().await.ok()?;
+                let value = read(&rx.borrow_and_update());
+                if value != last {
+                    return Some((Ok(u8::from(value)), (rx, value)));
+                }
+            }
+        }).boxed()
+    }
+
+    /// Presses of the wall button.
+    pub fn button_stream(&self) -> PinStream {
+        stream::unfold(self.buttons.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(()) => return Some((Ok(1), rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }).boxed()
+    }
+}
+
+async fn run(mut door: VirtualDoor, mut pokes: mpsc::UnboundedReceiver<Poke>, sensors: watch::Sender<Sensors>, buttons: broadcast::Sender<()>) {
+    let mut last_tick = Instant::now();
+    loop {
+        tokio::select! {
+            poke = pokes.recv() => match poke {
+                Some(Poke::Button) => {
+                    info!("simulated button press");
+                    let _ = buttons.send(());
+                },
+                Some(poke) => {
+                    let now = Instant::now();
+                    door.advance(now - last_tick);
+                    last_tick = now;
+                    door.poke(poke);
+                    info!(?poke, motion = ?door.motion(), percent = door.sensors().percent.round(), "simulated door poked");
+                },
+                None => return,
+            },
+            _ = sleep(TICK), if door.motion() != Motion::Stopped => {
+                let now = Instant::now();
+                door.advance(now - last_tick);
+                last_tick = now;
+                if door.motion() == Motion::Stopped {
+                    info!(percent = door.sensors().percent, "simulated door stopped");
+                }
+            },
+        }
+        sensors.send_if_modified(|current| {
+            let changed = *current != door.sensors();
+            *current = door.sensors();
+            changed
+        });
+    }
+}
+
+/// Feeds lines typed on stdin to the simulator until stdin closes.
+pub async fn read_stdin(pokes: mpsc::UnboundedSender<Poke>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse() {
+            Ok(poke) => {
+                if pokes.send(poke).is_err() {
+                    return;
+                }
+            }
+            Err(e) => warn!(error = %e, "ignoring input"),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! The door's last known position, kept so a restart between the sensors
+//! doesn't fall back to guessing. The vacation lock, maintenance mode,
+//! pending closes and usage counters are kept by their own modules.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::position::SavedPosition;
+
+/// JSON file holding the [`SavedPosition`] between runs.
+pub struct StateStore {
+    path: PathBuf,
+    saved: Option<SavedPosition>,
+}
+
+impl StateStore {
+    pub fn new(dir: &Path) -> StateStore {
+        StateStore { path: dir.join("state.json"), saved: None }
+    }
+
+    /// Reads the position left by the previous run, if there is a usable
+    /// one.
+    pub fn load(&mut self) -> Option<SavedPosition> {
+        let text = match std::fs::read(&self.path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!(path = %self.path.display(), error = %e, "failed to read the saved door state");
+                return None;
+            }
+        };
+        match serde_json::from_slice(&text) {
+            Ok(saved) => {
+                self.saved = Some(saved);
+                self.saved
+            }
+            Err(e) => {
+                warn!(path = %self.path.display(), error = %e, "corrupt saved door state, ignoring");
+                None
+            }
+        }
+    }
+
+    /// Writes `state` via a temporary file if it differs from what was last
+    /// saved. A failed write isn't retried until the state changes again.
+    pub fn update(&mut self, state: SavedPosition) -> io::Result<()> {
+        if self.saved == Some(state) {
+            return Ok(());
+        }
+        self.saved = Some(state);
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&state)?)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
@@ -0,0 +1,233 @@
+//! Door usage counters, persisted so they survive restarts.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::correlation::OutcomeStats;
+use crate::door::Status;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageStats {
+    /// Open/close cycles since the counters were first created, counted
+    /// when the door opens.
+    pub cycles: u64,
+    pub day: NaiveDate,
+    pub cycles_today: u32,
+    #[serde(with = "secs")]
+    pub open_today: Duration,
+    pub last_opened: Option<DateTime<Utc>>,
+    /// Start of the open time not yet added to `open_today`, while the door
+    /// is open.
+    open_mark: Option<DateTime<Utc>>,
+    /// When the door opened, while it is open.
+    #[serde(default)]
+    opened_at: Option<DateTime<Utc>>,
+    /// When the door's position last changed, opening and closing included.
+    #[serde(default)]
+    pub last_changed: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub heatmap: Heatmap,
+    /// What recent relay presses did.
+    #[serde(default)]
+    pub outcomes: OutcomeStats,
+}
+
+/// What the usage sensors show.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub cycles: u64,
+    pub cycles_today: u32,
+    pub open_today_secs: u64,
+    pub last_opened: Option<DateTime<Utc>>,
+    /// How long the door has been open, 0 while closed.
+    pub open_secs: u64,
+    pub last_changed: Option<DateTime<Utc>>,
+}
+
+impl Default for UsageStats {
+    fn default() -> UsageStats {
+        UsageStats {
+            cycles: 0,
+            day: Local::now().date_naive(),
+            cycles_today: 0,
+            open_today: Duration::ZERO,
+            last_opened: None,
+            open_mark: None,
+            opened_at: None,
+            last_changed: None,
+            heatmap: Heatmap::default(),
+            outcomes: OutcomeStats::default(),
+        }
+    }
+}
+
+impl UsageStats {
+    /// Reconciles the counters with the door state read at startup. Time
+    /// spent down while the door stayed open counts as open time; an opening
+    /// that happened while down is not counted as a cycle, but does count
+    /// as a change, dated to the restart as nothing better is known.
+    pub fn resume(&mut self, status: Status, now: DateTime<Utc>) {
+        self.advance(now);
+        if self.open_mark.is_some() != (status == Status::Open) {
+            self.last_changed = Some(now);
+        }
+        (self.open_mark, self.opened_at) = match status {
+            Status::Open => (Some(self.open_mark.unwrap_or(now)), Some(self.opened_at.unwrap_or(now))),
+            Status::Closed => (None, None),
+        };
+    }
+
+    /// Records a door state change, returning whether the counters changed.
+    pub fn door_changed(&mut self, status: Status, now: DateTime<Utc>) -> bool {
+        self.advance(now);
+        match (status, self.open_mark) {
+            (Status::Open, None) => {
+                self.cycles += 1;
+                self.cycles_today += 1;
+                self.last_opened = Some(now);
+                self.open_mark = Some(now);
+                self.opened_at = Some(now);
+                self.heatmap.record(now);
+                true
+            }
+            (Status::Closed, Some(_)) => {
+                self.open_mark = None;
+                self.opened_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records a change of the door's position, at either end or not.
+    pub fn position_changed(&mut self, now: DateTime<Utc>) {
+        self.last_changed = Some(now);
+    }
+
+    pub fn report(&mut self, now: DateTime<Utc>) -> StatsReport {
+        self.advance(now);
+        StatsReport {
+            cycles: self.cycles,
+            cycles_today: self.cycles_today,
+            open_today_secs: self.open_today.as_secs(),
+            last_opened: self.last_opened,
+            open_secs: self.opened_at.map_or(0, |at| (now - at).num_seconds().max(0) as u64),
+            last_changed: self.last_changed,
+        }
+    }
+
+    /// Brings `open_today` up to date, starting a new day at local midnight.
+    pub fn advance(&mut self, now: DateTime<Utc>) {
+        let today = now.with_timezone(&Local).date_naive();
+        if today != self.day {
+            self.day = today;
+            self.cycles_today = 0;
+            self.open_today = Duration::ZERO;
+            if let (Some(mark), Some(midnight)) = (self.open_mark, local_midnight(today)) {
+                self.open_mark = Some(mark.max(midnight));
+            }
+        }
+        if let Some(mark) = self.open_mark {
+            self.open_today += (now - mark).to_std().unwrap_or_default();
+            self.open_mark = Some(now);
+        }
+    }
+}
+
+/// Door openings by local weekday and hour, for choosing auto-close and
+/// lockout schedules.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Heatmap {
+    /// Monday first, then hours from midnight.
+    counts: [[u32; 24]; 7],
+}
+
+/// What the heatmap topic and endpoint show.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeatmapReport {
+    /// Labels for the rows of `counts`.
+    pub days: [&'static str; 7],
+    pub counts: [[u32; 24]; 7],
+    pub total: u64,
+}
+
+impl Heatmap {
+    pub fn record(&mut self, at: DateTime<Utc>) {
+        let local = at.with_timezone(&Local);
+        let cell = &mut self.counts[local.weekday().num_days_from_monday() as usize][local.hour() as usize];
+        *cell = cell.saturating_add(1);
+    }
+
+    pub fn report(&self) -> HeatmapReport {
+        HeatmapReport {
+            days: ["mon", "tue", "wed", "thu", "fri", "sat", "sun"],
+            counts: self.counts,
+            total: self.counts.iter().flatten().map(|&c| u64::from(c)).sum(),
+        }
+    }
+}
+
+fn local_midnight(day: NaiveDate) -> Option<DateTime<Utc>> {
+    day.and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// JSON file holding [`UsageStats`] between runs.
+pub struct StatsStore {
+    path: PathBuf,
+}
+
+impl StatsStore {
+    pub fn new(dir: &Path) -> StatsStore {
+        StatsStore { path: dir.join("stats.json") }
+    }
+
+    /// Loads the saved counters, starting fresh if there are none or the
+    /// file can't be read.
+    pub fn load(&self) -> UsageStats {
+        let text = match std::fs::read(&self.path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return UsageStats::default(),
+            Err(e) => {
+                warn!(path = %self.path.display(), error = %e, "failed to read usage stats, starting fresh");
+                return UsageStats::default();
+            }
+        };
+        serde_json::from_slice(&text).unwrap_or_else(|e| {
+            warn!(path = %self.path.display(), error = %e, "corrupt usage stats, starting fresh");
+            UsageStats::default()
+        })
+    }
+
+    /// Writes the counters via a temporary file so a power cut can't leave a
+    /// truncated file behind.
+    pub fn save(&self, stats: &UsageStats) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(stats)?)?;
+        std::fs::rename(&tmp, &self.path)
+    }
+}
+
+mod secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(d.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        u64::deserialize(d).map(Duration::from_secs)
+    }
+}
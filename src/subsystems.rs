@@ -0,0 +1,151 @@
+//! Per-subsystem status, so a remote operator can tell which part of a
+//! partly working controller is unhealthy.
+//!
+//! The door keeps working through most failures; what each one costs:
+//!
+//! | subsystem       | failing means                        | still working                            |
+//! |-----------------|--------------------------------------|------------------------------------------|
+//! | `gpio`          | a door sensor can't be read          | relay, commands (state reported unknown) |
+//! | `mqtt`          | broker unreachable, or shedding load | button, keypad, HTTP, automated closes   |
+//! | `http`          | local API down                       | everything over MQTT                     |
+//! | `scheduler`     | pending closes can't be journaled    | closes, until the next restart           |
+//! | `storage`       | counters or vacation lock unsaved    | both, in memory until the next restart   |
+//! | `notifications` | audit or other webhooks failing      | events on MQTT; audit dead-letter file   |
+//! | `links`         | a linked controller is unhealthy     | rules on the healthy links               |
+//! | `acl`           | broker permissions have gaps         | topics the broker does allow             |
+//! | `history`       | history database can't be written    | everything; entries meanwhile are lost   |
+//! | `watchdog`      | the hardware watchdog can't be fed   | everything, until the board resets       |
+//!
+//! `gpio` is only degraded while the wall button can't be read: everything
+//! but the button keeps working.
+//!
+//! The daemon sets its own subsystems directly; tasks running outside its
+//! loop, such as the HTTP server, report through a [`StatusReporter`].
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use strum::Display;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Subsystem {
+    Gpio,
+    Mqtt,
+    Http,
+    Socket,
+    Scheduler,
+    Storage,
+    Notifications,
+    Links,
+    Acl,
+    History,
+    Watchdog,
+}
+
+/// Ordered from best to worst, so the overall condition is the maximum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Condition {
+    /// Not configured.
+    Disabled,
+    Ok,
+    /// Working, but not fully.
+    Degraded,
+    Failing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubsystemStatus {
+    pub condition: Condition,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl SubsystemStatus {
+    pub fn new(condition: Condition, detail: Option<String>) -> SubsystemStatus {
+        SubsystemStatus { condition, detail }
+    }
+
+    pub fn ok() -> SubsystemStatus {
+        SubsystemStatus::new(Condition::Ok, None)
+    }
+
+    pub fn disabled() -> SubsystemStatus {
+        SubsystemStatus::new(Condition::Disabled, None)
+    }
+
+    pub fn degraded(detail: impl Into<String>) -> SubsystemStatus {
+        SubsystemStatus::new(Condition::Degraded, Some(detail.into()))
+    }
+
+    pub fn failing(detail: impl Into<String>) -> SubsystemStatus {
+        SubsystemStatus::new(Condition::Failing, Some(detail.into()))
+    }
+
+    /// Ok or disabled, depending on whether the subsystem is configured.
+    pub fn enabled(enabled: bool) -> SubsystemStatus {
+        if enabled { SubsystemStatus::ok() } else { SubsystemStatus::disabled() }
+    }
+}
+
+/// What the status topic shows.
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemsReport<'a> {
+    /// The worst condition of any subsystem.
+    pub overall: Condition,
+    pub subsystems: &'a BTreeMap<Subsystem, SubsystemStatus>,
+}
+
+#[derive(Debug, Default)]
+pub struct Subsystems {
+    statuses: BTreeMap<Subsystem, SubsystemStatus>,
+    changed: bool,
+}
+
+impl Subsystems {
+    /// Records a subsystem's status, returning whether it changed.
+    pub fn set(&mut self, subsystem: Subsystem, status: SubsystemStatus) -> bool {
+        if self.statuses.get(&subsystem) == Some(&status) {
+            return false;
+        }
+        self.statuses.insert(subsystem, status);
+        self.changed = true;
+        true
+    }
+
+    pub fn get(&self, subsystem: Subsystem) -> Option<&SubsystemStatus> {
+        self.statuses.get(&subsystem)
+    }
+
+    /// Whether anything changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn report(&self) -> SubsystemsReport<'_> {
+        let overall = self.statuses.values().map(|s| s.condition).max().unwrap_or(Condition::Ok);
+        SubsystemsReport { overall: overall.max(Condition::Ok), subsystems: &self.statuses }
+    }
+}
+
+/// Handle for tasks outside the daemon loop to report their status.
+#[derive(Debug, Clone)]
+pub struct StatusReporter {
+    tx: mpsc::UnboundedSender<(Subsystem, SubsystemStatus)>,
+}
+
+impl StatusReporter {
+    pub fn report(&self, subsystem: Subsystem, status: SubsystemStatus) {
+        // The daemon is gone once the receiver is, so there's no one to tell.
+        let _ = self.tx.send((subsystem, status));
+    }
+}
+
+pub fn channel() -> (StatusReporter, mpsc::UnboundedReceiver<(Subsystem, SubsystemStatus)>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (StatusReporter { tx }, rx)
+}
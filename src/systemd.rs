@@ -0,0 +1,72 @@
+//! systemd service integration, compiled in with the `systemd` feature.
+//!
+//! Without the feature every function is a no-op, so callers don't need to
+//! care whether the daemon runs under systemd.
+
+use std::time::Duration;
+
+#[cfg(feature = "systemd")]
+mod imp {
+    use std::time::Duration;
+
+    use sd_notify::NotifyState;
+    use tracing::warn;
+
+    fn notify(state: NotifyState) {
+        if let Err(e) = sd_notify::notify(false, &[state]) {
+            warn!(error = %e, "failed to notify systemd");
+        }
+    }
+
+    pub fn ready() {
+        notify(NotifyState::Ready);
+    }
+
+    pub fn watchdog() {
+        notify(NotifyState::Watchdog);
+    }
+
+    pub fn stopping() {
+        notify(NotifyState::Stopping);
+    }
+
+    pub fn watchdog_timeout() -> Option<Duration> {
+        let mut usec = 0;
+        sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec))
+    }
+}
+
+#[cfg(not(feature = "systemd"))]
+mod imp {
+    use std::time::Duration;
+
+    pub fn ready() {}
+
+    pub fn watchdog() {}
+
+    pub fn stopping() {}
+
+    pub fn watchdog_timeout() -> Option<Duration> {
+        None
+    }
+}
+
+/// Tells systemd the service is up. Only call once GPIO is initialized and
+/// the broker connection has been established.
+pub fn notify_ready() {
+    imp::ready();
+}
+
+pub fn notify_watchdog() {
+    imp::watchdog();
+}
+
+pub fn notify_stopping() {
+    imp::stopping();
+}
+
+/// How often the watchdog should be pinged, half of `WatchdogSec=`, or
+/// `None` if the watchdog isn't enabled for this service.
+pub fn watchdog_interval() -> Option<Duration> {
+    imp::watchdog_timeout().map(|t| t / 2)
+}
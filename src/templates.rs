@@ -0,0 +1,149 @@
+//! Human-readable alert messages, rendered from per-language templates.
+//!
+//! Every alert on the notifications topic carries a `message` rendered with
+//! minijinja from a template named after the alert. Built-in templates cover
+//! the languages entity names are translated to, falling back to English; a
+//! `templates` directory in `[locale]` overrides them with files named
+//! `<language>/<alert>.j2`, e.g. `nb/left_open.j2`.
+//!
+//! Templates see the alert's JSON fields along with `door` (the door's
+//! friendly name), `time` (now, in the configured clock format), `state`
+//! (the door position unless the alert gives its own) and `source` (what the
+//! alert is about: a quarantined source, the trigger of a press that never
+//! moved the door, a link). The `time` filter formats any other timestamp
+//! the same way.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use minijinja::Environment;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::config::LocaleConfig;
+use crate::error::TemplateError;
+use crate::locale::{Entity, Locale};
+
+/// Alerts with a message template.
+pub const ALERTS: &[&str] = &["left_open", "link", "quarantine", "missed_action", "never_moved"];
+
+pub struct Templates {
+    env: Environment<'static>,
+    locale: Locale,
+}
+
+impl Templates {
+    /// Loads the templates for the configured language, reading overrides
+    /// from the templates directory if there is one.
+    pub fn load(config: &LocaleConfig) -> Result<Templates, TemplateError> {
+        let locale = Locale::new(config.clone());
+        let mut env = Environment::new();
+        let filter_locale = locale.clone();
+        env.add_filter("time", move |value: String| match DateTime::parse_from_rfc3339(&value) {
+            Ok(time) => filter_locale.format_time(time.with_timezone(&Utc)),
+            Err(_) => value,
+        });
+        for alert in ALERTS {
+            let source = match &config.templates {
+                Some(dir) => read_override(dir, &config.language, alert)?,
+                None => None,
+            };
+            let source = source.unwrap_or_else(|| builtin(&config.language, alert).to_owned());
+            env.add_template_owned(*alert, source)
+                .map_err(|e| TemplateError::Syntax(format!("{}/{}", config.language, alert), e))?;
+        }
+        Ok(Templates { env, locale })
+    }
+
+    /// The built-in templates alone, for when the configured ones can't be
+    /// loaded.
+    pub fn builtin(config: &LocaleConfig) -> Templates {
+        let config = LocaleConfig { templates: None, ..config.clone() };
+        Templates::load(&config).expect("built-in templates are valid")
+    }
+
+    /// Renders the message for `alert` from its JSON fields.
+    pub fn render(&self, alert: &str, details: &Value, state: &str, now: DateTime<Utc>) -> Option<String> {
+        let mut context = details.clone();
+        let fields = context.as_object_mut()?;
+        let source = ["source", "trigger", "link"].iter().find_map(|k| fields.get(*k)).cloned();
+        fields.insert("door".to_owned(), self.locale.name(Entity::Door).into());
+        fields.insert("time".to_owned(), self.locale.format_time(now).into());
+        fields.entry("state").or_insert_with(|| state.into());
+        fields.insert("source".to_owned(), source.unwrap_or(Value::Null));
+        let template = self.env.get_template(alert).ok()?;
+        match template.render(&context) {
+            Ok(message) => Some(message.trim().to_owned()),
+            Err(e) => {
+                warn!(alert, error = %e, "failed to render alert message");
+                None
+            }
+        }
+    }
+}
+
+fn read_override(dir: &Path, language: &str, alert: &str) -> Result<Option<String>, TemplateError> {
+    let path = dir.join(language).join(format!("{}.j2", alert));
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(TemplateError::Read(path, e)),
+    }
+}
+
+fn builtin(language: &str, alert: &str) -> &'static str {
+    translate(language, alert).unwrap_or_else(|| english(alert))
+}
+
+fn english(alert: &str) -> &'static str {
+    match alert {
+        "left_open" => "{% if state == \"resolved\" %}{{ door }} is closed again.\
+            {% else %}{{ door }} has been open since {{ open_since | time }} ({{ open_secs // 60 }} min).{% endif %}",
+        "link" => "{{ door }}: {{ link }} reported {{ value }}.",
+        "quarantine" => "{{ door }}: {{ source }} is blocked until {{ until | time }} after too many \
+            {% if reason == \"failed_auth\" %}wrong codes{% else %}rejected commands{% endif %}.",
+        "missed_action" => "{{ door }}: the {% if action == \"auto_close\" %}auto-close{% else %}countdown close{% endif %} \
+            due at {{ due | time }} was missed while the controller was down.",
+        "never_moved" => "{{ door }} didn't move when the opener was pressed ({{ source }}).",
+        _ => "{{ door }}: {{ alert }}",
+    }
+}
+
+fn translate(language: &str, alert: &str) -> Option<&'static str> {
+    let template = match (language, alert) {
+        ("de", "left_open") => "{% if state == \"resolved\" %}{{ door }} ist wieder geschlossen.\
+            {% else %}{{ door }} steht seit {{ open_since | time }} offen ({{ open_secs // 60 }} Min.).{% endif %}",
+        ("de", "link") => "{{ door }}: {{ link }} meldet {{ value }}.",
+        ("de", "quarantine") => "{{ door }}: {{ source }} ist bis {{ until | time }} gesperrt, nach zu vielen \
+            {% if reason == \"failed_auth\" %}falschen Codes{% else %}abgelehnten Befehlen{% endif %}.",
+        ("de", "missed_action") => "{{ door }}: {% if action == \"auto_close\" %}Das automatische Schließen\
+            {% else %}Das Schließen nach dem Countdown{% endif %} um {{ due | time }} wurde verpasst, während die Steuerung aus war.",
+        ("de", "never_moved") => "{{ door }} hat sich nach dem Tastendruck nicht bewegt ({{ source }}).",
+        ("fr", "left_open") => "{% if state == \"resolved\" %}{{ door }} est de nouveau fermé.\
+            {% else %}{{ door }} est ouvert depuis {{ open_since | time }} ({{ open_secs // 60 }} min).{% endif %}",
+        ("fr", "link") => "{{ door }} : {{ link }} signale {{ value }}.",
+        ("fr", "quarantine") => "{{ door }} : {{ source }} est bloqué jusqu'à {{ until | time }} après trop \
+            {% if reason == \"failed_auth\" %}de codes erronés{% else %}de commandes refusées{% endif %}.",
+        ("fr", "missed_action") => "{{ door }} : {% if action == \"auto_close\" %}la fermeture automatique\
+            {% else %}la fermeture après le compte à rebours{% endif %} prévue à {{ due | time }} a été manquée pendant l'arrêt du contrôleur.",
+        ("fr", "never_moved") => "{{ door }} n'a pas bougé après l'appui sur la télécommande ({{ source }}).",
+        ("es", "left_open") => "{% if state == \"resolved\" %}{{ door }} vuelve a estar cerrado.\
+            {% else %}{{ door }} está abierto desde {{ open_since | time }} ({{ open_secs // 60 }} min).{% endif %}",
+        ("es", "link") => "{{ door }}: {{ link }} informa {{ value }}.",
+        ("es", "quarantine") => "{{ door }}: {{ source }} está bloqueado hasta {{ until | time }} tras demasiados \
+            {% if reason == \"failed_auth\" %}códigos incorrectos{% else %}comandos rechazados{% endif %}.",
+        ("es", "missed_action") => "{{ door }}: {% if action == \"auto_close\" %}el cierre automático\
+            {% else %}el cierre tras la cuenta atrás{% endif %} previsto para {{ due | time }} se perdió mientras el controlador estaba apagado.",
+        ("es", "never_moved") => "{{ door }} no se movió al pulsar el mando ({{ source }}).",
+        ("nl", "left_open") => "{% if state == \"resolved\" %}{{ door }} is weer dicht.\
+            {% else %}{{ door }} staat open sinds {{ open_since | time }} ({{ open_secs // 60 }} min).{% endif %}",
+        ("nl", "link") => "{{ door }}: {{ link }} meldt {{ value }}.",
+        ("nl", "quarantine") => "{{ door }}: {{ source }} is geblokkeerd tot {{ until | time }} na te veel \
+            {% if reason == \"failed_auth\" %}foute codes{% else %}geweigerde opdrachten{% endif %}.",
+        ("nl", "missed_action") => "{{ door }}: {% if action == \"auto_close\" %}het automatisch sluiten\
+            {% else %}het sluiten na het aftellen{% endif %} gepland om {{ due | time }} is gemist terwijl de controller uit stond.",
+        ("nl", "never_moved") => "{{ door }} bewoog niet na het indrukken van de knop ({{ source }}).",
+        _ => return None,
+    };
+    Some(template)
+}
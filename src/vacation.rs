@@ -0,0 +1,70 @@
+//! Vacation lock: a switch that refuses every command that could open the
+//! door, until it is turned off again.
+//!
+//! The state is persisted so a restart or power cut can't quietly unlock the
+//! door while nobody is home.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LockState {
+    pub locked: bool,
+    /// When the lock was last turned on or off.
+    pub since: Option<DateTime<Utc>>,
+    /// Who last turned it on or off, e.g. `mqtt` or `link:house`.
+    pub by: Option<String>,
+}
+
+pub struct VacationLock {
+    path: PathBuf,
+    state: LockState,
+}
+
+impl VacationLock {
+    /// Loads the saved state, starting unlocked if there is none. A file that
+    /// can't be read starts locked, erring on the side of a closed door.
+    pub fn load(dir: &Path) -> VacationLock {
+        let path = dir.join("vacation_lock.json");
+        let state = match std::fs::read(&path) {
+            Ok(text) => serde_json::from_slice(&text).unwrap_or_else(|e| {
+                warn!(path = %path.display(), error = %e, "corrupt vacation lock state, starting locked");
+                LockState { locked: true, ..LockState::default() }
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => LockState::default(),
+            Err(e) => {
+                warn!(path = %path.display(), error = %e, "failed to read vacation lock state, starting locked");
+                LockState { locked: true, ..LockState::default() }
+            }
+        };
+        VacationLock { path, state }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state.locked
+    }
+
+    pub fn state(&self) -> &LockState {
+        &self.state
+    }
+
+    /// Turns the lock on or off, returning whether that changed anything.
+    /// The new state applies even if it can't be saved.
+    pub fn set(&mut self, locked: bool, by: &str) -> io::Result<bool> {
+        if locked == self.state.locked {
+            return Ok(false);
+        }
+        self.state = LockState { locked, since: Some(Utc::now()), by: Some(by.to_owned()) };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.state)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(true)
+    }
+}
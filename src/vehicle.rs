@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use strum::{Display, EnumIter};
+
+use crate::door::Status;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumIter, Serialize)]
+pub enum VehicleEvent {
+    #[strum(serialize = "car_departed")]
+    #[serde(rename = "car_departed")]
+    Departed,
+    #[strum(serialize = "car_arrived")]
+    #[serde(rename = "car_arrived")]
+    Arrived,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VehicleRecord {
+    pub event: VehicleEvent,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Correlates vehicle presence with door cycles: presence is sampled when the
+/// door opens and again when it closes, and a change between the two means a
+/// car drove through.
+#[derive(Debug, Default)]
+pub struct VehicleTracker {
+    present_at_open: Option<bool>,
+}
+
+impl VehicleTracker {
+    pub fn door_changed(&mut self, status: Status, present: bool) -> Option<VehicleRecord> {
+        match status {
+            Status::Open => {
+                self.present_at_open.get_or_insert(present);
+                None
+            }
+            Status::Closed => {
+                let event = match (self.present_at_open.take()?, present) {
+                    (true, false) => VehicleEvent::Departed,
+                    (false, true) => VehicleEvent::Arrived,
+                    _ => return None,
+                };
+                Some(VehicleRecord { event, timestamp: Utc::now() })
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! The buzzer or strobe pattern sounded before the door closes unattended.
+//!
+//! The pattern is a number of evenly spaced beeps over the last seconds of a
+//! close countdown. It is worked out from the time left, so a countdown
+//! that is cancelled, restored after a restart or started part way through
+//! the pattern needs no bookkeeping of its own.
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarningPattern {
+    /// How long before the close the pattern starts.
+    pub length: Duration,
+    pub beeps: u32,
+    /// How long each beep lasts, at most its share of `length`.
+    pub beep: Duration,
+}
+
+impl WarningPattern {
+    /// Whether the output should be on with `left` until the close.
+    pub fn active(&self, left: Duration) -> bool {
+        match self.position(left) {
+            Some((_, within)) => within < self.beep.min(self.period()),
+            None => false,
+        }
+    }
+
+    /// How long from now, with `left` until the close, the output next
+    /// changes, if it does before the close.
+    pub fn next_change(&self, left: Duration) -> Option<Duration> {
+        if left.is_zero() || self.beeps == 0 {
+            return None;
+        }
+        let (beep, within) = match self.position(left) {
+            Some(p) => p,
+            None => return left.checked_sub(self.length).filter(|d| !d.is_zero()),
+        };
+        let on = self.beep.min(self.period());
+        if within < on {
+            return Some(on - within);
+        }
+        (beep + 1 < self.beeps).then(|| self.period() - within)
+    }
+
+    fn period(&self) -> Duration {
+        self.length / self.beeps.max(1)
+    }
+
+    /// The beep under way and how far into its period, once the pattern
+    /// has started.
+    fn position(&self, left: Duration) -> Option<(u32, Duration)> {
+        if left > self.length || left.is_zero() || self.beeps == 0 || self.period().is_zero() {
+            return None;
+        }
+        let elapsed = self.length - left;
+        let period = self.period();
+        let beep = (elapsed.as_nanos() / period.as_nanos()) as u32;
+        Some((beep.min(self.beeps - 1), elapsed - period * beep.min(self.beeps - 1)))
+    }
+}
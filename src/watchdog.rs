@@ -0,0 +1,81 @@
+//! The Iono Pi's hardware watchdog, which power-cycles the board unless it
+//! is fed a heartbeat. It is driven through the Sfera Labs kernel module's
+//! sysfs attributes, the same switch libionoPi flips.
+//!
+//! The daemon only feeds it while GPIO and MQTT are healthy, so a wedged
+//! loop or a frozen kernel ends in a power cycle rather than a door nobody
+//! can reach. It is turned off again on a clean shutdown.
+//!
+//! The attributes are opened once, while still root, so feeding and
+//! disabling keep working after `[privileges]` has switched user.
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use tracing::{info, warn};
+
+use crate::config::WatchdogConfig;
+
+pub struct HardwareWatchdog {
+    heartbeat: File,
+    enabled: File,
+    /// The level last written to `heartbeat`; the board wants it toggled.
+    level: bool,
+    /// Whether feeding is held off while something is unhealthy.
+    held: bool,
+}
+
+impl HardwareWatchdog {
+    /// Turns the watchdog on, noting if it was what restarted the board.
+    pub fn enable(config: &WatchdogConfig) -> io::Result<HardwareWatchdog> {
+        let dir = &config.dir;
+        match std::fs::read_to_string(dir.join("expired")) {
+            Ok(expired) if expired.trim() == "1" => warn!("the hardware watchdog expired before this start"),
+            Ok(_) => (),
+            Err(e) => warn!(dir = %dir.display(), error = %e, "failed to read whether the hardware watchdog expired"),
+        }
+        let heartbeat = open(&dir.join("heartbeat"))?;
+        let enabled = open(&dir.join("enabled"))?;
+        let mut watchdog = HardwareWatchdog { heartbeat, enabled, level: false, held: false };
+        watchdog.feed()?;
+        watchdog.enabled.write_all_at(b"1", 0)?;
+        info!(dir = %dir.display(), "hardware watchdog enabled");
+        Ok(watchdog)
+    }
+
+    /// Feeds the watchdog, unless `unhealthy` names a failing subsystem.
+    pub fn tick(&mut self, unhealthy: Option<&str>) -> io::Result<()> {
+        match unhealthy {
+            Some(subsystem) => {
+                if !self.held {
+                    warn!(subsystem, "holding off the hardware watchdog while unhealthy");
+                    self.held = true;
+                }
+                Ok(())
+            }
+            None => {
+                if std::mem::take(&mut self.held) {
+                    info!("feeding the hardware watchdog again");
+                }
+                self.feed()
+            }
+        }
+    }
+
+    fn feed(&mut self) -> io::Result<()> {
+        self.level = !self.level;
+        self.heartbeat.write_all_at(if self.level { b"1" } else { b"0" }, 0)
+    }
+
+    pub fn disable(&self) -> io::Result<()> {
+        self.enabled.write_all_at(b"0", 0)?;
+        info!("hardware watchdog disabled");
+        Ok(())
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().write(true).create(true).truncate(false).open(path)
+}
@@ -0,0 +1,111 @@
+//! Outbound webhooks for door events.
+//!
+//! Each configured URL gets a JSON POST for the events it subscribes to (the
+//! door opening or closing, a left-open alert, a rejected command), so
+//! services such as ntfy, Pushover or Slack can be notified without going
+//! through the broker. Every URL has its own delivery task, so a slow or
+//! failing one doesn't hold up the others. Failed deliveries are retried
+//! with exponential backoff and then dropped: unlike the audit webhook these
+//! are notifications, and a late "door opened" is worth little.
+
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+use serde_json::Value;
+use strum::Display;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::config::WebhookConfig;
+use crate::delivery::{self, Target};
+use crate::http_client::{self, HttpsClient};
+use crate::subsystems::StatusReporter;
+
+/// Payloads waiting for delivery to one URL before new ones are dropped.
+const QUEUE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum WebhookEvent {
+    /// The door reached fully open.
+    Open,
+    /// The door reached closed.
+    Close,
+    /// A "door left open" alert fired.
+    LeftOpen,
+    /// A command was rejected.
+    Rejected,
+}
+
+impl WebhookEvent {
+    pub fn all() -> BTreeSet<WebhookEvent> {
+        [WebhookEvent::Open, WebhookEvent::Close, WebhookEvent::LeftOpen, WebhookEvent::Rejected].into()
+    }
+}
+
+/// Handle for queueing payloads to the configured webhooks.
+pub struct Webhooks {
+    hooks: Vec<Hook>,
+}
+
+struct Hook {
+    url: String,
+    events: BTreeSet<WebhookEvent>,
+    tx: mpsc::Sender<Value>,
+}
+
+impl Webhooks {
+    /// Starts a delivery task per webhook, each reporting how deliveries go
+    /// as the `notifications` subsystem.
+    pub fn spawn(configs: &[WebhookConfig], status: StatusReporter) -> Webhooks {
+        let client = http_client::build();
+        let hooks = configs.iter().map(|config| {
+            let (tx, rx) = mpsc::channel(QUEUE);
+            let delivery = Delivery { config: config.clone(), client: client.clone(), status: status.clone() };
+            tokio::spawn(delivery.run(rx));
+            Hook { url: config.url.clone(), events: config.events.clone(), tx }
+        }).collect();
+        Webhooks { hooks }
+    }
+
+    /// Queues `payload` for every webhook subscribed to `event`, without
+    /// waiting.
+    pub fn send(&self, event: WebhookEvent, payload: &Value) {
+        for hook in self.hooks.iter().filter(|h| h.events.contains(&event)) {
+            if hook.tx.try_send(payload.clone()).is_err() {
+                warn!(url = %hook.url, %event, "webhook delivery backed up, dropping event");
+            }
+        }
+    }
+}
+
+struct Delivery {
+    config: WebhookConfig,
+    client: HttpsClient,
+    status: StatusReporter,
+}
+
+impl Delivery {
+    async fn run(self, mut rx: mpsc::Receiver<Value>) {
+        while let Some(payload) = rx.recv().await {
+            self.deliver(payload.to_string()).await;
+        }
+    }
+
+    async fn deliver(&self, body: String) {
+        let url = &self.config.url;
+        let name = format!("webhook {}", url);
+        let target = Target {
+            name: &name,
+            url,
+            max_attempts: self.config.max_attempts,
+            backoff: self.config.backoff(),
+            max_backoff: self.config.max_backoff(),
+            timeout: self.config.timeout(),
+        };
+        if let Err(failure) = delivery::post(&self.client, &target, &[], &body, &self.status).await {
+            error!(%url, attempts = failure.attempts, error = %failure.message, "giving up on webhook");
+        }
+    }
+}
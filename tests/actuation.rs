@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use garaged::actuation::{check, Enqueued, PressQueue, Queued, Stage, MAX_QUEUED};
+use garaged::config::ActuationConfig;
+use garaged::door::{Command, Trigger};
+
+#[test]
+fn default_chain_presses_once() {
+    assert!(check(&ActuationConfig::default().stages).is_ok());
+    assert!(check(&[Stage::Warning, Stage::RateLimit, Stage::Interlock, Stage::Verify, Stage::Pulse]).is_ok());
+}
+
+#[test]
+fn chains_that_cannot_work_are_refused() {
+    assert!(check(&[Stage::Interlock, Stage::Verify]).is_err());
+    assert!(check(&[Stage::Pulse, Stage::Pulse]).is_err());
+    assert!(check(&[Stage::Interlock, Stage::Pulse, Stage::RateLimit]).is_err());
+    assert!(check(&[Stage::Pulse, Stage::Warning, Stage::Verify]).is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn presses_wait_their_turn_and_duplicates_merge() {
+    let spacing = Duration::from_secs(1);
+    let mut queue = PressQueue::default();
+    let open = Queued { cause: Trigger::Mqtt, command: Some(Command::Open) };
+    let button = Queued { cause: Trigger::Button, command: None };
+    assert_eq!(queue.push(open), Enqueued::Added);
+    assert_eq!(queue.pop(spacing), Some(open));
+    queue.pressed();
+
+    assert_eq!(queue.push(button), Enqueued::Added);
+    assert_eq!(queue.push(button), Enqueued::Added);
+    assert_eq!(queue.push(open), Enqueued::Added);
+    assert_eq!(queue.push(open), Enqueued::Coalesced);
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.pop(spacing), None);
+    tokio::time::advance(spacing).await;
+    assert_eq!(queue.pop(spacing), Some(button));
+
+    while queue.push(button) == Enqueued::Added {}
+    assert_eq!(queue.len(), MAX_QUEUED);
+}
@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use garaged::analog;
+use garaged::config::{AnalogChannel, AnalogInputConfig};
+
+fn input(path: Option<PathBuf>, channel: Option<AnalogChannel>) -> AnalogInputConfig {
+    AnalogInputConfig {
+        id: "battery".to_owned(),
+        name: "Backup Battery".to_owned(),
+        channel,
+        path,
+        scale: 0.001,
+        offset: 0.5,
+        unit: Some("V".to_owned()),
+        device_class: Some("voltage".to_owned()),
+    }
+}
+
+#[test]
+fn readings_are_scaled_and_offset() {
+    let path = std::env::temp_dir().join(format!("garaged-analog-{}", std::process::id()));
+    std::fs::write(&path, "12840\n").unwrap();
+    let value = analog::read(&input(Some(path.clone()), None));
+    std::fs::remove_file(&path).unwrap();
+    assert!((value.unwrap() - 13.34).abs() < 1e-9);
+}
+
+#[test]
+fn channels_read_the_iono_pi_sysfs_files() {
+    assert_eq!(input(None, Some(AnalogChannel::Av2)).path(), PathBuf::from("/sys/class/ionopi/analog_in/av2"));
+    assert_eq!(input(None, Some(AnalogChannel::Ai4)).path(), PathBuf::from("/sys/class/ionopi/analog_in/ai4"));
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use garaged::anomaly::{AnomalyDetector, AnomalyKind};
+use garaged::door::{Position, Status};
+use tokio::time::advance;
+
+/// Runs the door from open to closed, taking `secs`.
+async fn close(detector: &mut AnomalyDetector, secs: u64) -> Option<AnomalyKind> {
+    assert!(detector.position_changed(Position::Open, Position::Closing).is_none());
+    advance(Duration::from_secs(secs)).await;
+    detector.position_changed(Position::Closing, Position::Closed).map(|a| a.kind)
+}
+
+#[tokio::test(start_paused = true)]
+async fn runs_off_the_learned_baseline_are_anomalies() {
+    let mut detector = AnomalyDetector::new(0.3, 3);
+    for _ in 0..3 {
+        assert_eq!(close(&mut detector, 12).await, None);
+    }
+    assert_eq!(detector.baseline(Status::Closed), Some(Duration::from_secs(12)));
+    assert_eq!(detector.baseline(Status::Open), None);
+
+    assert_eq!(close(&mut detector, 20).await, Some(AnomalyKind::Slow));
+    assert_eq!(detector.problem().unwrap().baseline_secs, Some(12.0));
+    assert_eq!(close(&mut detector, 6).await, Some(AnomalyKind::Fast));
+    assert_eq!(close(&mut detector, 13).await, None);
+    assert!(detector.problem().is_none());
+
+    // Stopping partway and carrying on isn't timed as a full run.
+    detector.position_changed(Position::Open, Position::Closing);
+    detector.position_changed(Position::Closing, Position::Stopped);
+    advance(Duration::from_secs(30)).await;
+    detector.position_changed(Position::Stopped, Position::Closing);
+    assert!(detector.position_changed(Position::Closing, Position::Closed).is_none());
+
+    detector.position_changed(Position::Open, Position::Closing);
+    let reversed = detector.position_changed(Position::Closing, Position::Opening).unwrap();
+    assert_eq!((reversed.kind, reversed.direction), (AnomalyKind::Reversed, Status::Closed));
+    assert!(detector.problem().is_some());
+}
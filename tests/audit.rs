@@ -0,0 +1,10 @@
+use garaged::audit::sign;
+
+#[test]
+fn signature_is_hmac_sha256_of_the_body() {
+    // RFC 4231, test case 2.
+    assert_eq!(
+        sign("Jefe", b"what do ya want for nothing?"),
+        "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+    );
+}
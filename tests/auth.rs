@@ -0,0 +1,34 @@
+use garaged::auth::{self, Authenticator, Credential, CredentialKind};
+use garaged::config::{AuthConfig, ProviderConfig};
+use sha2::{Digest, Sha256};
+
+#[tokio::test]
+async fn codes_are_hashed_with_argon2() {
+    let hash = auth::hash_secret("1234");
+    assert!(hash.starts_with("$argon2id$"));
+    assert_ne!(auth::hash_secret("1234"), hash);
+
+    let other = auth::hash_secret("5678");
+    // Anything but an argon2 hash matches nothing, a salted sha256 included.
+    let sha256 = format!("sha256:abcd:{:x}", Sha256::digest(b"abcd9999"));
+    let path = std::env::temp_dir().join(format!("garaged-auth-{}.toml", std::process::id()));
+    std::fs::write(&path, format!(
+        "[[credential]]\nid = \"alice\"\nkind = \"keypad\"\nhash = {:?}\n\n\
+         [[credential]]\nid = \"bob\"\nkind = \"keypad\"\nhash = {:?}\n\n\
+         [[credential]]\nid = \"carol\"\nkind = \"keypad\"\nhash = {:?}\n",
+        hash, other, sha256,
+    )).unwrap();
+    let auth = Authenticator::from_config(&AuthConfig { providers: vec![ProviderConfig::File { path: path.clone() }] });
+    let id = |code: &str| {
+        let credential = Credential::new(CredentialKind::Keypad, code);
+        let auth = &auth;
+        async move { auth.validate(&credential).await.unwrap().map(|i| i.id) }
+    };
+
+    assert_eq!(id("1234").await.as_deref(), Some("alice"));
+    assert_eq!(id("5678").await.as_deref(), Some("bob"));
+    assert_eq!(id("0000").await, None);
+    assert_eq!(id("9999").await, None);
+    assert_eq!(auth.validate(&Credential::new(CredentialKind::Token, "1234")).await.unwrap(), None);
+    let _ = std::fs::remove_file(&path);
+}
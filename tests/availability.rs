@@ -0,0 +1,25 @@
+use garaged::availability::SensorHealth;
+
+#[test]
+fn sensor_goes_unavailable_after_failures_in_a_row() {
+    let mut health = SensorHealth::new(3);
+    assert!(!health.failed("probe"));
+    assert!(!health.failed("probe"));
+    assert!(health.is_available("probe"));
+    assert!(health.failed("probe"));
+    assert!(!health.is_available("probe"));
+    // Only the transition is reported.
+    assert!(!health.failed("probe"));
+    assert!(health.is_available("other"));
+}
+
+#[test]
+fn good_read_restores_availability_and_resets_the_count() {
+    let mut health = SensorHealth::new(2);
+    assert!(!health.failed("probe"));
+    assert!(!health.read("probe"));
+    assert!(!health.failed("probe"));
+    assert!(health.failed("probe"));
+    assert!(health.read("probe"));
+    assert!(health.is_available("probe"));
+}
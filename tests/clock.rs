@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
+use garaged::clock::Clock;
+use garaged::door::Status;
+use garaged::lockout::LockoutSchedule;
+use garaged::stats::UsageStats;
+
+fn local(hour: u32, minute: u32) -> DateTime<Utc> {
+    let time = NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+    Local.from_local_datetime(&date.and_time(time)).earliest().unwrap().with_timezone(&Utc)
+}
+
+#[tokio::test(start_paused = true)]
+async fn clock_follows_paused_time() {
+    let clock = Clock::tokio(local(21, 0));
+    tokio::time::sleep(Duration::from_secs(3 * 3600)).await;
+    assert_eq!(clock.now(), local(21, 0) + chrono::Duration::hours(3));
+}
+
+#[tokio::test(start_paused = true)]
+async fn lockout_window_opens_and_closes() {
+    let schedule: LockoutSchedule = toml::from_str(r#"
+        [[windows]]
+        start = "22:00"
+        end = "06:00"
+    "#).unwrap();
+    let clock = Clock::tokio(local(21, 30));
+    assert!(schedule.active_at(clock.local_now()).is_none());
+    tokio::time::advance(Duration::from_secs(3600)).await;
+    assert!(schedule.active_at(clock.local_now()).is_some());
+    tokio::time::advance(Duration::from_secs(8 * 3600)).await;
+    assert!(schedule.active_at(clock.local_now()).is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn open_time_rolls_over_at_midnight() {
+    let clock = Clock::tokio(local(23, 0));
+    let mut stats = UsageStats::default();
+    stats.resume(Status::Closed, clock.now());
+    stats.door_changed(Status::Open, clock.now());
+    tokio::time::advance(Duration::from_secs(2 * 3600)).await;
+    let report = stats.report(clock.now());
+    assert_eq!(report.open_today_secs, 3600);
+    assert_eq!(report.cycles_today, 0);
+}
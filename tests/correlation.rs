@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use garaged::correlation::{CommandTracker, Outcome, OutcomeStats};
+use garaged::door::Trigger;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[tokio::test(start_paused = true)]
+async fn press_is_followed_to_the_door_arriving() {
+    let mut tracker = CommandTracker::default();
+    assert!(tracker.pressed(Trigger::Mqtt, true, TIMEOUT, Utc::now()).is_none());
+    tokio::time::advance(Duration::from_millis(800)).await;
+    tracker.moved();
+    tokio::time::advance(Duration::from_secs(12)).await;
+    tracker.moved();
+    let correlation = tracker.arrived().unwrap();
+    assert_eq!(correlation.outcome, Outcome::Completed);
+    assert_eq!(correlation.trigger, "mqtt");
+    assert_eq!(correlation.started_ms, Some(800));
+    assert_eq!(correlation.completed_secs, Some(12.8));
+    assert!(tracker.deadline().is_none());
+}
+
+#[tokio::test(start_paused = true)]
+async fn press_without_motion_never_moved() {
+    let mut tracker = CommandTracker::default();
+    tracker.pressed(Trigger::Button, true, TIMEOUT, Utc::now());
+    tokio::time::advance(TIMEOUT).await;
+    assert_eq!(tracker.timed_out().unwrap().outcome, Outcome::NeverMoved);
+
+    // Out of the sensors' sight a quiet press proves nothing, and a retry
+    // ends the first press early.
+    tracker.pressed(Trigger::Button, false, TIMEOUT, Utc::now());
+    let first = tracker.pressed(Trigger::Button, true, TIMEOUT, Utc::now()).unwrap();
+    assert_eq!(first.outcome, Outcome::Unconfirmed);
+    tracker.moved();
+    assert_eq!(tracker.stopped().unwrap().outcome, Outcome::Moved);
+}
+
+#[tokio::test(start_paused = true)]
+async fn success_rate_covers_presses_that_could_tell() {
+    let mut tracker = CommandTracker::default();
+    let mut stats = OutcomeStats::default();
+    assert_eq!(stats.report().success_rate, None);
+    for visible in [true, true, true, false] {
+        tracker.pressed(Trigger::Http, visible, TIMEOUT, Utc::now());
+        stats.record(&tracker.timed_out().unwrap());
+        tracker.pressed(Trigger::Http, true, TIMEOUT, Utc::now());
+        tracker.moved();
+        stats.record(&tracker.arrived().unwrap());
+    }
+    let report = stats.report();
+    assert_eq!(report.presses, 7);
+    assert_eq!(report.never_moved, 3);
+    assert_eq!(report.success_rate, Some(57.1));
+    assert_eq!(report.last.unwrap().outcome, Outcome::Completed);
+}
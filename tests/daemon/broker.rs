@@ -0,0 +1,356 @@
+//! A minimal in-process MQTT broker to run the daemon against, speaking
+//! 3.1.1 or 5 as each client asks. It keeps every message clients publish,
+//! honours retained messages and last wills, and can drop its clients to
+//! exercise reconnecting.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use rumqttc::v5::mqttbytes::v5::{self as mqtt5, PublishProperties};
+use rumqttc::v5::mqttbytes::QoS as QoS5;
+use rumqttc::{
+    matches, mqttbytes, ConnAck, ConnectReturnCode, Packet, PingResp, PubAck, PubComp, PubRec, Publish, QoS, SubAck,
+    SubscribeReasonCode, UnsubAck,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Notify};
+use tokio::time::timeout;
+
+const MAX_PACKET: usize = 1 << 20;
+
+/// How long `wait_until` waits before failing the test.
+const WAIT: Duration = Duration::from_secs(10);
+
+pub struct Broker {
+    pub port: u16,
+    shared: Arc<Shared>,
+}
+
+/// A message as the broker keeps it, whichever protocol it came in on.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Bytes,
+    pub retain: bool,
+    /// Only MQTT 5 messages have them.
+    pub properties: Option<PublishProperties>,
+}
+
+struct Shared {
+    /// Everything published by clients, in order.
+    log: Mutex<Vec<Message>>,
+    retained: Mutex<BTreeMap<String, Message>>,
+    published: Notify,
+    routes: broadcast::Sender<Message>,
+    kick: broadcast::Sender<()>,
+    connects: AtomicUsize,
+}
+
+impl Broker {
+    pub async fn start() -> Broker {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let shared = Arc::new(Shared {
+            log: Mutex::new(Vec::new()),
+            retained: Mutex::new(BTreeMap::new()),
+            published: Notify::new(),
+            routes: broadcast::channel(1024).0,
+            kick: broadcast::channel(1).0,
+            connects: AtomicUsize::new(0),
+        });
+        let accepting = shared.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(accepting.clone(), socket));
+            }
+        });
+        Broker { port, shared }
+    }
+
+    /// Publishes as another client would.
+    pub fn publish(&self, topic: &str, payload: &str) {
+        self.publish_with(topic, payload, None);
+    }
+
+    /// Publishes as an MQTT 5 client would, with `properties`.
+    pub fn publish_with(&self, topic: &str, payload: &str, properties: Option<PublishProperties>) {
+        let message = Message { topic: topic.to_owned(), payload: Bytes::copy_from_slice(payload.as_bytes()), retain: false, properties };
+        self.shared.publish(message);
+    }
+
+    /// Drops every client without a DISCONNECT, as a restarting broker would.
+    pub fn disconnect_all(&self) {
+        let _ = self.shared.kick.send(());
+    }
+
+    pub fn connects(&self) -> usize {
+        self.shared.connects.load(Ordering::SeqCst)
+    }
+
+    /// Payloads published on `topic` so far, as text.
+    pub fn payloads(&self, topic: &str) -> Vec<String> {
+        self.messages(topic).iter().map(|m| String::from_utf8_lossy(&m.payload).into_owned()).collect()
+    }
+
+    /// Messages published on `topic` so far.
+    pub fn messages(&self, topic: &str) -> Vec<Message> {
+        let log = self.shared.log.lock().unwrap();
+        log.iter().filter(|m| m.topic == topic).cloned().collect()
+    }
+
+    /// Waits until `done` holds, failing the test if it takes too long.
+    pub async fn wait_until<F: Fn(&Broker) -> bool>(&self, what: &str, done: F) {
+        let wait = async {
+            loop {
+                let published = self.shared.published.notified();
+                if done(self) {
+                    return;
+                }
+                published.await;
+            }
+        };
+        if timeout(WAIT, wait).await.is_err() {
+            panic!("timed out waiting for {}", what);
+        }
+    }
+
+    /// Waits for `payload` to be published on `topic`.
+    pub async fn wait_for(&self, topic: &str, payload: &str) {
+        self.wait_until(&format!("{} on {}", payload, topic), |b| b.payloads(topic).iter().any(|p| p == payload)).await
+    }
+}
+
+impl Shared {
+    fn publish(&self, message: Message) {
+        if message.retain {
+            let mut retained = self.retained.lock().unwrap();
+            if message.payload.is_empty() {
+                retained.remove(&message.topic);
+            } else {
+                retained.insert(message.topic.clone(), message.clone());
+            }
+        }
+        self.log.lock().unwrap().push(message.clone());
+        let _ = self.routes.send(message);
+        self.published.notify_waiters();
+    }
+}
+
+/// What a client's packets are read and answered as, from its CONNECT.
+#[derive(Clone, Copy, PartialEq)]
+enum Protocol {
+    V311,
+    V5,
+}
+
+/// What to do after handling the packets read so far.
+enum Step {
+    More,
+    Close,
+}
+
+/// A client's subscriptions and last will.
+#[derive(Default)]
+struct Session {
+    filters: Vec<String>,
+    will: Option<Message>,
+}
+
+async fn serve(shared: Arc<Shared>, mut socket: TcpStream) {
+    let mut routes = shared.routes.subscribe();
+    let mut kick = shared.kick.subscribe();
+    let mut input = BytesMut::new();
+    let mut protocol = None;
+    let mut session = Session::default();
+    loop {
+        if protocol.is_none() {
+            protocol = protocol_level(&input).map(|level| if level == 5 { Protocol::V5 } else { Protocol::V311 });
+        }
+        let mut output = BytesMut::new();
+        let step = match protocol {
+            Some(Protocol::V311) => handle(&shared, &mut input, &mut session, &mut output),
+            Some(Protocol::V5) => handle_v5(&shared, &mut input, &mut session, &mut output),
+            None => Step::More,
+        };
+        if socket.write_all(&output).await.is_err() {
+            break;
+        }
+        if let Step::Close = step {
+            return;
+        }
+        tokio::select! {
+            read = socket.read_buf(&mut input) => {
+                if !matches!(read, Ok(n) if n > 0) {
+                    break;
+                }
+            },
+            routed = routes.recv() => match routed {
+                Ok(message) if session.filters.iter().any(|f| matches(&message.topic, f)) => {
+                    let mut output = BytesMut::new();
+                    forward(protocol, &message, false, &mut output);
+                    if socket.write_all(&output).await.is_err() {
+                        break;
+                    }
+                },
+                Err(broadcast::error::RecvError::Closed) => break,
+                _ => (),
+            },
+            _ = kick.recv() => break,
+        }
+    }
+    if let Some(will) = session.will {
+        shared.publish(will);
+    }
+}
+
+/// The protocol level in a CONNECT at the start of `input`, once enough of
+/// it has arrived.
+fn protocol_level(input: &[u8]) -> Option<u8> {
+    // The fixed header's remaining length takes one to four bytes, then
+    // comes the length-prefixed protocol name "MQTT".
+    let length_bytes = input.iter().skip(1).take(4).position(|b| b & 0x80 == 0)? + 1;
+    input.get(1 + length_bytes + 6).copied()
+}
+
+fn handle(shared: &Shared, input: &mut BytesMut, session: &mut Session, output: &mut BytesMut) -> Step {
+    loop {
+        let packet = match mqttbytes::v4::read(input, MAX_PACKET) {
+            Ok(packet) => packet,
+            Err(mqttbytes::Error::InsufficientBytes(_)) => return Step::More,
+            Err(_) => return Step::Close,
+        };
+        match packet {
+            Packet::Connect(connect) => {
+                session.will = connect.last_will.map(|will| Message {
+                    topic: will.topic,
+                    payload: will.message,
+                    retain: will.retain,
+                    properties: None,
+                });
+                shared.connects.fetch_add(1, Ordering::SeqCst);
+                ConnAck::new(ConnectReturnCode::Success, false).write(output).unwrap();
+            }
+            Packet::Subscribe(subscribe) => {
+                let codes = subscribe.filters.iter().map(|f| SubscribeReasonCode::Success(f.qos)).collect();
+                SubAck::new(subscribe.pkid, codes).write(output).unwrap();
+                subscribed(shared, session, subscribe.filters.into_iter().map(|f| f.path), Some(Protocol::V311), output);
+            }
+            Packet::Unsubscribe(unsubscribe) => {
+                session.filters.retain(|f| !unsubscribe.topics.contains(f));
+                UnsubAck::new(unsubscribe.pkid).write(output).unwrap();
+            }
+            Packet::Publish(publish) => {
+                match publish.qos {
+                    QoS::AtMostOnce => (),
+                    QoS::AtLeastOnce => {
+                        PubAck::new(publish.pkid).write(output).unwrap();
+                    }
+                    QoS::ExactlyOnce => {
+                        PubRec::new(publish.pkid).write(output).unwrap();
+                    }
+                }
+                shared.publish(Message { topic: publish.topic, payload: publish.payload, retain: publish.retain, properties: None });
+            }
+            Packet::PubRel(release) => {
+                PubComp::new(release.pkid).write(output).unwrap();
+            }
+            Packet::PingReq => {
+                PingResp.write(output).unwrap();
+            }
+            Packet::Disconnect => {
+                session.will = None;
+                return Step::Close;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn handle_v5(shared: &Shared, input: &mut BytesMut, session: &mut Session, output: &mut BytesMut) -> Step {
+    loop {
+        let packet = match mqtt5::Packet::read(input, Some(MAX_PACKET)) {
+            Ok(packet) => packet,
+            Err(rumqttc::v5::mqttbytes::Error::InsufficientBytes(_)) => return Step::More,
+            Err(_) => return Step::Close,
+        };
+        match packet {
+            mqtt5::Packet::Connect(_, will, _) => {
+                session.will = will.map(|will| Message {
+                    topic: String::from_utf8_lossy(&will.topic).into_owned(),
+                    payload: will.message,
+                    retain: will.retain,
+                    properties: None,
+                });
+                shared.connects.fetch_add(1, Ordering::SeqCst);
+                let ack = mqtt5::ConnAck { session_present: false, code: mqtt5::ConnectReturnCode::Success, properties: None };
+                ack.write(output).unwrap();
+            }
+            mqtt5::Packet::Subscribe(subscribe) => {
+                let return_codes = subscribe.filters.iter().map(|f| mqtt5::SubscribeReasonCode::Success(f.qos)).collect();
+                mqtt5::SubAck { pkid: subscribe.pkid, return_codes, properties: None }.write(output).unwrap();
+                subscribed(shared, session, subscribe.filters.into_iter().map(|f| f.path), Some(Protocol::V5), output);
+            }
+            mqtt5::Packet::Unsubscribe(unsubscribe) => {
+                session.filters.retain(|f| !unsubscribe.filters.contains(f));
+                let reasons = unsubscribe.filters.iter().map(|_| mqtt5::UnsubAckReason::Success).collect();
+                mqtt5::UnsubAck { pkid: unsubscribe.pkid, reasons, properties: None }.write(output).unwrap();
+            }
+            mqtt5::Packet::Publish(publish) => {
+                match publish.qos {
+                    QoS5::AtMostOnce => (),
+                    QoS5::AtLeastOnce => {
+                        mqtt5::PubAck::new(publish.pkid, None).write(output).unwrap();
+                    }
+                    QoS5::ExactlyOnce => {
+                        mqtt5::PubRec::new(publish.pkid, None).write(output).unwrap();
+                    }
+                }
+                let topic = String::from_utf8_lossy(&publish.topic).into_owned();
+                shared.publish(Message { topic, payload: publish.payload, retain: publish.retain, properties: publish.properties });
+            }
+            mqtt5::Packet::PubRel(release) => {
+                mqtt5::PubComp::new(release.pkid, None).write(output).unwrap();
+            }
+            mqtt5::Packet::PingReq(_) => {
+                mqtt5::PingResp::write(output).unwrap();
+            }
+            mqtt5::Packet::Disconnect(_) => {
+                session.will = None;
+                return Step::Close;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Adds `filters` to the session and sends what is retained under them.
+fn subscribed(shared: &Shared, session: &mut Session, filters: impl Iterator<Item = String>, protocol: Option<Protocol>, output: &mut BytesMut) {
+    let retained = shared.retained.lock().unwrap().clone();
+    for filter in filters {
+        for message in retained.values().filter(|m| matches(&m.topic, &filter)) {
+            forward(protocol, message, true, output);
+        }
+        session.filters.push(filter);
+    }
+}
+
+/// A message as sent on to a subscriber, at QoS 0.
+fn forward(protocol: Option<Protocol>, message: &Message, retain: bool, output: &mut BytesMut) {
+    match protocol {
+        Some(Protocol::V311) => {
+            let mut publish = Publish::new(message.topic.clone(), QoS::AtMostOnce, message.payload.to_vec());
+            publish.retain = retain;
+            publish.write(output).unwrap();
+        }
+        Some(Protocol::V5) => {
+            let mut publish = mqtt5::Publish::new(message.topic.clone(), QoS5::AtMostOnce, message.payload.clone(), message.properties.clone());
+            publish.retain = retain;
+            publish.write(output).unwrap();
+        }
+        None => (),
+    }
+}
@@ -0,0 +1,362 @@
+//! Runs the daemon's control loop against the simulated door and an
+//! in-process broker.
+
+mod broker;
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use garaged::clock::Clock;
+use garaged::auth;
+use garaged::actuation::Stage;
+use garaged::config::{Config, HeartbeatConfig, HistoryConfig, MqttProtocol, PinConfig, ProviderConfig, QuarantineConfig, WebhookConfig};
+use garaged::daemon::Daemon;
+use garaged::hardware::Hardware;
+use garaged::mqtt::{self, Topics};
+use garaged::mqtt_client;
+use garaged::simulate::Poke;
+use garaged::webhooks::WebhookEvent;
+use bytes::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use rumqttc::v5::mqttbytes::v5::PublishProperties;
+use hyper::{Body, Request, Response, Server};
+use std::convert::Infallible;
+use tokio::sync::mpsc;
+
+use broker::Broker;
+
+fn config(name: &str, broker: &Broker) -> Config {
+    let dir = std::env::temp_dir().join(format!("garaged-daemon-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = Config::default();
+    config.mqtt.host = "127.0.0.1".to_owned();
+    config.mqtt.port = broker.port;
+    config.mqtt.client_id = Some(format!("garaged-test-{}", name));
+    config.storage.dir = dir;
+    config.gpio.open = Some(PinConfig::new(5));
+    config.simulation.travel_secs = 0.5;
+    config.motor.travel_secs = 0.5;
+    config
+}
+
+/// Runs `test` alongside the daemon, handing it the topics and a way to
+/// poke the virtual door.
+async fn with_daemon<F, Fut>(config: Config, test: F)
+where
+    F: FnOnce(Topics, mpsc::UnboundedSender<Poke>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let hw = Hardware::simulate(&config.gpio, &config.simulation);
+    with_hardware(config, hw, test).await;
+}
+
+/// [`with_daemon`] on hardware already set up, e.g. with the door moved
+/// before the daemon starts.
+async fn with_hardware<F, Fut>(config: Config, hw: Hardware, test: F)
+where
+    F: FnOnce(Topics, mpsc::UnboundedSender<Poke>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let topics = Topics::new(&config.mqtt.topic_prefix, &config.mqtt.discovery_prefix);
+    let door = hw.simulator().unwrap().poker();
+    let (client, event_loop) = mqtt_client::connect(&config.mqtt, &topics);
+    let mut daemon = Daemon::new(config, PathBuf::from("/nonexistent/garaged.toml"), hw, client, Clock::System);
+    tokio::select! {
+        result = daemon.run(event_loop) => panic!("daemon stopped: {:?}", result),
+        _ = test(topics, door) => (),
+    }
+}
+
+#[tokio::test]
+async fn announces_itself_on_connecting() {
+    let broker = Broker::start().await;
+    with_daemon(config("announce", &broker), |topics, _| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.wait_for(&topics.state, "closed").await;
+        broker.wait_until("cover discovery", |b| !b.payloads(&topics.config).is_empty()).await;
+        let discovery: serde_json::Value = serde_json::from_str(&broker.payloads(&topics.config)[0]).unwrap();
+        assert_eq!(discovery["command_topic"], topics.command);
+        assert_eq!(discovery["state_topic"], topics.state);
+    }).await;
+}
+
+#[tokio::test]
+async fn open_command_runs_the_door_open() {
+    let broker = Broker::start().await;
+    with_daemon(config("open", &broker), |topics, _| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.wait_for(&topics.state, "closed").await;
+        broker.publish(&topics.command, "OPEN");
+        broker.wait_for(&topics.state, "opening").await;
+        broker.wait_for(&topics.state, "open").await;
+    }).await;
+}
+
+#[tokio::test]
+async fn mqtt5_commands_are_answered_on_their_response_topic() {
+    let broker = Broker::start().await;
+    let mut config = config("mqtt5", &broker);
+    config.mqtt.protocol = MqttProtocol::V5;
+    config.mqtt.state_expiry_secs = Some(60);
+    with_daemon(config, |topics, _| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.wait_for(&topics.state, "closed").await;
+        let state = broker.messages(&topics.state).remove(0).properties.unwrap();
+        assert_eq!(state.message_expiry_interval, Some(60));
+        assert!(state.user_properties.contains(&("door".to_owned(), mqtt::DOOR_ID.to_owned())));
+        assert!(state.user_properties.contains(&("version".to_owned(), env!("CARGO_PKG_VERSION").to_owned())));
+
+        let request = |correlation: &'static [u8]| Some(PublishProperties {
+            response_topic: Some("phone/reply".to_owned()),
+            correlation_data: Some(Bytes::from_static(correlation)),
+            ..PublishProperties::default()
+        });
+        broker.publish_with(&topics.command, "CLOSE", request(b"first"));
+        broker.publish_with(&topics.command, "OPEN", request(b"second"));
+        broker.wait_until("two replies", |b| b.messages("phone/reply").len() == 2).await;
+        let replies = broker.messages("phone/reply");
+        let reply = |i: usize| -> serde_json::Value { serde_json::from_slice(&replies[i].payload).unwrap() };
+        let correlation = |i: usize| replies[i].properties.as_ref().unwrap().correlation_data.clone();
+        assert_eq!(reply(0)["reason"], "already_closed");
+        assert_eq!(correlation(0), Some(Bytes::from_static(b"first")));
+        assert_eq!(reply(1), serde_json::json!({ "ok": true, "command": "OPEN" }));
+        assert_eq!(correlation(1), Some(Bytes::from_static(b"second")));
+        broker.wait_for(&topics.state, "open").await;
+    }).await;
+}
+
+#[tokio::test]
+async fn door_moved_by_hand_is_reported() {
+    let broker = Broker::start().await;
+    with_daemon(config("button", &broker), |topics, door| async move {
+        broker.wait_for(&topics.state, "closed").await;
+        door.send(Poke::Button).unwrap();
+        broker.wait_for(&topics.state, "open").await;
+        door.send(Poke::Closed).unwrap();
+        broker.wait_until("closed again", |b| b.payloads(&topics.state).last().map(String::as_str) == Some("closed")).await;
+    }).await;
+}
+
+#[tokio::test]
+async fn reconnects_and_announces_again_after_the_broker_drops_it() {
+    let broker = Broker::start().await;
+    with_daemon(config("reconnect", &broker), |topics, _| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.wait_until("discovery", |b| !b.payloads(&topics.config).is_empty()).await;
+        broker.disconnect_all();
+        broker.wait_for(&topics.availability, mqtt::OFFLINE).await;
+        broker.wait_until("a second connection", |b| b.connects() == 2).await;
+        broker.wait_until("online again", |b| b.payloads(&topics.availability).last().map(String::as_str) == Some(mqtt::ONLINE)).await;
+        broker.wait_until("discovery again", |b| b.payloads(&topics.config).len() == 2).await;
+    }).await;
+}
+
+#[tokio::test]
+async fn heartbeat_reports_sensors_and_reconnects() {
+    let broker = Broker::start().await;
+    let mut config = config("heartbeat", &broker);
+    config.heartbeat = Some(HeartbeatConfig { interval_secs: 1 });
+    with_daemon(config, |topics, _| async move {
+        broker.wait_until("a heartbeat", |b| !b.payloads(&topics.heartbeat).is_empty()).await;
+        let heartbeat: serde_json::Value = serde_json::from_str(&broker.payloads(&topics.heartbeat)[0]).unwrap();
+        assert_eq!(heartbeat["last_sensor_read"]["ok"], true);
+        assert_eq!(heartbeat["relay_ready"], true);
+
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.disconnect_all();
+        broker.wait_until("a second connection", |b| b.connects() == 2).await;
+        broker.wait_until("a heartbeat after reconnecting", |b| {
+            b.payloads(&topics.heartbeat).iter().any(|p| p.contains(r#""mqtt_reconnects":1"#))
+        }).await;
+    }).await;
+}
+
+#[tokio::test]
+async fn history_is_replayed_oldest_first() {
+    let broker = Broker::start().await;
+    let mut config = config("replay", &broker);
+    config.history = Some(HistoryConfig::default());
+    with_daemon(config, |topics, _| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.publish(&topics.command, "OPEN");
+        broker.wait_for(&topics.state, "open").await;
+        broker.publish(&topics.history_replay_request, r#"{"request_id": "resync", "event": "state"}"#);
+        broker.wait_until("the end of the replay", |b| b.payloads(&topics.history_replay).iter().any(|p| p.contains(r#""end":true"#))).await;
+
+        let replayed: Vec<serde_json::Value> = broker.payloads(&topics.history_replay).iter()
+            .map(|p| serde_json::from_str(p).unwrap())
+            .collect();
+        let (end, entries) = replayed.split_last().unwrap();
+        assert_eq!(end["count"], entries.len());
+        assert!(entries.len() >= 2);
+        assert!(entries.iter().all(|e| e["request_id"] == "resync" && e["event"] == "state"));
+        assert!(entries.windows(2).all(|w| w[0]["id"].as_i64() < w[1]["id"].as_i64()));
+    }).await;
+}
+
+#[tokio::test]
+async fn extra_outputs_are_switched_from_home_assistant() {
+    let broker = Broker::start().await;
+    let mut config = config("extra", &broker);
+    config.gpio.extra = toml::from_str(r#"
+        outputs = [{ id = "lights", name = "Garage Lights", output = { pin = 22 } }]
+        inputs = [{ id = "motion", name = "Garage Motion", input = { pin = 16 }, device_class = "motion" }]
+    "#).unwrap();
+    with_daemon(config, |topics, _| async move {
+        broker.wait_for(&topics.io("lights"), "OFF").await;
+        broker.wait_for(&topics.io("motion"), "OFF").await;
+        let discovery: serde_json::Value = serde_json::from_str(&broker.payloads(&topics.io_switch_config("lights"))[0]).unwrap();
+        assert_eq!(discovery["command_topic"], topics.io_set("lights"));
+        let discovery: serde_json::Value = serde_json::from_str(&broker.payloads(&topics.io_binary_sensor_config("motion"))[0]).unwrap();
+        assert_eq!(discovery["device_class"], "motion");
+        broker.publish(&topics.io_set("lights"), "ON");
+        broker.wait_for(&topics.io("lights"), "ON").await;
+    }).await;
+}
+
+#[tokio::test]
+async fn only_admins_can_release_a_quarantine() {
+    let broker = Broker::start().await;
+    let mut config = config("release", &broker);
+    std::fs::create_dir_all(&config.storage.dir).unwrap();
+    let credentials = config.storage.dir.join("credentials.toml");
+    std::fs::write(&credentials, format!(
+        "[[credential]]\nid = \"root\"\nkind = \"token\"\nhash = \"{}\"\nadmin = true\n",
+        auth::hash_secret("admin-token"),
+    )).unwrap();
+    config.auth.providers = vec![ProviderConfig::File { path: credentials }];
+    config.quarantine = Some(QuarantineConfig { max_failed_auth: 2, ..QuarantineConfig::default() });
+    with_daemon(config, |topics, _| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        for _ in 0..2 {
+            broker.publish(&topics.command, r#"{"command": "OPEN", "credential": "guess"}"#);
+        }
+        broker.wait_until("a quarantine", |b| b.payloads(&topics.quarantine).iter().any(|p| p.contains("token:"))).await;
+
+        broker.publish(&topics.quarantine_release, "all");
+        broker.publish(&topics.quarantine_release, r#"{"source": "all", "credential": "guess"}"#);
+        broker.wait_until("two refusals", |b| {
+            b.payloads(&topics.events).iter().filter(|p| p.contains("release_refused")).count() == 2
+        }).await;
+        let events = broker.payloads(&topics.events);
+        assert!(events.iter().any(|p| p.contains("admin_required")));
+        assert!(events.iter().any(|p| p.contains("\"reason\":\"quarantined\"")));
+        assert!(!events.iter().any(|p| p.contains("\"event\":\"released\"")));
+
+        broker.publish(&topics.quarantine_release, r#"{"source": "all", "credential": "admin-token"}"#);
+        broker.wait_until("the release", |b| b.payloads(&topics.events).iter().any(|p| p.contains("\"event\":\"released\""))).await;
+        assert!(broker.payloads(&topics.events).iter().any(|p| p.contains("mqtt:root")));
+    }).await;
+}
+
+#[tokio::test]
+async fn restarting_with_the_door_open_sends_no_webhook() {
+    let (tx, mut bodies) = mpsc::unbounded_channel::<serde_json::Value>();
+    let make = make_service_fn(move |_| {
+        let tx = tx.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let tx = tx.clone();
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    tx.send(serde_json::from_slice(&body).unwrap()).unwrap();
+                    Ok::<_, Infallible>(Response::new(Body::empty()))
+                }
+            }))
+        }
+    });
+    let receiver = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+    let url = format!("http://{}/hook", receiver.local_addr());
+    tokio::spawn(receiver);
+
+    let broker = Broker::start().await;
+    let mut config = config("restart-open", &broker);
+    config.webhooks = vec![WebhookConfig {
+        url,
+        events: [WebhookEvent::Open, WebhookEvent::Close].into(),
+        max_attempts: 1,
+        backoff_secs: 0.01,
+        max_backoff_secs: 0.01,
+        timeout_secs: 5,
+    }];
+    let hw = Hardware::simulate(&config.gpio, &config.simulation);
+    let simulator = hw.simulator().unwrap();
+    simulator.poke(Poke::Open);
+    while !simulator.sensors().open {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    with_hardware(config, hw, |topics, door| async move {
+        broker.wait_for(&topics.state, "open").await;
+        door.send(Poke::Closed).unwrap();
+        // Deliveries to one URL go in order, so the close arriving first
+        // means nothing was sent for the position found at startup.
+        let first = tokio::time::timeout(Duration::from_secs(5), bodies.recv()).await.unwrap().unwrap();
+        assert_eq!(first["state"], "closed");
+    }).await;
+}
+
+#[tokio::test]
+async fn close_warning_can_be_cancelled_and_skips_the_wall_button() {
+    let broker = Broker::start().await;
+    let mut config = config("warning", &broker);
+    config.gpio.warning = Some(toml::from_str("pin = 6").unwrap());
+    config.actuation.stages = vec![Stage::Interlock, Stage::Warning, Stage::Pulse, Stage::Verify];
+    config.actuation.warning_ms = 1000;
+    config.rate_limit.relay_cooldown_secs = 0.0;
+    let hw = Hardware::simulate(&config.gpio, &config.simulation);
+    let simulator = hw.simulator().unwrap();
+    simulator.poke(Poke::Open);
+    while !simulator.sensors().open {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    with_hardware(config, hw, |topics, door| async move {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.wait_for(&topics.state, "open").await;
+        // The loop keeps going while the warning sounds, so the CANCEL is
+        // seen before the press.
+        broker.publish(&topics.command, "CLOSE");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        broker.publish(&topics.command, "CANCEL");
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!broker.payloads(&topics.state).iter().any(|p| p == "closing"));
+
+        let pressed = tokio::time::Instant::now();
+        door.send(Poke::Button).unwrap();
+        broker.wait_for(&topics.state, "closing").await;
+        assert!(pressed.elapsed() < Duration::from_millis(1000));
+    }).await;
+}
+
+#[tokio::test]
+async fn a_failing_wall_button_is_reported_and_the_loop_keeps_going() {
+    let broker = Broker::start().await;
+    let mut config = config("button-fault", &broker);
+    // Iono Pi lines are polled from files, so the button's can be broken.
+    let ionopi = config.storage.dir.join("ionopi");
+    for (attr, value) in [("relay/o1", "0"), ("digital_in/di1", "1"), ("digital_in/di2", "0")] {
+        std::fs::create_dir_all(ionopi.join(attr).parent().unwrap()).unwrap();
+        std::fs::write(ionopi.join(attr), value).unwrap();
+    }
+    config.gpio.open = None;
+    config.gpio.status = PinConfig::new(16);
+    config.gpio.input = PinConfig::new(19);
+    let hw = Hardware::init(&config.gpio, None, None, Some(&ionopi)).unwrap();
+    let topics = Topics::new(&config.mqtt.topic_prefix, &config.mqtt.discovery_prefix);
+    let (client, event_loop) = mqtt_client::connect(&config.mqtt, &topics);
+    let mut daemon = Daemon::new(config, PathBuf::from("/nonexistent/garaged.toml"), hw, client, Clock::System);
+    let button_fault = |b: &broker::Broker| b.payloads(&topics.attributes).last()
+        .map(|a| serde_json::from_str::<serde_json::Value>(a).unwrap()["button_fault"].clone());
+    let test = async {
+        broker.wait_for(&topics.availability, mqtt::ONLINE).await;
+        broker.wait_until("attributes", |b| button_fault(b) == Some(serde_json::Value::Null)).await;
+        std::fs::write(ionopi.join("digital_in/di2"), "?").unwrap();
+        broker.wait_until("button fault", |b| button_fault(b).is_some_and(|f| f.is_string())).await;
+        std::fs::write(ionopi.join("digital_in/di2"), "1").unwrap();
+        broker.wait_until("button recovered", |b| button_fault(b) == Some(serde_json::Value::Null)).await;
+    };
+    tokio::select! {
+        result = daemon.run(event_loop) => panic!("daemon stopped: {:?}", result),
+        _ = test => (),
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use garaged::door::{Position, Status};
+use garaged::estimator::{CurrentEstimator, EncoderEstimator, PositionEstimator, SwitchEstimator};
+use garaged::position::{PositionTracker, Readings};
+
+#[test]
+fn encoder_counts_pulses_towards_the_heading() {
+    let mut estimator = EncoderEstimator::new(200);
+    estimator.fix(0.0);
+    estimator.encoder_pulse();
+    assert_eq!(estimator.estimate(), 0.0);
+    estimator.start(Status::Open);
+    for _ in 0..50 {
+        estimator.encoder_pulse();
+    }
+    assert_eq!(estimator.estimate(), 25.0);
+    estimator.stop();
+    estimator.start(Status::Closed);
+    for _ in 0..20 {
+        estimator.encoder_pulse();
+    }
+    assert_eq!(estimator.estimate(), 15.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn current_estimate_pauses_while_the_motor_is_idle() {
+    let mut estimator = CurrentEstimator::new(Duration::from_secs(10), 0.5);
+    estimator.fix(0.0);
+    estimator.start(Status::Open);
+    tokio::time::advance(Duration::from_secs(2)).await;
+    estimator.motor_current(0.1);
+    tokio::time::advance(Duration::from_secs(5)).await;
+    assert_eq!(estimator.estimate().round(), 20.0);
+    estimator.motor_current(1.2);
+    tokio::time::advance(Duration::from_secs(3)).await;
+    assert_eq!(estimator.estimate().round(), 50.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn switches_only_move_at_a_sensor() {
+    let readings = |closed, zone| Readings { closed, open: Some(false), zones: vec![(50, zone)] };
+    let estimator = Box::new(SwitchEstimator::default());
+    let mut tracker = PositionTracker::with_estimator(true, vec![50], Duration::from_secs(10), estimator);
+    tracker.resume(&readings(true, false));
+    assert_eq!(tracker.sensors_changed(&readings(false, false)), Some(Position::Opening));
+    tokio::time::advance(Duration::from_secs(3)).await;
+    assert_eq!(tracker.percent(), 0);
+    assert!(!tracker.is_moving());
+    tracker.sensors_changed(&readings(false, true));
+    assert_eq!(tracker.percent(), 50);
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+use std::time::Duration;
+
+use garaged::config::{Config, EdgeConfig, GpioConfig, GpioLine, HardwareBackend, PinConfig, Pull};
+use garaged::hardware::{probe, resolve_line, Hardware};
+use tokio::time::Instant;
+
+fn chip(class: &Path, name: &str, label: &str, base: u64, ngpio: u64) {
+    let dir = class.join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("label"), format!("{}\n", label)).unwrap();
+    std::fs::write(dir.join("base"), format!("{}\n", base)).unwrap();
+    std::fs::write(dir.join("ngpio"), format!("{}\n", ngpio)).unwrap();
+}
+
+#[test]
+fn chip_lines_resolve_to_sysfs_numbers() {
+    let class = std::env::temp_dir().join(format!("garaged-gpio-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&class);
+    chip(&class, "gpiochip512", "pinctrl-bcm2711", 512, 58);
+    chip(&class, "gpiochip570", "raspberrypi-exp-gpio", 570, 8);
+    std::fs::create_dir_all(class.join("devices/gpiochip1")).unwrap();
+    std::os::unix::fs::symlink(class.join("devices/gpiochip1"), class.join("gpiochip570/device")).unwrap();
+
+    assert_eq!(resolve_line(&class, "pinctrl-bcm2711", 17).unwrap(), 529);
+    assert_eq!(resolve_line(&class, "gpiochip1", 2).unwrap(), 572);
+    assert!(resolve_line(&class, "raspberrypi-exp-gpio", 8).is_err());
+    assert!(resolve_line(&class, "gpiochip7", 0).is_err());
+    let _ = std::fs::remove_dir_all(&class);
+}
+
+#[test]
+fn inputs_take_a_pin_or_a_chip_line() {
+    let path = std::env::temp_dir().join(format!("garaged-pins-{}.toml", std::process::id()));
+    std::fs::write(&path, r#"
+        [gpio]
+        status = { chip = "pinctrl-bcm2711", line = 6, pull = "up", edge = "falling" }
+        zones = [{ pin = 19, percent = 50 }]
+    "#).unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.gpio.status.line(), Some(GpioLine::Chip { chip: "pinctrl-bcm2711", line: 6 }));
+    assert_eq!(config.gpio.status.pull, Some(Pull::Up));
+    assert_eq!(config.gpio.status.edge, Some(EdgeConfig::Falling));
+    assert_eq!(config.gpio.input.line(), Some(GpioLine::Number(12)));
+    assert_eq!(config.gpio.zones[0].pin_config().line(), Some(GpioLine::Number(19)));
+
+    std::fs::write(&path, "[gpio]\nstatus = { pin = 6, line = 6 }\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    std::fs::write(&path, "[gpio]\nzones = [{ chip = \"gpiochip0\", percent = 50 }]\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn backends_are_probed_in_order() {
+    let dir = std::env::temp_dir().join(format!("garaged-ionopi-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config = GpioConfig { ionopi_dir: dir.clone(), ..GpioConfig::default() };
+    assert_eq!(config.backends, vec![HardwareBackend::IonoPi, HardwareBackend::Sysfs]);
+    assert!(probe(HardwareBackend::IonoPi, &config).is_err());
+    std::fs::create_dir_all(dir.join("relay")).unwrap();
+    assert!(probe(HardwareBackend::IonoPi, &config).is_ok());
+    assert!(probe(HardwareBackend::Simulated, &config).is_ok());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let path = std::env::temp_dir().join(format!("garaged-backends-{}.toml", std::process::id()));
+    std::fs::write(&path, "[gpio]\nbackends = [\"sysfs\", \"simulated\"]\n").unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.gpio.backends, vec![HardwareBackend::Sysfs, HardwareBackend::Simulated]);
+    std::fs::write(&path, "[gpio]\nbackends = []\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn read_retries_leave_the_loop_free() {
+    let dir = std::env::temp_dir().join(format!("garaged-ionopi-read-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (attr, value) in [("relay/o1", "0"), ("digital_in/di1", "1"), ("digital_in/di2", "0")] {
+        std::fs::create_dir_all(dir.join(attr).parent().unwrap()).unwrap();
+        std::fs::write(dir.join(attr), value).unwrap();
+    }
+    let config = GpioConfig { status: PinConfig::new(16), input: PinConfig::new(19), ..GpioConfig::default() };
+    let hw = Hardware::init(&config, None, None, Some(&dir)).unwrap();
+    std::fs::write(dir.join("digital_in/di1"), "?").unwrap();
+
+    // Every attempt fails, so the read takes its whole backoff. A timer
+    // started alongside it still fires first.
+    let (read_at, ticked_at) = tokio::join!(
+        async {
+            assert!(hw.door_status().await.is_err());
+            Instant::now()
+        },
+        async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Instant::now()
+        },
+    );
+    assert!(ticked_at < read_at);
+    let _ = std::fs::remove_dir_all(&dir);
+}
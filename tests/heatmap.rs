@@ -0,0 +1,28 @@
+use chrono::{Local, TimeZone, Utc};
+use garaged::door::Status;
+use garaged::stats::UsageStats;
+
+#[test]
+fn openings_are_counted_by_local_weekday_and_hour() {
+    let mut stats = UsageStats::default();
+    // Saturday 2026-03-14, 08:30 and 08:45 local time, then Monday at 17:05.
+    let opened = [(14, 8, 30), (14, 8, 45), (16, 17, 5)];
+    for (day, hour, minute) in opened {
+        let at = Local.with_ymd_and_hms(2026, 3, day, hour, minute, 0).unwrap().with_timezone(&Utc);
+        stats.door_changed(Status::Open, at);
+        stats.door_changed(Status::Closed, at + chrono::Duration::minutes(2));
+    }
+
+    let report = stats.heatmap.report();
+    assert_eq!(report.total, 3);
+    assert_eq!(report.days[5], "sat");
+    assert_eq!(report.counts[5][8], 2);
+    assert_eq!(report.counts[0][17], 1);
+}
+
+#[test]
+fn closing_is_not_counted() {
+    let mut stats = UsageStats::default();
+    stats.door_changed(Status::Closed, Utc::now());
+    assert_eq!(stats.heatmap.report().total, 0);
+}
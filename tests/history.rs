@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use garaged::history::{HistoryDb, HistoryEntry, HistoryQuery};
+use serde_json::json;
+
+fn db(name: &str) -> (HistoryDb, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("garaged-history-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    (HistoryDb::open(&dir.join("history.db")).unwrap(), dir)
+}
+
+fn entry(event: &str, identity: Option<&str>, timestamp: DateTime<Utc>) -> HistoryEntry {
+    HistoryEntry {
+        id: None,
+        door: "garage".to_owned(),
+        event: event.to_owned(),
+        source: Some("keypad".to_owned()),
+        identity: identity.map(str::to_owned),
+        detail: json!({ "command": "OPEN", "result": "accepted" }),
+        timestamp,
+    }
+}
+
+#[test]
+fn finds_who_opened_the_door_at_night() {
+    let (db, dir) = db("night");
+    let night = Utc.with_ymd_and_hms(2026, 3, 14, 3, 2, 0).unwrap();
+    db.record(&entry("state", None, night - Duration::hours(5))).unwrap();
+    db.record(&entry("command", Some("alice"), night - Duration::hours(4))).unwrap();
+    db.record(&entry("command", Some("bob"), night)).unwrap();
+    db.record(&entry("state", None, night + Duration::seconds(1))).unwrap();
+
+    let query = HistoryQuery {
+        since: Some(night - Duration::hours(1)),
+        until: Some(night + Duration::hours(1)),
+        event: Some("command".to_owned()),
+        ..HistoryQuery::default()
+    };
+    let found = db.query(&query).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].identity.as_deref(), Some("bob"));
+    assert_eq!(found[0].timestamp, night);
+    assert_eq!(found[0].detail, json!({ "command": "OPEN", "result": "accepted" }));
+}
+
+#[test]
+fn latest_entries_come_first_up_to_the_limit() {
+    let (db, dir) = db("limit");
+    let start = Utc.with_ymd_and_hms(2026, 3, 14, 12, 0, 0).unwrap();
+    for minute in 0..5 {
+        db.record(&entry("state", None, start + Duration::minutes(minute))).unwrap();
+    }
+    let found = db.query(&HistoryQuery { limit: Some(2), ..HistoryQuery::default() }).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let times: Vec<_> = found.iter().map(|e| e.timestamp).collect();
+    assert_eq!(times, vec![start + Duration::minutes(4), start + Duration::minutes(3)]);
+    assert_eq!(HistoryQuery { limit: Some(1_000_000), ..HistoryQuery::default() }.limit(), 1000);
+}
+
+#[test]
+fn pruning_drops_entries_before_the_cutoff() {
+    let (db, dir) = db("prune");
+    let now = Utc.with_ymd_and_hms(2026, 3, 14, 12, 0, 0).unwrap();
+    db.record(&entry("state", None, now - Duration::days(100))).unwrap();
+    db.record(&entry("state", None, now - Duration::days(10))).unwrap();
+    let deleted = db.prune(now - Duration::days(90)).unwrap();
+    let left = db.query(&HistoryQuery::default()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(deleted, 1);
+    assert_eq!(left.len(), 1);
+}
@@ -0,0 +1,52 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use garaged::api;
+use garaged::auth::Authenticator;
+use garaged::config::AuthConfig;
+use garaged::door::Command;
+use garaged::http;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// Sends `body` to `/command` in chunks of `chunk` bytes, with no
+/// Content-Length, and returns the response's status line.
+async fn post_chunked(addr: SocketAddr, body: &[u8], chunk: usize) -> String {
+    let mut stream = loop {
+        match TcpStream::connect(addr).await {
+            Ok(s) => break s,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    let mut request = b"POST /command HTTP/1.1\r\nHost: garage\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    for part in body.chunks(chunk) {
+        request.extend_from_slice(format!("{:x}\r\n", part.len()).as_bytes());
+        request.extend_from_slice(part);
+        request.extend_from_slice(b"\r\n");
+    }
+    request.extend_from_slice(b"0\r\n\r\n");
+    stream.write_all(&request).await.unwrap();
+    let mut response = vec![0; 1024];
+    let n = stream.read(&mut response).await.unwrap();
+    String::from_utf8_lossy(&response[..n]).lines().next().unwrap_or_default().to_owned()
+}
+
+#[tokio::test]
+async fn chunked_bodies_are_limited_as_they_are_read() {
+    let listener = http::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (handle, mut server) = api::channel(None);
+    let auth = Arc::new(Authenticator::from_config(&AuthConfig::default()));
+    tokio::spawn(http::serve(listener, handle, auth, false));
+    tokio::spawn(async move {
+        while let Some(request) = server.commands.recv().await {
+            assert_eq!(request.command, Command::Open);
+            let _ = request.reply.send(Ok(()));
+        }
+    });
+
+    assert_eq!(post_chunked(addr, b"OPEN", 2).await, "HTTP/1.1 200 OK");
+    let oversized = vec![b' '; 64 * 1024];
+    assert_eq!(post_chunked(addr, &oversized, 512).await, "HTTP/1.1 413 Payload Too Large");
+}
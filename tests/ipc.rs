@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use garaged::api;
+use garaged::api::QuarantineRequest;
+use garaged::auth::{self, Authenticator};
+use garaged::config::{AuthConfig, ProviderConfig, SocketConfig};
+use garaged::door::{Command, Source};
+use garaged::ipc;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// Sends a request line and reads the reply.
+async fn ask(client: &mut BufReader<UnixStream>, request: &str) -> Value {
+    client.get_mut().write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+    next(client).await
+}
+
+async fn next(client: &mut BufReader<UnixStream>) -> Value {
+    let mut line = String::new();
+    client.read_line(&mut line).await.unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+#[tokio::test]
+async fn socket_requests_reach_the_daemon() {
+    let path = std::env::temp_dir().join(format!("garaged-ipc-{}.sock", std::process::id()));
+    let listener = ipc::bind(&SocketConfig { path: path.clone(), ..SocketConfig::default() }).unwrap();
+    let (handle, mut server) = api::channel(None);
+    let auth = Arc::new(Authenticator::from_config(&AuthConfig::default()));
+    tokio::spawn(ipc::serve(listener, handle, auth));
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(request) = server.commands.recv() => {
+                    assert_eq!((request.command, request.source), (Command::Open, Source::Socket));
+                    let _ = request.reply.send(Ok(()));
+                },
+                Some(request) = server.vacation_lock.recv() => {
+                    assert_eq!(request.by, "socket");
+                    let _ = request.reply.send(request.locked);
+                    let _ = server.events.send(json!({ "event": "locked" }));
+                },
+            }
+        }
+    });
+
+    let mut client = BufReader::new(UnixStream::connect(&path).await.unwrap());
+    let reply = ask(&mut client, r#"{"request": "status"}"#).await;
+    assert_eq!((reply["ok"].clone(), reply["status"]["vacation_lock"].clone()), (json!(true), json!(false)));
+    let reply = ask(&mut client, r#"{"request": "command", "command": "OPEN"}"#).await;
+    assert_eq!(reply, json!({ "ok": true, "command": "OPEN" }));
+    let reply = ask(&mut client, r#"{"request": "command", "command": "JUMP"}"#).await;
+    assert_eq!(reply["reason"], "invalid_payload");
+
+    let mut events = BufReader::new(UnixStream::connect(&path).await.unwrap());
+    assert_eq!(ask(&mut events, r#"{"request": "events"}"#).await, json!({ "ok": true }));
+    let reply = ask(&mut client, r#"{"request": "lock", "locked": true}"#).await;
+    assert_eq!(reply, json!({ "ok": true, "vacation_lock": true }));
+    assert_eq!(next(&mut events).await, json!({ "event": "locked" }));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn unblocking_needs_an_admin_token() {
+    let dir = std::env::temp_dir().join(format!("garaged-ipc-unblock-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let credentials = dir.join("credentials.toml");
+    std::fs::write(&credentials, format!(
+        "[[credential]]\nid = \"root\"\nkind = \"token\"\nhash = \"{}\"\nadmin = true\n\n\
+         [[credential]]\nid = \"alice\"\nkind = \"token\"\nhash = \"{}\"\n",
+        auth::hash_secret("admin-token"),
+        auth::hash_secret("alice-token"),
+    )).unwrap();
+    let path = dir.join("garaged.sock");
+    let listener = ipc::bind(&SocketConfig { path: path.clone(), ..SocketConfig::default() }).unwrap();
+    let (handle, mut server) = api::channel(None);
+    let auth = AuthConfig { providers: vec![ProviderConfig::File { path: credentials }] };
+    tokio::spawn(ipc::serve(listener, handle, Arc::new(Authenticator::from_config(&auth))));
+    tokio::spawn(async move {
+        while let Some(request) = server.quarantine.recv().await {
+            if let QuarantineRequest::Release { source, by, reply } = request {
+                assert_eq!((source.as_str(), by.as_str()), ("keypad", "socket:root"));
+                let _ = reply.send(vec![source]);
+            }
+        }
+    });
+
+    let mut client = BufReader::new(UnixStream::connect(&path).await.unwrap());
+    let reply = ask(&mut client, r#"{"request": "quarantine"}"#).await;
+    assert_eq!(reply, json!({ "ok": true, "quarantine": [] }));
+    let reply = ask(&mut client, r#"{"request": "unblock", "source": "keypad"}"#).await;
+    assert_eq!(reply["error"], "forbidden");
+    let reply = ask(&mut client, r#"{"request": "unblock", "source": "keypad", "token": "alice-token"}"#).await;
+    assert_eq!(reply["error"], "forbidden");
+    let reply = ask(&mut client, r#"{"request": "unblock", "source": "keypad", "token": "admin-token"}"#).await;
+    assert_eq!(reply, json!({ "ok": true, "released": ["keypad"] }));
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,53 @@
+use garaged::config::{KeypadConfig, PinConfig};
+use garaged::keypad::{Frame, PinEntry, Wiegand};
+
+fn send(wiegand: &mut Wiegand, bits: &str) -> Frame {
+    for b in bits.chars() {
+        wiegand.bit(b == '1');
+    }
+    wiegand.finish()
+}
+
+fn config() -> KeypadConfig {
+    KeypadConfig { d0: PinConfig::new(20), d1: PinConfig::new(21), entry_timeout_secs: 10, max_failures: 2, block_secs: 60 }
+}
+
+#[tokio::test(start_paused = true)]
+async fn decodes_keys_and_cards() {
+    let mut wiegand = Wiegand::default();
+    assert_eq!(send(&mut wiegand, "0111"), Frame::Key('7'));
+    assert_eq!(send(&mut wiegand, "1011"), Frame::Key('#'));
+    assert_eq!(send(&mut wiegand, "10100101"), Frame::Key('5'));
+    assert_eq!(send(&mut wiegand, "10100100"), Frame::Invalid(8));
+    assert_eq!(send(&mut wiegand, "1111"), Frame::Invalid(4));
+    // Facility 1, card 2: even parity over the first half, odd over the second.
+    assert_eq!(send(&mut wiegand, "10000000100000000000000100"), Frame::Card(0x01_0002));
+    assert_eq!(send(&mut wiegand, "00000000100000000000000100"), Frame::Invalid(26));
+}
+
+#[tokio::test(start_paused = true)]
+async fn collects_codes_until_hash() {
+    let config = config();
+    let mut entry = PinEntry::default();
+    for key in "12*34".chars() {
+        assert_eq!(entry.key(key, &config), None);
+    }
+    assert_eq!(entry.key('#', &config).as_deref(), Some("34"));
+    assert_eq!(entry.key('#', &config), None);
+
+    entry.key('9', &config);
+    tokio::time::advance(config.entry_timeout() * 2).await;
+    entry.key('8', &config);
+    assert_eq!(entry.key('#', &config).as_deref(), Some("8"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn blocks_after_repeated_failures() {
+    let config = config();
+    let mut entry = PinEntry::default();
+    assert!(!entry.failed(&config));
+    assert!(entry.failed(&config));
+    assert!(entry.is_blocked());
+    tokio::time::advance(config.block()).await;
+    assert!(!entry.is_blocked());
+}
@@ -0,0 +1,164 @@
+//! The LDAP provider against a fake directory that speaks just enough of
+//! the protocol: simple binds, searches with an equality filter and unbind.
+
+use garaged::auth::{Authenticator, Credential, CredentialKind, Identity};
+use garaged::config::{AuthConfig, ProviderConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const ADMINS: &str = "cn=garage-admins,ou=groups,dc=example,dc=com";
+
+/// People in the fake directory: uid, pin and groups.
+const PEOPLE: &[(&str, &str, &[&str])] = &[
+    ("alice", "1234", &[ADMINS]),
+    ("bob", "5678", &[]),
+];
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match content.len() {
+        n if n < 0x80 => out.push(n as u8),
+        n if n < 0x100 => out.extend([0x81, n as u8]),
+        n => out.extend([0x82, (n >> 8) as u8, n as u8]),
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Splits the first element off `input` as (tag, content, rest).
+fn split(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let count = (first & 0x7f) as usize;
+        let len = input.get(..count)?.iter().fold(0, |acc, &b| acc << 8 | b as usize);
+        input = &input[count..];
+        len
+    };
+    Some((tag, input.get(..len)?, &input[len..]))
+}
+
+fn elements(mut input: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut out = Vec::new();
+    while let Some((tag, content, rest)) = split(input) {
+        out.push((tag, content));
+        input = rest;
+    }
+    out
+}
+
+fn success(tag: u8) -> Vec<u8> {
+    tlv(tag, &[tlv(0x0a, &[0]), tlv(0x04, b""), tlv(0x04, b"")].concat())
+}
+
+fn entry(uid: &str, groups: &[&str]) -> Vec<u8> {
+    let dn = format!("uid={},ou=people,dc=example,dc=com", uid);
+    let attribute = |name: &str, values: &[&str]| {
+        let values: Vec<u8> = values.iter().flat_map(|v| tlv(0x04, v.as_bytes())).collect();
+        tlv(0x30, &[tlv(0x04, name.as_bytes()), tlv(0x31, &values)].concat())
+    };
+    let attributes = [attribute("uid", &[uid]), attribute("memberOf", groups)].concat();
+    tlv(0x64, &[tlv(0x04, dn.as_bytes()), tlv(0x30, &attributes)].concat())
+}
+
+/// Answers one connection, checking the service account's bind. An
+/// equality filter on `pin` finds that person; a presence filter, which is
+/// what an unescaped `*` would turn into, finds everyone.
+async fn answer(mut socket: TcpStream) {
+    let mut input = Vec::new();
+    let mut bound = false;
+    loop {
+        let (message, rest) = match split(&input) {
+            Some((0x30, message, rest)) => (message.to_vec(), rest.to_vec()),
+            _ => {
+                let mut buf = [0; 4096];
+                match socket.read(&mut buf).await {
+                    Ok(n) if n > 0 => input.extend_from_slice(&buf[..n]),
+                    _ => return,
+                }
+                continue;
+            }
+        };
+        input = rest;
+        let parts = elements(&message);
+        let id = tlv(0x02, parts[0].1);
+        let (op, body) = parts[1];
+        let mut replies = Vec::new();
+        match op {
+            0x60 => {
+                let fields = elements(body);
+                assert_eq!(fields[1].1, b"cn=garaged,dc=example,dc=com");
+                assert_eq!(fields[2].1, b"service-password");
+                bound = true;
+                replies.push(success(0x61));
+            }
+            0x63 => {
+                assert!(bound, "searched before binding");
+                let (filter_tag, filter) = elements(body)[6];
+                let found: Vec<_> = match filter_tag {
+                    0xa3 => {
+                        let assertion = elements(filter);
+                        assert_eq!(assertion[0].1, b"pin");
+                        PEOPLE.iter().filter(|p| p.1.as_bytes() == assertion[1].1).collect()
+                    }
+                    0x87 => PEOPLE.iter().collect(),
+                    _ => Vec::new(),
+                };
+                replies.extend(found.iter().map(|(uid, _, groups)| entry(uid, groups)));
+                replies.push(success(0x65));
+            }
+            _ => return,
+        }
+        for reply in replies {
+            let message = tlv(0x30, &[id.clone(), reply].concat());
+            socket.write_all(&message).await.unwrap();
+        }
+    }
+}
+
+fn provider(port: u16) -> Authenticator {
+    let config: ProviderConfig = toml::from_str(&format!(
+        "type = \"ldap\"\n\
+         url = \"ldap://127.0.0.1:{}\"\n\
+         bind_dn = \"cn=garaged,dc=example,dc=com\"\n\
+         bind_password = \"service-password\"\n\
+         base_dn = \"ou=people,dc=example,dc=com\"\n\
+         keypad_filter = \"(pin={{secret}})\"\n\
+         admin_group = \"{}\"\n",
+        port, ADMINS,
+    )).unwrap();
+    Authenticator::from_config(&AuthConfig { providers: vec![config] })
+}
+
+#[tokio::test]
+async fn codes_are_looked_up_in_the_directory() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(answer(socket));
+        }
+    });
+    let auth = provider(port);
+    let check = |kind, code: &str| {
+        let credential = Credential::new(kind, code);
+        let auth = &auth;
+        async move { auth.validate(&credential).await.unwrap() }
+    };
+
+    assert_eq!(check(CredentialKind::Keypad, "1234").await, Some(Identity { id: "alice".to_owned(), admin: true }));
+    assert_eq!(check(CredentialKind::Keypad, "5678").await, Some(Identity { id: "bob".to_owned(), admin: false }));
+    assert_eq!(check(CredentialKind::Keypad, "0000").await, None);
+    assert_eq!(check(CredentialKind::Keypad, "*").await, None);
+    // No token_filter, so tokens aren't looked up at all.
+    assert_eq!(check(CredentialKind::Token, "1234").await, None);
+}
+
+#[tokio::test]
+async fn an_unreachable_directory_is_an_error_not_a_wrong_code() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let auth = provider(port);
+    assert!(auth.validate(&Credential::new(CredentialKind::Keypad, "1234")).await.is_err());
+}
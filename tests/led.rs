@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use garaged::led::{pattern, Indication};
+
+fn lit_count(indication: Indication) -> usize {
+    pattern(indication).iter().filter(|(lit, _)| *lit).count()
+}
+
+#[test]
+fn error_codes_are_told_apart_by_blink_count() {
+    assert_eq!(lit_count(Indication::MqttDisconnected), 2);
+    assert_eq!(lit_count(Indication::SensorFault), 3);
+    let pause = pattern(Indication::SensorFault).last().copied();
+    assert_eq!(pause, Some((false, Duration::from_millis(1500))));
+}
+
+#[test]
+fn moving_blinks_faster_than_open() {
+    let period = |i| pattern(i).iter().map(|(_, d)| *d).sum::<Duration>();
+    assert!(period(Indication::Moving) < period(Indication::Open));
+    assert!(pattern(Indication::Closed).iter().all(|(lit, _)| *lit));
+}
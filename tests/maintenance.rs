@@ -0,0 +1,30 @@
+use garaged::maintenance::{parse_message, MaintenanceMode};
+
+#[test]
+fn set_payloads_parse() {
+    assert!(parse_message(b"ON").unwrap().active);
+    let message = parse_message(br#"{"active": false, "credential": "secret"}"#).unwrap();
+    assert!(!message.active);
+    assert_eq!(message.credential.as_deref(), Some("secret"));
+    assert!(parse_message(b"on").is_err());
+    assert!(parse_message(br#"{"active": true, "until": "noon"}"#).is_err());
+}
+
+#[test]
+fn state_survives_a_restart() {
+    let dir = std::env::temp_dir().join(format!("garaged-maintenance-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut mode = MaintenanceMode::load(&dir);
+    assert!(!mode.is_active());
+    assert!(mode.set(true, "mqtt", Some("new springs".to_owned())).unwrap());
+    assert!(!mode.set(true, "mqtt", None).unwrap());
+
+    let mode = MaintenanceMode::load(&dir);
+    assert!(mode.is_active());
+    assert_eq!(mode.state().reason.as_deref(), Some("new springs"));
+
+    // A state that can't be read errs on the side of keeping the door still.
+    std::fs::write(dir.join("maintenance.json"), b"{").unwrap();
+    assert!(MaintenanceMode::load(&dir).is_active());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,19 @@
+use garaged::door::Source;
+use garaged::error::{Error, RejectReason, StateMachineError};
+use garaged::metrics::Metrics;
+
+#[test]
+fn commands_are_counted_by_their_error_codes() {
+    let mut metrics = Metrics::default();
+    metrics.command(Source::Mqtt, &Ok(()));
+    metrics.command(Source::Mqtt, &Ok(()));
+    metrics.command(Source::Http, &Err(Error::rejected(RejectReason::Lockout)));
+    metrics.command(Source::Socket, &Err(StateMachineError::StreamEnded("status").into()));
+
+    let text = metrics.render();
+    assert!(text.contains("garaged_commands_total{source=\"mqtt\",result=\"accepted\",error=\"\",reason=\"\"} 2\n"));
+    assert!(text.contains(
+        "garaged_commands_total{source=\"http\",result=\"rejected\",error=\"command_rejected\",reason=\"lockout\"} 1\n"
+    ));
+    assert!(text.contains("garaged_commands_total{source=\"socket\",result=\"error\",error=\"state_machine\",reason=\"\"} 1\n"));
+}
@@ -0,0 +1,54 @@
+use chrono::{Local, TimeZone};
+use garaged::options::{Options, RuntimeOptions};
+
+#[test]
+fn payloads_are_checked_against_the_schema() {
+    let (options, _) = RuntimeOptions::parse(br#"{"auto_close": false, "notifications": {"webhooks": false}}"#).unwrap();
+    assert!(!options.auto_close_enabled());
+    assert!(options.notifications.alerts);
+    assert!(!options.notifications.webhooks);
+
+    let (cleared, raw) = RuntimeOptions::parse(b"").unwrap();
+    assert_eq!(cleared, RuntimeOptions::default());
+    assert!(raw.is_null());
+    assert!(cleared.auto_close_enabled());
+
+    assert!(RuntimeOptions::parse(br#"{"auto_close": "no"}"#).is_err());
+    assert!(RuntimeOptions::parse(br#"{"auto_close": true, "lights": true}"#).is_err());
+    assert!(RuntimeOptions::parse(br#"{"quiet_hours": [{"start": "25:00", "end": "07:00"}]}"#).is_err());
+}
+
+#[test]
+fn quiet_hours_hold_back_notifications() {
+    let (options, _) = RuntimeOptions::parse(br#"{"quiet_hours": [{"start": "22:00", "end": "07:00"}]}"#).unwrap();
+    let night = Local.with_ymd_and_hms(2024, 3, 5, 23, 30, 0).unwrap();
+    let day = Local.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap();
+    assert!(!options.alerts_enabled(night));
+    assert!(!options.webhooks_enabled(night));
+    assert!(options.alerts_enabled(day));
+    assert!(options.webhooks_enabled(day));
+}
+
+#[test]
+fn options_survive_a_restart() {
+    let dir = std::env::temp_dir().join(format!("garaged-options-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut store = Options::load(&dir);
+    assert_eq!(store.get(), &RuntimeOptions::default());
+    let (options, raw) = RuntimeOptions::parse(br#"{"auto_close": false}"#).unwrap();
+    assert!(store.set(options.clone(), raw.clone()).unwrap());
+    assert!(!store.set(options, raw).unwrap());
+
+    let mut store = Options::load(&dir);
+    assert!(!store.get().auto_close_enabled());
+
+    // Clearing them removes the saved copy.
+    let (cleared, raw) = RuntimeOptions::parse(b"").unwrap();
+    assert!(store.set(cleared, raw).unwrap());
+    assert!(Options::load(&dir).get().auto_close_enabled());
+
+    // A saved copy that can't be read is ignored.
+    std::fs::write(dir.join("options.json"), b"{").unwrap();
+    assert_eq!(Options::load(&dir).get(), &RuntimeOptions::default());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,42 @@
+use garaged::outbox::{DropCounts, Outbox, Priority, SATURATED_AFTER};
+
+#[tokio::test(start_paused = true)]
+async fn telemetry_is_shed_once_the_queue_stays_full() {
+    let mut outbox = Outbox::default();
+    assert!(outbox.admit(Priority::Telemetry));
+    outbox.full("stats", true, b"{}".to_vec(), Priority::Telemetry);
+    assert!(outbox.admit(Priority::Telemetry));
+
+    tokio::time::advance(SATURATED_AFTER).await;
+    outbox.full("discovery", false, b"{}".to_vec(), Priority::Normal);
+    assert!(!outbox.admit(Priority::Telemetry));
+    assert!(outbox.admit(Priority::Critical));
+    assert!(outbox.admit(Priority::Normal));
+
+    outbox.sent("state");
+    assert!(outbox.admit(Priority::Telemetry));
+    assert_eq!(outbox.dropped(), DropCounts { normal: 1, telemetry: 2, superseded: 0 });
+}
+
+#[tokio::test(start_paused = true)]
+async fn critical_publishes_are_held_latest_per_topic() {
+    let mut outbox = Outbox::default();
+    outbox.full("state", true, b"opening".to_vec(), Priority::Critical);
+    outbox.full("state", true, b"open".to_vec(), Priority::Critical);
+    outbox.full("availability", true, b"online".to_vec(), Priority::Critical);
+
+    let mut held = Vec::new();
+    while let Some((topic, _, payload)) = outbox.next_held() {
+        held.push((topic, payload));
+    }
+    assert_eq!(held, [("availability".to_owned(), b"online".to_vec()), ("state".to_owned(), b"open".to_vec())]);
+    assert_eq!(outbox.dropped().superseded, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_direct_publish_replaces_a_held_one() {
+    let mut outbox = Outbox::default();
+    outbox.full("state", true, b"opening".to_vec(), Priority::Critical);
+    outbox.sent("state");
+    assert!(outbox.next_held().is_none());
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use garaged::config::PresenceConfig;
+use garaged::presence::Presence;
+use tokio::time::Instant;
+
+fn config() -> PresenceConfig {
+    PresenceConfig {
+        topics: vec!["people/alice".to_owned(), "people/bob".to_owned()],
+        ..PresenceConfig::default()
+    }
+}
+
+#[test]
+fn nobody_is_home_until_a_topic_says_so() {
+    let config = config();
+    let mut presence = Presence::default();
+    let now = Instant::now();
+    assert!(!presence.allows_remote_open(&config, now));
+
+    assert!(!presence.report(&config, "people/alice", b"not_home", now));
+    assert!(!presence.allows_remote_open(&config, now));
+    assert!(presence.report(&config, "people/bob", b"HOME", now));
+    assert!(presence.allows_remote_open(&config, now));
+    // Another person arriving changes nothing.
+    assert!(!presence.report(&config, "people/alice", b"on", now));
+}
+
+#[test]
+fn remote_opens_are_allowed_for_the_grace_period_after_leaving() {
+    let config = config();
+    let mut presence = Presence::default();
+    let now = Instant::now();
+    presence.report(&config, "people/alice", b"home", now);
+    assert!(presence.report(&config, "people/alice", b"not_home", now));
+    assert!(!presence.anyone_home(&config));
+    assert!(presence.allows_remote_open(&config, now + Duration::from_secs(299)));
+    assert!(!presence.allows_remote_open(&config, now + config.grace()));
+}
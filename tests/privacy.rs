@@ -0,0 +1,28 @@
+use chrono::{Local, TimeZone, Timelike, Utc};
+use garaged::privacy;
+use serde_json::json;
+
+#[test]
+fn events_lose_identities_and_time_of_day() {
+    let at = Utc.with_ymd_and_hms(2026, 3, 14, 15, 9, 26).unwrap();
+    let mut event = json!({
+        "event": "access",
+        "result": "granted",
+        "code_id": "alice",
+        "timestamp": at,
+    });
+    privacy::redact(&mut event);
+    assert_eq!(event, json!({
+        "event": "access",
+        "result": "granted",
+        "date": at.with_timezone(&Local).date_naive(),
+    }));
+}
+
+#[test]
+fn start_of_day_is_local_midnight() {
+    let at = Utc.with_ymd_and_hms(2026, 3, 14, 15, 9, 26).unwrap();
+    let day = privacy::start_of_day(at).with_timezone(&Local);
+    assert_eq!(day.date_naive(), at.with_timezone(&Local).date_naive());
+    assert_eq!((day.hour(), day.minute(), day.second()), (0, 0, 0));
+}
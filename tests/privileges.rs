@@ -0,0 +1,19 @@
+use garaged::config::{Config, PrivilegesConfig};
+use garaged::error::PrivilegeError;
+use garaged::privileges::drop_to;
+
+#[test]
+fn unknown_users_are_refused_before_anything_changes() {
+    let path = std::env::temp_dir().join(format!("garaged-privileges-{}.toml", std::process::id()));
+    std::fs::write(&path, "[privileges]\nuser = \"garaged\"\n").unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.privileges, Some(PrivilegesConfig { user: "garaged".to_owned(), group: None }));
+    std::fs::write(&path, "[privileges]\ngroup = \"gpio\"\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    let _ = std::fs::remove_file(&path);
+
+    let storage = std::env::temp_dir().join(format!("garaged-privileges-{}", std::process::id()));
+    let config = PrivilegesConfig { user: "no-such-garaged-user".to_owned(), group: None };
+    assert!(matches!(drop_to(&config, &storage, &[]), Err(PrivilegeError::NoUser(_))));
+    assert!(!storage.exists());
+}
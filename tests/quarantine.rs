@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use garaged::config::QuarantineConfig;
+use garaged::quarantine::{self, Quarantine, Strike};
+
+fn config() -> QuarantineConfig {
+    QuarantineConfig { window_secs: 60, max_failed_auth: 3, max_rejections: 5, cooldown_secs: 600 }
+}
+
+#[tokio::test(start_paused = true)]
+async fn too_many_failed_logins_quarantine_the_token() {
+    let mut quarantine = Quarantine::default();
+    let source = quarantine::token_key("guess");
+    assert!(quarantine.strike(&source, Strike::FailedAuth, &config(), Utc::now()).is_none());
+    assert!(quarantine.strike(&source, Strike::FailedAuth, &config(), Utc::now()).is_none());
+    let entry = quarantine.strike(&source, Strike::FailedAuth, &config(), Utc::now()).unwrap();
+    assert_eq!(entry.reason, Strike::FailedAuth);
+    assert!(quarantine.is_quarantined(&source));
+    assert!(!source.contains("guess"));
+
+    tokio::time::advance(Duration::from_secs(600)).await;
+    assert!(!quarantine.is_quarantined(&source));
+    assert_eq!(quarantine.expire(), vec![source]);
+    assert!(quarantine.list().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn strikes_outside_the_window_are_forgotten() {
+    let mut quarantine = Quarantine::default();
+    let source = quarantine::identity_key("alice");
+    for _ in 0..4 {
+        assert!(quarantine.strike(&source, Strike::Rejected, &config(), Utc::now()).is_none());
+    }
+    tokio::time::advance(Duration::from_secs(61)).await;
+    assert!(quarantine.strike(&source, Strike::Rejected, &config(), Utc::now()).is_none());
+    assert!(!quarantine.is_quarantined(&source));
+}
+
+#[tokio::test(start_paused = true)]
+async fn admins_can_release_by_hand() {
+    let mut quarantine = Quarantine::default();
+    for _ in 0..3 {
+        quarantine.strike(quarantine::KEYPAD, Strike::FailedAuth, &config(), Utc::now());
+    }
+    assert!(quarantine.is_quarantined(quarantine::KEYPAD));
+    assert!(quarantine.release(quarantine::KEYPAD));
+    assert!(!quarantine.is_quarantined(quarantine::KEYPAD));
+    assert!(!quarantine.release(quarantine::KEYPAD));
+}
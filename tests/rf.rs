@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use garaged::config::{Config, RfAction};
+use garaged::rf::Ev1527;
+
+const T: Duration = Duration::from_micros(350);
+
+/// The edges of one frame of `code`, as the level and when, with the line
+/// having gone high at `at` to start the sync pulse.
+fn frame(code: u32, mut at: Instant) -> Vec<(bool, Instant)> {
+    let mut pulses = vec![(1, 31)];
+    pulses.extend((0..24).rev().map(|bit| if code >> bit & 1 == 1 { (3, 1) } else { (1, 3) }));
+    let mut edges = Vec::new();
+    for (high, low) in pulses {
+        at += T * high;
+        edges.push((false, at));
+        at += T * low;
+        edges.push((true, at));
+    }
+    edges
+}
+
+fn decode(decoder: &mut Ev1527, edges: &[(bool, Instant)]) -> Vec<u32> {
+    edges.iter().filter_map(|&(high, at)| decoder.edge(high, at)).collect()
+}
+
+#[test]
+fn remote_frames_decode_once_per_press() {
+    let mut decoder = Ev1527::default();
+    let start = Instant::now();
+    decoder.edge(true, start);
+    let first = frame(0xa1b2c3, start);
+    assert_eq!(decode(&mut decoder, &first), vec![0xa1b2c3]);
+
+    // Held down, the remote repeats the frame; that is still one press.
+    let at = first.last().unwrap().1;
+    let repeat = frame(0xa1b2c3, at);
+    assert!(decode(&mut decoder, &repeat).is_empty());
+
+    // A missed edge throws the frame away rather than misreading it, and
+    // the next repeat is read in full.
+    let at = repeat.last().unwrap().1 + Duration::from_secs(2);
+    decoder.edge(false, at - T * 31);
+    decoder.edge(true, at);
+    let mut broken = frame(0x0f0f0f, at);
+    broken.remove(20);
+    assert!(decode(&mut decoder, &broken).is_empty());
+    let at = broken.last().unwrap().1;
+    assert_eq!(decode(&mut decoder, &frame(0x0f0f0f, at)), vec![0x0f0f0f]);
+}
+
+#[test]
+fn learned_codes_are_configured_in_hex() {
+    let path = std::env::temp_dir().join(format!("garaged-rf-{}.toml", std::process::id()));
+    std::fs::write(&path, r#"
+        [rf]
+        data = { pin = 8 }
+        codes = [{ code = 0xa1b2c3, name = "car" }, { code = 0xa1b2c4, action = "close" }]
+    "#).unwrap();
+    let config = Config::load(&path).unwrap();
+    let rf = config.rf.unwrap();
+    assert_eq!(rf.codes[0].code, 0xa1b2c3);
+    assert_eq!(rf.codes[0].action, RfAction::Toggle);
+    assert_eq!(rf.codes[1].action, RfAction::Close);
+
+    std::fs::write(&path, "[rf]\ndata = { pin = 8 }\ncodes = [{ code = 0x1000000 }]\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    std::fs::write(&path, "[rf]\ndata = { pin = 8 }\ncodes = [{ code = 1 }, { code = 1, action = \"open\" }]\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    // Pin 19 is the Iono Pi's DI2, which is only polled.
+    std::fs::write(&path, "[rf]\ndata = { pin = 19 }\n").unwrap();
+    assert!(Config::load(&path).is_err());
+    std::fs::write(&path, "[gpio]\nbackends = [\"sysfs\"]\n[rf]\ndata = { pin = 19 }\n").unwrap();
+    assert!(Config::load(&path).is_ok());
+    let _ = std::fs::remove_file(&path);
+}
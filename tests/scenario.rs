@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use garaged::config::SimulationConfig;
+use garaged::error::ScenarioError;
+use garaged::scenario::{self, Action, BrokerLink, Scenario};
+use garaged::simulate::{Poke, Simulator};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn write_scenario(name: &str, text: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("garaged-scenario-{}-{}.yaml", name, std::process::id()));
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn scenarios_load_in_time_order() {
+    let path = write_scenario("load", "steps:\n\
+        \x20 - { at: 1m, do: restore_broker }\n\
+        \x20 - { at: 12s, do: jam }\n\
+        \x20 - { at: 5, do: button }\n\
+        \x20 - { at: 500ms, do: Press }\n");
+    let scenario = Scenario::load(&path).unwrap();
+    let steps: Vec<_> = scenario.steps.iter().map(|s| (s.at, s.action)).collect();
+    assert_eq!(steps, vec![
+        (Duration::from_millis(500), Action::Poke(Poke::Press)),
+        (Duration::from_secs(5), Action::Poke(Poke::Button)),
+        (Duration::from_secs(12), Action::Poke(Poke::Jam)),
+        (Duration::from_secs(60), Action::RestoreBroker),
+    ]);
+    assert!(scenario.cuts_broker());
+
+    std::fs::write(&path, "steps:\n  - { at: 5s, do: fly }\n").unwrap();
+    assert!(matches!(Scenario::load(&path), Err(ScenarioError::Parse(..))));
+    std::fs::write(&path, "steps:\n  - { at: -1s, do: press }\n").unwrap();
+    assert!(matches!(Scenario::load(&path), Err(ScenarioError::Parse(..))));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_jam_stops_the_virtual_door_until_it_is_freed() {
+    let path = write_scenario("jam", "steps:\n\
+        \x20 - { at: 1s, do: press }\n\
+        \x20 - { at: 4s, do: jam }\n\
+        \x20 - { at: 5s, do: press }\n\
+        \x20 - { at: 8s, do: unjam }\n\
+        \x20 - { at: 9s, do: press }\n");
+    let scenario = Scenario::load(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    let door = Simulator::spawn(&SimulationConfig { travel_secs: 10.0, scenario: None });
+    tokio::spawn(scenario::run(scenario, door.poker(), None));
+
+    tokio::time::sleep(Duration::from_millis(4500)).await;
+    let jammed = door.sensors().percent;
+    assert!((29.0..=31.0).contains(&jammed), "jammed at {}", jammed);
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert_eq!(door.sensors().percent, jammed);
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    // Free again, and the next press sends it back the way it came.
+    assert!(door.sensors().percent < jammed);
+}
+
+async fn echo(socket: &mut TcpStream) -> usize {
+    socket.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    tokio::time::timeout(Duration::from_secs(5), socket.read(&mut buf)).await.unwrap().unwrap_or(0)
+}
+
+#[tokio::test]
+async fn dropping_the_broker_cuts_and_refuses_relayed_connections() {
+    let broker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = broker.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = broker.accept().await {
+            tokio::spawn(async move {
+                let (mut read, mut write) = socket.split();
+                let _ = tokio::io::copy(&mut read, &mut write).await;
+            });
+        }
+    });
+    let link = BrokerLink::spawn("127.0.0.1", port).await.unwrap();
+
+    let mut connected = TcpStream::connect(link.addr()).await.unwrap();
+    assert_eq!(echo(&mut connected).await, 4);
+    link.set_up(false);
+    assert_eq!(echo(&mut connected).await, 0);
+    let mut refused = TcpStream::connect(link.addr()).await.unwrap();
+    assert_eq!(echo(&mut refused).await, 0);
+    link.set_up(true);
+    let mut restored = TcpStream::connect(link.addr()).await.unwrap();
+    assert_eq!(echo(&mut restored).await, 4);
+}
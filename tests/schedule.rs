@@ -0,0 +1,56 @@
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Europe::Oslo;
+use garaged::schedule::{CronExpr, ScheduleAction, ScheduleEntry, Scheduler};
+
+fn utc(text: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+}
+
+fn entry(name: &str, cron: &str, action: ScheduleAction) -> ScheduleEntry {
+    ScheduleEntry { name: name.to_owned(), cron: cron.parse().unwrap(), action, timezone: Some(Oslo) }
+}
+
+#[test]
+fn expressions_find_their_next_run() {
+    let night: CronExpr = "30 22 * * *".parse().unwrap();
+    // 22:30 in Oslo is 20:30 UTC in summer and 21:30 in winter.
+    assert_eq!(night.next_after(utc("2026-07-01T12:00:00Z"), &Oslo), Some(utc("2026-07-01T20:30:00Z")));
+    assert_eq!(night.next_after(utc("2026-12-01T21:30:00Z"), &Oslo), Some(utc("2026-12-02T21:30:00Z")));
+
+    // From a Friday evening to Monday morning.
+    let weekdays: CronExpr = "0 9 * * Mon-Fri".parse().unwrap();
+    assert_eq!(weekdays.next_after(utc("2026-10-16T18:00:00Z"), &Utc), Some(utc("2026-10-19T09:00:00Z")));
+    let weekends: CronExpr = "*/20 8 * * Sat-Sun".parse().unwrap();
+    assert_eq!(weekends.next_after(utc("2026-10-18T08:20:00Z"), &Utc), Some(utc("2026-10-18T08:40:00Z")));
+
+    // 02:30 doesn't exist in Oslo the night summer time starts.
+    let early: CronExpr = "30 2 * * *".parse().unwrap();
+    let spring = Oslo.with_ymd_and_hms(2026, 3, 29, 0, 0, 0).unwrap().with_timezone(&Utc);
+    assert_eq!(early.next_after(spring, &Oslo), Some(utc("2026-03-30T00:30:00Z")));
+
+    for invalid in ["30 22 * *", "60 * * * *", "0 9 * * Mon-Funday", "*/0 * * * *", "0 17-9 * * *"] {
+        assert!(invalid.parse::<CronExpr>().is_err(), "{}", invalid);
+    }
+    let never: CronExpr = "0 0 30 Feb *".parse().unwrap();
+    assert_eq!(never.next_after(Utc::now(), &Utc), None);
+}
+
+#[test]
+fn due_entries_move_on_to_their_next_run() {
+    let entries = [
+        entry("night", "30 22 * * *", ScheduleAction::Close),
+        entry("work", "0 9 * * Mon-Fri", ScheduleAction::Lock),
+    ];
+    let mut scheduler = Scheduler::new(&entries, utc("2026-10-15T12:00:00Z"));
+    assert_eq!(scheduler.next_due(), Some(utc("2026-10-15T20:30:00Z")));
+    assert!(scheduler.due(&entries, utc("2026-10-15T20:29:59Z")).is_empty());
+
+    let due = scheduler.due(&entries, utc("2026-10-15T20:30:00Z"));
+    assert_eq!(due.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["night"]);
+    let (next, at) = scheduler.upcoming(&entries).unwrap();
+    assert_eq!((next.name.as_str(), at), ("work", utc("2026-10-16T07:00:00Z")));
+
+    // After the clock jumps a week ahead each entry runs once.
+    assert_eq!(scheduler.due(&entries, utc("2026-10-22T23:00:00Z")).len(), 2);
+    assert_eq!(scheduler.next_due(), Some(utc("2026-10-23T07:00:00Z")));
+}
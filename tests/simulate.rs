@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use garaged::config::SimulationConfig;
+use garaged::simulate::{Motion, Poke, Simulator, VirtualDoor};
+
+#[test]
+fn press_starts_stops_and_reverses_the_door() {
+    let mut door = VirtualDoor::new(Duration::from_secs(10));
+    door.press();
+    assert_eq!(door.motion(), Motion::Opening);
+    door.advance(Duration::from_secs(4));
+    assert_eq!(door.sensors().percent, 40.0);
+    door.press();
+    assert_eq!(door.motion(), Motion::Stopped);
+    door.press();
+    assert_eq!(door.motion(), Motion::Closing);
+    door.advance(Duration::from_secs(30));
+    assert_eq!(door.motion(), Motion::Stopped);
+    assert!(door.sensors().closed);
+}
+
+#[test]
+fn breaking_the_beam_reverses_a_closing_door() {
+    let mut door = VirtualDoor::new(Duration::from_secs(10));
+    door.poke(Poke::Open);
+    assert!(door.sensors().open);
+    door.press();
+    door.advance(Duration::from_secs(3));
+    door.poke(Poke::Obstruct);
+    assert_eq!(door.motion(), Motion::Opening);
+    assert!(door.sensors().obstructed);
+}
+
+#[test]
+fn pokes_parse_from_text() {
+    assert_eq!("button\n".parse::<Poke>(), Ok(Poke::Button));
+    assert_eq!("Closed".parse::<Poke>(), Ok(Poke::Closed));
+    assert!("jump".parse::<Poke>().is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn closed_sensor_follows_the_virtual_door() {
+    let door = Simulator::spawn(&SimulationConfig { travel_secs: 5.0, scenario: None });
+    let mut closed = door.stream(|s| s.closed);
+    let mut buttons = door.button_stream();
+    door.poke(Poke::Press);
+    assert_eq!(closed.next().await.unwrap().unwrap(), 0);
+    tokio::time::sleep(Duration::from_secs(6)).await;
+    assert!(door.sensors().open);
+    door.poke(Poke::Button);
+    assert_eq!(buttons.next().await.unwrap().unwrap(), 1);
+}
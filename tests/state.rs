@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use garaged::door::{Command, Position, Status};
+use garaged::position::{PositionTracker, Readings, SavedPosition};
+use garaged::state::StateStore;
+
+#[tokio::test(start_paused = true)]
+async fn a_partway_stop_survives_a_restart() {
+    let readings = |closed, open| Readings { closed, open: Some(open), zones: Vec::new() };
+    let mut tracker = PositionTracker::new(true, Vec::new(), Duration::from_secs(10));
+    tracker.resume(&readings(false, true));
+    assert_eq!(tracker.sensors_changed(&readings(false, false)), Some(Position::Closing));
+    assert_eq!(tracker.saved(), None);
+    tokio::time::advance(Duration::from_secs(7)).await;
+    assert_eq!(tracker.relay_triggered(), Some(Position::Stopped));
+    let saved = tracker.saved().unwrap();
+    assert_eq!(saved, SavedPosition { position: Position::Stopped, percent: 30, heading: Some(Status::Closed) });
+
+    let dir = std::env::temp_dir().join(format!("garaged-state-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    StateStore::new(&dir).update(saved).unwrap();
+    let saved = StateStore::new(&dir).load().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut restarted = PositionTracker::new(true, Vec::new(), Duration::from_secs(10));
+    assert_eq!(restarted.resume(&readings(false, false)), Position::Stopped);
+    assert_eq!(restarted.percent(), 50);
+    assert!(restarted.restore(&saved));
+    assert_eq!(restarted.percent(), 30);
+    assert!(restarted.check_heading(Command::Close).is_err());
+
+    let mut closed = PositionTracker::new(true, Vec::new(), Duration::from_secs(10));
+    closed.resume(&readings(true, false));
+    assert!(!closed.restore(&saved));
+    assert_eq!(closed.percent(), 0);
+}
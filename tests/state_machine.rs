@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use garaged::door::{Command, Position};
+use garaged::error::RejectReason;
+use garaged::machine::{self, Event, Machine};
+use proptest::prelude::*;
+
+fn command() -> impl Strategy<Value = Command> {
+    prop_oneof![
+        Just(Command::Open),
+        Just(Command::Close),
+        Just(Command::Cancel),
+        Just(Command::HealthCheck),
+        Just(Command::Vent),
+    ]
+}
+
+fn event() -> impl Strategy<Value = Event> {
+    prop_oneof![
+        4 => (any::<bool>(), any::<bool>()).prop_map(|(closed, open)| Event::Sensors { closed, open }),
+        4 => (command(), any::<bool>()).prop_map(|(command, admin)| Event::Command { command, admin }),
+        2 => Just(Event::Button),
+        1 => Just(Event::DeadlineReached),
+        1 => any::<bool>().prop_map(Event::Lockout),
+        1 => any::<bool>().prop_map(Event::NobodyHome),
+        1 => any::<bool>().prop_map(Event::VacationLock),
+        1 => any::<bool>().prop_map(Event::Maintenance),
+        1 => any::<bool>().prop_map(Event::Obstruction),
+        2 => (0u64..5_000).prop_map(|ms| Event::Elapse(Duration::from_millis(ms))),
+    ]
+}
+
+fn machine() -> impl Strategy<Value = Machine> {
+    (any::<bool>(), any::<bool>(), any::<bool>())
+        .prop_map(|(dual, closed, open)| Machine::new(dual, Duration::from_secs(2), closed, open))
+}
+
+proptest! {
+    #[test]
+    fn invariants_hold(mut machine in machine(), events in prop::collection::vec(event(), 0..200)) {
+        if let Err(v) = machine::check(&mut machine, &events) {
+            prop_assert!(false, "{} at step {} ({:?})", v.message, v.step, v.event);
+        }
+    }
+
+    #[test]
+    fn lockout_blocks_every_remote_press(
+        mut machine in machine(),
+        commands in prop::collection::vec(command().prop_filter("cancel is exempt", |c| *c != Command::Cancel), 1..50),
+    ) {
+        machine.step(Event::Lockout(true));
+        for command in commands {
+            let outcome = machine.step(Event::Command { command, admin: false });
+            prop_assert!(!outcome.pulsed);
+            machine.step(Event::Elapse(Duration::from_secs(10)));
+        }
+    }
+
+    #[test]
+    fn maintenance_blocks_every_press(
+        mut machine in machine(),
+        commands in prop::collection::vec((command(), any::<bool>()), 1..50),
+    ) {
+        machine.step(Event::Maintenance(true));
+        for (command, admin) in commands {
+            let outcome = machine.step(Event::Command { command, admin });
+            prop_assert!(!outcome.pulsed);
+            prop_assert!(!machine.step(Event::Button).pulsed);
+            machine.step(Event::Elapse(Duration::from_secs(10)));
+        }
+    }
+
+    #[test]
+    fn presses_alone_never_leave_closed(dual in any::<bool>(), presses in 1usize..20) {
+        let mut machine = Machine::new(dual, Duration::ZERO, true, false);
+        for _ in 0..presses {
+            machine.step(Event::Button);
+            machine.step(Event::DeadlineReached);
+        }
+        prop_assert_eq!(machine.position(), Position::Closed);
+    }
+
+    #[test]
+    fn fuzz_entry_point_accepts_any_input(data in prop::collection::vec(any::<u8>(), 0..512)) {
+        machine::fuzz(&data);
+    }
+}
+
+#[test]
+fn obstruction_blocks_close_only() {
+    let mut machine = Machine::new(true, Duration::ZERO, false, true);
+    machine.step(Event::Obstruction(true));
+    let outcome = machine.step(Event::Command { command: Command::Close, admin: true });
+    assert_eq!(outcome.rejected, Some(RejectReason::Obstructed));
+    assert!(!outcome.pulsed);
+
+    machine.step(Event::Obstruction(false));
+    assert!(machine.step(Event::Command { command: Command::Close, admin: false }).pulsed);
+}
+
+#[test]
+fn nobody_home_blocks_remote_opens_only() {
+    let mut machine = Machine::new(true, Duration::ZERO, true, false);
+    machine.step(Event::NobodyHome(true));
+    let outcome = machine.step(Event::Command { command: Command::Open, admin: true });
+    assert_eq!(outcome.rejected, Some(RejectReason::NobodyHome));
+    assert!(machine.step(Event::Button).pulsed);
+
+    machine.step(Event::Sensors { closed: false, open: true });
+    assert!(machine.step(Event::Command { command: Command::Close, admin: false }).pulsed);
+}
@@ -0,0 +1,31 @@
+use chrono::{Duration, TimeZone, Utc};
+use garaged::door::Status;
+use garaged::stats::UsageStats;
+
+#[test]
+fn open_duration_and_last_change_survive_a_restart() {
+    let opened = Utc.with_ymd_and_hms(2024, 3, 5, 12, 0, 0).unwrap();
+    let mut stats = UsageStats::default();
+    stats.resume(Status::Closed, opened - Duration::minutes(5));
+    assert_eq!(stats.report(opened).last_changed, None);
+
+    assert!(stats.door_changed(Status::Open, opened));
+    stats.position_changed(opened);
+    let report = stats.report(opened + Duration::minutes(3));
+    assert_eq!(report.open_secs, 180);
+    assert_eq!(report.last_changed, Some(opened));
+
+    // Restarted with the door still open: the time down counts.
+    let mut restored: UsageStats = serde_json::from_value(serde_json::to_value(&stats).unwrap()).unwrap();
+    restored.resume(Status::Open, opened + Duration::minutes(10));
+    let report = restored.report(opened + Duration::minutes(10));
+    assert_eq!(report.open_secs, 600);
+    assert_eq!(report.last_changed, Some(opened));
+
+    // Closed while down: the change is put at the restart.
+    let back = opened + Duration::minutes(20);
+    restored.resume(Status::Closed, back);
+    let report = restored.report(back);
+    assert_eq!(report.open_secs, 0);
+    assert_eq!(report.last_changed, Some(back));
+}
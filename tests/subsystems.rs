@@ -0,0 +1,26 @@
+use garaged::subsystems::{Condition, Subsystem, SubsystemStatus, Subsystems};
+use serde_json::json;
+
+#[test]
+fn overall_is_the_worst_subsystem() {
+    let mut subsystems = Subsystems::default();
+    subsystems.set(Subsystem::Gpio, SubsystemStatus::ok());
+    subsystems.set(Subsystem::Http, SubsystemStatus::disabled());
+    assert_eq!(subsystems.report().overall, Condition::Ok);
+
+    subsystems.set(Subsystem::Notifications, SubsystemStatus::failing("webhook returned 503"));
+    subsystems.set(Subsystem::Mqtt, SubsystemStatus::degraded("shedding telemetry"));
+    let report = serde_json::to_value(subsystems.report()).unwrap();
+    assert_eq!(report["overall"], "failing");
+    assert_eq!(report["subsystems"]["http"], json!({ "condition": "disabled" }));
+    assert_eq!(report["subsystems"]["notifications"]["detail"], "webhook returned 503");
+}
+
+#[test]
+fn only_changes_are_flagged() {
+    let mut subsystems = Subsystems::default();
+    assert!(subsystems.set(Subsystem::Scheduler, SubsystemStatus::ok()));
+    assert!(subsystems.take_changed());
+    assert!(!subsystems.set(Subsystem::Scheduler, SubsystemStatus::ok()));
+    assert!(!subsystems.take_changed());
+}
@@ -0,0 +1,44 @@
+use chrono::{TimeZone, Utc};
+use garaged::config::LocaleConfig;
+use garaged::templates::Templates;
+use serde_json::json;
+
+fn locale(language: &str) -> LocaleConfig {
+    LocaleConfig { language: language.to_owned(), ..LocaleConfig::default() }
+}
+
+#[test]
+fn built_in_messages_follow_the_language() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 15, 18, 30, 0).unwrap();
+    let details = json!({ "door": "garage", "alert": "never_moved", "trigger": "keypad" });
+    let english = Templates::load(&locale("en")).unwrap();
+    assert_eq!(english.render("never_moved", &details, "closed", now).unwrap(), "Garage didn't move when the opener was pressed (keypad).");
+    let dutch = Templates::load(&locale("nl")).unwrap();
+    assert_eq!(dutch.render("never_moved", &details, "closed", now).unwrap(), "Garage bewoog niet na het indrukken van de knop (keypad).");
+
+    let resolved = json!({ "alert": "left_open", "state": "resolved" });
+    let german = Templates::load(&locale("de")).unwrap();
+    assert_eq!(german.render("left_open", &resolved, "closed", now).unwrap(), "Garage ist wieder geschlossen.");
+    let open = json!({ "alert": "left_open", "state": "open", "open_secs": 2700, "open_since": "2026-10-15T17:45:00Z" });
+    let message = english.render("left_open", &open, "open", now).unwrap();
+    assert!(message.starts_with("Garage has been open since 2026-10-15 ") && message.ends_with(" (45 min)."), "{}", message);
+}
+
+#[test]
+fn template_files_override_the_built_in_ones() {
+    let dir = std::env::temp_dir().join(format!("garaged-templates-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("nb")).unwrap();
+    std::fs::write(dir.join("nb/link.j2"), "{{ door }}: {{ source }} sier {{ value }}").unwrap();
+    let config = LocaleConfig { templates: Some(dir.clone()), ..locale("nb") };
+    let templates = Templates::load(&config).unwrap();
+    let details = json!({ "link": "alarm", "value": "armed_away" });
+    let now = Utc::now();
+    assert_eq!(templates.render("link", &details, "open", now).unwrap(), "Garage: alarm sier armed_away");
+    // Languages without built-in templates fall back to English.
+    assert_eq!(templates.render("never_moved", &json!({ "trigger": "mqtt" }), "open", now).unwrap(),
+        "Garage didn't move when the opener was pressed (mqtt).");
+
+    std::fs::write(dir.join("nb/link.j2"), "{% if %}").unwrap();
+    assert!(Templates::load(&config).is_err());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,26 @@
+use garaged::config::MqttConfig;
+use garaged::mqtt::{self, Topics};
+use garaged::vehicle::VehicleEvent;
+use rumqttc::QoS;
+
+#[test]
+fn discovery_is_kept_apart_from_the_door_topics() {
+    let topics = Topics::new("garaged/garage", "ha");
+    assert_eq!(topics.config, "ha/cover/garage/config");
+    assert_eq!(topics.vehicle_trigger_config(VehicleEvent::Arrived), "ha/device_automation/garage/car_arrived/config");
+    assert_eq!(topics.temperature_config("ambient"), "ha/sensor/garage/temperature_ambient/config");
+    assert_eq!(topics.temperature("ambient"), "garaged/garage/temperature/ambient");
+    for topic in topics.all() {
+        let discovery = topic.starts_with("ha/") && topic.ends_with("/config");
+        assert!(discovery || topic.starts_with("garaged/garage/"), "{} is in neither namespace", topic);
+    }
+}
+
+#[test]
+fn topic_qos_overrides_the_default() {
+    let mut config = MqttConfig::default();
+    config.topic_qos.insert("garaged/garage/command".to_owned(), 1);
+    assert_eq!(config.qos_for("garaged/garage/command", 2), 1);
+    assert_eq!(config.qos_for("alarm/state", 0), 0);
+    assert_eq!(mqtt::qos(config.qos_for("garaged/garage/state", config.qos)), QoS::AtLeastOnce);
+}
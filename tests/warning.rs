@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use garaged::warning::WarningPattern;
+
+fn pattern() -> WarningPattern {
+    WarningPattern { length: Duration::from_secs(5), beeps: 5, beep: Duration::from_millis(500) }
+}
+
+#[test]
+fn beeps_are_spread_over_the_last_seconds() {
+    let pattern = pattern();
+    let ms = Duration::from_millis;
+    assert!(!pattern.active(ms(8_000)));
+    assert_eq!(pattern.next_change(ms(8_000)), Some(ms(3_000)));
+    assert!(pattern.active(ms(5_000)));
+    assert_eq!(pattern.next_change(ms(5_000)), Some(ms(500)));
+    assert!(!pattern.active(ms(4_500)));
+    assert_eq!(pattern.next_change(ms(4_500)), Some(ms(500)));
+    assert!(pattern.active(ms(1_000)));
+    assert!(!pattern.active(ms(400)));
+    assert_eq!(pattern.next_change(ms(400)), None);
+}
+
+#[test]
+fn every_beep_turns_on_and_off_once() {
+    let pattern = pattern();
+    let mut left = Duration::from_secs(12);
+    let mut on = pattern.active(left);
+    let mut beeps = 0;
+    while let Some(step) = pattern.next_change(left) {
+        left -= step;
+        let now = pattern.active(left);
+        assert_ne!(now, on, "woke without a change at {:?}", left);
+        if now {
+            beeps += 1;
+        }
+        on = now;
+    }
+    assert!(!on);
+    assert_eq!(beeps, 5);
+}
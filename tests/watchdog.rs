@@ -0,0 +1,25 @@
+use garaged::config::WatchdogConfig;
+use garaged::watchdog::HardwareWatchdog;
+
+#[test]
+fn heartbeat_toggles_only_while_healthy() {
+    let dir = std::env::temp_dir().join(format!("garaged-watchdog-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+    let config = WatchdogConfig { dir: dir.clone(), ..WatchdogConfig::default() };
+
+    let mut watchdog = HardwareWatchdog::enable(&config).unwrap();
+    assert_eq!(read("enabled"), "1");
+    assert_eq!(read("heartbeat"), "1");
+    watchdog.tick(None).unwrap();
+    assert_eq!(read("heartbeat"), "0");
+    watchdog.tick(Some("mqtt")).unwrap();
+    assert_eq!(read("heartbeat"), "0");
+    watchdog.tick(None).unwrap();
+    assert_eq!(read("heartbeat"), "1");
+
+    watchdog.disable().unwrap();
+    assert_eq!(read("enabled"), "0");
+    let _ = std::fs::remove_dir_all(&dir);
+}
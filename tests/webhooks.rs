@@ -0,0 +1,82 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use garaged::config::WebhookConfig;
+use garaged::subsystems::{self, Subsystem};
+use garaged::webhooks::{WebhookEvent, Webhooks};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+/// A receiver that fails its first `failures` requests with a 503 and
+/// hands the bodies of the rest to the returned channel.
+fn receiver(failures: u32) -> (SocketAddr, mpsc::UnboundedReceiver<Value>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let seen = Arc::new(AtomicU32::new(0));
+    let make = make_service_fn(move |_| {
+        let (tx, seen) = (tx.clone(), seen.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let (tx, seen) = (tx.clone(), seen.clone());
+                async move {
+                    if seen.fetch_add(1, Ordering::SeqCst) < failures {
+                        let response = Response::builder().status(StatusCode::SERVICE_UNAVAILABLE).body(Body::empty());
+                        return Ok::<_, Infallible>(response.unwrap());
+                    }
+                    let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                    tx.send(serde_json::from_slice(&body).unwrap()).unwrap();
+                    Ok(Response::new(Body::empty()))
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    (addr, rx)
+}
+
+fn config(addr: SocketAddr, events: &[WebhookEvent]) -> WebhookConfig {
+    WebhookConfig {
+        url: format!("http://{}/hook", addr),
+        events: events.iter().copied().collect(),
+        max_attempts: 3,
+        backoff_secs: 0.01,
+        max_backoff_secs: 0.05,
+        timeout_secs: 5,
+    }
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried() {
+    let (addr, mut bodies) = receiver(2);
+    let (reporter, mut statuses) = subsystems::channel();
+    let webhooks = Webhooks::spawn(&[config(addr, &[WebhookEvent::Open])], reporter);
+    webhooks.send(WebhookEvent::Open, &json!({ "event": "open" }));
+
+    let body = tokio::time::timeout(Duration::from_secs(5), bodies.recv()).await.unwrap();
+    assert_eq!(body, Some(json!({ "event": "open" })));
+    let (subsystem, _) = statuses.recv().await.unwrap();
+    assert_eq!(subsystem, Subsystem::Notifications);
+}
+
+#[tokio::test]
+async fn webhooks_only_get_the_events_they_list() {
+    let (closes, mut close_bodies) = receiver(0);
+    let (alerts, mut alert_bodies) = receiver(0);
+    let configs = [config(closes, &[WebhookEvent::Close]), config(alerts, &[WebhookEvent::LeftOpen, WebhookEvent::Close])];
+    let (reporter, _statuses) = subsystems::channel();
+    let webhooks = Webhooks::spawn(&configs, reporter);
+    webhooks.send(WebhookEvent::LeftOpen, &json!({ "event": "left_open" }));
+    webhooks.send(WebhookEvent::Close, &json!({ "event": "close" }));
+
+    let wait = Duration::from_secs(5);
+    assert_eq!(tokio::time::timeout(wait, alert_bodies.recv()).await.unwrap(), Some(json!({ "event": "left_open" })));
+    assert_eq!(tokio::time::timeout(wait, alert_bodies.recv()).await.unwrap(), Some(json!({ "event": "close" })));
+    assert_eq!(tokio::time::timeout(wait, close_bodies.recv()).await.unwrap(), Some(json!({ "event": "close" })));
+    assert!(close_bodies.try_recv().is_err());
+}